@@ -0,0 +1,115 @@
+use model::Sex;
+
+// Activity multipliers applied to BMR
+pub const ACTIVITY_LEVELS: [(&str, f64); 5] = [
+    ("Сидячая активность", 1.2),
+    ("Легкая активность", 1.375),
+    ("Средняя активность", 1.55),
+    ("Полноценная активность", 1.725),
+    ("Супер активность", 1.9),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Formula {
+    MifflinStJeor,
+    HarrisBenedict,
+    KatchMcArdle,
+}
+
+impl Formula {
+    pub fn new(s: &str) -> Option<Self> {
+        match s {
+            "msj" => Some(Self::MifflinStJeor),
+            "hb" => Some(Self::HarrisBenedict),
+            "km" => Some(Self::KatchMcArdle),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::MifflinStJeor => "Миффлина-Сан Жеора",
+            Self::HarrisBenedict => "Харриса-Бенедикта",
+            Self::KatchMcArdle => "Кэтча-МакАрдла",
+        }
+    }
+}
+
+// Mifflin-St Jeor: weight in kg, height in cm, age in years
+pub fn mifflin_st_jeor(sex: Sex, weight: f64, height: f64, age: f64) -> f64 {
+    let bmr = 10.0 * weight + 6.25 * height - 5.0 * age;
+    match sex {
+        Sex::Male => bmr + 5.0,
+        Sex::Female => bmr - 161.0,
+    }
+}
+
+// Revised Harris-Benedict (Roza and Shizgal, 1984)
+pub fn harris_benedict(sex: Sex, weight: f64, height: f64, age: f64) -> f64 {
+    match sex {
+        Sex::Male => 88.362 + 13.397 * weight + 4.799 * height - 5.677 * age,
+        Sex::Female => 447.593 + 9.247 * weight + 3.098 * height - 4.330 * age,
+    }
+}
+
+// Katch-McArdle: based on lean body mass, body fat in percents
+pub fn katch_mcardle(weight: f64, body_fat: f64) -> f64 {
+    370.0 + 21.6 * weight * (1.0 - body_fat / 100.0)
+}
+
+// Apply deficit (negative) or surplus (positive) goal in percents
+pub fn apply_goal(kcal: f64, goal_percent: f64) -> f64 {
+    kcal * (1.0 + goal_percent / 100.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn approx(a: f64, b: f64) -> bool {
+        (a - b).abs() < 0.01
+    }
+
+    #[test]
+    fn test_formula() {
+        assert_eq!(Some(Formula::MifflinStJeor), Formula::new("msj"));
+        assert_eq!(Some(Formula::HarrisBenedict), Formula::new("hb"));
+        assert_eq!(Some(Formula::KatchMcArdle), Formula::new("km"));
+        assert_eq!(None, Formula::new("x"));
+    }
+
+    #[test]
+    fn test_mifflin_st_jeor() {
+        assert!(approx(
+            1780.0,
+            mifflin_st_jeor(Sex::Male, 80.0, 180.0, 30.0)
+        ));
+        assert!(approx(
+            1614.0,
+            mifflin_st_jeor(Sex::Female, 80.0, 180.0, 30.0)
+        ));
+    }
+
+    #[test]
+    fn test_harris_benedict() {
+        assert!(approx(
+            1853.63,
+            harris_benedict(Sex::Male, 80.0, 180.0, 30.0)
+        ));
+        assert!(approx(
+            1615.09,
+            harris_benedict(Sex::Female, 80.0, 180.0, 30.0)
+        ));
+    }
+
+    #[test]
+    fn test_katch_mcardle() {
+        assert!(approx(1752.4, katch_mcardle(80.0, 20.0)));
+    }
+
+    #[test]
+    fn test_apply_goal() {
+        assert!(approx(1700.0, apply_goal(2000.0, -15.0)));
+        assert!(approx(2200.0, apply_goal(2000.0, 10.0)));
+    }
+}
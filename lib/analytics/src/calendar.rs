@@ -0,0 +1,152 @@
+use chrono::Duration;
+use model::{JournalReport, SportActivityReport, Weight};
+use types::timestamp::Timestamp;
+
+use crate::tdee::daily_calories;
+
+pub const DAYS_IN_WEEK: usize = 7;
+// Difference with the limit (share of the limit) considered as full deviation
+pub const FULL_DEVIATION: f64 = 0.25;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalendarDay {
+    pub day: Timestamp,
+    // False for days of adjacent months filling the first and last weeks
+    pub in_month: bool,
+    // Total calories, None if the day is not logged
+    pub cal: Option<f64>,
+    // Last weigh-in of the day
+    pub weight: Option<f64>,
+    pub workout: bool,
+}
+
+impl CalendarDay {
+    // Calories left to the limit, negative if the limit is exceeded
+    pub fn diff(&self, cal_limit: f64) -> Option<f64> {
+        self.cal.map(|cal| cal_limit - cal)
+    }
+
+    // Deviation from the limit in range [0, 1], 1 for FULL_DEVIATION and more
+    pub fn deviation(&self, cal_limit: f64) -> Option<f64> {
+        if cal_limit <= 0.0 {
+            return None;
+        }
+        self.diff(cal_limit)
+            .map(|diff| (diff.abs() / (cal_limit * FULL_DEVIATION)).min(1.0))
+    }
+}
+
+// Month grid of weeks (Monday first) for month containing timestamp.
+// Data is matched to days by time ranges, so it may be in any timezone.
+pub fn month_calendar(
+    month: &Timestamp,
+    rep: &[JournalReport],
+    weights: &[Weight],
+    activities: &[SportActivityReport],
+) -> Vec<Vec<CalendarDay>> {
+    let (month_from, month_to) = (month.start_of_month(), month.end_of_month());
+    let daily_cal = daily_calories(rep);
+
+    let mut weeks: Vec<Vec<CalendarDay>> = Vec::new();
+    let mut day = month_from.start_of_week();
+    while day.unix_millis() <= month_to.unix_millis() {
+        let mut week = Vec::with_capacity(DAYS_IN_WEEK);
+        for _ in 0..DAYS_IN_WEEK {
+            let (from, to) = (day.unix_millis(), day.end_of_day().unix_millis());
+            let in_day = |ts: &Timestamp| (from..=to).contains(&ts.unix_millis());
+
+            let cal: Vec<f64> = daily_cal
+                .iter()
+                .filter(|(ts, _)| in_day(ts))
+                .map(|(_, cal)| *cal)
+                .collect();
+
+            week.push(CalendarDay {
+                in_month: from >= month_from.unix_millis() && to <= month_to.unix_millis(),
+                cal: (!cal.is_empty()).then(|| cal.iter().sum()),
+                weight: weights
+                    .iter()
+                    .rev()
+                    .find(|w| in_day(&w.timestamp))
+                    .map(|w| w.value),
+                workout: activities.iter().any(|a| in_day(&a.timestamp)),
+                day: day.clone(),
+            });
+            day = day.add(Duration::days(1));
+        }
+        weeks.push(week);
+    }
+
+    weeks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use model::Meal;
+
+    fn ts(date: &str) -> Timestamp {
+        Timestamp::parse_date(date, "%d.%m.%Y", chrono::Utc).unwrap()
+    }
+
+    fn jr(date: &str, cal: f64) -> JournalReport {
+        JournalReport {
+            timestamp: ts(date),
+            meal: Meal::Breakfast,
+            food_key: "f".into(),
+            food_name: "F".into(),
+            food_brand: "".into(),
+            food_weight: 100.0,
+            cal,
+            prot: 0.0,
+            fat: 0.0,
+            carb: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_month_calendar() {
+        let rep = vec![
+            jr("01.10.2026", 1500.0),
+            jr("01.10.2026", 700.0),
+            jr("15.10.2026", 1800.0),
+        ];
+        let weights = vec![
+            Weight {
+                timestamp: ts("02.10.2026"),
+                value: 80.0,
+            },
+            Weight {
+                timestamp: ts("02.10.2026").add(Duration::hours(10)),
+                value: 79.5,
+            },
+        ];
+        let activities = vec![SportActivityReport {
+            sport_name: "run".into(),
+            timestamp: ts("31.10.2026"),
+            sets: vec![1],
+        }];
+
+        let weeks = month_calendar(&ts("18.10.2026"), &rep, &weights, &activities);
+
+        // 01.10.2026 is Thursday, 31.10.2026 is Saturday
+        assert_eq!(5, weeks.len());
+        assert!(weeks.iter().all(|w| w.len() == DAYS_IN_WEEK));
+        assert_eq!(ts("28.09.2026"), weeks[0][0].day);
+        assert!(!weeks[0][2].in_month);
+        assert!(weeks[0][3].in_month);
+        assert!(!weeks[4][6].in_month);
+
+        let first = &weeks[0][3];
+        assert_eq!(Some(2200.0), first.cal);
+        assert_eq!(Some(-200.0), first.diff(2000.0));
+        assert_eq!(Some(79.5), weeks[0][4].weight);
+        assert_eq!(None, weeks[0][5].cal);
+        assert_eq!(Some(200.0), weeks[2][3].diff(2000.0));
+        assert_eq!(Some(0.4), weeks[2][3].deviation(2000.0));
+        assert_eq!(Some(1.0), weeks[2][3].deviation(1000.0));
+        assert_eq!(None, weeks[0][5].deviation(2000.0));
+        assert!(weeks[4][5].workout);
+        assert!(!weeks[4][4].workout);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::digest::Digest;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Indicator {
+    LoggedDays,
+    AvgCal,
+    AvgProt,
+    AvgFat,
+    AvgCarb,
+    DaysUnder,
+    DaysOver,
+    WeightChange,
+    WeighIns,
+    TrainingDays,
+}
+
+// Which direction of change is an improvement
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Better {
+    Higher,
+    Lower,
+    Neither,
+}
+
+impl Indicator {
+    pub fn better(&self) -> Better {
+        match self {
+            Self::LoggedDays | Self::DaysUnder | Self::WeighIns | Self::TrainingDays => {
+                Better::Higher
+            }
+            Self::DaysOver => Better::Lower,
+            Self::AvgCal | Self::AvgProt | Self::AvgFat | Self::AvgCarb | Self::WeightChange => {
+                Better::Neither
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delta {
+    pub indicator: Indicator,
+    pub first: Option<f64>,
+    pub second: Option<f64>,
+}
+
+impl Delta {
+    fn new(indicator: Indicator, first: Option<f64>, second: Option<f64>) -> Self {
+        Self {
+            indicator,
+            first,
+            second,
+        }
+    }
+
+    // Second period compared to the first one
+    pub fn diff(&self) -> Option<f64> {
+        Some(self.second? - self.first?)
+    }
+
+    pub fn percent(&self) -> Option<f64> {
+        let first = self.first?;
+        if first == 0.0 {
+            return None;
+        }
+        Some(self.diff()? / first.abs() * 100.0)
+    }
+
+    // None if there is no change or direction doesn't matter
+    pub fn is_improvement(&self) -> Option<bool> {
+        let diff = self.diff().filter(|v| *v != 0.0)?;
+        match self.indicator.better() {
+            Better::Higher => Some(diff > 0.0),
+            Better::Lower => Some(diff < 0.0),
+            Better::Neither => None,
+        }
+    }
+}
+
+// Deltas of the same aggregates for two periods, limit related indicators
+// are included only if calories limit is set.
+pub fn compare(first: &Digest, second: &Digest, with_limit: bool) -> Vec<Delta> {
+    let count = |v: usize| Some(v as f64);
+    // Averages are undefined without logged days
+    let avg = |dg: &Digest, v: f64| (dg.logged_days > 0).then_some(v);
+
+    let mut res = vec![
+        Delta::new(
+            Indicator::LoggedDays,
+            count(first.logged_days),
+            count(second.logged_days),
+        ),
+        Delta::new(
+            Indicator::AvgCal,
+            avg(first, first.avg_cal),
+            avg(second, second.avg_cal),
+        ),
+        Delta::new(
+            Indicator::AvgProt,
+            avg(first, first.avg_prot),
+            avg(second, second.avg_prot),
+        ),
+        Delta::new(
+            Indicator::AvgFat,
+            avg(first, first.avg_fat),
+            avg(second, second.avg_fat),
+        ),
+        Delta::new(
+            Indicator::AvgCarb,
+            avg(first, first.avg_carb),
+            avg(second, second.avg_carb),
+        ),
+    ];
+    if with_limit {
+        res.push(Delta::new(
+            Indicator::DaysUnder,
+            count(first.days_under),
+            count(second.days_under),
+        ));
+        res.push(Delta::new(
+            Indicator::DaysOver,
+            count(first.days_over),
+            count(second.days_over),
+        ));
+    }
+    res.push(Delta::new(
+        Indicator::WeightChange,
+        first.weight_change(),
+        second.weight_change(),
+    ));
+    res.push(Delta::new(
+        Indicator::WeighIns,
+        count(first.weigh_ins),
+        count(second.weigh_ins),
+    ));
+    res.push(Delta::new(
+        Indicator::TrainingDays,
+        count(first.training_days),
+        count(second.training_days),
+    ));
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let first = Digest {
+            logged_days: 10,
+            avg_cal: 2000.0,
+            days_over: 4,
+            days_under: 6,
+            weight_first: Some(80.0),
+            weight_last: Some(79.0),
+            training_days: 3,
+            ..Default::default()
+        };
+        let second = Digest {
+            logged_days: 12,
+            avg_cal: 1800.0,
+            days_over: 2,
+            days_under: 10,
+            training_days: 3,
+            ..Default::default()
+        };
+
+        let res = compare(&first, &second, true);
+        assert_eq!(10, res.len());
+
+        let get = |i: Indicator| res.iter().find(|d| d.indicator == i).unwrap();
+
+        let cal = get(Indicator::AvgCal);
+        assert_eq!(Some(-200.0), cal.diff());
+        assert_eq!(Some(-10.0), cal.percent());
+        assert_eq!(None, cal.is_improvement());
+
+        assert_eq!(Some(true), get(Indicator::DaysOver).is_improvement());
+        assert_eq!(Some(true), get(Indicator::LoggedDays).is_improvement());
+        assert_eq!(None, get(Indicator::TrainingDays).is_improvement());
+
+        let weight = get(Indicator::WeightChange);
+        assert_eq!(Some(-1.0), weight.first);
+        assert_eq!(None, weight.second);
+        assert_eq!(None, weight.diff());
+
+        assert_eq!(8, compare(&first, &second, false).len());
+    }
+
+    #[test]
+    fn test_compare_empty() {
+        let res = compare(&Digest::default(), &Digest::default(), false);
+        let cal = res
+            .iter()
+            .find(|d| d.indicator == Indicator::AvgCal)
+            .unwrap();
+        assert_eq!(None, cal.first);
+        assert_eq!(None, cal.percent());
+
+        let days = &res[0];
+        assert_eq!(Some(0.0), days.diff());
+        assert_eq!(None, days.percent());
+        assert_eq!(None, days.is_improvement());
+    }
+}
@@ -0,0 +1,146 @@
+use model::{JournalReport, Meal};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Nutrients {
+    pub cal: f64,
+    pub prot: f64,
+    pub fat: f64,
+    pub carb: f64,
+}
+
+impl Nutrients {
+    fn add(&mut self, jr: &JournalReport) {
+        self.cal += jr.cal;
+        self.prot += jr.prot;
+        self.fat += jr.fat;
+        self.carb += jr.carb;
+    }
+
+    // Share of protein, fat and carbohydrates by weight, percent
+    pub fn pfc_percent(&self) -> (f64, f64, f64) {
+        let total = self.prot + self.fat + self.carb;
+        if total <= 0.0 {
+            return (0.0, 0.0, 0.0);
+        }
+        (
+            self.prot / total * 100.0,
+            self.fat / total * 100.0,
+            self.carb / total * 100.0,
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MealTotal {
+    pub meal: Meal,
+    pub total: Nutrients,
+}
+
+// Day journal totals, shared by all day report renderings
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DayReport {
+    // In journal order
+    pub meals: Vec<MealTotal>,
+    pub total: Nutrients,
+    pub cal_limit: Option<f64>,
+}
+
+impl DayReport {
+    // Journal report should be for one day and ordered by meal
+    pub fn new(rep: &[JournalReport], cal_limit: Option<f64>) -> Self {
+        let mut res = Self {
+            cal_limit,
+            ..Default::default()
+        };
+
+        for jr in rep {
+            match res.meals.last_mut() {
+                Some(mt) if mt.meal == jr.meal => mt.total.add(jr),
+                _ => {
+                    let mut total = Nutrients::default();
+                    total.add(jr);
+                    res.meals.push(MealTotal {
+                        meal: jr.meal,
+                        total,
+                    });
+                }
+            }
+            res.total.add(jr);
+        }
+
+        res
+    }
+
+    // Calories left to the limit, negative if the limit is exceeded
+    pub fn remaining_cal(&self) -> Option<f64> {
+        self.cal_limit.map(|v| v - self.total.cal)
+    }
+
+    // Share of the limit consumed, percent
+    pub fn limit_percent(&self) -> Option<f64> {
+        self.cal_limit
+            .filter(|v| *v > 0.0)
+            .map(|v| self.total.cal / v * 100.0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.meals.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use types::timestamp::Timestamp;
+
+    fn jr(meal: Meal, cal: f64) -> JournalReport {
+        JournalReport {
+            timestamp: Timestamp::from_unix_millis(0).unwrap(),
+            meal,
+            food_key: "f".into(),
+            food_name: "F".into(),
+            food_brand: "".into(),
+            food_weight: 100.0,
+            cal,
+            prot: cal / 20.0,
+            fat: cal / 40.0,
+            carb: cal / 10.0,
+        }
+    }
+
+    #[test]
+    fn test_day_report() {
+        let dr = DayReport::new(
+            &[
+                jr(Meal::Breakfast, 400.0),
+                jr(Meal::Breakfast, 200.0),
+                jr(Meal::Dinner, 800.0),
+            ],
+            Some(2000.0),
+        );
+
+        assert_eq!(2, dr.meals.len());
+        assert_eq!(Meal::Breakfast, dr.meals[0].meal);
+        assert_eq!(600.0, dr.meals[0].total.cal);
+        assert_eq!(30.0, dr.meals[0].total.prot);
+        assert_eq!(Meal::Dinner, dr.meals[1].meal);
+        assert_eq!(1400.0, dr.total.cal);
+        assert_eq!(140.0, dr.total.carb);
+        assert_eq!(Some(600.0), dr.remaining_cal());
+        assert_eq!(Some(70.0), dr.limit_percent());
+
+        let (p, f, c) = dr.total.pfc_percent();
+        assert!((p - 28.5714).abs() < 1e-3);
+        assert!((f - 14.2857).abs() < 1e-3);
+        assert!((c - 57.1428).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_day_report_empty() {
+        let dr = DayReport::new(&[], None);
+        assert!(dr.is_empty());
+        assert_eq!(None, dr.remaining_cal());
+        assert_eq!(None, dr.limit_percent());
+        assert_eq!((0.0, 0.0, 0.0), dr.total.pfc_percent());
+    }
+}
@@ -0,0 +1,262 @@
+use model::{JournalReport, SportActivityReport, Weight};
+use std::collections::{HashMap, HashSet};
+
+use crate::tdee::daily_calories;
+
+// Default number of foods in top list
+pub const TOP_FOODS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoodTotal {
+    pub name: String,
+    pub brand: String,
+    pub weight: f64,
+    pub cal: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SportTotal {
+    pub name: String,
+    pub sessions: usize,
+    pub sets: usize,
+    pub reps: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Digest {
+    pub logged_days: usize,
+    // Averages per logged day
+    pub avg_cal: f64,
+    pub avg_prot: f64,
+    pub avg_fat: f64,
+    pub avg_carb: f64,
+    // Logged days compared to calories limit
+    pub days_over: usize,
+    pub days_under: usize,
+    pub weight_first: Option<f64>,
+    pub weight_last: Option<f64>,
+    pub weigh_ins: usize,
+    pub training_days: usize,
+    pub sports: Vec<SportTotal>,
+    pub top_foods: Vec<FoodTotal>,
+}
+
+impl Digest {
+    pub fn new(
+        rep: &[JournalReport],
+        weights: &[Weight],
+        activities: &[SportActivityReport],
+        cal_limit: Option<f64>,
+        top_foods: usize,
+    ) -> Self {
+        let mut res = Self::default();
+
+        // Nutrition
+        let daily_cal = daily_calories(rep);
+        res.logged_days = daily_cal.len();
+        if res.logged_days > 0 {
+            let days = res.logged_days as f64;
+            res.avg_cal = rep.iter().map(|jr| jr.cal).sum::<f64>() / days;
+            res.avg_prot = rep.iter().map(|jr| jr.prot).sum::<f64>() / days;
+            res.avg_fat = rep.iter().map(|jr| jr.fat).sum::<f64>() / days;
+            res.avg_carb = rep.iter().map(|jr| jr.carb).sum::<f64>() / days;
+        }
+
+        if let Some(cal_limit) = cal_limit {
+            res.days_over = daily_cal.iter().filter(|(_, c)| *c > cal_limit).count();
+            res.days_under = res.logged_days - res.days_over;
+        }
+
+        // Weight
+        res.weight_first = weights.first().map(|w| w.value);
+        res.weight_last = weights.last().map(|w| w.value);
+        res.weigh_ins = weights.len();
+
+        // Training
+        res.training_days = activities
+            .iter()
+            .map(|a| a.timestamp.unix_millis())
+            .collect::<HashSet<_>>()
+            .len();
+        res.sports = sport_totals(activities);
+
+        // Foods
+        res.top_foods = food_totals(rep);
+        res.top_foods.truncate(top_foods);
+
+        res
+    }
+
+    pub fn weight_change(&self) -> Option<f64> {
+        Some(self.weight_last? - self.weight_first?)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.logged_days == 0 && self.weigh_ins == 0 && self.training_days == 0
+    }
+}
+
+// Totals per sport, ordered by sport name
+fn sport_totals(activities: &[SportActivityReport]) -> Vec<SportTotal> {
+    let mut map: HashMap<&str, (HashSet<i64>, usize, i64)> = HashMap::new();
+    for a in activities {
+        let e = map.entry(&a.sport_name).or_default();
+        e.0.insert(a.timestamp.unix_millis());
+        e.1 += a.sets.len();
+        e.2 += a.sets.iter().sum::<i64>();
+    }
+
+    let mut res: Vec<SportTotal> = map
+        .into_iter()
+        .map(|(name, (days, sets, reps))| SportTotal {
+            name: name.into(),
+            sessions: days.len(),
+            sets,
+            reps,
+        })
+        .collect();
+    res.sort_by(|a, b| a.name.cmp(&b.name));
+
+    res
+}
+
+// Totals per food, ordered by calories desc
+fn food_totals(rep: &[JournalReport]) -> Vec<FoodTotal> {
+    let mut map: HashMap<&str, FoodTotal> = HashMap::new();
+    for jr in rep {
+        let e = map.entry(&jr.food_key).or_insert_with(|| FoodTotal {
+            name: jr.food_name.clone(),
+            brand: jr.food_brand.clone(),
+            weight: 0.0,
+            cal: 0.0,
+        });
+        e.weight += jr.food_weight;
+        e.cal += jr.cal;
+    }
+
+    let mut res: Vec<FoodTotal> = map.into_values().collect();
+    res.sort_by(|a, b| b.cal.total_cmp(&a.cal).then_with(|| a.name.cmp(&b.name)));
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use model::Meal;
+    use types::timestamp::Timestamp;
+
+    const MILLIS_IN_DAY: i64 = 86_400_000;
+
+    fn ts(day: i64) -> Timestamp {
+        Timestamp::from_unix_millis(day * MILLIS_IN_DAY).unwrap()
+    }
+
+    fn jr(day: i64, key: &str, cal: f64) -> JournalReport {
+        JournalReport {
+            timestamp: ts(day),
+            meal: Meal::Breakfast,
+            food_key: key.into(),
+            food_name: key.to_uppercase(),
+            food_brand: "".into(),
+            food_weight: 100.0,
+            cal,
+            prot: cal / 10.0,
+            fat: cal / 20.0,
+            carb: cal / 5.0,
+        }
+    }
+
+    fn sa(day: i64, name: &str, sets: Vec<i64>) -> SportActivityReport {
+        SportActivityReport {
+            sport_name: name.into(),
+            timestamp: ts(day),
+            sets,
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let d = Digest::new(&[], &[], &[], Some(2000.0), TOP_FOODS);
+        assert!(d.is_empty());
+        assert_eq!(None, d.weight_change());
+        assert_eq!(0.0, d.avg_cal);
+    }
+
+    #[test]
+    fn test_digest() {
+        let rep = vec![
+            jr(1, "a", 1500.0),
+            jr(1, "b", 700.0),
+            jr(2, "a", 1000.0),
+            jr(3, "c", 1800.0),
+        ];
+        let weights = vec![
+            Weight {
+                timestamp: ts(1),
+                value: 80.0,
+            },
+            Weight {
+                timestamp: ts(3),
+                value: 79.4,
+            },
+        ];
+        let acts = vec![
+            sa(1, "Подтягивания", vec![10, 8]),
+            sa(1, "Отжимания", vec![20]),
+            sa(3, "Подтягивания", vec![12]),
+        ];
+
+        let d = Digest::new(&rep, &weights, &acts, Some(2000.0), 2);
+        assert!(!d.is_empty());
+        assert_eq!(3, d.logged_days);
+        assert_eq!(1666.6666666666667, d.avg_cal);
+        assert_eq!(166.66666666666666, d.avg_prot);
+        assert_eq!(1, d.days_over);
+        assert_eq!(2, d.days_under);
+        assert!((d.weight_change().unwrap() + 0.6).abs() < 1e-9);
+        assert_eq!(2, d.weigh_ins);
+        assert_eq!(2, d.training_days);
+        assert_eq!(
+            vec![
+                SportTotal {
+                    name: "Отжимания".into(),
+                    sessions: 1,
+                    sets: 1,
+                    reps: 20,
+                },
+                SportTotal {
+                    name: "Подтягивания".into(),
+                    sessions: 2,
+                    sets: 3,
+                    reps: 30,
+                },
+            ],
+            d.sports
+        );
+        assert_eq!(
+            vec![
+                FoodTotal {
+                    name: "A".into(),
+                    brand: "".into(),
+                    weight: 200.0,
+                    cal: 2500.0,
+                },
+                FoodTotal {
+                    name: "C".into(),
+                    brand: "".into(),
+                    weight: 100.0,
+                    cal: 1800.0,
+                },
+            ],
+            d.top_foods
+        );
+    }
+
+    #[test]
+    fn test_without_limit() {
+        let d = Digest::new(&[jr(1, "a", 3000.0)], &[], &[], None, TOP_FOODS);
+        assert_eq!(0, d.days_over);
+        assert_eq!(0, d.days_under);
+    }
+}
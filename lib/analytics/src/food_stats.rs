@@ -0,0 +1,150 @@
+use model::{FoodMealUsage, FoodUsage, Meal};
+
+// Number of meal kinds
+pub const MEALS: usize = 6;
+
+// Food usage split by meal, counts are indexed by meal
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoodMeals {
+    pub food_key: String,
+    pub food_name: String,
+    pub food_brand: String,
+    pub counts: [i64; MEALS],
+}
+
+impl FoodMeals {
+    pub fn count(&self, meal: Meal) -> i64 {
+        self.counts[u8::from(meal) as usize]
+    }
+
+    pub fn total(&self) -> i64 {
+        self.counts.iter().sum()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FoodStats {
+    pub by_cal: Vec<FoodUsage>,
+    pub by_count: Vec<FoodUsage>,
+    pub by_prot: Vec<FoodUsage>,
+    // Ordered by total count desc
+    pub meals: Vec<FoodMeals>,
+}
+
+impl FoodStats {
+    pub fn new(usage: &[FoodUsage], meal_usage: &[FoodMealUsage], top_foods: usize) -> Self {
+        Self {
+            by_cal: top_by(usage, top_foods, |fu| fu.cal),
+            by_count: top_by(usage, top_foods, |fu| fu.count as f64),
+            by_prot: top_by(usage, top_foods, |fu| fu.prot),
+            meals: food_meals(meal_usage),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_cal.is_empty()
+    }
+}
+
+// Top foods by value desc, ties are ordered by name
+fn top_by<F>(usage: &[FoodUsage], top_foods: usize, value: F) -> Vec<FoodUsage>
+where
+    F: Fn(&FoodUsage) -> f64,
+{
+    let mut res = usage.to_vec();
+    res.sort_by(|a, b| {
+        value(b)
+            .total_cmp(&value(a))
+            .then_with(|| a.food_name.cmp(&b.food_name))
+    });
+    res.truncate(top_foods);
+
+    res
+}
+
+fn food_meals(meal_usage: &[FoodMealUsage]) -> Vec<FoodMeals> {
+    let mut res: Vec<FoodMeals> = Vec::new();
+    for fmu in meal_usage {
+        let idx = match res.iter().position(|fm| fm.food_key == fmu.food_key) {
+            Some(idx) => idx,
+            None => {
+                res.push(FoodMeals {
+                    food_key: fmu.food_key.clone(),
+                    food_name: fmu.food_name.clone(),
+                    food_brand: fmu.food_brand.clone(),
+                    counts: [0; MEALS],
+                });
+                res.len() - 1
+            }
+        };
+        res[idx].counts[u8::from(fmu.meal) as usize] += fmu.count;
+    }
+    res.sort_by(|a, b| {
+        b.total()
+            .cmp(&a.total())
+            .then_with(|| a.food_name.cmp(&b.food_name))
+    });
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fu(key: &str, count: i64, cal: f64, prot: f64) -> FoodUsage {
+        FoodUsage {
+            food_key: key.into(),
+            food_name: key.into(),
+            food_brand: "".into(),
+            count,
+            food_weight: 100.0,
+            cal,
+            prot,
+        }
+    }
+
+    fn fmu(key: &str, meal: Meal, count: i64) -> FoodMealUsage {
+        FoodMealUsage {
+            food_key: key.into(),
+            food_name: key.into(),
+            food_brand: "".into(),
+            meal,
+            count,
+        }
+    }
+
+    #[test]
+    fn test_food_stats() {
+        let usage = vec![
+            fu("a", 1, 500.0, 5.0),
+            fu("b", 5, 300.0, 30.0),
+            fu("c", 3, 100.0, 50.0),
+        ];
+        let meal_usage = vec![
+            fmu("a", Meal::Supper, 1),
+            fmu("b", Meal::Breakfast, 4),
+            fmu("b", Meal::Dinner, 1),
+            fmu("c", Meal::Breakfast, 3),
+        ];
+
+        let keys = |v: &[FoodUsage]| v.iter().map(|f| f.food_key.clone()).collect::<Vec<_>>();
+
+        let fs = FoodStats::new(&usage, &meal_usage, 2);
+        assert!(!fs.is_empty());
+        assert_eq!(vec!["a", "b"], keys(&fs.by_cal));
+        assert_eq!(vec!["b", "c"], keys(&fs.by_count));
+        assert_eq!(vec!["c", "b"], keys(&fs.by_prot));
+
+        assert_eq!(3, fs.meals.len());
+        assert_eq!("b", fs.meals[0].food_key);
+        assert_eq!(5, fs.meals[0].total());
+        assert_eq!(4, fs.meals[0].count(Meal::Breakfast));
+        assert_eq!(1, fs.meals[0].count(Meal::Dinner));
+        assert_eq!(0, fs.meals[0].count(Meal::Supper));
+        assert_eq!("c", fs.meals[1].food_key);
+        assert_eq!("a", fs.meals[2].food_key);
+
+        assert!(FoodStats::new(&[], &[], 10).is_empty());
+    }
+}
@@ -0,0 +1,10 @@
+pub mod bmr;
+pub mod calendar;
+pub mod compare;
+pub mod day_report;
+pub mod digest;
+pub mod food_stats;
+pub mod tdee;
+pub mod weight;
+
+const MILLIS_IN_DAY: f64 = 86_400_000.0;
@@ -0,0 +1,158 @@
+use model::{JournalReport, Weight};
+use types::timestamp::Timestamp;
+
+use crate::weight::weekly_rate;
+
+// Energy equivalent of 1 kg of body weight change
+pub const KCAL_PER_KG: f64 = 7700.0;
+// Default rolling window for estimation
+pub const WINDOW_DAYS: i64 = 28;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TdeeEstimate {
+    pub tdee: f64,
+    pub avg_intake: f64,
+    // kg per week, negative if losing weight
+    pub weekly_rate: f64,
+    pub logged_days: usize,
+    pub weigh_ins: usize,
+    pub confidence: Confidence,
+}
+
+impl TdeeEstimate {
+    // Daily calories to reach desired weekly weight change (kg per week)
+    pub fn suggest_cal_limit(&self, weekly_change: f64) -> f64 {
+        self.tdee + weekly_change * KCAL_PER_KG / 7.0
+    }
+}
+
+// Total calories per day, ordered by day
+pub fn daily_calories(rep: &[JournalReport]) -> Vec<(Timestamp, f64)> {
+    let mut res: Vec<(Timestamp, f64)> = Vec::new();
+
+    for jr in rep {
+        match res.last_mut() {
+            Some((ts, cal)) if *ts == jr.timestamp => *cal += jr.cal,
+            _ => res.push((jr.timestamp.clone(), jr.cal)),
+        }
+    }
+
+    res
+}
+
+// Estimate actual energy expenditure from intake and weight change over window:
+// TDEE = average intake - daily weight change * energy per kg.
+pub fn estimate(
+    daily_cal: &[(Timestamp, f64)],
+    weights: &[Weight],
+    window_days: i64,
+) -> Option<TdeeEstimate> {
+    if daily_cal.is_empty() {
+        return None;
+    }
+
+    let values: Vec<f64> = weights.iter().map(|w| w.value).collect();
+    let weekly_rate = weekly_rate(weights, &values, window_days)?;
+
+    let avg_intake = daily_cal.iter().map(|(_, cal)| cal).sum::<f64>() / daily_cal.len() as f64;
+    let tdee = avg_intake - weekly_rate / 7.0 * KCAL_PER_KG;
+
+    Some(TdeeEstimate {
+        tdee,
+        avg_intake,
+        weekly_rate,
+        logged_days: daily_cal.len(),
+        weigh_ins: weights.len(),
+        confidence: confidence(daily_cal.len(), weights.len(), window_days),
+    })
+}
+
+fn confidence(logged_days: usize, weigh_ins: usize, window_days: i64) -> Confidence {
+    let logged_ratio = logged_days as f64 / window_days as f64;
+    let weigh_ratio = weigh_ins as f64 / window_days as f64;
+
+    if logged_ratio >= 0.85 && weigh_ratio >= 0.5 {
+        Confidence::High
+    } else if logged_ratio >= 0.6 && weigh_ratio >= 0.25 {
+        Confidence::Medium
+    } else {
+        Confidence::Low
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use model::Meal;
+
+    const MILLIS_IN_DAY: i64 = 86_400_000;
+
+    fn ts(day: i64) -> Timestamp {
+        Timestamp::from_unix_millis(day * MILLIS_IN_DAY).unwrap()
+    }
+
+    fn jr(day: i64, cal: f64) -> JournalReport {
+        JournalReport {
+            timestamp: ts(day),
+            meal: Meal::Breakfast,
+            food_key: "key".into(),
+            food_name: "name".into(),
+            food_brand: "".into(),
+            food_weight: 100.0,
+            cal,
+            prot: 0.0,
+            fat: 0.0,
+            carb: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_daily_calories() {
+        assert!(daily_calories(&[]).is_empty());
+        assert_eq!(
+            vec![(ts(1), 300.0), (ts(2), 50.0)],
+            daily_calories(&[jr(1, 100.0), jr(1, 200.0), jr(2, 50.0)])
+        );
+    }
+
+    #[test]
+    fn test_estimate() {
+        // Not enough data
+        assert_eq!(None, estimate(&[], &[], WINDOW_DAYS));
+        assert_eq!(None, estimate(&[(ts(0), 2000.0)], &[], WINDOW_DAYS));
+
+        // Losing 0.5 kg per week with 2000 kcal per day
+        let daily_cal: Vec<(Timestamp, f64)> = (0..28).map(|d| (ts(d), 2000.0)).collect();
+        let weights: Vec<Weight> = (0..28)
+            .map(|d| Weight {
+                timestamp: ts(d),
+                value: 80.0 - 0.5 / 7.0 * d as f64,
+            })
+            .collect();
+
+        let res = estimate(&daily_cal, &weights, WINDOW_DAYS).unwrap();
+        assert!((res.avg_intake - 2000.0).abs() < 1e-6);
+        assert!((res.weekly_rate + 0.5).abs() < 1e-6);
+        assert!((res.tdee - 2550.0).abs() < 1e-6);
+        assert_eq!(Confidence::High, res.confidence);
+
+        // Maintain weight -> limit equals TDEE, lose 0.5 kg -> 550 kcal deficit
+        assert!((res.suggest_cal_limit(0.0) - 2550.0).abs() < 1e-6);
+        assert!((res.suggest_cal_limit(-0.5) - 2000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_confidence() {
+        assert_eq!(Confidence::High, confidence(28, 14, 28));
+        assert_eq!(Confidence::Medium, confidence(20, 7, 28));
+        assert_eq!(Confidence::Low, confidence(20, 2, 28));
+        assert_eq!(Confidence::Low, confidence(5, 28, 28));
+    }
+}
@@ -0,0 +1,249 @@
+use chrono::Duration;
+use model::Weight;
+use types::timestamp::Timestamp;
+
+use crate::MILLIS_IN_DAY;
+
+// Smoothing factor of exponential trend (Hacker's Diet default)
+pub const TREND_ALPHA: f64 = 0.1;
+// Trailing window to calculate weekly rate of change
+pub const RATE_WINDOW_DAYS: i64 = 28;
+// Goal further than this is not projected, tiny rate gives meaningless date
+pub const GOAL_HORIZON_DAYS: f64 = 3650.0;
+
+#[derive(Debug, PartialEq)]
+pub struct WeightTrend {
+    pub trend: Vec<f64>,
+    pub ma7: Vec<f64>,
+    pub ma30: Vec<f64>,
+    // kg per week, negative if losing weight
+    pub weekly_rate: Option<f64>,
+    last_timestamp: Option<Timestamp>,
+}
+
+impl WeightTrend {
+    pub fn new(weights: &[Weight]) -> Self {
+        let trend = smoothed_trend(weights, TREND_ALPHA);
+        let weekly_rate = weekly_rate(weights, &trend, RATE_WINDOW_DAYS);
+
+        Self {
+            ma7: moving_average(weights, 7),
+            ma30: moving_average(weights, 30),
+            weekly_rate,
+            trend,
+            last_timestamp: weights.last().map(|w| w.timestamp.clone()),
+        }
+    }
+
+    pub fn last_trend(&self) -> Option<f64> {
+        self.trend.last().copied()
+    }
+
+    // Projected date when trend reaches target weight with current weekly rate.
+    // None if there is not enough data, trend moves away from target
+    // or target is beyond projection horizon.
+    pub fn goal_date(&self, target: f64) -> Option<Timestamp> {
+        goal_date(
+            self.last_timestamp.as_ref()?,
+            self.last_trend()?,
+            self.weekly_rate?,
+            target,
+        )
+    }
+}
+
+// Exponentially smoothed trend, gaps between weigh-ins are treated
+// as if smoothing was applied for each missed day.
+pub fn smoothed_trend(weights: &[Weight], alpha: f64) -> Vec<f64> {
+    let mut res: Vec<f64> = Vec::with_capacity(weights.len());
+
+    for (i, w) in weights.iter().enumerate() {
+        if i == 0 {
+            res.push(w.value);
+            continue;
+        }
+
+        let gap = days_between(&weights[i - 1].timestamp, &w.timestamp).max(1.0);
+        let k = 1.0 - (1.0 - alpha).powf(gap);
+        let prev = res[i - 1];
+        res.push(prev + k * (w.value - prev));
+    }
+
+    res
+}
+
+// Average of weigh-ins within trailing window of days (including current day).
+pub fn moving_average(weights: &[Weight], window_days: i64) -> Vec<f64> {
+    let mut res = Vec::with_capacity(weights.len());
+    let mut start = 0;
+    let mut sum = 0.0;
+
+    for (i, w) in weights.iter().enumerate() {
+        sum += w.value;
+        while days_between(&weights[start].timestamp, &w.timestamp) > window_days as f64 - 0.5 {
+            sum -= weights[start].value;
+            start += 1;
+        }
+
+        res.push(sum / (i - start + 1) as f64);
+    }
+
+    res
+}
+
+// Weekly rate of change as least squares slope of trend over trailing window.
+pub fn weekly_rate(weights: &[Weight], trend: &[f64], window_days: i64) -> Option<f64> {
+    let last = weights.last()?;
+
+    let points: Vec<(f64, f64)> = weights
+        .iter()
+        .zip(trend)
+        .map(|(w, t)| (-days_between(&w.timestamp, &last.timestamp), *t))
+        .filter(|(d, _)| -d < window_days as f64 - 0.5)
+        .collect();
+
+    if points.len() < 2 {
+        return None;
+    }
+
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+
+    let mut num = 0.0;
+    let mut den = 0.0;
+    for (x, y) in &points {
+        num += (x - mean_x) * (y - mean_y);
+        den += (x - mean_x) * (x - mean_x);
+    }
+
+    if den == 0.0 {
+        return None;
+    }
+
+    Some(num / den * 7.0)
+}
+
+pub fn goal_date(
+    from: &Timestamp,
+    current: f64,
+    weekly_rate: f64,
+    target: f64,
+) -> Option<Timestamp> {
+    let diff = target - current;
+    if diff.abs() < f64::EPSILON {
+        return Some(from.clone());
+    }
+
+    if weekly_rate == 0.0 || diff.signum() != weekly_rate.signum() {
+        return None;
+    }
+
+    let days = diff / weekly_rate * 7.0;
+    if !days.is_finite() || days > GOAL_HORIZON_DAYS {
+        return None;
+    }
+
+    from.checked_add(Duration::try_milliseconds((days * MILLIS_IN_DAY) as i64)?)
+}
+
+fn days_between(from: &Timestamp, to: &Timestamp) -> f64 {
+    (to.unix_millis() - from.unix_millis()) as f64 / MILLIS_IN_DAY
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn weights(data: &[(i64, f64)]) -> Vec<Weight> {
+        data.iter()
+            .map(|(day, value)| Weight {
+                timestamp: Timestamp::from_unix_millis(day * MILLIS_IN_DAY as i64).unwrap(),
+                value: *value,
+            })
+            .collect()
+    }
+
+    fn assert_approx(expected: &[f64], actual: &[f64]) {
+        assert_eq!(expected.len(), actual.len());
+        for (e, a) in expected.iter().zip(actual) {
+            assert!((e - a).abs() < 1e-6, "expected {e}, got {a}");
+        }
+    }
+
+    #[test]
+    fn test_smoothed_trend() {
+        assert!(smoothed_trend(&[], TREND_ALPHA).is_empty());
+
+        let w = weights(&[(0, 100.0), (1, 90.0), (2, 90.0)]);
+        assert_approx(&[100.0, 99.0, 98.1], &smoothed_trend(&w, TREND_ALPHA));
+
+        // Gap of two days applies smoothing twice
+        let w = weights(&[(0, 100.0), (2, 90.0)]);
+        assert_approx(&[100.0, 98.1], &smoothed_trend(&w, TREND_ALPHA));
+    }
+
+    #[test]
+    fn test_moving_average() {
+        let w = weights(&[(0, 1.0), (3, 2.0), (6, 3.0), (7, 4.0), (20, 5.0)]);
+
+        assert_approx(&[1.0, 1.5, 2.0, 3.0, 5.0], &moving_average(&w, 7));
+        assert_approx(&[1.0, 1.5, 2.0, 2.5, 3.0], &moving_average(&w, 30));
+    }
+
+    #[test]
+    fn test_weekly_rate() {
+        assert_eq!(None, weekly_rate(&[], &[], RATE_WINDOW_DAYS));
+
+        // Single point
+        let w = weights(&[(0, 100.0)]);
+        assert_eq!(None, weekly_rate(&w, &[100.0], RATE_WINDOW_DAYS));
+
+        // Linear loss of 0.1 kg per day
+        let w = weights(&[(0, 100.0), (1, 99.9), (2, 99.8), (3, 99.7)]);
+        let trend: Vec<f64> = w.iter().map(|w| w.value).collect();
+        assert!((weekly_rate(&w, &trend, RATE_WINDOW_DAYS).unwrap() + 0.7).abs() < 1e-6);
+
+        // Points outside window are ignored
+        let w = weights(&[(0, 200.0), (100, 100.0), (101, 100.1)]);
+        let trend: Vec<f64> = w.iter().map(|w| w.value).collect();
+        assert!((weekly_rate(&w, &trend, RATE_WINDOW_DAYS).unwrap() - 0.7).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_goal_date() {
+        let from = Timestamp::from_unix_millis(0).unwrap();
+
+        // Already reached
+        assert_eq!(Some(from.clone()), goal_date(&from, 80.0, -0.5, 80.0));
+        // Moving away from target
+        assert_eq!(None, goal_date(&from, 80.0, 0.5, 70.0));
+        assert_eq!(None, goal_date(&from, 80.0, 0.0, 70.0));
+        // 10 kg with 0.5 kg/week = 20 weeks
+        assert_eq!(
+            Some(from.add(Duration::weeks(20))),
+            goal_date(&from, 80.0, -0.5, 70.0)
+        );
+        // Beyond horizon
+        assert_eq!(None, goal_date(&from, 80.0, -1e-300, 70.0));
+        assert_eq!(None, goal_date(&from, 80.0, -f64::MIN_POSITIVE, 70.0));
+        assert_eq!(None, goal_date(&from, 80.0, -0.01, 70.0));
+    }
+
+    #[test]
+    fn test_weight_trend() {
+        let w = weights(&[(0, 100.0), (7, 99.0), (14, 98.0)]);
+        let t = WeightTrend::new(&w);
+
+        assert_eq!(3, t.trend.len());
+        assert_approx(&[100.0, 99.0, 98.0], &t.ma7);
+        assert_approx(&[100.0, 99.5, 99.0], &t.ma30);
+        assert!(t.weekly_rate.unwrap() < 0.0);
+        assert!(t.goal_date(90.0).is_some());
+        assert!(t.goal_date(110.0).is_none());
+
+        let t = WeightTrend::new(&[]);
+        assert_eq!(None, t.last_trend());
+        assert_eq!(None, t.goal_date(90.0));
+    }
+}
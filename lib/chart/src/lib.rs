@@ -1,6 +1,8 @@
 use anyhow::{anyhow, Context, Result};
 use minijinja::{context, Environment};
-use serde::Serialize;
+use serde_json::{json, Value};
+
+pub mod render;
 
 pub const CHART_COLOR_RED: &str = "rgb(255, 99, 132)";
 pub const CHART_COLOR_ORANGE: &str = "rgb(255, 159, 64)";
@@ -9,23 +11,176 @@ pub const CHART_COLOR_GREEN: &str = "rgb(75, 192, 192)";
 pub const CHART_COLOR_BLUE: &str = "rgb(54, 162, 235)";
 pub const CHART_COLOR_PURPLE: &str = "rgb(153, 102, 255)";
 pub const CHART_COLOR_GREY: &str = "rgb(201, 203, 207)";
+pub const FILL_ALPHA: f64 = 0.2;
+
+// Palette for charts with an arbitrary number of datasets or slices
+pub const CHART_COLORS: [&str; 7] = [
+    CHART_COLOR_BLUE,
+    CHART_COLOR_RED,
+    CHART_COLOR_GREEN,
+    CHART_COLOR_ORANGE,
+    CHART_COLOR_PURPLE,
+    CHART_COLOR_YELLOW,
+    CHART_COLOR_GREY,
+];
+
+pub const Y_AXIS: &str = "y";
+pub const BORDER_WIDTH: u32 = 2;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ChartType {
+    #[default]
+    Line,
+    Bar,
+    Pie,
+    Doughnut,
+}
+
+impl ChartType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Line => "line",
+            Self::Bar => "bar",
+            Self::Pie => "pie",
+            Self::Doughnut => "doughnut",
+        }
+    }
+
+    pub fn is_circular(&self) -> bool {
+        matches!(self, Self::Pie | Self::Doughnut)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AxisPosition {
+    #[default]
+    Left,
+    Right,
+}
+
+// Value axis, the first one is the primary and has the grid
+#[derive(Clone, Debug)]
+pub struct Axis {
+    pub id: String,
+    pub position: AxisPosition,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub unit: String,
+}
+
+impl Default for Axis {
+    fn default() -> Self {
+        Self {
+            id: Y_AXIS.into(),
+            position: AxisPosition::default(),
+            min: None,
+            max: None,
+            unit: String::default(),
+        }
+    }
+}
+
+// Area under the line
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Fill {
+    #[default]
+    None,
+    Origin,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PointStyle {
+    #[default]
+    Circle,
+    Rect,
+    Triangle,
+    Hidden,
+}
+
+impl PointStyle {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Circle | Self::Hidden => "circle",
+            Self::Rect => "rect",
+            Self::Triangle => "triangle",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LegendPosition {
+    #[default]
+    Top,
+    Bottom,
+    Hidden,
+}
+
+// Horizontal line, e.g. calorie limit or target weight
+#[derive(Clone, Debug, Default)]
+pub struct Annotation {
+    pub label: String,
+    pub value: f64,
+    pub color: String,
+    // Axis id, primary axis if None
+    pub axis: Option<String>,
+}
 
-#[derive(Serialize)]
+#[derive(Default)]
 pub struct ChartData {
     pub elem_id: String,
     pub x_labels: Vec<String>,
-    pub ctype: String,
+    pub ctype: ChartType,
     pub datasets: Vec<ChartDataset>,
+    // Primary axis with default options is used if empty
+    pub axes: Vec<Axis>,
+    pub stacked: bool,
+    pub annotations: Vec<Annotation>,
+    pub legend: LegendPosition,
 }
 
-#[derive(Serialize)]
+impl ChartData {
+    pub fn axes(&self) -> Vec<Axis> {
+        if self.axes.is_empty() {
+            vec![Axis::default()]
+        } else {
+            self.axes.clone()
+        }
+    }
+
+    fn axis_id<'a>(&'a self, id: &'a Option<String>) -> &'a str {
+        match id {
+            Some(id) => id,
+            None => self.axes.first().map(|a| a.id.as_str()).unwrap_or(Y_AXIS),
+        }
+    }
+}
+
+#[derive(Default)]
 pub struct ChartDataset {
     pub data: Vec<f64>,
     pub label: String,
     pub color: String,
+    // Per-value colors for pie/doughnut slices, `color` is used when empty
+    pub colors: Vec<String>,
+    // Axis id, primary axis if None
+    pub axis: Option<String>,
+    pub fill: Fill,
+    pub point_style: PointStyle,
+    // BORDER_WIDTH if None
+    pub border_width: Option<u32>,
+}
+
+impl ChartDataset {
+    pub fn value_color(&self, idx: usize) -> &str {
+        self.colors.get(idx).unwrap_or(&self.color)
+    }
+
+    pub fn border_width(&self) -> u32 {
+        self.border_width.unwrap_or(BORDER_WIDTH)
+    }
 }
 
-pub fn get_chart_snippet(data: ChartData) -> Result<String> {
+pub fn get_chart_snippet(data: &ChartData) -> Result<String> {
     let mut env = Environment::new();
 
     env.add_template(
@@ -33,42 +188,329 @@ pub fn get_chart_snippet(data: ChartData) -> Result<String> {
         r#"
 <script>
 	function plot() {
-		const ctx = document.getElementById('{{ data.elem_id }}');
-
-		new Chart(ctx, {
-			type: '{{ data.ctype }}',
-			data: {
-				labels: [
-                {% for x in data.x_labels %}
-                    '{{ x }}',
-                {% endfor %}
-				],
-				datasets: [
-                {% for ds in data.datasets %}
-					{
-						label: '{{ ds.label }}',
-						data: [
-                        {% for d in ds.data %}
-                            {{ d }},
-                        {% endfor %}
-						],
-						borderWidth: 2,
-						borderColor: '{{ ds.color }}',
-						backgroundColor: '{{ ds.color }}'
-					},
-				{% endfor %}
-				]
-			}
-		});		
+		const ctx = document.getElementById({{ elem_id }});
+
+		new Chart(ctx, {{ config }});
 	}
 	window.onload = plot;
-</script>        
+</script>
     "#,
     )
     .context("add template error")?;
     let tmpl = env.get_template("chart").context("get template error")?;
 
-    tmpl.render(context!(data => data))
-        .map_err(|e| anyhow!(e))
-        .context("render template error")
+    tmpl.render(context!(
+        elem_id => to_script_json(&json!(data.elem_id))?,
+        config => to_script_json(&chart_config(data))?,
+    ))
+    .map_err(|e| anyhow!(e))
+    .context("render template error")
+}
+
+// Chart.js configuration object
+fn chart_config(data: &ChartData) -> Value {
+    let circular = data.ctype.is_circular();
+    let mut datasets: Vec<Value> = data
+        .datasets
+        .iter()
+        .map(|ds| dataset_config(data, ds))
+        .collect();
+
+    // Annotations are drawn as dashed line datasets,
+    // so annotation plugin is not required
+    if !circular {
+        for (i, an) in data.annotations.iter().enumerate() {
+            datasets.push(json!({
+                "type": ChartType::Line.name(),
+                "label": an.label,
+                "data": vec![an.value; data.x_labels.len()],
+                "borderWidth": BORDER_WIDTH,
+                "borderColor": an.color,
+                "backgroundColor": an.color,
+                "borderDash": [6, 4],
+                "pointRadius": 0,
+                "fill": false,
+                "yAxisID": data.axis_id(&an.axis),
+                "stack": format!("annotation{i}"),
+            }));
+        }
+    }
+
+    let legend = match data.legend {
+        LegendPosition::Hidden => json!({ "display": false }),
+        LegendPosition::Top => json!({ "display": true, "position": "top" }),
+        LegendPosition::Bottom => json!({ "display": true, "position": "bottom" }),
+    };
+
+    let mut options = json!({ "plugins": { "legend": legend } });
+    if !circular {
+        options["scales"] = scales_config(data);
+    }
+
+    json!({
+        "type": data.ctype.name(),
+        "data": {
+            "labels": data.x_labels,
+            "datasets": datasets,
+        },
+        "options": options,
+    })
+}
+
+fn dataset_config(data: &ChartData, ds: &ChartDataset) -> Value {
+    if data.ctype.is_circular() {
+        let colors: Vec<&str> = (0..ds.data.len()).map(|i| ds.value_color(i)).collect();
+        return json!({
+            "label": ds.label,
+            "data": ds.data,
+            "borderWidth": ds.border_width(),
+            "borderColor": "white",
+            "backgroundColor": colors,
+        });
+    }
+
+    let background = match ds.fill {
+        Fill::Origin => with_alpha(&ds.color, FILL_ALPHA),
+        Fill::None => ds.color.clone(),
+    };
+
+    json!({
+        "label": ds.label,
+        "data": ds.data,
+        "borderWidth": ds.border_width(),
+        "borderColor": ds.color,
+        "backgroundColor": background,
+        "fill": match ds.fill {
+            Fill::None => json!(false),
+            Fill::Origin => json!("origin"),
+        },
+        "pointStyle": ds.point_style.name(),
+        "pointRadius": if ds.point_style == PointStyle::Hidden { 0 } else { 3 },
+        "yAxisID": data.axis_id(&ds.axis),
+    })
+}
+
+fn scales_config(data: &ChartData) -> Value {
+    let mut scales = json!({ "x": { "stacked": data.stacked } });
+    for (i, axis) in data.axes().iter().enumerate() {
+        let mut scale = json!({
+            "type": "linear",
+            "position": match axis.position {
+                AxisPosition::Left => "left",
+                AxisPosition::Right => "right",
+            },
+            "stacked": data.stacked,
+            "beginAtZero": data.ctype == ChartType::Bar,
+            "title": { "display": !axis.unit.is_empty(), "text": axis.unit },
+            // Only the primary axis draws the grid
+            "grid": { "drawOnChartArea": i == 0 },
+        });
+        if let Some(min) = axis.min {
+            scale["min"] = json!(min);
+        }
+        if let Some(max) = axis.max {
+            scale["max"] = json!(max);
+        }
+        scales[&axis.id] = scale;
+    }
+
+    scales
+}
+
+// "rgb(r, g, b)" to "rgba(r, g, b, a)", other formats are returned as is
+pub fn with_alpha(color: &str, alpha: f64) -> String {
+    match color.strip_prefix("rgb(").and_then(|c| c.strip_suffix(')')) {
+        Some(rgb) => format!("rgba({rgb}, {alpha})"),
+        None => color.into(),
+    }
+}
+
+// JSON safe to embed into <script> element: "<" is escaped,
+// so data can't close the script tag or open a comment.
+fn to_script_json(val: &Value) -> Result<String> {
+    Ok(serde_json::to_string(val)
+        .context("json encode error")?
+        .replace('<', "\\u003c"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chart_snippet_escape() {
+        let snip = get_chart_snippet(&ChartData {
+            elem_id: "chart".into(),
+            x_labels: vec!["</script><script>alert(1)</script>".into()],
+            ctype: ChartType::Line,
+            datasets: vec![ChartDataset {
+                data: vec![1.5],
+                label: "it's \"label\"".into(),
+                color: CHART_COLOR_RED.into(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(1, snip.matches("</script>").count());
+        assert!(snip.contains(r#"document.getElementById("chart")"#));
+        assert!(snip.contains(r#""labels":["\u003c/script>\u003cscript>alert(1)\u003c/script>"]"#));
+        assert!(snip.contains(r#""label":"it's \"label\"""#));
+        assert!(snip.contains(r#""data":[1.5]"#));
+    }
+
+    #[test]
+    fn test_chart_doughnut_colors() {
+        let snip = get_chart_snippet(&ChartData {
+            elem_id: "pfc".into(),
+            x_labels: vec!["P".into(), "F".into()],
+            ctype: ChartType::Doughnut,
+            datasets: vec![ChartDataset {
+                data: vec![30.0, 70.0],
+                label: "PFC".into(),
+                color: CHART_COLOR_GREY.into(),
+                colors: vec![CHART_COLOR_RED.into(), CHART_COLOR_YELLOW.into()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert!(snip.contains(r#""type":"doughnut""#));
+        assert!(snip.contains(&format!(
+            r#""backgroundColor":["{CHART_COLOR_RED}","{CHART_COLOR_YELLOW}"]"#
+        )));
+        assert!(snip.contains(r#""borderColor":"white""#));
+        assert!(!snip.contains(r#""scales""#));
+    }
+
+    fn two_axes_chart() -> ChartData {
+        ChartData {
+            elem_id: "chart".into(),
+            x_labels: vec!["01.01".into(), "02.01".into(), "03.01".into()],
+            ctype: ChartType::Line,
+            datasets: vec![
+                ChartDataset {
+                    data: vec![80.0, 79.5, 79.0],
+                    label: "Вес".into(),
+                    color: CHART_COLOR_BLUE.into(),
+                    fill: Fill::Origin,
+                    ..Default::default()
+                },
+                ChartDataset {
+                    data: vec![20.0, 19.8, 19.5],
+                    label: "Жир".into(),
+                    color: CHART_COLOR_RED.into(),
+                    axis: Some("y1".into()),
+                    point_style: PointStyle::Hidden,
+                    border_width: Some(1),
+                    ..Default::default()
+                },
+            ],
+            axes: vec![
+                Axis {
+                    unit: "кг".into(),
+                    min: Some(70.0),
+                    ..Default::default()
+                },
+                Axis {
+                    id: "y1".into(),
+                    position: AxisPosition::Right,
+                    max: Some(30.0),
+                    unit: "%".into(),
+                    ..Default::default()
+                },
+            ],
+            annotations: vec![Annotation {
+                label: "Цель".into(),
+                value: 75.0,
+                color: CHART_COLOR_GREEN.into(),
+                axis: None,
+            }],
+            legend: LegendPosition::Bottom,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_chart_config_axes() {
+        let cfg = chart_config(&two_axes_chart());
+
+        let y = &cfg["options"]["scales"]["y"];
+        assert_eq!(json!("left"), y["position"]);
+        assert_eq!(json!(70.0), y["min"]);
+        assert!(y.get("max").is_none());
+        assert_eq!(json!({ "display": true, "text": "кг" }), y["title"]);
+        assert_eq!(json!(true), y["grid"]["drawOnChartArea"]);
+
+        let y1 = &cfg["options"]["scales"]["y1"];
+        assert_eq!(json!("right"), y1["position"]);
+        assert_eq!(json!(30.0), y1["max"]);
+        assert_eq!(json!(false), y1["grid"]["drawOnChartArea"]);
+
+        assert_eq!(
+            json!({ "display": true, "position": "bottom" }),
+            cfg["options"]["plugins"]["legend"]
+        );
+    }
+
+    #[test]
+    fn test_chart_config_datasets() {
+        let cfg = chart_config(&two_axes_chart());
+        let datasets = cfg["data"]["datasets"].as_array().unwrap();
+        assert_eq!(3, datasets.len());
+
+        let weight = &datasets[0];
+        assert_eq!(json!("origin"), weight["fill"]);
+        assert_eq!(json!("y"), weight["yAxisID"]);
+        assert_eq!(json!(CHART_COLOR_BLUE), weight["borderColor"]);
+        assert_eq!(json!("rgba(54, 162, 235, 0.2)"), weight["backgroundColor"]);
+        assert_eq!(json!(BORDER_WIDTH), weight["borderWidth"]);
+        assert_eq!(json!(3), weight["pointRadius"]);
+
+        let fat = &datasets[1];
+        assert_eq!(json!(false), fat["fill"]);
+        assert_eq!(json!("y1"), fat["yAxisID"]);
+        assert_eq!(json!(0), fat["pointRadius"]);
+        assert_eq!(json!(1), fat["borderWidth"]);
+
+        let goal = &datasets[2];
+        assert_eq!(json!("line"), goal["type"]);
+        assert_eq!(json!("Цель"), goal["label"]);
+        assert_eq!(json!([75.0, 75.0, 75.0]), goal["data"]);
+        assert_eq!(json!([6, 4]), goal["borderDash"]);
+        assert_eq!(json!("y"), goal["yAxisID"]);
+    }
+
+    #[test]
+    fn test_chart_config_stacked() {
+        let cfg = chart_config(&ChartData {
+            elem_id: "chart".into(),
+            x_labels: vec!["a".into()],
+            ctype: ChartType::Bar,
+            datasets: vec![ChartDataset {
+                data: vec![1.0],
+                ..Default::default()
+            }],
+            stacked: true,
+            legend: LegendPosition::Hidden,
+            ..Default::default()
+        });
+
+        assert_eq!(json!("bar"), cfg["type"]);
+        assert_eq!(json!(true), cfg["options"]["scales"]["x"]["stacked"]);
+        assert_eq!(json!(true), cfg["options"]["scales"]["y"]["stacked"]);
+        assert_eq!(json!(true), cfg["options"]["scales"]["y"]["beginAtZero"]);
+        assert_eq!(
+            json!({ "display": false }),
+            cfg["options"]["plugins"]["legend"]
+        );
+    }
+
+    #[test]
+    fn test_with_alpha() {
+        assert_eq!("rgba(1, 2, 3, 0.5)", with_alpha("rgb(1, 2, 3)", 0.5));
+        assert_eq!("#fff", with_alpha("#fff", 0.5));
+    }
 }
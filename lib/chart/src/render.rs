@@ -0,0 +1,578 @@
+use std::{
+    collections::HashMap,
+    f64::consts::PI,
+    sync::{Arc, OnceLock},
+};
+
+use anyhow::{anyhow, Context, Result};
+use resvg::{
+    tiny_skia::{Color, Pixmap, Transform},
+    usvg::{fontdb::Database, Options, Tree},
+};
+
+use crate::{
+    Axis, AxisPosition, ChartData, ChartType, Fill, LegendPosition, PointStyle, BORDER_WIDTH,
+    FILL_ALPHA,
+};
+
+pub const IMAGE_WIDTH: u32 = 800;
+pub const IMAGE_HEIGHT: u32 = 450;
+
+const MARGIN_LEFT: f64 = 60.0;
+const MARGIN_RIGHT: f64 = 20.0;
+const MARGIN_TOP: f64 = 40.0;
+const MARGIN_BOTTOM: f64 = 40.0;
+const Y_TICKS: usize = 5;
+const MAX_X_LABELS: usize = 8;
+// Points are drawn only for sparse line charts
+const MAX_POINTS: usize = 60;
+const FONT_FAMILY: &str = "DejaVu Sans, Arial, sans-serif";
+const COLOR_AXIS: &str = "rgb(102, 102, 102)";
+const COLOR_GRID: &str = "rgb(230, 230, 230)";
+// Inner radius of doughnut relative to outer one
+const DOUGHNUT_CUTOUT: f64 = 0.5;
+
+static FONT_DB: OnceLock<Arc<Database>> = OnceLock::new();
+
+// Render chart to SVG document. Supported chart types: line, bar, pie, doughnut.
+pub fn render_svg(data: &ChartData) -> String {
+    let (w, h) = (IMAGE_WIDTH as f64, IMAGE_HEIGHT as f64);
+    let circular = data.ctype.is_circular();
+    let has_right = data
+        .axes()
+        .iter()
+        .any(|a| a.position == AxisPosition::Right);
+    let margin_right = if has_right && !circular {
+        MARGIN_LEFT
+    } else {
+        MARGIN_RIGHT
+    };
+    let area = Area {
+        x: MARGIN_LEFT,
+        y: MARGIN_TOP,
+        w: w - MARGIN_LEFT - margin_right,
+        h: h - MARGIN_TOP - MARGIN_BOTTOM,
+    };
+
+    let mut svg = String::new();
+    svg.push_str(&format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="{FONT_FAMILY}" font-size="12">"#
+    ));
+    svg.push_str(&format!(
+        r#"<rect x="0" y="0" width="{w}" height="{h}" fill="white"/>"#
+    ));
+
+    if circular {
+        render_circular(&mut svg, data, &area);
+    } else {
+        render_cartesian(&mut svg, data, &area);
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+// Render chart to PNG image using system fonts
+pub fn render_png(data: &ChartData) -> Result<Vec<u8>> {
+    let opt = Options {
+        fontdb: FONT_DB
+            .get_or_init(|| {
+                let mut db = Database::new();
+                db.load_system_fonts();
+                Arc::new(db)
+            })
+            .clone(),
+        ..Default::default()
+    };
+
+    let tree = Tree::from_str(&render_svg(data), &opt).context("parse svg")?;
+
+    let mut pixmap = Pixmap::new(IMAGE_WIDTH, IMAGE_HEIGHT).ok_or(anyhow!("create pixmap"))?;
+    pixmap.fill(Color::WHITE);
+    resvg::render(&tree, Transform::default(), &mut pixmap.as_mut());
+
+    pixmap.encode_png().context("encode png")
+}
+
+// Plot area
+struct Area {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+}
+
+// Line and bar charts
+fn render_cartesian(svg: &mut String, data: &ChartData, area: &Area) {
+    let is_bar = data.ctype == ChartType::Bar;
+    let n = data.x_labels.len();
+    let axes = data.axes();
+    let values = stack_values(data);
+
+    let ranges: Vec<(f64, f64)> = axes
+        .iter()
+        .map(|axis| axis_range(data, axis, &values))
+        .collect();
+    let y_pos = |axis_id: &str, v: f64| {
+        let idx = axes.iter().position(|a| a.id == axis_id).unwrap_or(0);
+        let (min, max) = ranges[idx];
+        area.y + area.h - (v.clamp(min, max) - min) / (max - min) * area.h
+    };
+    let x_pos = |i: usize| {
+        if is_bar {
+            area.x + area.w * (i as f64 + 0.5) / n.max(1) as f64
+        } else if n <= 1 {
+            area.x + area.w / 2.0
+        } else {
+            area.x + area.w * i as f64 / (n - 1) as f64
+        }
+    };
+
+    // Y axes, the primary one draws the grid
+    for (k, (axis, (min, max))) in axes.iter().zip(&ranges).enumerate() {
+        let (tick_x, anchor) = match axis.position {
+            AxisPosition::Left => (area.x - 6.0, "end"),
+            AxisPosition::Right => (area.x + area.w + 6.0, "start"),
+        };
+        for i in 0..=Y_TICKS {
+            let v = min + (max - min) * i as f64 / Y_TICKS as f64;
+            let y = y_pos(&axis.id, v);
+            if k == 0 {
+                svg.push_str(&format!(
+                    r#"<line x1="{:.1}" y1="{y:.1}" x2="{:.1}" y2="{y:.1}" stroke="{COLOR_GRID}"/>"#,
+                    area.x,
+                    area.x + area.w
+                ));
+            }
+            svg.push_str(&format!(
+                r#"<text x="{tick_x:.1}" y="{:.1}" text-anchor="{anchor}" fill="{COLOR_AXIS}">{}</text>"#,
+                y + 4.0,
+                format_tick(v, max - min)
+            ));
+        }
+        if !axis.unit.is_empty() {
+            svg.push_str(&format!(
+                r#"<text x="{tick_x:.1}" y="{:.1}" text-anchor="{anchor}" fill="{COLOR_AXIS}">{}</text>"#,
+                area.y - 10.0,
+                escape(&axis.unit)
+            ));
+        }
+    }
+
+    // X axis
+    svg.push_str(&format!(
+        r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{COLOR_AXIS}"/>"#,
+        area.x,
+        area.y + area.h,
+        area.x + area.w,
+        area.y + area.h
+    ));
+
+    let step = n.div_ceil(MAX_X_LABELS).max(1);
+    for (i, lbl) in data.x_labels.iter().enumerate().step_by(step) {
+        svg.push_str(&format!(
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="middle" fill="{COLOR_AXIS}">{}</text>"#,
+            x_pos(i),
+            area.y + area.h + 18.0,
+            escape(lbl)
+        ));
+    }
+
+    // Datasets, stacked bars share one slot
+    let slots = if data.stacked {
+        1.0
+    } else {
+        data.datasets.len().max(1) as f64
+    };
+    for (k, (ds, vals)) in data.datasets.iter().zip(&values).enumerate() {
+        let axis_id = data.axis_id(&ds.axis);
+        if is_bar {
+            let band = area.w / n.max(1) as f64 * 0.8;
+            let bar_w = band / slots;
+            let slot = if data.stacked { 0.0 } else { k as f64 };
+            for (i, (base, top)) in vals.iter().enumerate() {
+                if !top.is_finite() {
+                    continue;
+                }
+                let x = x_pos(i) - band / 2.0 + bar_w * slot;
+                let (y0, y1) = (y_pos(axis_id, *base), y_pos(axis_id, *top));
+                svg.push_str(&format!(
+                    r#"<rect x="{x:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}"/>"#,
+                    y0.min(y1),
+                    bar_w,
+                    (y0 - y1).abs(),
+                    ds.color
+                ));
+            }
+            continue;
+        }
+
+        let points: Vec<(f64, f64)> = vals
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, top))| top.is_finite())
+            .map(|(i, (_, top))| (x_pos(i), y_pos(axis_id, *top)))
+            .collect();
+        let polyline = points
+            .iter()
+            .map(|(x, y)| format!("{x:.1},{y:.1}"))
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        if ds.fill == Fill::Origin && !points.is_empty() {
+            let y0 = y_pos(axis_id, 0.0);
+            svg.push_str(&format!(
+                r#"<polygon fill="{}" fill-opacity="{FILL_ALPHA}" points="{:.1},{y0:.1} {polyline} {:.1},{y0:.1}"/>"#,
+                ds.color,
+                points[0].0,
+                points[points.len() - 1].0
+            ));
+        }
+        svg.push_str(&format!(
+            r#"<polyline fill="none" stroke="{}" stroke-width="{}" points="{polyline}"/>"#,
+            ds.color,
+            ds.border_width()
+        ));
+        if points.len() <= MAX_POINTS {
+            for (x, y) in &points {
+                render_point(svg, ds.point_style, *x, *y, &ds.color);
+            }
+        }
+    }
+
+    // Annotations
+    for an in &data.annotations {
+        let y = y_pos(data.axis_id(&an.axis), an.value);
+        svg.push_str(&format!(
+            r#"<line x1="{:.1}" y1="{y:.1}" x2="{:.1}" y2="{y:.1}" stroke="{}" stroke-width="{BORDER_WIDTH}" stroke-dasharray="6,4"/>"#,
+            area.x,
+            area.x + area.w,
+            an.color
+        ));
+        svg.push_str(&format!(
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="end" fill="{}">{}</text>"#,
+            area.x + area.w - 4.0,
+            y - 4.0,
+            an.color,
+            escape(&an.label)
+        ));
+    }
+
+    render_legend(
+        svg,
+        data.legend,
+        data.datasets
+            .iter()
+            .map(|ds| (ds.label.as_str(), ds.color.as_str()))
+            .chain(
+                data.annotations
+                    .iter()
+                    .map(|an| (an.label.as_str(), an.color.as_str())),
+            ),
+    );
+}
+
+// Pie and doughnut: only the first dataset is drawn, legend shows slices
+fn render_circular(svg: &mut String, data: &ChartData, area: &Area) {
+    let Some(ds) = data.datasets.first() else {
+        return;
+    };
+
+    let (cx, cy) = (area.x + area.w / 2.0, area.y + area.h / 2.0);
+    let r = area.w.min(area.h) / 2.0;
+    let ir = if data.ctype == ChartType::Doughnut {
+        r * DOUGHNUT_CUTOUT
+    } else {
+        0.0
+    };
+
+    let total: f64 = ds.data.iter().filter(|v| v.is_finite() && **v > 0.0).sum();
+    if total > 0.0 {
+        let point = |radius: f64, angle: f64| {
+            // Start from 12 o'clock, clockwise
+            (
+                cx + radius * (angle - PI / 2.0).cos(),
+                cy + radius * (angle - PI / 2.0).sin(),
+            )
+        };
+
+        let mut start = 0.0;
+        for (i, v) in ds.data.iter().enumerate() {
+            if !v.is_finite() || *v <= 0.0 {
+                continue;
+            }
+            let sweep = v / total * 2.0 * PI;
+            let color = ds.value_color(i);
+
+            // Arc can't be drawn as a full circle
+            if sweep >= 2.0 * PI - 1e-6 {
+                svg.push_str(&format!(
+                    r#"<circle cx="{cx:.1}" cy="{cy:.1}" r="{r:.1}" fill="{color}"/>"#
+                ));
+            } else {
+                let large = if sweep > PI { 1 } else { 0 };
+                let (x0, y0) = point(r, start);
+                let (x1, y1) = point(r, start + sweep);
+                let (ix0, iy0) = point(ir, start + sweep);
+                let (ix1, iy1) = point(ir, start);
+                svg.push_str(&format!(
+                    r#"<path d="M {x0:.1} {y0:.1} A {r:.1} {r:.1} 0 {large} 1 {x1:.1} {y1:.1} L {ix0:.1} {iy0:.1} A {ir:.1} {ir:.1} 0 {large} 0 {ix1:.1} {iy1:.1} Z" fill="{color}" stroke="white" stroke-width="{}"/>"#,
+                    ds.border_width()
+                ));
+            }
+            start += sweep;
+        }
+
+        if ir > 0.0 {
+            svg.push_str(&format!(
+                r#"<circle cx="{cx:.1}" cy="{cy:.1}" r="{ir:.1}" fill="white"/>"#
+            ));
+        }
+    }
+
+    render_legend(
+        svg,
+        data.legend,
+        data.x_labels
+            .iter()
+            .enumerate()
+            .map(|(i, lbl)| (lbl.as_str(), ds.value_color(i))),
+    );
+}
+
+fn render_point(svg: &mut String, style: PointStyle, x: f64, y: f64, color: &str) {
+    match style {
+        PointStyle::Circle => svg.push_str(&format!(
+            r#"<circle cx="{x:.1}" cy="{y:.1}" r="3" fill="{color}"/>"#
+        )),
+        PointStyle::Rect => svg.push_str(&format!(
+            r#"<rect x="{:.1}" y="{:.1}" width="6" height="6" fill="{color}"/>"#,
+            x - 3.0,
+            y - 3.0
+        )),
+        PointStyle::Triangle => svg.push_str(&format!(
+            r#"<polygon points="{x:.1},{:.1} {:.1},{:.1} {:.1},{:.1}" fill="{color}"/>"#,
+            y - 4.0,
+            x - 4.0,
+            y + 3.0,
+            x + 4.0,
+            y + 3.0
+        )),
+        PointStyle::Hidden => {}
+    }
+}
+
+fn render_legend<'a>(
+    svg: &mut String,
+    position: LegendPosition,
+    items: impl Iterator<Item = (&'a str, &'a str)>,
+) {
+    let y = match position {
+        LegendPosition::Top => 12.0,
+        LegendPosition::Bottom => IMAGE_HEIGHT as f64 - 16.0,
+        LegendPosition::Hidden => return,
+    };
+
+    let mut x = MARGIN_LEFT;
+    for (label, color) in items {
+        svg.push_str(&format!(
+            r#"<rect x="{x:.1}" y="{y:.1}" width="24" height="12" fill="{color}"/>"#
+        ));
+        svg.push_str(&format!(
+            r#"<text x="{:.1}" y="{:.1}">{}</text>"#,
+            x + 30.0,
+            y + 10.0,
+            escape(label)
+        ));
+        // Approximate text width
+        x += 30.0 + label.chars().count() as f64 * 7.0 + 20.0;
+    }
+}
+
+// Base and top of every value, values are accumulated per axis in stacked mode.
+// Non-finite values are NaN.
+fn stack_values(data: &ChartData) -> Vec<Vec<(f64, f64)>> {
+    let n = data.x_labels.len();
+    let mut sums: HashMap<&str, (Vec<f64>, Vec<f64>)> = HashMap::new();
+
+    data.datasets
+        .iter()
+        .map(|ds| {
+            let (pos, neg) = sums
+                .entry(data.axis_id(&ds.axis))
+                .or_insert_with(|| (vec![0.0; n], vec![0.0; n]));
+            ds.data
+                .iter()
+                .take(n)
+                .enumerate()
+                .map(|(i, v)| {
+                    if !v.is_finite() {
+                        (f64::NAN, f64::NAN)
+                    } else if !data.stacked {
+                        (0.0, *v)
+                    } else {
+                        let sum = if *v >= 0.0 { &mut pos[i] } else { &mut neg[i] };
+                        let base = *sum;
+                        *sum += v;
+                        (base, *sum)
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn axis_range(data: &ChartData, axis: &Axis, values: &[Vec<(f64, f64)>]) -> (f64, f64) {
+    let from_zero = data.ctype == ChartType::Bar || data.stacked;
+    let vals = data
+        .datasets
+        .iter()
+        .zip(values)
+        .filter(|(ds, _)| data.axis_id(&ds.axis) == axis.id)
+        .flat_map(|(_, vals)| vals.iter().map(|(_, top)| *top))
+        .chain(
+            data.annotations
+                .iter()
+                .filter(|an| data.axis_id(&an.axis) == axis.id)
+                .map(|an| an.value),
+        )
+        .filter(|v| v.is_finite());
+
+    let (mut min, mut max) = vals.fold((f64::MAX, f64::MIN), |(mn, mx), v| (mn.min(v), mx.max(v)));
+    if min > max {
+        (min, max) = (0.0, 1.0);
+    }
+    if from_zero {
+        min = min.min(0.0);
+        max = max.max(0.0);
+    }
+    if (max - min).abs() < f64::EPSILON {
+        (min, max) = (min - 1.0, max + 1.0);
+    }
+
+    // Padding, bars start exactly from zero
+    let pad = (max - min) * 0.05;
+    if !(from_zero && min == 0.0) {
+        min -= pad;
+    }
+    max += pad;
+
+    // Explicit bounds
+    min = axis.min.unwrap_or(min);
+    max = axis.max.unwrap_or(max);
+    if max <= min {
+        max = min + 1.0;
+    }
+
+    (min, max)
+}
+
+fn format_tick(v: f64, range: f64) -> String {
+    if range >= 50.0 {
+        format!("{:.0}", v)
+    } else if range >= 5.0 {
+        format!("{:.1}", v)
+    } else {
+        format!("{:.2}", v)
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Annotation, ChartDataset, CHART_COLOR_BLUE, CHART_COLOR_GREEN, CHART_COLOR_RED};
+
+    fn bar_chart(stacked: bool) -> ChartData {
+        ChartData {
+            elem_id: "chart".into(),
+            x_labels: vec!["a".into(), "b".into()],
+            ctype: ChartType::Bar,
+            datasets: vec![
+                ChartDataset {
+                    data: vec![10.0, 20.0],
+                    label: "A".into(),
+                    color: CHART_COLOR_BLUE.into(),
+                    ..Default::default()
+                },
+                ChartDataset {
+                    data: vec![30.0, 40.0],
+                    label: "B".into(),
+                    color: CHART_COLOR_RED.into(),
+                    ..Default::default()
+                },
+            ],
+            axes: vec![Axis {
+                unit: "ккал".into(),
+                ..Default::default()
+            }],
+            stacked,
+            annotations: vec![Annotation {
+                label: "Лимит".into(),
+                value: 50.0,
+                color: CHART_COLOR_GREEN.into(),
+                axis: None,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_stack_values() {
+        let chart = bar_chart(true);
+        assert_eq!(
+            vec![
+                vec![(0.0, 10.0), (0.0, 20.0)],
+                vec![(10.0, 40.0), (20.0, 60.0)]
+            ],
+            stack_values(&chart)
+        );
+        // Stacked sums and annotation are in range, bars start from zero
+        let (min, max) = axis_range(&chart, &chart.axes()[0], &stack_values(&chart));
+        assert_eq!(0.0, min);
+        assert!((max - 63.0).abs() < 1e-9);
+
+        let chart = bar_chart(false);
+        assert_eq!(
+            vec![
+                vec![(0.0, 10.0), (0.0, 20.0)],
+                vec![(0.0, 30.0), (0.0, 40.0)]
+            ],
+            stack_values(&chart)
+        );
+    }
+
+    #[test]
+    fn test_axis_bounds() {
+        let mut chart = bar_chart(false);
+        chart.axes[0].min = Some(5.0);
+        chart.axes[0].max = Some(45.0);
+        assert_eq!(
+            (5.0, 45.0),
+            axis_range(&chart, &chart.axes[0], &stack_values(&chart))
+        );
+    }
+
+    #[test]
+    fn test_render_svg() {
+        let svg = render_svg(&bar_chart(true));
+        // Two bars and legend
+        assert_eq!(
+            3,
+            svg.matches(&format!(r#"fill="{CHART_COLOR_RED}""#)).count()
+        );
+        assert!(svg.contains(r#"stroke-dasharray="6,4""#));
+        assert!(svg.contains(">Лимит</text>"));
+        assert!(svg.contains(">ккал</text>"));
+
+        let mut chart = bar_chart(true);
+        chart.legend = LegendPosition::Hidden;
+        assert!(!render_svg(&chart).contains(">A</text>"));
+    }
+}
@@ -0,0 +1,83 @@
+use std::{collections::HashMap, env, fs, path::Path};
+
+use sha2::{Digest, Sha256};
+
+// Assets that can be inlined into generated documents: (const name, file name)
+const ASSETS: [(&str, &str); 3] = [
+    ("CSS_BOOTSTRAP", "bootstrap.min.css"),
+    ("JS_BOOTSTRAP", "bootstrap.bundle.min.js"),
+    ("JS_CHART", "chart.umd.min.js"),
+];
+
+// Pinned asset checksums in `sha256sum` format, written by `make assets-pin`
+const CHECKSUMS_FILE: &str = "assets.sha256";
+
+fn main() {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let manifest_dir = Path::new(&manifest_dir);
+    let assets_dir = manifest_dir.join("assets");
+    let checksums_path = manifest_dir.join(CHECKSUMS_FILE);
+    println!("cargo:rerun-if-changed={}", assets_dir.display());
+    println!("cargo:rerun-if-changed={}", checksums_path.display());
+
+    let embed = env::var_os("CARGO_FEATURE_EMBED_ASSETS").is_some();
+    let checksums = if embed {
+        read_checksums(&checksums_path)
+    } else {
+        HashMap::new()
+    };
+
+    let mut out = String::new();
+    for (name, file) in ASSETS {
+        if !embed {
+            out.push_str(&format!("pub const {name}: Option<&str> = None;\n"));
+            continue;
+        }
+
+        let path = assets_dir.join(file);
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        let data = fs::read(&path).unwrap_or_else(|e| {
+            panic!("asset {file} not readable ({e}), run `make assets` to download it")
+        });
+        let expected = checksums
+            .get(file)
+            .unwrap_or_else(|| panic!("asset {file} has no checksum in {CHECKSUMS_FILE}"));
+        let actual = hex(&Sha256::digest(&data));
+        if &actual != expected {
+            panic!("asset {file} checksum mismatch: expected {expected}, got {actual}");
+        }
+
+        out.push_str(&format!(
+            "pub const {name}: Option<&str> = Some(include_str!({:?}));\n",
+            path.display().to_string()
+        ));
+    }
+
+    let out_path = Path::new(&env::var("OUT_DIR").unwrap()).join("assets.rs");
+    fs::write(out_path, out).unwrap();
+}
+
+// Parses `<sha256>  <file name>` lines into file name -> checksum map
+fn read_checksums(path: &Path) -> HashMap<String, String> {
+    let data = fs::read_to_string(path).unwrap_or_else(|e| {
+        panic!(
+            "checksums file {} not readable ({e}), run `make assets-pin` to create it",
+            path.display()
+        )
+    });
+
+    data.lines()
+        .filter_map(|line| {
+            let (sum, file) = line.split_once(char::is_whitespace)?;
+            Some((
+                file.trim_start().trim_start_matches('*').to_string(),
+                sum.to_lowercase(),
+            ))
+        })
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
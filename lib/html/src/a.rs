@@ -0,0 +1,40 @@
+use crate::{attrs::Attrs, escape, Element};
+
+pub struct A {
+    href: String,
+    elements: Vec<Box<dyn Element>>,
+    attrs: Attrs,
+}
+
+impl A {
+    pub fn new(href: &str, elements: Vec<Box<dyn Element>>) -> Self {
+        Self {
+            href: href.into(),
+            elements,
+            attrs: Attrs::default(),
+        }
+    }
+
+    pub fn set_attrs(mut self, attrs: Attrs) -> Self {
+        self.attrs = attrs;
+        self
+    }
+
+    pub fn as_box(self) -> Box<dyn Element> {
+        Box::new(self)
+    }
+}
+
+impl Element for A {
+    fn build(&self) -> String {
+        let mut a = format!(r#"<a href="{}" {}>"#, escape(&self.href), self.attrs);
+
+        for elem in &self.elements {
+            a.push_str(&elem.build());
+        }
+
+        a.push_str("</a>");
+
+        a
+    }
+}
@@ -1,4 +1,4 @@
-use crate::Element;
+use crate::{escape, Element};
 
 pub struct Accordion {
     id: String,
@@ -29,7 +29,7 @@ impl Element for Accordion {
             r#"
         <div class="accordion" id="{}">
         "#,
-            self.id
+            escape(&self.id)
         );
 
         for item in &self.items {
@@ -78,7 +78,11 @@ impl Element for AccordionItem {
 		<div id="{}" class="accordion-collapse collapse" data-bs-parent="#{}">
 			<div class="accordion-body">
         "##,
-            self.id, self.id, self.header, self.id, self.accordion_id
+            escape(&self.id),
+            escape(&self.id),
+            escape(&self.header),
+            escape(&self.id),
+            escape(&self.accordion_id)
         );
 
         item.push_str(&self.body.build());
@@ -1,6 +1,8 @@
 use core::fmt;
 use std::collections::HashMap;
 
+use crate::escape;
+
 pub type Attr<'a> = (&'a str, &'a str);
 
 #[derive(Default)]
@@ -31,7 +33,7 @@ impl fmt::Display for Attrs {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut s = Vec::with_capacity(self.0.len());
         for (k, v) in &self.0 {
-            s.push(format!("{}=\"{}\"", k, v));
+            s.push(format!("{}=\"{}\"", escape(k), escape(v)));
         }
 
         f.write_str(&s.join(" "))
@@ -1,4 +1,4 @@
-use crate::{attrs::Attrs, Element};
+use crate::{attrs::Attrs, escape, Element};
 
 pub struct B {
     val: String,
@@ -25,6 +25,6 @@ impl B {
 
 impl Element for B {
     fn build(&self) -> String {
-        format!("<b {}>{}</b>", self.attrs, self.val)
+        format!("<b {}>{}</b>", self.attrs, escape(&self.val))
     }
 }
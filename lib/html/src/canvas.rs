@@ -1,4 +1,4 @@
-use crate::Element;
+use crate::{escape, Element};
 
 pub struct Canvas {
     id: String,
@@ -12,6 +12,6 @@ impl Canvas {
 
 impl Element for Canvas {
     fn build(&self) -> String {
-        format!(r#"<canvas id="{}"></canvas>"#, self.id)
+        format!(r#"<canvas id="{}"></canvas>"#, escape(&self.id))
     }
 }
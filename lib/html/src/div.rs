@@ -1,4 +1,4 @@
-use crate::Element;
+use crate::{escape, Element};
 
 pub struct Div {
     class: String,
@@ -29,7 +29,7 @@ impl Div {
 
 impl Element for Div {
     fn build(&self) -> String {
-        let mut div = format!(r#"<div class="{}">"#, self.class);
+        let mut div = format!(r#"<div class="{}">"#, escape(&self.class));
 
         for elem in &self.elements {
             div.push_str(&elem.build());
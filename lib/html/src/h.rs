@@ -1,4 +1,4 @@
-use crate::{attrs::Attrs, Element};
+use crate::{attrs::Attrs, escape, Element};
 
 pub struct H {
     val: String,
@@ -29,7 +29,10 @@ impl Element for H {
     fn build(&self) -> String {
         format!(
             "<h{} {}>{}</h{}>",
-            self.size, self.attrs, self.val, self.size
+            self.size,
+            self.attrs,
+            escape(&self.val),
+            self.size
         )
     }
 }
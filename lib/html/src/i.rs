@@ -1,4 +1,4 @@
-use crate::Element;
+use crate::{escape, Element};
 
 pub struct I {
     val: String,
@@ -12,6 +12,6 @@ impl I {
 
 impl Element for I {
     fn build(&self) -> String {
-        format!("<i>{}</i>", self.val)
+        format!("<i>{}</i>", escape(&self.val))
     }
 }
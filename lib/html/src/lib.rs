@@ -1,3 +1,4 @@
+pub mod a;
 pub mod accordion;
 pub mod attrs;
 pub mod b;
@@ -5,20 +6,105 @@ pub mod canvas;
 pub mod div;
 pub mod h;
 pub mod i;
+pub mod progress;
+pub mod raw;
 pub mod s;
 pub mod script;
 pub mod span;
 pub mod table;
 
+use std::sync::OnceLock;
+
 pub const CSS_BOOTSTRAP_URL: &str = "https://devldavydov.github.io/css/bootstrap/bootstrap.min.css";
 pub const JS_BOOTSTRAP_URL: &str =
     "https://devldavydov.github.io/js/bootstrap/bootstrap.bundle.min.js";
 pub const JS_CHART_URL: &str = "https://devldavydov.github.io/js/chartjs/chart.umd.min.js";
 
+// Asset contents embedded at build time, None unless built with `embed-assets` feature
+mod embedded {
+    include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+}
+
+static ASSET_MODE: OnceLock<AssetMode> = OnceLock::new();
+
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub enum AssetMode {
+    // Assets content is inlined into document
+    #[default]
+    Inline,
+    // Assets are linked by URL
+    Cdn,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Asset {
+    CssBootstrap,
+    JsBootstrap,
+    JsChart,
+}
+
+impl Asset {
+    pub fn url(&self) -> &'static str {
+        match self {
+            Asset::CssBootstrap => CSS_BOOTSTRAP_URL,
+            Asset::JsBootstrap => JS_BOOTSTRAP_URL,
+            Asset::JsChart => JS_CHART_URL,
+        }
+    }
+
+    pub fn content(&self) -> Option<&'static str> {
+        match self {
+            Asset::CssBootstrap => embedded::CSS_BOOTSTRAP,
+            Asset::JsBootstrap => embedded::JS_BOOTSTRAP,
+            Asset::JsChart => embedded::JS_CHART,
+        }
+    }
+
+    // Content to inline according to asset mode, None if asset should be linked
+    pub fn inline_content(&self) -> Option<&'static str> {
+        match asset_mode() {
+            AssetMode::Inline => self.content(),
+            AssetMode::Cdn => None,
+        }
+    }
+}
+
+// Sets asset mode for all documents, can be called only once at startup
+pub fn set_asset_mode(mode: AssetMode) -> bool {
+    ASSET_MODE.set(mode).is_ok()
+}
+
+pub fn asset_mode() -> AssetMode {
+    ASSET_MODE.get().copied().unwrap_or_default()
+}
+
+// Whether all assets were embedded at build time
+pub fn assets_embedded() -> bool {
+    [Asset::CssBootstrap, Asset::JsBootstrap, Asset::JsChart]
+        .iter()
+        .all(|a| a.content().is_some())
+}
+
 pub trait Element: Send + Sync {
     fn build(&self) -> String;
 }
 
+// Escapes text for safe use in HTML content and attribute values
+pub fn escape(val: &str) -> String {
+    let mut res = String::with_capacity(val.len());
+    for c in val.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&#39;"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
 pub struct Builder {
     title: String,
     elements: Vec<Box<dyn Element>>,
@@ -46,11 +132,18 @@ impl Builder {
         <head>
             <meta charset="utf-8">
             <title>{}</title>
-            <link href="{}" rel="stylesheet">
+            {}
         </head>
         <body>
         "#,
-            self.title, CSS_BOOTSTRAP_URL
+            escape(&self.title),
+            match Asset::CssBootstrap.inline_content() {
+                Some(css) => format!("<style>{}</style>", css),
+                None => format!(
+                    r#"<link href="{}" rel="stylesheet">"#,
+                    escape(Asset::CssBootstrap.url())
+                ),
+            }
         );
 
         for elem in &self.elements {
@@ -67,3 +160,56 @@ impl Builder {
         doc
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        a::A, attrs::Attrs, b::B, progress::Progress, raw::Raw, s::S, table::Table, Element,
+    };
+
+    #[test]
+    fn test_escape_text() {
+        assert_eq!(
+            "&lt;script&gt;alert(&quot;x&quot; &amp; &#39;y&#39;)&lt;/script&gt;",
+            S::create(r#"<script>alert("x" & 'y')</script>"#).build()
+        );
+        assert_eq!("<b >a&lt;b</b>", B::new("a<b").build());
+        assert!(Table::new(vec!["<i>".into()])
+            .build()
+            .contains("<th>&lt;i&gt;</th>"));
+    }
+
+    #[test]
+    fn test_escape_attrs() {
+        let attrs = Attrs::from_items(vec![("title", r#"x" onclick="y"#)].into_iter());
+        assert_eq!(r#"title="x&quot; onclick=&quot;y""#, attrs.to_string());
+    }
+
+    #[test]
+    fn test_raw() {
+        assert_eq!("<b>raw</b>", Raw::create("<b>raw</b>").build());
+        assert_eq!("&nbsp;", S::create_nbsp().build());
+    }
+
+    #[test]
+    fn test_link() {
+        assert_eq!(
+            r##"<a href="#x&quot;y" ><b >1</b></a>"##,
+            A::new(r##"#x"y"##, vec![B::new("1").as_box()]).build()
+        );
+    }
+
+    #[test]
+    fn test_progress() {
+        let p = Progress::new(1500.0, 2000.0)
+            .set_label("1500 / 2000 <kcal>")
+            .set_class("bg-success");
+        assert_eq!(75.0, p.percent());
+        let html = p.build();
+        assert!(html.contains(r#"class="progress-bar bg-success" style="width: 75.0%""#));
+        assert!(html.contains("1500 / 2000 &lt;kcal&gt;"));
+
+        assert_eq!(100.0, Progress::new(2500.0, 2000.0).percent());
+        assert_eq!(0.0, Progress::new(100.0, 0.0).percent());
+    }
+}
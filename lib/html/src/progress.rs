@@ -0,0 +1,61 @@
+use crate::{escape, Element};
+
+// Bootstrap progress bar, value is clamped to [0, max]
+pub struct Progress {
+    value: f64,
+    max: f64,
+    label: String,
+    class: String,
+}
+
+impl Progress {
+    pub fn new(value: f64, max: f64) -> Self {
+        Self {
+            value,
+            max,
+            label: String::default(),
+            class: String::default(),
+        }
+    }
+
+    pub fn set_label(mut self, label: &str) -> Self {
+        self.label = label.into();
+        self
+    }
+
+    // Extra bar class, e.g. "bg-success" or "bg-danger"
+    pub fn set_class(mut self, class: &str) -> Self {
+        self.class = class.into();
+        self
+    }
+
+    pub fn percent(&self) -> f64 {
+        if self.max <= 0.0 || !self.value.is_finite() {
+            return 0.0;
+        }
+        (self.value / self.max * 100.0).clamp(0.0, 100.0)
+    }
+
+    pub fn as_box(self) -> Box<dyn Element> {
+        Box::new(self)
+    }
+}
+
+impl Element for Progress {
+    fn build(&self) -> String {
+        let percent = self.percent();
+        let mut class = String::from("progress-bar");
+        if !self.class.is_empty() {
+            class.push(' ');
+            class.push_str(&self.class);
+        }
+
+        format!(
+            r#"<div class="progress" role="progressbar" aria-valuenow="{:.0}" aria-valuemin="0" aria-valuemax="100" style="height: 24px"><div class="{}" style="width: {:.1}%">{}</div></div>"#,
+            percent,
+            escape(&class),
+            percent,
+            escape(&self.label)
+        )
+    }
+}
@@ -0,0 +1,18 @@
+use crate::Element;
+
+// Trusted markup inserted as is, without escaping
+pub struct Raw {
+    val: String,
+}
+
+impl Raw {
+    pub fn create(val: &str) -> Box<dyn Element> {
+        Box::new(Self { val: val.into() })
+    }
+}
+
+impl Element for Raw {
+    fn build(&self) -> String {
+        self.val.clone()
+    }
+}
@@ -1,5 +1,6 @@
-use crate::Element;
+use crate::{escape, raw::Raw, Element};
 
+// Text, escaped on build
 pub struct S {
     val: String,
 }
@@ -10,12 +11,12 @@ impl S {
     }
 
     pub fn create_nbsp() -> Box<dyn Element> {
-        S::create("&nbsp;")
+        Raw::create("&nbsp;")
     }
 }
 
 impl Element for S {
     fn build(&self) -> String {
-        self.val.clone()
+        escape(&self.val)
     }
 }
@@ -1,17 +1,32 @@
-use crate::Element;
+use crate::{escape, Asset, Element};
 
 pub struct Script {
     url: String,
+    content: Option<&'static str>,
 }
 
 impl Script {
     pub fn create(url: &str) -> Box<dyn Element> {
-        Box::new(Self { url: url.into() })
+        Box::new(Self {
+            url: url.into(),
+            content: None,
+        })
+    }
+
+    // Script for known asset, inlined or linked according to asset mode
+    pub fn create_asset(asset: Asset) -> Box<dyn Element> {
+        Box::new(Self {
+            url: asset.url().into(),
+            content: asset.inline_content(),
+        })
     }
 }
 
 impl Element for Script {
     fn build(&self) -> String {
-        format!(r#"<script src="{}"></script>"#, self.url)
+        match self.content {
+            Some(content) => format!("<script>{}</script>", content),
+            None => format!(r#"<script src="{}"></script>"#, escape(&self.url)),
+        }
     }
 }
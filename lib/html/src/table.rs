@@ -1,4 +1,4 @@
-use crate::{attrs::Attrs, Element};
+use crate::{attrs::Attrs, escape, Element};
 
 pub struct Table {
     header: Vec<String>,
@@ -39,7 +39,7 @@ impl Element for Table {
         .to_string();
 
         for h in &self.header {
-            table.push_str(&format!("<th>{}</th>", h));
+            table.push_str(&format!("<th>{}</th>", escape(h)));
         }
 
         table.push_str(
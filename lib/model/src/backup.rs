@@ -18,6 +18,14 @@ pub struct Backup {
     pub sport: Vec<SportBackup>,
     #[serde(rename = "sport_activity")]
     pub sport_activity: Vec<SportActivityBackup>,
+    #[serde(rename = "metric", default)]
+    pub metric: Vec<MetricBackup>,
+    #[serde(rename = "metric_value", default)]
+    pub metric_value: Vec<MetricValueBackup>,
+    #[serde(rename = "schedule", default)]
+    pub schedule: Vec<ScheduleBackup>,
+    #[serde(rename = "audit_log", default)]
+    pub audit_log: Vec<AuditLogBackup>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
@@ -58,6 +66,12 @@ pub struct UserSettingsBackup {
     pub user_id: i64,
     #[serde(rename = "cal_limit")]
     pub cal_limit: f64,
+    #[serde(rename = "sex", default)]
+    pub sex: Option<u8>,
+    #[serde(rename = "height", default)]
+    pub height: Option<f64>,
+    #[serde(rename = "birth_date", default)]
+    pub birth_date: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
@@ -107,3 +121,64 @@ pub struct SportActivityBackup {
     #[serde(rename = "sets")]
     pub sets: String,
 }
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct MetricBackup {
+    #[serde(rename = "user_id")]
+    pub user_id: i64,
+    #[serde(rename = "key")]
+    pub key: String,
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "unit")]
+    pub unit: String,
+    #[serde(rename = "fields")]
+    pub fields: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct MetricValueBackup {
+    #[serde(rename = "user_id")]
+    pub user_id: i64,
+    #[serde(rename = "metric_key")]
+    pub metric_key: String,
+    #[serde(rename = "timestamp")]
+    pub timestamp: i64,
+    #[serde(rename = "values")]
+    pub values: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ScheduleBackup {
+    #[serde(rename = "user_id")]
+    pub user_id: i64,
+    #[serde(rename = "kind")]
+    pub kind: u8,
+    #[serde(rename = "hour")]
+    pub hour: u8,
+    #[serde(rename = "minute")]
+    pub minute: u8,
+    #[serde(rename = "tz")]
+    pub tz: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct AuditLogBackup {
+    // Keeps order of entries, restored entries get new ids
+    #[serde(rename = "id")]
+    pub id: i64,
+    #[serde(rename = "user_id")]
+    pub user_id: i64,
+    #[serde(rename = "timestamp")]
+    pub timestamp: i64,
+    #[serde(rename = "entity")]
+    pub entity: String,
+    #[serde(rename = "key")]
+    pub key: String,
+    #[serde(rename = "action")]
+    pub action: u8,
+    #[serde(rename = "old_value")]
+    pub old_value: Option<String>,
+    #[serde(rename = "new_value")]
+    pub new_value: Option<String>,
+}
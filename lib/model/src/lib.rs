@@ -53,9 +53,41 @@ pub struct JournalReport {
     pub carb: f64,
 }
 
+// Journal totals of one food over a period
 #[derive(Debug, Clone, PartialEq)]
+pub struct FoodUsage {
+    pub food_key: String,
+    pub food_name: String,
+    pub food_brand: String,
+    // Number of meals with the food
+    pub count: i64,
+    pub food_weight: f64,
+    pub cal: f64,
+    pub prot: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoodMealUsage {
+    pub food_key: String,
+    pub food_name: String,
+    pub food_brand: String,
+    pub meal: Meal,
+    pub count: i64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sex {
+    Male,
+    Female,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct UserSettings {
     pub cal_limit: f64,
+    // Body profile
+    pub sex: Option<Sex>,
+    pub height: Option<f64>,
+    pub birth_date: Option<Timestamp>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -88,6 +120,100 @@ pub struct SportActivityReport {
     pub sets: Vec<i64>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    pub key: String,
+    pub name: String,
+    pub unit: String,
+    // Names of metric value fields, e.g. ["sys", "dia"] for blood pressure
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricValue {
+    pub metric_key: String,
+    pub timestamp: Timestamp,
+    // Values in the same order as metric fields
+    pub values: Vec<f64>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ScheduleKind {
+    WeightReminder,
+    JournalReminder,
+    DaySummary,
+    WeeklyDigest,
+    MonthlyDigest,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    pub kind: ScheduleKind,
+    // Local time of day in schedule timezone
+    pub hour: u8,
+    pub minute: u8,
+    // IANA timezone name, e.g. Europe/Moscow
+    pub tz: String,
+    pub last_run: Option<Timestamp>,
+}
+
+// Mutation of user data, recorded to change log so it can be undone
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChangeAction {
+    SetFood,
+    DeleteFood,
+    SetBundle,
+    DeleteBundle,
+    SetWeight,
+    DeleteWeight,
+    SetUserSettings,
+    SetJournal,
+    SetJournalBundle,
+    DeleteJournal,
+    DeleteJournalMeal,
+    SetSport,
+    DeleteSport,
+    SetSportActivity,
+    DeleteSportActivity,
+    SetMetric,
+    DeleteMetric,
+    SetMetricValue,
+    DeleteMetricValue,
+    SetSchedule,
+    DeleteSchedule,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub timestamp: Timestamp,
+    pub action: ChangeAction,
+    // Key of changed entry, empty for entries without key
+    pub key: String,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AuditAction {
+    Set,
+    Delete,
+    Undo,
+    Restore,
+}
+
+// Mutation of data by any user, kept in append-only audit log
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub timestamp: Timestamp,
+    pub user_id: i64,
+    // Changed entity, e.g. food or journal
+    pub entity: String,
+    // Key of changed entry, empty for entries without key
+    pub key: String,
+    pub action: AuditAction,
+    // Affected rows as JSON before and after mutation, None if there were no rows
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
 impl Food {
     pub fn validate(&self) -> bool {
         !self.key.is_empty()
@@ -165,7 +291,43 @@ impl Journal {
 
 impl UserSettings {
     pub fn validate(&self) -> bool {
-        self.cal_limit > 0.0
+        self.cal_limit > 0.0 && self.height.is_none_or(|h| h > 0.0)
+    }
+}
+
+impl Sex {
+    pub fn new(v: u8) -> Result<Sex> {
+        match v {
+            0 => Ok(Sex::Male),
+            1 => Ok(Sex::Female),
+            _ => Err(anyhow!("wrong sex")),
+        }
+    }
+
+    pub fn new_str(s: &str) -> Result<Sex> {
+        match s.to_lowercase().as_str() {
+            "m" | "м" => Ok(Sex::Male),
+            "f" | "ж" => Ok(Sex::Female),
+            _ => Err(anyhow!("wrong sex")),
+        }
+    }
+}
+
+impl From<Sex> for String {
+    fn from(value: Sex) -> Self {
+        match value {
+            Sex::Male => "Мужской".into(),
+            Sex::Female => "Женский".into(),
+        }
+    }
+}
+
+impl From<Sex> for u8 {
+    fn from(value: Sex) -> Self {
+        match value {
+            Sex::Male => 0,
+            Sex::Female => 1,
+        }
     }
 }
 
@@ -197,15 +359,264 @@ impl SportActivity {
     }
 }
 
+impl Metric {
+    pub fn validate(&self) -> bool {
+        !self.key.is_empty()
+            && !self.name.is_empty()
+            && !self.fields.is_empty()
+            && self.fields.iter().all(|f| !f.is_empty())
+    }
+}
+
+impl MetricValue {
+    pub fn validate(&self) -> bool {
+        !self.metric_key.is_empty() && !self.values.is_empty()
+    }
+}
+
+impl ScheduleKind {
+    pub fn new(v: u8) -> Result<ScheduleKind> {
+        match v {
+            0 => Ok(ScheduleKind::WeightReminder),
+            1 => Ok(ScheduleKind::JournalReminder),
+            2 => Ok(ScheduleKind::DaySummary),
+            3 => Ok(ScheduleKind::WeeklyDigest),
+            4 => Ok(ScheduleKind::MonthlyDigest),
+            _ => Err(anyhow!("wrong schedule kind")),
+        }
+    }
+
+    pub fn new_str(s: &str) -> Result<ScheduleKind> {
+        match s {
+            "w" => Ok(ScheduleKind::WeightReminder),
+            "j" => Ok(ScheduleKind::JournalReminder),
+            "sum" => Ok(ScheduleKind::DaySummary),
+            "dw" => Ok(ScheduleKind::WeeklyDigest),
+            "dm" => Ok(ScheduleKind::MonthlyDigest),
+            _ => Err(anyhow!("wrong schedule kind")),
+        }
+    }
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            ScheduleKind::WeightReminder => "w",
+            ScheduleKind::JournalReminder => "j",
+            ScheduleKind::DaySummary => "sum",
+            ScheduleKind::WeeklyDigest => "dw",
+            ScheduleKind::MonthlyDigest => "dm",
+        }
+    }
+}
+
+impl From<ScheduleKind> for String {
+    fn from(value: ScheduleKind) -> Self {
+        match value {
+            ScheduleKind::WeightReminder => "Напоминание о взвешивании".into(),
+            ScheduleKind::JournalReminder => "Напоминание о журнале приема пищи".into(),
+            ScheduleKind::DaySummary => "Итоги дня".into(),
+            ScheduleKind::WeeklyDigest => "Отчет за неделю (по понедельникам)".into(),
+            ScheduleKind::MonthlyDigest => "Отчет за месяц (1-го числа)".into(),
+        }
+    }
+}
+
+impl From<ScheduleKind> for u8 {
+    fn from(value: ScheduleKind) -> Self {
+        match value {
+            ScheduleKind::WeightReminder => 0,
+            ScheduleKind::JournalReminder => 1,
+            ScheduleKind::DaySummary => 2,
+            ScheduleKind::WeeklyDigest => 3,
+            ScheduleKind::MonthlyDigest => 4,
+        }
+    }
+}
+
+impl ChangeAction {
+    pub fn new(v: u8) -> Result<ChangeAction> {
+        match v {
+            0 => Ok(ChangeAction::SetFood),
+            1 => Ok(ChangeAction::DeleteFood),
+            2 => Ok(ChangeAction::SetBundle),
+            3 => Ok(ChangeAction::DeleteBundle),
+            4 => Ok(ChangeAction::SetWeight),
+            5 => Ok(ChangeAction::DeleteWeight),
+            6 => Ok(ChangeAction::SetUserSettings),
+            7 => Ok(ChangeAction::SetJournal),
+            8 => Ok(ChangeAction::SetJournalBundle),
+            9 => Ok(ChangeAction::DeleteJournal),
+            10 => Ok(ChangeAction::DeleteJournalMeal),
+            11 => Ok(ChangeAction::SetSport),
+            12 => Ok(ChangeAction::DeleteSport),
+            13 => Ok(ChangeAction::SetSportActivity),
+            14 => Ok(ChangeAction::DeleteSportActivity),
+            15 => Ok(ChangeAction::SetMetric),
+            16 => Ok(ChangeAction::DeleteMetric),
+            17 => Ok(ChangeAction::SetMetricValue),
+            18 => Ok(ChangeAction::DeleteMetricValue),
+            19 => Ok(ChangeAction::SetSchedule),
+            20 => Ok(ChangeAction::DeleteSchedule),
+            _ => Err(anyhow!("wrong change action")),
+        }
+    }
+
+    // Entity name used in audit log
+    pub fn entity(&self) -> &'static str {
+        match self {
+            ChangeAction::SetFood | ChangeAction::DeleteFood => "food",
+            ChangeAction::SetBundle | ChangeAction::DeleteBundle => "bundle",
+            ChangeAction::SetWeight | ChangeAction::DeleteWeight => "weight",
+            ChangeAction::SetUserSettings => "user_settings",
+            ChangeAction::SetJournal
+            | ChangeAction::SetJournalBundle
+            | ChangeAction::DeleteJournal
+            | ChangeAction::DeleteJournalMeal => "journal",
+            ChangeAction::SetSport | ChangeAction::DeleteSport => "sport",
+            ChangeAction::SetSportActivity | ChangeAction::DeleteSportActivity => "sport_activity",
+            ChangeAction::SetMetric | ChangeAction::DeleteMetric => "metric",
+            ChangeAction::SetMetricValue | ChangeAction::DeleteMetricValue => "metric_value",
+            ChangeAction::SetSchedule | ChangeAction::DeleteSchedule => "schedule",
+        }
+    }
+}
+
+impl From<ChangeAction> for String {
+    fn from(value: ChangeAction) -> Self {
+        match value {
+            ChangeAction::SetFood => "Изменение еды".into(),
+            ChangeAction::DeleteFood => "Удаление еды".into(),
+            ChangeAction::SetBundle => "Изменение бандла".into(),
+            ChangeAction::DeleteBundle => "Удаление бандла".into(),
+            ChangeAction::SetWeight => "Изменение веса".into(),
+            ChangeAction::DeleteWeight => "Удаление веса".into(),
+            ChangeAction::SetUserSettings => "Изменение настроек".into(),
+            ChangeAction::SetJournal => "Запись в журнал".into(),
+            ChangeAction::SetJournalBundle => "Запись бандла в журнал".into(),
+            ChangeAction::DeleteJournal => "Удаление из журнала".into(),
+            ChangeAction::DeleteJournalMeal => "Удаление приема пищи".into(),
+            ChangeAction::SetSport => "Изменение спорта".into(),
+            ChangeAction::DeleteSport => "Удаление спорта".into(),
+            ChangeAction::SetSportActivity => "Запись активности".into(),
+            ChangeAction::DeleteSportActivity => "Удаление активности".into(),
+            ChangeAction::SetMetric => "Изменение метрики".into(),
+            ChangeAction::DeleteMetric => "Удаление метрики".into(),
+            ChangeAction::SetMetricValue => "Запись значения метрики".into(),
+            ChangeAction::DeleteMetricValue => "Удаление значения метрики".into(),
+            ChangeAction::SetSchedule => "Изменение расписания".into(),
+            ChangeAction::DeleteSchedule => "Удаление расписания".into(),
+        }
+    }
+}
+
+impl From<ChangeAction> for u8 {
+    fn from(value: ChangeAction) -> Self {
+        match value {
+            ChangeAction::SetFood => 0,
+            ChangeAction::DeleteFood => 1,
+            ChangeAction::SetBundle => 2,
+            ChangeAction::DeleteBundle => 3,
+            ChangeAction::SetWeight => 4,
+            ChangeAction::DeleteWeight => 5,
+            ChangeAction::SetUserSettings => 6,
+            ChangeAction::SetJournal => 7,
+            ChangeAction::SetJournalBundle => 8,
+            ChangeAction::DeleteJournal => 9,
+            ChangeAction::DeleteJournalMeal => 10,
+            ChangeAction::SetSport => 11,
+            ChangeAction::DeleteSport => 12,
+            ChangeAction::SetSportActivity => 13,
+            ChangeAction::DeleteSportActivity => 14,
+            ChangeAction::SetMetric => 15,
+            ChangeAction::DeleteMetric => 16,
+            ChangeAction::SetMetricValue => 17,
+            ChangeAction::DeleteMetricValue => 18,
+            ChangeAction::SetSchedule => 19,
+            ChangeAction::DeleteSchedule => 20,
+        }
+    }
+}
+
+impl From<ChangeAction> for AuditAction {
+    fn from(value: ChangeAction) -> Self {
+        match value {
+            ChangeAction::SetFood
+            | ChangeAction::SetBundle
+            | ChangeAction::SetWeight
+            | ChangeAction::SetUserSettings
+            | ChangeAction::SetJournal
+            | ChangeAction::SetJournalBundle
+            | ChangeAction::SetSport
+            | ChangeAction::SetSportActivity
+            | ChangeAction::SetMetric
+            | ChangeAction::SetMetricValue
+            | ChangeAction::SetSchedule => AuditAction::Set,
+            ChangeAction::DeleteFood
+            | ChangeAction::DeleteBundle
+            | ChangeAction::DeleteWeight
+            | ChangeAction::DeleteJournal
+            | ChangeAction::DeleteJournalMeal
+            | ChangeAction::DeleteSport
+            | ChangeAction::DeleteSportActivity
+            | ChangeAction::DeleteMetric
+            | ChangeAction::DeleteMetricValue
+            | ChangeAction::DeleteSchedule => AuditAction::Delete,
+        }
+    }
+}
+
+impl AuditAction {
+    pub fn new(v: u8) -> Result<AuditAction> {
+        match v {
+            0 => Ok(AuditAction::Set),
+            1 => Ok(AuditAction::Delete),
+            2 => Ok(AuditAction::Undo),
+            3 => Ok(AuditAction::Restore),
+            _ => Err(anyhow!("wrong audit action")),
+        }
+    }
+}
+
+impl From<AuditAction> for String {
+    fn from(value: AuditAction) -> Self {
+        match value {
+            AuditAction::Set => "Изменение".into(),
+            AuditAction::Delete => "Удаление".into(),
+            AuditAction::Undo => "Отмена".into(),
+            AuditAction::Restore => "Восстановление".into(),
+        }
+    }
+}
+
+impl From<AuditAction> for u8 {
+    fn from(value: AuditAction) -> Self {
+        match value {
+            AuditAction::Set => 0,
+            AuditAction::Delete => 1,
+            AuditAction::Undo => 2,
+            AuditAction::Restore => 3,
+        }
+    }
+}
+
+impl Schedule {
+    pub fn validate(&self) -> bool {
+        self.hour < 24 && self.minute < 60 && !self.tz.is_empty()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::collections::HashMap;
 
     use types::timestamp::Timestamp;
 
-    use crate::{Bundle, Food, Journal, Meal, Sport, SportActivity, UserSettings, Weight};
+    use crate::{
+        AuditAction, Bundle, ChangeAction, Food, Journal, Meal, Metric, MetricValue, Schedule,
+        ScheduleKind, Sex, Sport, SportActivity, UserSettings, Weight,
+    };
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_validate_food() {
         for t in vec![
             (
@@ -355,11 +766,42 @@ mod test {
 
     #[test]
     fn test_validate_user_settings() {
-        assert!(!UserSettings { cal_limit: 0.0 }.validate());
-        assert!(UserSettings { cal_limit: 1.0 }.validate());
+        assert!(!UserSettings {
+            cal_limit: 0.0,
+            ..Default::default()
+        }
+        .validate());
+        assert!(UserSettings {
+            cal_limit: 1.0,
+            ..Default::default()
+        }
+        .validate());
+        assert!(!UserSettings {
+            cal_limit: 1.0,
+            height: Some(0.0),
+            ..Default::default()
+        }
+        .validate());
+        assert!(UserSettings {
+            cal_limit: 1.0,
+            sex: Some(Sex::Female),
+            height: Some(170.0),
+            birth_date: Some(Timestamp::now()),
+        }
+        .validate());
+    }
+
+    #[test]
+    fn test_sex() {
+        assert_eq!(Sex::Male, Sex::new_str("m").unwrap());
+        assert_eq!(Sex::Female, Sex::new_str("Ж").unwrap());
+        assert!(Sex::new_str("x").is_err());
+        assert_eq!(Sex::Female, Sex::new(u8::from(Sex::Female)).unwrap());
+        assert!(Sex::new(2).is_err());
     }
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_validate_bundle() {
         for t in vec![
             (
@@ -445,4 +887,138 @@ mod test {
         }
         .validate());
     }
+
+    #[test]
+    fn test_validate_metric() {
+        for t in [
+            (
+                Metric {
+                    key: "".into(),
+                    name: "".into(),
+                    unit: "".into(),
+                    fields: vec![],
+                },
+                false,
+            ),
+            (
+                Metric {
+                    key: "key".into(),
+                    name: "".into(),
+                    unit: "".into(),
+                    fields: vec![],
+                },
+                false,
+            ),
+            (
+                Metric {
+                    key: "key".into(),
+                    name: "name".into(),
+                    unit: "".into(),
+                    fields: vec![],
+                },
+                false,
+            ),
+            (
+                Metric {
+                    key: "key".into(),
+                    name: "name".into(),
+                    unit: "".into(),
+                    fields: vec!["sys".into(), "".into()],
+                },
+                false,
+            ),
+            (
+                Metric {
+                    key: "key".into(),
+                    name: "name".into(),
+                    unit: "".into(),
+                    fields: vec!["sys".into(), "dia".into()],
+                },
+                true,
+            ),
+        ] {
+            assert_eq!(t.0.validate(), t.1);
+        }
+    }
+
+    #[test]
+    fn test_validate_metric_value() {
+        assert!(!MetricValue {
+            metric_key: "".into(),
+            values: vec![],
+            timestamp: Timestamp::now(),
+        }
+        .validate());
+        assert!(!MetricValue {
+            metric_key: "key".into(),
+            values: vec![],
+            timestamp: Timestamp::now(),
+        }
+        .validate());
+        assert!(MetricValue {
+            metric_key: "key".into(),
+            values: vec![120.0, 80.0],
+            timestamp: Timestamp::now(),
+        }
+        .validate());
+    }
+
+    #[test]
+    fn test_schedule_kind() {
+        for k in [
+            ScheduleKind::WeightReminder,
+            ScheduleKind::JournalReminder,
+            ScheduleKind::DaySummary,
+            ScheduleKind::WeeklyDigest,
+            ScheduleKind::MonthlyDigest,
+        ] {
+            assert_eq!(k, ScheduleKind::new(u8::from(k)).unwrap());
+            assert_eq!(k, ScheduleKind::new_str(k.key()).unwrap());
+        }
+        assert!(ScheduleKind::new(100).is_err());
+        assert!(ScheduleKind::new_str("x").is_err());
+    }
+
+    #[test]
+    fn test_change_action() {
+        for v in 0..=20 {
+            assert_eq!(v, u8::from(ChangeAction::new(v).unwrap()));
+        }
+        assert!(ChangeAction::new(21).is_err());
+    }
+
+    #[test]
+    fn test_audit_action() {
+        for v in 0..=3 {
+            assert_eq!(v, u8::from(AuditAction::new(v).unwrap()));
+        }
+        assert!(AuditAction::new(4).is_err());
+        assert_eq!(
+            AuditAction::Delete,
+            AuditAction::from(ChangeAction::DeleteJournalMeal)
+        );
+        assert_eq!("journal", ChangeAction::DeleteJournalMeal.entity());
+    }
+
+    #[test]
+    fn test_validate_schedule() {
+        for (hour, minute, tz, res) in [
+            (24, 0, "UTC", false),
+            (23, 60, "UTC", false),
+            (8, 30, "", false),
+            (8, 30, "UTC", true),
+        ] {
+            assert_eq!(
+                res,
+                Schedule {
+                    kind: ScheduleKind::WeightReminder,
+                    hour,
+                    minute,
+                    tz: tz.into(),
+                    last_run: None,
+                }
+                .validate()
+            );
+        }
+    }
 }
@@ -0,0 +1,89 @@
+// Synthetic data shared by storage benchmarks
+#![allow(dead_code)]
+
+use std::time::Duration;
+
+use anyhow::Result;
+use model::{Food, Journal, Meal, Weight};
+use storage::Storage;
+use types::timestamp::Timestamp;
+
+pub const USER_ID: i64 = 1;
+pub const DAYS: i64 = 365;
+pub const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+const FOOD_CNT: usize = 200;
+const MEALS: [Meal; 4] = [
+    Meal::Breakfast,
+    Meal::Dinner,
+    Meal::SecondSnack,
+    Meal::Supper,
+];
+const ITEMS_PER_MEAL: usize = 3;
+
+// Year of journal (4 meals with 3 items a day) and daily weights
+pub fn seed_year(stg: &dyn Storage) -> Result<()> {
+    for i in 0..FOOD_CNT {
+        stg.set_food(
+            1,
+            &Food {
+                key: format!("food_{i}"),
+                name: format!("Food {i}"),
+                brand: "brand".into(),
+                cal100: 50.0 + i as f64,
+                prot100: 1.0,
+                fat100: 2.0,
+                carb100: 3.0,
+                comment: String::new(),
+            },
+        )?;
+    }
+
+    let mut rnd = Lcg(42);
+    for day in 0..DAYS {
+        let ts = Timestamp::from_unix_millis(day * DAY_MS).unwrap();
+        stg.set_weight(
+            USER_ID,
+            &Weight {
+                timestamp: ts.clone(),
+                value: 80.0 + (rnd.next() % 50) as f64 / 10.0,
+            },
+        )?;
+
+        for meal in MEALS {
+            for _ in 0..ITEMS_PER_MEAL {
+                stg.set_journal(
+                    USER_ID,
+                    &Journal {
+                        timestamp: ts.clone(),
+                        meal,
+                        food_key: format!("food_{}", rnd.next() as usize % FOOD_CNT),
+                        food_weight: 50.0 + (rnd.next() % 200) as f64,
+                    },
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Deterministic pseudo random numbers, so runs are comparable
+pub struct Lcg(pub u64);
+
+impl Lcg {
+    pub fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+}
+
+pub fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    sorted[((sorted.len() - 1) as f64 * p).round() as usize]
+}
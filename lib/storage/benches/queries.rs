@@ -0,0 +1,68 @@
+// Time of heavy queries on a year of data.
+//
+// cargo bench -p storage --bench queries
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use common::{percentile, seed_year, DAYS, DAY_MS, USER_ID};
+use storage::{storage_sqlite::StorageSqlite, Storage};
+use tempfile::NamedTempFile;
+use types::timestamp::Timestamp;
+
+const ITERATIONS: usize = 50;
+
+fn main() -> Result<()> {
+    let db_file = NamedTempFile::new()?;
+    let stg = StorageSqlite::new(db_file.path())?;
+    seed_year(&stg)?;
+
+    let ts = |day: i64| Timestamp::from_unix_millis(day * DAY_MS).unwrap();
+
+    println!("{ITERATIONS} iterations on {DAYS} days of journal");
+    println!(
+        "{:<24} {:>10} {:>10} {:>10}",
+        "query", "rows", "p50, ms", "p99, ms"
+    );
+
+    bench("journal report, day", || {
+        Ok(stg.get_journal_report(USER_ID, ts(100), ts(101))?.len())
+    })?;
+    bench("journal report, month", || {
+        Ok(stg.get_journal_report(USER_ID, ts(100), ts(130))?.len())
+    })?;
+    bench("journal report, year", || {
+        Ok(stg.get_journal_report(USER_ID, ts(0), ts(DAYS))?.len())
+    })?;
+    bench("backup", || {
+        let b = stg.backup(USER_ID)?;
+        Ok(b.journal.len() + b.weight.len() + b.food.len())
+    })?;
+
+    Ok(())
+}
+
+fn bench(name: &str, f: impl Fn() -> Result<usize>) -> Result<()> {
+    // Warm up caches
+    let rows = f()?;
+
+    let mut times: Vec<Duration> = Vec::with_capacity(ITERATIONS);
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        f()?;
+        times.push(start.elapsed());
+    }
+    times.sort();
+
+    println!(
+        "{:<24} {:>10} {:>10.2} {:>10.2}",
+        name,
+        rows,
+        percentile(&times, 0.5).as_secs_f64() * 1000.0,
+        percentile(&times, 0.99).as_secs_f64() * 1000.0,
+    );
+
+    Ok(())
+}
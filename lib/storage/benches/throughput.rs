@@ -0,0 +1,199 @@
+// Throughput of storage under concurrent requests, like bot handlers
+// of several users running at the same time.
+//
+// cargo bench -p storage --bench throughput
+
+mod common;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use common::{percentile, seed_year, Lcg, DAYS, DAY_MS, USER_ID};
+use model::Weight;
+use storage::{
+    storage_async::StorageAsync,
+    storage_sqlite::{StorageSqlite, READ_POOL_SIZE},
+    Storage,
+};
+use tempfile::NamedTempFile;
+use types::timestamp::Timestamp;
+
+const CLIENTS: u64 = 16;
+const OPS_PER_CLIENT: usize = 100;
+const WORKER_THREADS: usize = 4;
+const TICK: Duration = Duration::from_millis(1);
+
+// How handlers reach storage
+#[derive(Clone)]
+enum Target {
+    // Storage called right on executor threads
+    Direct(Arc<StorageSqlite>),
+    // Storage called through StorageAsync
+    Blocking(StorageAsync),
+}
+
+impl Target {
+    fn new(stg: StorageSqlite, blocking: bool) -> Self {
+        if blocking {
+            Target::Blocking(StorageAsync::new(Box::new(stg)))
+        } else {
+            Target::Direct(Arc::new(stg))
+        }
+    }
+
+    async fn run(&self, op: Op) -> Result<()> {
+        match self {
+            Target::Direct(stg) => op.run(stg.as_ref()),
+            Target::Blocking(stg) => stg.call(move |s| op.run(s)).await,
+        }
+    }
+}
+
+struct Stats {
+    elapsed: Duration,
+    latencies: Vec<Duration>,
+    // Max delay of 1ms timer, shows how long executor threads were blocked
+    max_tick_lag: Duration,
+}
+
+fn main() -> Result<()> {
+    let db_file = NamedTempFile::new()?;
+    seed_year(&StorageSqlite::new(db_file.path())?)?;
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(WORKER_THREADS)
+        .enable_all()
+        .build()?;
+
+    println!(
+        "{CLIENTS} clients x {OPS_PER_CLIENT} ops on {WORKER_THREADS} worker threads: \
+         day report 60%, week report 25%, set weight 10%, backup 5%"
+    );
+    println!(
+        "{:<10} {:>8} {:>10} {:>10} {:>10} {:>14}",
+        "mode", "readers", "ops/s", "p50, ms", "p99, ms", "tick lag, ms"
+    );
+
+    for (blocking, readers) in [
+        (false, 0),
+        (true, 0),
+        (true, 1),
+        (true, READ_POOL_SIZE),
+        (true, 2 * READ_POOL_SIZE),
+    ] {
+        let stg = StorageSqlite::with_read_pool(db_file.path(), readers)?;
+        let mut stats = runtime.block_on(run_clients(Target::new(stg, blocking)))?;
+        stats.latencies.sort();
+
+        println!(
+            "{:<10} {:>8} {:>10.0} {:>10.2} {:>10.2} {:>14.2}",
+            if blocking { "blocking" } else { "direct" },
+            readers,
+            stats.latencies.len() as f64 / stats.elapsed.as_secs_f64(),
+            ms(percentile(&stats.latencies, 0.5)),
+            ms(percentile(&stats.latencies, 0.99)),
+            ms(stats.max_tick_lag),
+        );
+    }
+
+    Ok(())
+}
+
+async fn run_clients(target: Target) -> Result<Stats> {
+    let max_lag_us = Arc::new(AtomicU64::new(0));
+    let ticker = tokio::spawn({
+        let max_lag_us = max_lag_us.clone();
+        async move {
+            loop {
+                let start = Instant::now();
+                tokio::time::sleep(TICK).await;
+                let lag = start.elapsed().saturating_sub(TICK);
+                max_lag_us.fetch_max(lag.as_micros() as u64, Ordering::Relaxed);
+            }
+        }
+    });
+
+    let start = Instant::now();
+    let mut clients = Vec::new();
+    for client in 0..CLIENTS {
+        let target = target.clone();
+        clients.push(tokio::spawn(async move {
+            let mut rnd = Lcg(client);
+            let mut latencies = Vec::with_capacity(OPS_PER_CLIENT);
+            for _ in 0..OPS_PER_CLIENT {
+                let op = Op::random(&mut rnd);
+                let op_start = Instant::now();
+                target.run(op).await?;
+                latencies.push(op_start.elapsed());
+                // Give executor to other tasks between requests, as handlers do
+                tokio::task::yield_now().await;
+            }
+            anyhow::Ok(latencies)
+        }));
+    }
+
+    let mut latencies = Vec::new();
+    for client in clients {
+        latencies.extend(client.await??);
+    }
+    let elapsed = start.elapsed();
+    ticker.abort();
+
+    Ok(Stats {
+        elapsed,
+        latencies,
+        max_tick_lag: Duration::from_micros(max_lag_us.load(Ordering::Relaxed)),
+    })
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    DayReport(i64),
+    WeekReport(i64),
+    SetWeight(i64),
+    Backup,
+}
+
+impl Op {
+    fn random(rnd: &mut Lcg) -> Self {
+        let day = (rnd.next() % DAYS as u64) as i64;
+        match rnd.next() % 100 {
+            0..60 => Op::DayReport(day),
+            60..85 => Op::WeekReport(day),
+            85..95 => Op::SetWeight(day),
+            _ => Op::Backup,
+        }
+    }
+
+    fn run(self, stg: &dyn Storage) -> Result<()> {
+        let ts = |day: i64| Timestamp::from_unix_millis(day * DAY_MS).unwrap();
+        match self {
+            Op::DayReport(day) => {
+                stg.get_journal_report(USER_ID, ts(day), ts(day + 1))?;
+            }
+            Op::WeekReport(day) => {
+                stg.get_journal_report(USER_ID, ts(day), ts(day + 7))?;
+            }
+            Op::SetWeight(day) => {
+                stg.set_weight(
+                    USER_ID,
+                    &Weight {
+                        timestamp: ts(day),
+                        value: 80.0,
+                    },
+                )?;
+            }
+            Op::Backup => {
+                stg.backup(USER_ID)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
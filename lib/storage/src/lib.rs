@@ -1,20 +1,34 @@
-use anyhow::Result;
 use model::{
-    backup::Backup, Bundle, Food, Journal, JournalReport, Meal, Sport, SportActivity,
+    backup::Backup, AuditEntry, Bundle, Change, Food, FoodMealUsage, FoodUsage, Journal,
+    JournalReport, Meal, Metric, MetricValue, Schedule, ScheduleKind, Sport, SportActivity,
     SportActivityReport, UserSettings, Weight,
 };
 use thiserror::Error;
 use types::timestamp::Timestamp;
 
+pub mod storage_async;
+pub mod storage_memory;
 pub mod storage_sqlite;
 
+#[cfg(test)]
+mod test;
+
+pub type Result<T> = std::result::Result<T, StorageError>;
+
+// Changes kept in change log of each user, older ones can't be undone
+pub const CHANGE_LOG_SIZE: usize = 100;
+
+// Audit log user of changes made by bot itself, e.g. restore of backup on start
+pub const SYSTEM_USER_ID: i64 = 0;
+
 pub trait Storage: Send + Sync {
     // Food
     fn get_food(&self, key: &str) -> Result<Food>;
     fn get_food_list(&self) -> Result<Vec<Food>>;
-    fn set_food(&self, food: &Food) -> Result<()>;
+    fn set_food(&self, user_id: i64, food: &Food) -> Result<()>;
     fn find_food(&self, pattern: &str) -> Result<Vec<Food>>;
-    fn delete_food(&self, key: &str) -> Result<()>;
+    fn get_unused_food_list(&self) -> Result<Vec<Food>>;
+    fn delete_food(&self, user_id: i64, key: &str) -> Result<()>;
 
     // Bundle
     fn get_bundle(&self, user_id: i64, key: &str) -> Result<Bundle>;
@@ -24,6 +38,8 @@ pub trait Storage: Send + Sync {
 
     // Weight
     fn get_weight_list(&self, user_id: i64, from: Timestamp, to: Timestamp) -> Result<Vec<Weight>>;
+    // Latest weight not after given timestamp
+    fn get_last_weight(&self, user_id: i64, to: Timestamp) -> Result<Weight>;
     fn set_weight(&self, user_id: i64, weight: &Weight) -> Result<()>;
     fn delete_weight(&self, user_id: i64, timestamp: Timestamp) -> Result<()>;
 
@@ -57,6 +73,18 @@ pub trait Storage: Send + Sync {
         from: Timestamp,
         to: Timestamp,
     ) -> Result<f64>;
+    fn get_journal_food_usage(
+        &self,
+        user_id: i64,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<Vec<FoodUsage>>;
+    fn get_journal_food_meal_usage(
+        &self,
+        user_id: i64,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<Vec<FoodMealUsage>>;
 
     // UserSettings
     fn get_user_settings(&self, user_id: i64) -> Result<UserSettings>;
@@ -65,8 +93,8 @@ pub trait Storage: Send + Sync {
     // Sport
     fn get_sport(&self, key: &str) -> Result<Sport>;
     fn get_sport_list(&self) -> Result<Vec<Sport>>;
-    fn set_sport(&self, sport: &Sport) -> Result<()>;
-    fn delete_sport(&self, key: &str) -> Result<()>;
+    fn set_sport(&self, user_id: i64, sport: &Sport) -> Result<()>;
+    fn delete_sport(&self, user_id: i64, key: &str) -> Result<()>;
 
     // SportActivity
     fn set_sport_activity(&self, user_id: i64, act: &SportActivity) -> Result<()>;
@@ -83,19 +111,75 @@ pub trait Storage: Send + Sync {
         to: Timestamp,
     ) -> Result<Vec<SportActivityReport>>;
 
+    // Metric
+    fn get_metric(&self, user_id: i64, key: &str) -> Result<Metric>;
+    fn get_metric_list(&self, user_id: i64) -> Result<Vec<Metric>>;
+    fn set_metric(&self, user_id: i64, metric: &Metric) -> Result<()>;
+    fn delete_metric(&self, user_id: i64, key: &str) -> Result<()>;
+
+    // MetricValue
+    fn set_metric_value(&self, user_id: i64, val: &MetricValue) -> Result<()>;
+    fn delete_metric_value(
+        &self,
+        user_id: i64,
+        timestamp: Timestamp,
+        metric_key: &str,
+    ) -> Result<()>;
+    fn get_metric_value_list(
+        &self,
+        user_id: i64,
+        metric_key: &str,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<Vec<MetricValue>>;
+
+    // Schedule
+    fn get_schedule_list(&self, user_id: i64) -> Result<Vec<Schedule>>;
+    fn set_schedule(&self, user_id: i64, schedule: &Schedule) -> Result<()>;
+    fn set_schedule_last_run(
+        &self,
+        user_id: i64,
+        schedule: &Schedule,
+        last_run: Timestamp,
+    ) -> Result<()>;
+    fn delete_schedule(&self, user_id: i64, kind: ScheduleKind, hour: u8, minute: u8)
+        -> Result<()>;
+
+    // Change log, newest changes first
+    fn get_change_list(&self, user_id: i64, limit: usize) -> Result<Vec<Change>>;
+    // Reverts last changes of user in single transaction, returns reverted changes
+    fn undo(&self, user_id: i64, count: usize) -> Result<Vec<Change>>;
+
+    // Audit log of all users, oldest entries first
+    fn get_audit_list(&self, from: Timestamp, to: Timestamp) -> Result<Vec<AuditEntry>>;
+
     // Backup/Restore
     fn backup(&self, user_id: i64) -> Result<Backup>;
+    // Restore is recorded to audit log as made by system user
     fn restore(&self, backup: &Backup) -> Result<()>;
+}
 
-    // Error
-    fn is_storage_error(&self, stg_err: StorageError, err: &anyhow::Error) -> bool;
+// New value of restore audit entry, count of restored rows of each table
+fn restore_audit_value(backup: &Backup) -> String {
+    serde_json::json!({
+        "timestamp": backup.timestamp,
+        "weight": backup.weight.len(),
+        "food": backup.food.len(),
+        "user_settings": backup.user_settings.len(),
+        "bundle": backup.bundle.len(),
+        "journal": backup.journal.len(),
+        "sport": backup.sport.len(),
+        "sport_activity": backup.sport_activity.len(),
+        "metric": backup.metric.len(),
+        "metric_value": backup.metric_value.len(),
+        "schedule": backup.schedule.len(),
+        "audit_log": backup.audit_log.len(),
+    })
+    .to_string()
 }
 
-#[derive(Error, Debug, PartialEq, Default)]
+#[derive(Error, Debug)]
 pub enum StorageError {
-    #[error("unknown")]
-    #[default]
-    Unknown,
     #[error("empty result")]
     EmptyResult,
     // Weight
@@ -104,17 +188,17 @@ pub enum StorageError {
     // Food
     #[error("food invalid")]
     FoodInvalid,
-    #[error("food is used")]
-    FoodIsUsed,
-    #[error("food not found")]
-    FoodNotFound,
+    #[error("food {0} is used")]
+    FoodIsUsed(String),
+    #[error("food {0} not found")]
+    FoodNotFound(String),
     // Sport
     #[error("sport invalid")]
     SportInvalid,
-    #[error("sport not found")]
-    SportNotFound,
-    #[error("sport is used in activity")]
-    SportIsUsedViolation,
+    #[error("sport {0} not found")]
+    SportNotFound(String),
+    #[error("sport {0} is used in activity")]
+    SportIsUsed(String),
     // Sport activity
     #[error("sport activity invalid")]
     SportActivityInvalid,
@@ -126,17 +210,36 @@ pub enum StorageError {
     // Bundle
     #[error("bundle invalid")]
     BundleInvalid,
-    #[error("dependent food not found")]
-    BundleDepFoodNotFound,
-    #[error("dependent bundle not found")]
-    BundleDepBundleNotFound,
-    #[error("dependent recursive bundle not allowed")]
-    BundleDepRecursive,
-    #[error("bundle is used")]
-    BundleIsUsed,
-    #[error("bundle not found")]
-    BundleNotFound,
+    #[error("dependent food {0} not found")]
+    BundleDepFoodNotFound(String),
+    #[error("dependent bundle {0} not found")]
+    BundleDepBundleNotFound(String),
+    #[error("dependent recursive bundle {0} not allowed")]
+    BundleDepRecursive(String),
+    #[error("bundle {key} is used in bundle {used_in}")]
+    BundleIsUsed { key: String, used_in: String },
+    #[error("bundle {0} not found")]
+    BundleNotFound(String),
     // Journal
     #[error("journal invalid")]
     JournalInvalid,
+    // Metric
+    #[error("metric invalid")]
+    MetricInvalid,
+    #[error("metric {0} not found")]
+    MetricNotFound(String),
+    #[error("metric {0} is used in values")]
+    MetricIsUsed(String),
+    // Metric value
+    #[error("metric value invalid")]
+    MetricValueInvalid,
+    // Schedule
+    #[error("schedule invalid")]
+    ScheduleInvalid,
+    // Change log
+    #[error("undo conflicts with later changes")]
+    UndoConflict,
+    // Database or data conversion failure, with context of the failed operation
+    #[error("internal: {0:#}")]
+    Internal(#[from] anyhow::Error),
 }
@@ -0,0 +1,31 @@
+use std::sync::Arc;
+
+use crate::Storage;
+
+// Storage for async code: each call runs on blocking thread pool,
+// so slow queries don't hold async executor threads
+#[derive(Clone)]
+pub struct StorageAsync {
+    stg: Arc<Box<dyn Storage>>,
+}
+
+impl StorageAsync {
+    pub fn new(stg: Box<dyn Storage>) -> Self {
+        Self { stg: Arc::new(stg) }
+    }
+
+    // Closure gets storage and may do several calls, error of storage task
+    // itself (panic or runtime shutdown) is returned as internal error
+    pub async fn call<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&dyn Storage) -> Result<T, E> + Send + 'static,
+        T: Send + 'static,
+        E: From<anyhow::Error> + Send + 'static,
+    {
+        let stg = self.stg.clone();
+        match tokio::task::spawn_blocking(move || f(stg.as_ref().as_ref())).await {
+            Ok(res) => res,
+            Err(err) => Err(anyhow::Error::new(err).context("storage task").into()),
+        }
+    }
+}
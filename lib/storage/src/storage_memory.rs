@@ -0,0 +1,2001 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Mutex;
+
+use crate::{restore_audit_value, Result, Storage, StorageError, CHANGE_LOG_SIZE, SYSTEM_USER_ID};
+use anyhow::Context;
+use model::{
+    backup::{
+        AuditLogBackup, Backup, BundleBackup, FoodBackup, JournalBackup, MetricBackup,
+        MetricValueBackup, ScheduleBackup, SportActivityBackup, SportBackup, UserSettingsBackup,
+        WeightBackup,
+    },
+    AuditAction, AuditEntry, Bundle, Change, ChangeAction, Food, FoodMealUsage, FoodUsage, Journal,
+    JournalReport, Meal, Metric, MetricValue, Schedule, ScheduleKind, Sex, Sport, SportActivity,
+    SportActivityReport, UserSettings, Weight,
+};
+use serde_json::{json, Map, Value as JsonValue};
+use types::timestamp::Timestamp;
+
+// Storage without database, data lives only as long as storage.
+// Tables are keyed by the same primary keys as in SQLite storage
+// and keep JSON columns as text, so both storages behave the same
+pub struct StorageMemory {
+    tables: Mutex<Tables>,
+}
+
+#[derive(Default, Clone)]
+struct Tables {
+    // key
+    food: BTreeMap<String, Food>,
+    // (user_id, key)
+    bundle: BTreeSet<(i64, String)>,
+    // (user_id, bundle_key, item_key) -> food weight, None for child bundle
+    bundle_item: BTreeMap<(i64, String, String), Option<f64>>,
+    // (user_id, timestamp) -> value
+    weight: BTreeMap<(i64, i64), f64>,
+    // (user_id, timestamp, meal, food_key) -> food_weight
+    journal: BTreeMap<(i64, i64, u8, String), f64>,
+    // user_id
+    user_settings: BTreeMap<i64, UserSettingsRow>,
+    // key
+    sport: BTreeMap<String, Sport>,
+    // (user_id, timestamp, sport_key) -> sets
+    sport_activity: BTreeMap<(i64, i64, String), String>,
+    // (user_id, key)
+    metric: BTreeMap<(i64, String), MetricRow>,
+    // (user_id, timestamp, metric_key) -> values
+    metric_value: BTreeMap<(i64, i64, String), String>,
+    // (user_id, kind, hour, minute)
+    schedule: BTreeMap<(i64, u8, u8, u8), ScheduleRow>,
+    // user_id -> changes, oldest first
+    change_log: BTreeMap<i64, Vec<ChangeRow>>,
+    // id -> entry
+    audit_log: BTreeMap<i64, AuditEntry>,
+}
+
+#[derive(Clone, PartialEq)]
+struct UserSettingsRow {
+    cal_limit: f64,
+    sex: Option<u8>,
+    height: Option<f64>,
+    birth_date: Option<i64>,
+}
+
+#[derive(Clone, PartialEq)]
+struct MetricRow {
+    name: String,
+    unit: String,
+    fields: String,
+}
+
+#[derive(Clone, PartialEq)]
+struct ScheduleRow {
+    tz: String,
+    last_run: Option<i64>,
+}
+
+#[derive(Clone)]
+struct ChangeRow {
+    change: Change,
+    prior: Vec<Prior>,
+    // State right after change, undo is refused if rows changed since
+    after: Vec<Prior>,
+}
+
+// Rows affected by change, given by key like in tables, with their state.
+// Journal rows are kept for whole meal, bundle items for whole bundle.
+#[derive(Clone, PartialEq)]
+enum Prior {
+    Food(String, Option<Food>),
+    Bundle((i64, String), bool),
+    BundleItems((i64, String), Vec<(String, Option<f64>)>),
+    Weight((i64, i64), Option<f64>),
+    UserSettings(i64, Option<UserSettingsRow>),
+    Journal((i64, i64, u8), Vec<(String, f64)>),
+    Sport(String, Option<Sport>),
+    SportActivity((i64, i64, String), Option<String>),
+    Metric((i64, String), Option<MetricRow>),
+    MetricValue((i64, i64, String), Option<String>),
+    // Timezone only, last run is kept as is like in SQLite storage
+    Schedule((i64, u8, u8, u8), Option<String>),
+}
+
+impl Prior {
+    // Same rows with their current state
+    fn capture(&self, t: &Tables) -> Prior {
+        match self {
+            Prior::Food(k, _) => Prior::Food(k.clone(), t.food.get(k).cloned()),
+            Prior::Bundle(k, _) => Prior::Bundle(k.clone(), t.bundle.contains(k)),
+            Prior::BundleItems(k, _) => Prior::BundleItems(
+                k.clone(),
+                t.bundle_items(&k.0, &k.1)
+                    .map(|(item_key, weight)| (item_key.clone(), *weight))
+                    .collect(),
+            ),
+            Prior::Weight(k, _) => Prior::Weight(*k, t.weight.get(k).copied()),
+            Prior::UserSettings(k, _) => Prior::UserSettings(*k, t.user_settings.get(k).cloned()),
+            Prior::Journal(k, _) => Prior::Journal(
+                *k,
+                t.journal
+                    .iter()
+                    .filter(|((u, ts, m, _), _)| (*u, *ts, *m) == *k)
+                    .map(|((_, _, _, food_key), weight)| (food_key.clone(), *weight))
+                    .collect(),
+            ),
+            Prior::Sport(k, _) => Prior::Sport(k.clone(), t.sport.get(k).cloned()),
+            Prior::SportActivity(k, _) => {
+                Prior::SportActivity(k.clone(), t.sport_activity.get(k).cloned())
+            }
+            Prior::Metric(k, _) => Prior::Metric(k.clone(), t.metric.get(k).cloned()),
+            Prior::MetricValue(k, _) => {
+                Prior::MetricValue(k.clone(), t.metric_value.get(k).cloned())
+            }
+            Prior::Schedule(k, _) => {
+                Prior::Schedule(*k, t.schedule.get(k).map(|row| row.tz.clone()))
+            }
+        }
+    }
+
+    // Table of rows and the rows as JSON objects with columns of SQLite storage
+    fn rows(&self) -> (&'static str, Vec<JsonValue>) {
+        match self {
+            Prior::Food(_, v) => (
+                "food",
+                v.iter()
+                    .map(|f| {
+                        json!({
+                            "key": f.key,
+                            "name": f.name,
+                            "brand": f.brand,
+                            "cal100": f.cal100,
+                            "prot100": f.prot100,
+                            "fat100": f.fat100,
+                            "carb100": f.carb100,
+                            "comment": f.comment,
+                        })
+                    })
+                    .collect(),
+            ),
+            Prior::Bundle((user_id, key), exists) => (
+                "bundle",
+                exists
+                    .then(|| json!({"user_id": user_id, "key": key}))
+                    .into_iter()
+                    .collect(),
+            ),
+            Prior::BundleItems((user_id, bundle_key), rows) => (
+                "bundle_item",
+                rows.iter()
+                    .map(|(item_key, weight)| {
+                        json!({
+                            "user_id": user_id,
+                            "bundle_key": bundle_key,
+                            "food_key": weight.map(|_| item_key),
+                            "child_key": weight.is_none().then_some(item_key),
+                            "weight": weight,
+                        })
+                    })
+                    .collect(),
+            ),
+            Prior::Weight((user_id, timestamp), v) => (
+                "weight",
+                v.iter()
+                    .map(
+                        |value| json!({"user_id": user_id, "timestamp": timestamp, "value": value}),
+                    )
+                    .collect(),
+            ),
+            Prior::UserSettings(user_id, v) => (
+                "user_settings",
+                v.iter()
+                    .map(|r| {
+                        json!({
+                            "user_id": user_id,
+                            "cal_limit": r.cal_limit,
+                            "sex": r.sex,
+                            "height": r.height,
+                            "birth_date": r.birth_date,
+                        })
+                    })
+                    .collect(),
+            ),
+            Prior::Journal((user_id, timestamp, meal), rows) => (
+                "journal",
+                rows.iter()
+                    .map(|(food_key, food_weight)| {
+                        json!({
+                            "user_id": user_id,
+                            "timestamp": timestamp,
+                            "meal": meal,
+                            "foodkey": food_key,
+                            "foodweight": food_weight,
+                        })
+                    })
+                    .collect(),
+            ),
+            Prior::Sport(_, v) => (
+                "sport",
+                v.iter()
+                    .map(|s| json!({"key": s.key, "name": s.name, "comment": s.comment}))
+                    .collect(),
+            ),
+            Prior::SportActivity((user_id, timestamp, sport_key), v) => (
+                "sport_activity",
+                v.iter()
+                    .map(|sets| {
+                        json!({
+                            "user_id": user_id,
+                            "timestamp": timestamp,
+                            "sport_key": sport_key,
+                            "sets": sets,
+                        })
+                    })
+                    .collect(),
+            ),
+            Prior::Metric((user_id, key), v) => (
+                "metric",
+                v.iter()
+                    .map(|r| {
+                        json!({
+                            "user_id": user_id,
+                            "key": key,
+                            "name": r.name,
+                            "unit": r.unit,
+                            "fields": r.fields,
+                        })
+                    })
+                    .collect(),
+            ),
+            Prior::MetricValue((user_id, timestamp, metric_key), v) => (
+                "metric_value",
+                v.iter()
+                    .map(|vals| {
+                        json!({
+                            "user_id": user_id,
+                            "timestamp": timestamp,
+                            "metric_key": metric_key,
+                            "vals": vals,
+                        })
+                    })
+                    .collect(),
+            ),
+            Prior::Schedule((user_id, kind, hour, minute), v) => (
+                "schedule",
+                v.iter()
+                    .map(|tz| {
+                        json!({
+                            "user_id": user_id,
+                            "kind": kind,
+                            "hour": hour,
+                            "minute": minute,
+                            "tz": tz,
+                        })
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    fn restore(self, t: &mut Tables) {
+        match self {
+            Prior::Food(k, v) => put(&mut t.food, k, v),
+            Prior::Bundle(k, exists) => {
+                if exists {
+                    t.bundle.insert(k);
+                } else {
+                    t.bundle.remove(&k);
+                }
+            }
+            Prior::BundleItems((user_id, bundle_key), rows) => {
+                t.set_bundle_items(user_id, &bundle_key, rows);
+            }
+            Prior::Weight(k, v) => put(&mut t.weight, k, v),
+            Prior::UserSettings(k, v) => put(&mut t.user_settings, k, v),
+            Prior::Journal(k, rows) => {
+                t.journal.retain(|(u, ts, m, _), _| (*u, *ts, *m) != k);
+                for (food_key, weight) in rows {
+                    t.journal.insert((k.0, k.1, k.2, food_key), weight);
+                }
+            }
+            Prior::Sport(k, v) => put(&mut t.sport, k, v),
+            Prior::SportActivity(k, v) => put(&mut t.sport_activity, k, v),
+            Prior::Metric(k, v) => put(&mut t.metric, k, v),
+            Prior::MetricValue(k, v) => put(&mut t.metric_value, k, v),
+            Prior::Schedule(k, None) => put(&mut t.schedule, k, None),
+            Prior::Schedule(k, Some(tz)) => {
+                t.schedule
+                    .entry(k)
+                    .and_modify(|row| row.tz = tz.clone())
+                    .or_insert(ScheduleRow { tz, last_run: None });
+            }
+        }
+    }
+}
+
+impl Tables {
+    // Items of bundle as (item key, food weight or None for child bundle)
+    fn bundle_items<'a>(
+        &'a self,
+        user_id: &i64,
+        bundle_key: &'a str,
+    ) -> impl Iterator<Item = (&'a String, &'a Option<f64>)> + 'a {
+        let user_id = *user_id;
+        self.bundle_item
+            .iter()
+            .filter(move |((u, b, _), _)| *u == user_id && b == bundle_key)
+            .map(|((_, _, item_key), weight)| (item_key, weight))
+    }
+
+    // Bundle data of model, child bundle has 0 weight
+    fn bundle_data(&self, user_id: i64, bundle_key: &str) -> HashMap<String, f64> {
+        self.bundle_items(&user_id, bundle_key)
+            .map(|(item_key, weight)| (item_key.clone(), weight.unwrap_or(0.0)))
+            .collect()
+    }
+
+    fn set_bundle_items(
+        &mut self,
+        user_id: i64,
+        bundle_key: &str,
+        items: impl IntoIterator<Item = (String, Option<f64>)>,
+    ) {
+        self.bundle_item
+            .retain(|(u, b, _), _| *u != user_id || b != bundle_key);
+        for (item_key, weight) in items {
+            self.bundle_item
+                .insert((user_id, bundle_key.into(), item_key), weight);
+        }
+    }
+}
+
+impl StorageMemory {
+    pub fn new() -> Self {
+        Self {
+            tables: Mutex::new(Tables::default()),
+        }
+    }
+
+    fn timestamp(v: i64) -> anyhow::Result<Timestamp> {
+        Timestamp::from_unix_millis(v).context("parse timestamp")
+    }
+
+    fn get_bundle_food_items(
+        tables: &Tables,
+        user_id: i64,
+        bndl_key: &str,
+    ) -> Result<HashMap<String, f64>> {
+        let mut bundles: Vec<String> = vec![bndl_key.into()];
+        let mut res = HashMap::new();
+        let mut i = 0;
+
+        while i < bundles.len() {
+            // Get next bundle
+            if !tables.bundle.contains(&(user_id, bundles[i].clone())) {
+                return Err(StorageError::BundleNotFound(bundles[i].clone()));
+            }
+
+            for (k, v) in tables.bundle_data(user_id, &bundles[i]) {
+                if v == 0.0 {
+                    // Add bundle next bundle
+                    bundles.push(k);
+                    continue;
+                }
+
+                // Check if food exists add to result map
+                if !tables.food.contains_key(&k) {
+                    return Err(StorageError::FoodNotFound(k));
+                }
+
+                res.insert(k, v);
+            }
+
+            i += 1;
+        }
+
+        Ok(res)
+    }
+
+    // Applies mutation with record of prior and resulting state of affected rows to change
+    // log of user,
+    // like in SQLite storage mutation that changed nothing is not recorded
+    fn execute_change<F>(
+        &self,
+        user_id: i64,
+        action: ChangeAction,
+        key: &str,
+        affected: &[Prior],
+        f: F,
+    ) -> Result<()>
+    where
+        F: FnOnce(&mut Tables) -> Result<()>,
+    {
+        let mut tables = self.tables.lock().unwrap();
+
+        let prior: Vec<Prior> = affected.iter().map(|p| p.capture(&tables)).collect();
+        f(&mut tables)?;
+
+        let current: Vec<Prior> = affected.iter().map(|p| p.capture(&tables)).collect();
+        if current != prior {
+            Self::insert_audit(
+                &mut tables,
+                user_id,
+                action.entity(),
+                key,
+                AuditAction::from(action),
+                &prior,
+                &current,
+            );
+
+            let log = tables.change_log.entry(user_id).or_default();
+            log.push(ChangeRow {
+                change: Change {
+                    timestamp: Timestamp::now(),
+                    action,
+                    key: key.into(),
+                },
+                prior,
+                after: current,
+            });
+            if log.len() > CHANGE_LOG_SIZE {
+                log.remove(0);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn insert_audit(
+        t: &mut Tables,
+        user_id: i64,
+        entity: &str,
+        key: &str,
+        action: AuditAction,
+        old: &[Prior],
+        new: &[Prior],
+    ) {
+        Self::push_audit(
+            t,
+            AuditEntry {
+                timestamp: Timestamp::now(),
+                user_id,
+                entity: entity.into(),
+                key: key.into(),
+                action,
+                old_value: Self::rows_json(old),
+                new_value: Self::rows_json(new),
+            },
+        );
+    }
+
+    fn push_audit(t: &mut Tables, entry: AuditEntry) {
+        let id = t.audit_log.last_key_value().map_or(1, |(id, _)| id + 1);
+        t.audit_log.insert(id, entry);
+    }
+
+    // Same format as in SQLite storage: rows grouped by table, None if there are no rows
+    fn rows_json(prior: &[Prior]) -> Option<String> {
+        let mut res = Map::new();
+        for (table, rows) in prior.iter().map(Prior::rows) {
+            if rows.is_empty() {
+                continue;
+            }
+            if let JsonValue::Array(v) = res
+                .entry(table)
+                .or_insert_with(|| JsonValue::Array(Vec::new()))
+            {
+                v.extend(rows);
+            }
+        }
+
+        (!res.is_empty()).then(|| JsonValue::Object(res).to_string())
+    }
+
+    // Same checks as foreign keys of SQLite storage
+    fn references_valid(t: &Tables) -> anyhow::Result<bool> {
+        let bundle_items_valid = t.bundle_item.iter().all(|((u, b, k), weight)| {
+            t.bundle.contains(&(*u, b.clone()))
+                && match weight {
+                    Some(_) => t.food.contains_key(k),
+                    None => t.bundle.contains(&(*u, k.clone())),
+                }
+        });
+
+        Ok(bundle_items_valid
+            && t.journal.keys().all(|(_, _, _, k)| t.food.contains_key(k))
+            && t.sport_activity
+                .keys()
+                .all(|(_, _, k)| t.sport.contains_key(k))
+            && t.metric_value
+                .keys()
+                .all(|(u, _, k)| t.metric.contains_key(&(*u, k.clone()))))
+    }
+
+    // Bundle row goes first, like in SQLite storage
+    fn bundle_rows(user_id: i64, key: &str) -> [Prior; 2] {
+        [
+            Prior::Bundle((user_id, key.into()), false),
+            Prior::BundleItems((user_id, key.into()), Vec::new()),
+        ]
+    }
+
+    fn schedule_key(kind: ScheduleKind, hour: u8, minute: u8) -> String {
+        format!("{} {hour:02}:{minute:02}", kind.key())
+    }
+
+    fn sort_food(mut food_list: Vec<Food>) -> Vec<Food> {
+        food_list.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.key.cmp(&b.key)));
+        food_list
+    }
+}
+
+impl Default for StorageMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Storage for StorageMemory {
+    //
+    // Food
+    //
+
+    fn get_food(&self, key: &str) -> Result<Food> {
+        let tables = self.tables.lock().unwrap();
+
+        tables
+            .food
+            .get(key)
+            .cloned()
+            .ok_or_else(|| StorageError::FoodNotFound(key.into()))
+    }
+
+    fn get_food_list(&self) -> Result<Vec<Food>> {
+        let tables = self.tables.lock().unwrap();
+
+        if tables.food.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        Ok(Self::sort_food(tables.food.values().cloned().collect()))
+    }
+
+    fn set_food(&self, user_id: i64, food: &Food) -> Result<()> {
+        if !food.validate() {
+            return Err(StorageError::FoodInvalid);
+        }
+
+        self.execute_change(
+            user_id,
+            ChangeAction::SetFood,
+            &food.key,
+            &[Prior::Food(food.key.clone(), None)],
+            |tables| {
+                tables.food.insert(food.key.clone(), food.clone());
+
+                Ok(())
+            },
+        )
+    }
+
+    fn find_food(&self, pattern: &str) -> Result<Vec<Food>> {
+        let tables = self.tables.lock().unwrap();
+
+        let pattern = pattern.to_uppercase();
+        let mut food_list: Vec<Food> = tables
+            .food
+            .values()
+            .filter(|f| {
+                [&f.key, &f.name, &f.brand, &f.comment]
+                    .iter()
+                    .any(|v| v.to_uppercase().contains(&pattern))
+            })
+            .cloned()
+            .collect();
+
+        if food_list.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        food_list.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(food_list)
+    }
+
+    fn get_unused_food_list(&self) -> Result<Vec<Food>> {
+        let tables = self.tables.lock().unwrap();
+
+        let mut used: Vec<String> = tables
+            .journal
+            .keys()
+            .map(|(_, _, _, food_key)| food_key.clone())
+            .collect();
+        for ((_, _, k), weight) in &tables.bundle_item {
+            if weight.is_some() {
+                used.push(k.clone());
+            }
+        }
+
+        let food_list: Vec<Food> = tables
+            .food
+            .values()
+            .filter(|f| !used.contains(&f.key))
+            .cloned()
+            .collect();
+
+        if food_list.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        Ok(Self::sort_food(food_list))
+    }
+
+    fn delete_food(&self, user_id: i64, key: &str) -> Result<()> {
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteFood,
+            key,
+            &[Prior::Food(key.into(), None)],
+            |tables| {
+                // Check that food not used in bundle
+                if tables
+                    .bundle_item
+                    .iter()
+                    .any(|((_, _, k), weight)| weight.is_some() && k == key)
+                {
+                    return Err(StorageError::FoodIsUsed(key.into()));
+                }
+
+                // Check that food not used in journal
+                if tables.journal.keys().any(|(_, _, _, k)| k == key) {
+                    return Err(StorageError::FoodIsUsed(key.into()));
+                }
+
+                tables.food.remove(key);
+
+                Ok(())
+            },
+        )
+    }
+
+    //
+    // Bundle
+    //
+
+    fn get_bundle(&self, user_id: i64, key: &str) -> Result<Bundle> {
+        let tables = self.tables.lock().unwrap();
+
+        if !tables.bundle.contains(&(user_id, key.into())) {
+            return Err(StorageError::BundleNotFound(key.into()));
+        }
+
+        Ok(Bundle {
+            key: key.into(),
+            data: tables.bundle_data(user_id, key),
+        })
+    }
+
+    fn get_bundle_list(&self, user_id: i64) -> Result<Vec<Bundle>> {
+        let tables = self.tables.lock().unwrap();
+
+        let mut res = Vec::new();
+        for (u, key) in &tables.bundle {
+            if *u != user_id {
+                continue;
+            }
+
+            res.push(Bundle {
+                key: key.clone(),
+                data: tables.bundle_data(user_id, key),
+            });
+        }
+
+        if res.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        Ok(res)
+    }
+
+    fn set_bundle(&self, user_id: i64, bndl: &Bundle) -> Result<()> {
+        if !bndl.validate() {
+            return Err(StorageError::BundleInvalid);
+        }
+
+        self.execute_change(
+            user_id,
+            ChangeAction::SetBundle,
+            &bndl.key,
+            &Self::bundle_rows(user_id, &bndl.key),
+            |tables| {
+                // Check bundle data
+                for (k, v) in &bndl.data {
+                    if *v == 0.0 {
+                        // Dependent bundle
+                        if *k == bndl.key {
+                            return Err(StorageError::BundleDepRecursive(k.clone()));
+                        }
+
+                        if !tables.bundle.contains(&(user_id, k.clone())) {
+                            return Err(StorageError::BundleDepBundleNotFound(k.clone()));
+                        }
+                    } else if !tables.food.contains_key(k) {
+                        // Dependent food
+                        return Err(StorageError::BundleDepFoodNotFound(k.clone()));
+                    }
+                }
+
+                // Set bundle
+                tables.bundle.insert((user_id, bndl.key.clone()));
+                tables.set_bundle_items(
+                    user_id,
+                    &bndl.key,
+                    bndl.data
+                        .iter()
+                        .map(|(k, v)| (k.clone(), (*v != 0.0).then_some(*v))),
+                );
+
+                Ok(())
+            },
+        )
+    }
+
+    fn delete_bundle(&self, user_id: i64, key: &str) -> Result<()> {
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteBundle,
+            key,
+            &Self::bundle_rows(user_id, key),
+            |tables| {
+                // Check that bundle not used in other bundles
+                for ((u, used_in, k), weight) in &tables.bundle_item {
+                    if *u == user_id && weight.is_none() && k == key {
+                        return Err(StorageError::BundleIsUsed {
+                            key: key.into(),
+                            used_in: used_in.clone(),
+                        });
+                    }
+                }
+
+                tables.bundle.remove(&(user_id, key.into()));
+                tables.set_bundle_items(user_id, key, []);
+
+                Ok(())
+            },
+        )
+    }
+
+    //
+    // Weight
+    //
+
+    fn get_weight_list(&self, user_id: i64, from: Timestamp, to: Timestamp) -> Result<Vec<Weight>> {
+        let tables = self.tables.lock().unwrap();
+
+        let mut res = Vec::new();
+        for ((u, ts), value) in &tables.weight {
+            if *u != user_id || !in_period(*ts, &from, &to) {
+                continue;
+            }
+
+            res.push(Weight {
+                timestamp: Self::timestamp(*ts)?,
+                value: *value,
+            });
+        }
+
+        if res.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        Ok(res)
+    }
+
+    fn get_last_weight(&self, user_id: i64, to: Timestamp) -> Result<Weight> {
+        let tables = self.tables.lock().unwrap();
+
+        let Some(((_, ts), value)) = tables
+            .weight
+            .range((user_id, i64::MIN)..=(user_id, to.unix_millis()))
+            .next_back()
+        else {
+            return Err(StorageError::EmptyResult);
+        };
+
+        Ok(Weight {
+            timestamp: Self::timestamp(*ts)?,
+            value: *value,
+        })
+    }
+
+    fn set_weight(&self, user_id: i64, weight: &Weight) -> Result<()> {
+        if !weight.validate() {
+            return Err(StorageError::WeightInvalid);
+        }
+
+        let ts = weight.timestamp.unix_millis();
+        self.execute_change(
+            user_id,
+            ChangeAction::SetWeight,
+            "",
+            &[Prior::Weight((user_id, ts), None)],
+            |tables| {
+                tables.weight.insert((user_id, ts), weight.value);
+
+                Ok(())
+            },
+        )
+    }
+
+    fn delete_weight(&self, user_id: i64, timestamp: Timestamp) -> Result<()> {
+        let ts = timestamp.unix_millis();
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteWeight,
+            "",
+            &[Prior::Weight((user_id, ts), None)],
+            |tables| {
+                tables.weight.remove(&(user_id, ts));
+
+                Ok(())
+            },
+        )
+    }
+
+    //
+    // User settings
+    //
+
+    fn get_user_settings(&self, user_id: i64) -> Result<UserSettings> {
+        let tables = self.tables.lock().unwrap();
+
+        let Some(row) = tables.user_settings.get(&user_id) else {
+            return Err(StorageError::UserSettingsNotFound);
+        };
+
+        let sex = match row.sex {
+            Some(v) => Some(Sex::new(v).context("wrong sex")?),
+            None => None,
+        };
+
+        let birth_date = match row.birth_date {
+            Some(v) => Some(Self::timestamp(v).context("parse birth_date field")?),
+            None => None,
+        };
+
+        Ok(UserSettings {
+            cal_limit: row.cal_limit,
+            sex,
+            height: row.height,
+            birth_date,
+        })
+    }
+
+    fn set_user_settings(&self, user_id: i64, settings: &UserSettings) -> Result<()> {
+        if !settings.validate() {
+            return Err(StorageError::UserSettingsInvalid);
+        }
+
+        self.execute_change(
+            user_id,
+            ChangeAction::SetUserSettings,
+            "",
+            &[Prior::UserSettings(user_id, None)],
+            |tables| {
+                tables.user_settings.insert(
+                    user_id,
+                    UserSettingsRow {
+                        cal_limit: settings.cal_limit,
+                        sex: settings.sex.map(u8::from),
+                        height: settings.height,
+                        birth_date: settings.birth_date.as_ref().map(|v| v.unix_millis()),
+                    },
+                );
+
+                Ok(())
+            },
+        )
+    }
+
+    //
+    // Journal
+    //
+
+    fn set_journal(&self, user_id: i64, journal: &Journal) -> Result<()> {
+        if !journal.validate() {
+            return Err(StorageError::JournalInvalid);
+        }
+
+        let (ts, meal) = (journal.timestamp.unix_millis(), u8::from(journal.meal));
+        self.execute_change(
+            user_id,
+            ChangeAction::SetJournal,
+            &journal.food_key,
+            &[Prior::Journal((user_id, ts, meal), Vec::new())],
+            |tables| {
+                if !tables.food.contains_key(&journal.food_key) {
+                    return Err(StorageError::FoodNotFound(journal.food_key.clone()));
+                }
+
+                tables.journal.insert(
+                    (user_id, ts, meal, journal.food_key.clone()),
+                    journal.food_weight,
+                );
+
+                Ok(())
+            },
+        )
+    }
+
+    fn set_journal_bundle(
+        &self,
+        user_id: i64,
+        timestamp: Timestamp,
+        meal: Meal,
+        bndl_key: &str,
+    ) -> Result<()> {
+        let (ts, meal) = (timestamp.unix_millis(), u8::from(meal));
+        self.execute_change(
+            user_id,
+            ChangeAction::SetJournalBundle,
+            bndl_key,
+            &[Prior::Journal((user_id, ts, meal), Vec::new())],
+            |tables| {
+                let food_items = Self::get_bundle_food_items(tables, user_id, bndl_key)?;
+                for (k, v) in food_items {
+                    tables.journal.insert((user_id, ts, meal, k), v);
+                }
+
+                Ok(())
+            },
+        )
+    }
+
+    fn delete_journal(
+        &self,
+        user_id: i64,
+        timestamp: Timestamp,
+        meal: Meal,
+        food_key: &str,
+    ) -> Result<()> {
+        let (ts, meal) = (timestamp.unix_millis(), u8::from(meal));
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteJournal,
+            food_key,
+            &[Prior::Journal((user_id, ts, meal), Vec::new())],
+            |tables| {
+                tables.journal.remove(&(user_id, ts, meal, food_key.into()));
+
+                Ok(())
+            },
+        )
+    }
+
+    fn delete_journal_meal(&self, user_id: i64, timestamp: Timestamp, meal: Meal) -> Result<()> {
+        let ts = timestamp.unix_millis();
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteJournalMeal,
+            &String::from(meal),
+            &[Prior::Journal((user_id, ts, u8::from(meal)), Vec::new())],
+            |tables| {
+                let meal = u8::from(meal);
+                tables
+                    .journal
+                    .retain(|(u, t, m, _), _| !(*u == user_id && *t == ts && *m == meal));
+
+                Ok(())
+            },
+        )
+    }
+
+    fn get_journal_report(
+        &self,
+        user_id: i64,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<Vec<JournalReport>> {
+        let tables = self.tables.lock().unwrap();
+
+        let mut report = Vec::new();
+        for ((_, ts, meal, food_key), food_weight) in journal_range(&tables, user_id, &from, &to) {
+            let Some(f) = tables.food.get(food_key) else {
+                continue;
+            };
+
+            report.push(JournalReport {
+                timestamp: Self::timestamp(*ts)?,
+                meal: Meal::new(*meal).context("wrong meal")?,
+                food_key: food_key.clone(),
+                food_name: f.name.clone(),
+                food_brand: f.brand.clone(),
+                food_weight: *food_weight,
+                cal: food_weight / 100.0 * f.cal100,
+                prot: food_weight / 100.0 * f.prot100,
+                fat: food_weight / 100.0 * f.fat100,
+                carb: food_weight / 100.0 * f.carb100,
+            });
+        }
+
+        if report.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        // Journal keys are already ordered by timestamp and meal
+        report.sort_by(|a, b| {
+            a.timestamp
+                .unix_millis()
+                .cmp(&b.timestamp.unix_millis())
+                .then_with(|| u8::from(a.meal).cmp(&u8::from(b.meal)))
+                .then_with(|| a.food_name.cmp(&b.food_name))
+        });
+
+        Ok(report)
+    }
+
+    fn get_journal_food_avg_weight(
+        &self,
+        user_id: i64,
+        food_key: &str,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<f64> {
+        let tables = self.tables.lock().unwrap();
+
+        let weights: Vec<f64> = journal_range(&tables, user_id, &from, &to)
+            .filter(|((_, _, _, k), _)| k == food_key)
+            .map(|(_, w)| *w)
+            .collect();
+
+        if weights.is_empty() {
+            return Ok(0.0);
+        }
+
+        Ok(weights.iter().sum::<f64>() / weights.len() as f64)
+    }
+
+    fn get_journal_food_usage(
+        &self,
+        user_id: i64,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<Vec<FoodUsage>> {
+        let tables = self.tables.lock().unwrap();
+
+        let mut usage: BTreeMap<String, FoodUsage> = BTreeMap::new();
+        for ((_, _, _, food_key), food_weight) in journal_range(&tables, user_id, &from, &to) {
+            let Some(f) = tables.food.get(food_key) else {
+                continue;
+            };
+
+            let u = usage.entry(food_key.clone()).or_insert_with(|| FoodUsage {
+                food_key: food_key.clone(),
+                food_name: f.name.clone(),
+                food_brand: f.brand.clone(),
+                count: 0,
+                food_weight: 0.0,
+                cal: 0.0,
+                prot: 0.0,
+            });
+            u.count += 1;
+            u.food_weight += food_weight;
+            u.cal += food_weight / 100.0 * f.cal100;
+            u.prot += food_weight / 100.0 * f.prot100;
+        }
+
+        if usage.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        let mut usage: Vec<FoodUsage> = usage.into_values().collect();
+        usage.sort_by(|a, b| {
+            b.cal
+                .total_cmp(&a.cal)
+                .then_with(|| a.food_name.cmp(&b.food_name))
+        });
+
+        Ok(usage)
+    }
+
+    fn get_journal_food_meal_usage(
+        &self,
+        user_id: i64,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<Vec<FoodMealUsage>> {
+        let tables = self.tables.lock().unwrap();
+
+        let mut counts: BTreeMap<(String, u8), i64> = BTreeMap::new();
+        for ((_, _, meal, food_key), _) in journal_range(&tables, user_id, &from, &to) {
+            if tables.food.contains_key(food_key) {
+                *counts.entry((food_key.clone(), *meal)).or_default() += 1;
+            }
+        }
+
+        if counts.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        let mut usage = Vec::with_capacity(counts.len());
+        for ((food_key, meal), count) in counts {
+            let f = &tables.food[&food_key];
+            usage.push(FoodMealUsage {
+                food_name: f.name.clone(),
+                food_brand: f.brand.clone(),
+                food_key,
+                meal: Meal::new(meal).context("wrong meal")?,
+                count,
+            });
+        }
+
+        // Counts are ordered by food key and meal, stable sort keeps it
+        usage.sort_by(|a, b| a.food_name.cmp(&b.food_name));
+
+        Ok(usage)
+    }
+
+    //
+    // Sport
+    //
+
+    fn get_sport(&self, key: &str) -> Result<Sport> {
+        let tables = self.tables.lock().unwrap();
+
+        tables
+            .sport
+            .get(key)
+            .cloned()
+            .ok_or_else(|| StorageError::SportNotFound(key.into()))
+    }
+
+    fn get_sport_list(&self) -> Result<Vec<Sport>> {
+        let tables = self.tables.lock().unwrap();
+
+        if tables.sport.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        let mut sport_list: Vec<Sport> = tables.sport.values().cloned().collect();
+        sport_list.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(sport_list)
+    }
+
+    fn set_sport(&self, user_id: i64, sport: &Sport) -> Result<()> {
+        if !sport.validate() {
+            return Err(StorageError::SportInvalid);
+        }
+
+        self.execute_change(
+            user_id,
+            ChangeAction::SetSport,
+            &sport.key,
+            &[Prior::Sport(sport.key.clone(), None)],
+            |tables| {
+                tables.sport.insert(sport.key.clone(), sport.clone());
+
+                Ok(())
+            },
+        )
+    }
+
+    fn delete_sport(&self, user_id: i64, key: &str) -> Result<()> {
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteSport,
+            key,
+            &[Prior::Sport(key.into(), None)],
+            |tables| {
+                if tables.sport_activity.keys().any(|(_, _, k)| k == key) {
+                    return Err(StorageError::SportIsUsed(key.into()));
+                }
+
+                tables.sport.remove(key);
+
+                Ok(())
+            },
+        )
+    }
+
+    //
+    // Sport activity
+    //
+
+    fn set_sport_activity(&self, user_id: i64, act: &SportActivity) -> Result<()> {
+        if !act.validate() {
+            return Err(StorageError::SportActivityInvalid);
+        }
+
+        // Convert sets to JSON array
+        let str_sets = serde_json::to_string(&json!(act.sets))
+            .context("convert sport activity sets to JSON")?;
+
+        let key = (user_id, act.timestamp.unix_millis(), act.sport_key.clone());
+        self.execute_change(
+            user_id,
+            ChangeAction::SetSportActivity,
+            &act.sport_key,
+            &[Prior::SportActivity(key.clone(), None)],
+            |tables| {
+                if !tables.sport.contains_key(&act.sport_key) {
+                    return Err(StorageError::SportNotFound(act.sport_key.clone()));
+                }
+
+                tables.sport_activity.insert(key, str_sets);
+
+                Ok(())
+            },
+        )
+    }
+
+    fn delete_sport_activity(
+        &self,
+        user_id: i64,
+        timestamp: Timestamp,
+        sport_key: &str,
+    ) -> Result<()> {
+        let key = (user_id, timestamp.unix_millis(), sport_key.to_string());
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteSportActivity,
+            sport_key,
+            &[Prior::SportActivity(key.clone(), None)],
+            |tables| {
+                tables.sport_activity.remove(&key);
+
+                Ok(())
+            },
+        )
+    }
+
+    fn get_sport_activity_report(
+        &self,
+        user_id: i64,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<Vec<SportActivityReport>> {
+        let tables = self.tables.lock().unwrap();
+
+        let mut res = Vec::new();
+        for ((u, ts, sport_key), json_sets) in &tables.sport_activity {
+            if *u != user_id || !in_period(*ts, &from, &to) {
+                continue;
+            }
+
+            let Some(s) = tables.sport.get(sport_key) else {
+                continue;
+            };
+
+            res.push(SportActivityReport {
+                sport_name: s.name.clone(),
+                timestamp: Self::timestamp(*ts)?,
+                sets: serde_json::from_str(json_sets).context("convert sets from JSON")?,
+            });
+        }
+
+        if res.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        // Activities are ordered by timestamp, stable sort keeps it
+        res.sort_by(|a, b| {
+            a.timestamp
+                .unix_millis()
+                .cmp(&b.timestamp.unix_millis())
+                .then_with(|| a.sport_name.cmp(&b.sport_name))
+        });
+
+        Ok(res)
+    }
+
+    //
+    // Metric
+    //
+
+    fn get_metric(&self, user_id: i64, key: &str) -> Result<Metric> {
+        let tables = self.tables.lock().unwrap();
+
+        let Some(row) = tables.metric.get(&(user_id, key.into())) else {
+            return Err(StorageError::MetricNotFound(key.into()));
+        };
+
+        Ok(Metric {
+            key: key.into(),
+            name: row.name.clone(),
+            unit: row.unit.clone(),
+            fields: serde_json::from_str(&row.fields).context("convert metric fields from JSON")?,
+        })
+    }
+
+    fn get_metric_list(&self, user_id: i64) -> Result<Vec<Metric>> {
+        let tables = self.tables.lock().unwrap();
+
+        let mut res = Vec::new();
+        for ((u, key), row) in &tables.metric {
+            if *u != user_id {
+                continue;
+            }
+
+            res.push(Metric {
+                key: key.clone(),
+                name: row.name.clone(),
+                unit: row.unit.clone(),
+                fields: serde_json::from_str(&row.fields)
+                    .context("convert metric fields from JSON")?,
+            });
+        }
+
+        if res.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        res.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.key.cmp(&b.key)));
+
+        Ok(res)
+    }
+
+    fn set_metric(&self, user_id: i64, metric: &Metric) -> Result<()> {
+        if !metric.validate() {
+            return Err(StorageError::MetricInvalid);
+        }
+
+        let fields = serde_json::to_string(&json!(metric.fields))
+            .context("convert metric fields to JSON")?;
+
+        self.execute_change(
+            user_id,
+            ChangeAction::SetMetric,
+            &metric.key,
+            &[Prior::Metric((user_id, metric.key.clone()), None)],
+            |tables| {
+                tables.metric.insert(
+                    (user_id, metric.key.clone()),
+                    MetricRow {
+                        name: metric.name.clone(),
+                        unit: metric.unit.clone(),
+                        fields,
+                    },
+                );
+
+                Ok(())
+            },
+        )
+    }
+
+    fn delete_metric(&self, user_id: i64, key: &str) -> Result<()> {
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteMetric,
+            key,
+            &[Prior::Metric((user_id, key.into()), None)],
+            |tables| {
+                if tables
+                    .metric_value
+                    .keys()
+                    .any(|(u, _, k)| *u == user_id && k == key)
+                {
+                    return Err(StorageError::MetricIsUsed(key.into()));
+                }
+
+                tables.metric.remove(&(user_id, key.into()));
+
+                Ok(())
+            },
+        )
+    }
+
+    //
+    // Metric value
+    //
+
+    fn set_metric_value(&self, user_id: i64, val: &MetricValue) -> Result<()> {
+        if !val.validate() {
+            return Err(StorageError::MetricValueInvalid);
+        }
+
+        let key = (user_id, val.timestamp.unix_millis(), val.metric_key.clone());
+        self.execute_change(
+            user_id,
+            ChangeAction::SetMetricValue,
+            &val.metric_key,
+            &[Prior::MetricValue(key.clone(), None)],
+            |tables| {
+                // Check that values match metric fields
+                let Some(row) = tables.metric.get(&(user_id, val.metric_key.clone())) else {
+                    return Err(StorageError::MetricNotFound(val.metric_key.clone()));
+                };
+
+                let fields: Vec<String> =
+                    serde_json::from_str(&row.fields).context("convert metric fields from JSON")?;
+
+                if fields.len() != val.values.len() {
+                    return Err(StorageError::MetricValueInvalid);
+                }
+
+                // Set metric value
+                let vals = serde_json::to_string(&json!(val.values))
+                    .context("convert metric values to JSON")?;
+
+                tables.metric_value.insert(key, vals);
+
+                Ok(())
+            },
+        )
+    }
+
+    fn delete_metric_value(
+        &self,
+        user_id: i64,
+        timestamp: Timestamp,
+        metric_key: &str,
+    ) -> Result<()> {
+        let key = (user_id, timestamp.unix_millis(), metric_key.to_string());
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteMetricValue,
+            metric_key,
+            &[Prior::MetricValue(key.clone(), None)],
+            |tables| {
+                tables.metric_value.remove(&key);
+
+                Ok(())
+            },
+        )
+    }
+
+    fn get_metric_value_list(
+        &self,
+        user_id: i64,
+        metric_key: &str,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<Vec<MetricValue>> {
+        let tables = self.tables.lock().unwrap();
+
+        let mut res = Vec::new();
+        for ((u, ts, key), json_vals) in &tables.metric_value {
+            if *u != user_id || key != metric_key || !in_period(*ts, &from, &to) {
+                continue;
+            }
+
+            res.push(MetricValue {
+                metric_key: key.clone(),
+                timestamp: Self::timestamp(*ts)?,
+                values: serde_json::from_str(json_vals).context("convert values from JSON")?,
+            });
+        }
+
+        if res.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        Ok(res)
+    }
+
+    //
+    // Schedule
+    //
+
+    fn get_schedule_list(&self, user_id: i64) -> Result<Vec<Schedule>> {
+        let tables = self.tables.lock().unwrap();
+
+        let mut res = Vec::new();
+        for ((u, kind, hour, minute), row) in &tables.schedule {
+            if *u != user_id {
+                continue;
+            }
+
+            let last_run = match row.last_run {
+                Some(v) => Some(Self::timestamp(v).context("parse last_run field")?),
+                None => None,
+            };
+
+            res.push(Schedule {
+                kind: ScheduleKind::new(*kind).context("wrong schedule kind")?,
+                hour: *hour,
+                minute: *minute,
+                tz: row.tz.clone(),
+                last_run,
+            });
+        }
+
+        if res.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        res.sort_by_key(|s| (s.hour, s.minute, u8::from(s.kind)));
+
+        Ok(res)
+    }
+
+    fn set_schedule(&self, user_id: i64, schedule: &Schedule) -> Result<()> {
+        if !schedule.validate() {
+            return Err(StorageError::ScheduleInvalid);
+        }
+
+        let key = (
+            user_id,
+            u8::from(schedule.kind),
+            schedule.hour,
+            schedule.minute,
+        );
+        self.execute_change(
+            user_id,
+            ChangeAction::SetSchedule,
+            &Self::schedule_key(schedule.kind, schedule.hour, schedule.minute),
+            &[Prior::Schedule(key, None)],
+            |tables| {
+                // Upsert keeps last run
+                tables
+                    .schedule
+                    .entry(key)
+                    .and_modify(|row| row.tz = schedule.tz.clone())
+                    .or_insert_with(|| ScheduleRow {
+                        tz: schedule.tz.clone(),
+                        last_run: None,
+                    });
+
+                Ok(())
+            },
+        )
+    }
+
+    fn set_schedule_last_run(
+        &self,
+        user_id: i64,
+        schedule: &Schedule,
+        last_run: Timestamp,
+    ) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+
+        if let Some(row) = tables.schedule.get_mut(&(
+            user_id,
+            u8::from(schedule.kind),
+            schedule.hour,
+            schedule.minute,
+        )) {
+            row.last_run = Some(last_run.unix_millis());
+        }
+
+        Ok(())
+    }
+
+    fn delete_schedule(
+        &self,
+        user_id: i64,
+        kind: ScheduleKind,
+        hour: u8,
+        minute: u8,
+    ) -> Result<()> {
+        let key = (user_id, u8::from(kind), hour, minute);
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteSchedule,
+            &Self::schedule_key(kind, hour, minute),
+            &[Prior::Schedule(key, None)],
+            |tables| {
+                tables.schedule.remove(&key);
+
+                Ok(())
+            },
+        )
+    }
+
+    //
+    // Change log
+    //
+
+    fn get_change_list(&self, user_id: i64, limit: usize) -> Result<Vec<Change>> {
+        let tables = self.tables.lock().unwrap();
+
+        let res: Vec<Change> = tables
+            .change_log
+            .get(&user_id)
+            .map(|log| {
+                log.iter()
+                    .rev()
+                    .take(limit)
+                    .map(|row| row.change.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if res.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        Ok(res)
+    }
+
+    fn undo(&self, user_id: i64, count: usize) -> Result<Vec<Change>> {
+        let mut tables = self.tables.lock().unwrap();
+
+        // Changes are reverted on copy, so conflict leaves tables untouched
+        let mut t = tables.clone();
+        let log = t.change_log.entry(user_id).or_default();
+        let count = count.min(log.len());
+        if count == 0 {
+            return Err(StorageError::EmptyResult);
+        }
+
+        let undone: Vec<ChangeRow> = log.drain(log.len() - count..).rev().collect();
+        let mut res = Vec::with_capacity(undone.len());
+        for row in undone {
+            let current: Vec<Prior> = row.prior.iter().map(|p| p.capture(&t)).collect();
+            // Rows changed later by other user, e.g. shared food, are not overwritten
+            if current != row.after {
+                return Err(StorageError::UndoConflict);
+            }
+
+            for prior in row.prior.iter().cloned() {
+                prior.restore(&mut t);
+            }
+            Self::insert_audit(
+                &mut t,
+                user_id,
+                row.change.action.entity(),
+                &row.change.key,
+                AuditAction::Undo,
+                &current,
+                &row.prior,
+            );
+            res.push(row.change);
+        }
+
+        if !Self::references_valid(&t)? {
+            return Err(StorageError::UndoConflict);
+        }
+        *tables = t;
+
+        Ok(res)
+    }
+
+    //
+    // Audit log
+    //
+
+    fn get_audit_list(&self, from: Timestamp, to: Timestamp) -> Result<Vec<AuditEntry>> {
+        let tables = self.tables.lock().unwrap();
+
+        let res: Vec<AuditEntry> = tables
+            .audit_log
+            .values()
+            .filter(|e| in_period(e.timestamp.unix_millis(), &from, &to))
+            .cloned()
+            .collect();
+
+        if res.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        Ok(res)
+    }
+
+    //
+    // Backup/Restore
+    //
+
+    fn backup(&self, user_id: i64) -> Result<Backup> {
+        let tables = self.tables.lock().unwrap();
+
+        Ok(Backup {
+            timestamp: Timestamp::now().unix_millis(),
+            food: tables
+                .food
+                .values()
+                .map(|f| FoodBackup {
+                    user_id,
+                    key: f.key.clone(),
+                    name: f.name.clone(),
+                    brand: f.brand.clone(),
+                    cal100: f.cal100,
+                    prot100: f.prot100,
+                    fat100: f.fat100,
+                    carb100: f.carb100,
+                    comment: f.comment.clone(),
+                })
+                .collect(),
+            weight: tables
+                .weight
+                .iter()
+                .map(|((user_id, timestamp), value)| WeightBackup {
+                    user_id: *user_id,
+                    timestamp: *timestamp,
+                    value: *value,
+                })
+                .collect(),
+            user_settings: tables
+                .user_settings
+                .iter()
+                .map(|(user_id, row)| UserSettingsBackup {
+                    user_id: *user_id,
+                    cal_limit: row.cal_limit,
+                    sex: row.sex,
+                    height: row.height,
+                    birth_date: row.birth_date,
+                })
+                .collect(),
+            bundle: tables
+                .bundle
+                .iter()
+                .map(|(user_id, key)| {
+                    // Sorted keys keep backup stable, like in SQLite storage
+                    let data: BTreeMap<_, _> =
+                        tables.bundle_data(*user_id, key).into_iter().collect();
+                    serde_json::to_string(&data)
+                        .map(|data| BundleBackup {
+                            user_id: *user_id,
+                            key: key.clone(),
+                            data,
+                        })
+                        .context("convert bundle data to JSON")
+                })
+                .collect::<anyhow::Result<_>>()?,
+            journal: tables
+                .journal
+                .iter()
+                .map(
+                    |((user_id, timestamp, meal, food_key), food_weight)| JournalBackup {
+                        user_id: *user_id,
+                        timestamp: *timestamp,
+                        meal: *meal,
+                        food_key: food_key.clone(),
+                        food_weight: *food_weight,
+                    },
+                )
+                .collect(),
+            sport: tables
+                .sport
+                .values()
+                .map(|s| SportBackup {
+                    user_id,
+                    key: s.key.clone(),
+                    name: s.name.clone(),
+                    comment: s.comment.clone(),
+                })
+                .collect(),
+            sport_activity: tables
+                .sport_activity
+                .iter()
+                .map(
+                    |((user_id, timestamp, sport_key), sets)| SportActivityBackup {
+                        user_id: *user_id,
+                        timestamp: *timestamp,
+                        sport_key: sport_key.clone(),
+                        sets: sets.clone(),
+                    },
+                )
+                .collect(),
+            metric: tables
+                .metric
+                .iter()
+                .map(|((user_id, key), row)| MetricBackup {
+                    user_id: *user_id,
+                    key: key.clone(),
+                    name: row.name.clone(),
+                    unit: row.unit.clone(),
+                    fields: row.fields.clone(),
+                })
+                .collect(),
+            metric_value: tables
+                .metric_value
+                .iter()
+                .map(
+                    |((user_id, timestamp, metric_key), values)| MetricValueBackup {
+                        user_id: *user_id,
+                        timestamp: *timestamp,
+                        metric_key: metric_key.clone(),
+                        values: values.clone(),
+                    },
+                )
+                .collect(),
+            schedule: tables
+                .schedule
+                .iter()
+                .map(|((user_id, kind, hour, minute), row)| ScheduleBackup {
+                    user_id: *user_id,
+                    kind: *kind,
+                    hour: *hour,
+                    minute: *minute,
+                    tz: row.tz.clone(),
+                })
+                .collect(),
+            audit_log: tables
+                .audit_log
+                .iter()
+                .map(|(id, e)| AuditLogBackup {
+                    id: *id,
+                    user_id: e.user_id,
+                    timestamp: e.timestamp.unix_millis(),
+                    entity: e.entity.clone(),
+                    key: e.key.clone(),
+                    action: e.action.into(),
+                    old_value: e.old_value.clone(),
+                    new_value: e.new_value.clone(),
+                })
+                .collect(),
+        })
+    }
+
+    fn restore(&self, backup: &Backup) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+
+        for w in &backup.weight {
+            tables.weight.insert((w.user_id, w.timestamp), w.value);
+        }
+
+        for f in &backup.food {
+            tables.food.insert(
+                f.key.clone(),
+                Food {
+                    key: f.key.clone(),
+                    name: f.name.clone(),
+                    brand: f.brand.clone(),
+                    cal100: f.cal100,
+                    prot100: f.prot100,
+                    fat100: f.fat100,
+                    carb100: f.carb100,
+                    comment: f.comment.clone(),
+                },
+            );
+        }
+
+        for us in &backup.user_settings {
+            tables.user_settings.insert(
+                us.user_id,
+                UserSettingsRow {
+                    cal_limit: us.cal_limit,
+                    sex: us.sex,
+                    height: us.height,
+                    birth_date: us.birth_date,
+                },
+            );
+        }
+
+        for b in &backup.bundle {
+            let data: HashMap<String, f64> =
+                serde_json::from_str(&b.data).context("convert bundle data from JSON")?;
+            tables.bundle.insert((b.user_id, b.key.clone()));
+            tables.set_bundle_items(
+                b.user_id,
+                &b.key,
+                data.into_iter().map(|(k, v)| (k, (v != 0.0).then_some(v))),
+            );
+        }
+
+        for j in &backup.journal {
+            if !tables.food.contains_key(&j.food_key) {
+                return Err(StorageError::FoodNotFound(j.food_key.clone()));
+            }
+            tables.journal.insert(
+                (j.user_id, j.timestamp, j.meal, j.food_key.clone()),
+                j.food_weight,
+            );
+        }
+
+        for s in &backup.sport {
+            tables.sport.insert(
+                s.key.clone(),
+                Sport {
+                    key: s.key.clone(),
+                    name: s.name.clone(),
+                    comment: s.comment.clone(),
+                },
+            );
+        }
+
+        for sa in &backup.sport_activity {
+            if !tables.sport.contains_key(&sa.sport_key) {
+                return Err(StorageError::SportNotFound(sa.sport_key.clone()));
+            }
+            tables.sport_activity.insert(
+                (sa.user_id, sa.timestamp, sa.sport_key.clone()),
+                sa.sets.clone(),
+            );
+        }
+
+        for m in &backup.metric {
+            tables.metric.insert(
+                (m.user_id, m.key.clone()),
+                MetricRow {
+                    name: m.name.clone(),
+                    unit: m.unit.clone(),
+                    fields: m.fields.clone(),
+                },
+            );
+        }
+
+        for mv in &backup.metric_value {
+            if !tables
+                .metric
+                .contains_key(&(mv.user_id, mv.metric_key.clone()))
+            {
+                return Err(StorageError::MetricNotFound(mv.metric_key.clone()));
+            }
+            tables.metric_value.insert(
+                (mv.user_id, mv.timestamp, mv.metric_key.clone()),
+                mv.values.clone(),
+            );
+        }
+
+        for sc in &backup.schedule {
+            tables
+                .schedule
+                .entry((sc.user_id, sc.kind, sc.hour, sc.minute))
+                .and_modify(|row| row.tz = sc.tz.clone())
+                .or_insert_with(|| ScheduleRow {
+                    tz: sc.tz.clone(),
+                    last_run: None,
+                });
+        }
+
+        // Restored entries get new ids, entry equal to one already present is skipped
+        for a in &backup.audit_log {
+            let entry = AuditEntry {
+                timestamp: Self::timestamp(a.timestamp)?,
+                user_id: a.user_id,
+                entity: a.entity.clone(),
+                key: a.key.clone(),
+                action: AuditAction::new(a.action)?,
+                old_value: a.old_value.clone(),
+                new_value: a.new_value.clone(),
+            };
+            // Timestamps are compared in millis, as they are kept in backup
+            let present = tables.audit_log.values().any(|e| {
+                e.timestamp.unix_millis() == a.timestamp
+                    && AuditEntry {
+                        timestamp: entry.timestamp.clone(),
+                        ..e.clone()
+                    } == entry
+            });
+            if present {
+                continue;
+            }
+            Self::push_audit(&mut tables, entry);
+        }
+
+        Self::push_audit(
+            &mut tables,
+            AuditEntry {
+                timestamp: Timestamp::now(),
+                user_id: SYSTEM_USER_ID,
+                entity: "backup".into(),
+                key: String::new(),
+                action: AuditAction::Restore,
+                old_value: None,
+                new_value: Some(restore_audit_value(backup)),
+            },
+        );
+
+        Ok(())
+    }
+}
+
+// Journal entries of user in period, ordered by timestamp and meal
+fn journal_range<'a>(
+    tables: &'a Tables,
+    user_id: i64,
+    from: &'a Timestamp,
+    to: &'a Timestamp,
+) -> impl Iterator<Item = (&'a (i64, i64, u8, String), &'a f64)> {
+    tables
+        .journal
+        .iter()
+        .filter(move |((u, ts, _, _), _)| *u == user_id && in_period(*ts, from, to))
+}
+
+fn in_period(ts: i64, from: &Timestamp, to: &Timestamp) -> bool {
+    from.unix_millis() <= ts && ts <= to.unix_millis()
+}
+
+// Inserts row or removes it if there is no value
+fn put<K: Ord, V>(map: &mut BTreeMap<K, V>, key: K, value: Option<V>) {
+    match value {
+        Some(v) => {
+            map.insert(key, v);
+        }
+        None => {
+            map.remove(&key);
+        }
+    }
+}
@@ -1,84 +1,191 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 use std::sync::Mutex;
+use std::time::Duration;
 
-use crate::{Storage, StorageError};
-use anyhow::{anyhow, bail, ensure, Context, Error, Result};
+use crate::{restore_audit_value, Result, Storage, StorageError, CHANGE_LOG_SIZE, SYSTEM_USER_ID};
+use anyhow::{anyhow, bail, Context, Error};
 use model::{
     backup::{
-        Backup, BundleBackup, FoodBackup, JournalBackup, SportActivityBackup, SportBackup,
-        UserSettingsBackup, WeightBackup,
+        AuditLogBackup, Backup, BundleBackup, FoodBackup, JournalBackup, MetricBackup,
+        MetricValueBackup, ScheduleBackup, SportActivityBackup, SportBackup, UserSettingsBackup,
+        WeightBackup,
     },
-    Bundle, Food, Journal, JournalReport, Meal, Sport, SportActivity, SportActivityReport,
-    UserSettings, Weight,
+    AuditAction, AuditEntry, Bundle, Change, ChangeAction, Food, FoodMealUsage, FoodUsage, Journal,
+    JournalReport, Meal, Metric, MetricValue, Schedule, ScheduleKind, Sex, Sport, SportActivity,
+    SportActivityReport, UserSettings, Weight,
 };
+use pool::Pool;
 use rusqlite::{
-    functions::FunctionFlags, params, types::Value, Connection, Error::SqliteFailure, Params,
-    Transaction,
+    functions::FunctionFlags,
+    params,
+    types::{FromSql, Type},
+    Connection,
+    Error::{FromSqlConversionFailure, SqliteFailure},
+    OpenFlags, OptionalExtension, Params, Row, Transaction,
 };
-use serde_json::json;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value as JsonValue};
 use types::timestamp::Timestamp;
 
+mod changes;
 mod migrations;
+mod pool;
+
+pub use migrations::{MigrationState, MigrationStatus, Migrator};
 mod queries;
 
 #[cfg(test)]
 mod test;
 
+// Bundle joined with one of its items: user id, bundle key, item key and weight
+type BundleRow = (i64, String, Option<(String, f64)>);
+
+pub const READ_POOL_SIZE: usize = 4;
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Database in WAL mode: all writes go through single writer connection,
+// reads are served by pool of read-only connections and don't wait for writer
 pub struct StorageSqlite {
     conn: Mutex<Connection>,
+    readers: Pool,
 }
 
 impl StorageSqlite {
-    pub fn new(db_file: &Path) -> Result<Self> {
-        let conn = Connection::open(format!(
-            "file:{}?mode=rwc&_timeout=5000&_fk=1&_sync=1&_journal=wal",
-            db_file.to_str().unwrap(),
-        ))
-        .context("open db connection")?;
+    pub fn new(db_file: &Path) -> anyhow::Result<Self> {
+        Self::with_read_pool(db_file, READ_POOL_SIZE)
+    }
+
+    // Without read connections all queries go through writer connection
+    pub fn with_read_pool(db_file: &Path, read_pool_size: usize) -> anyhow::Result<Self> {
+        let conn = Connection::open(db_file).context("open db connection")?;
+        conn.busy_timeout(BUSY_TIMEOUT)
+            .context("set writer busy timeout")?;
+        let journal_mode: String = conn
+            .pragma_update_and_check(None, "journal_mode", "wal", |row| row.get(0))
+            .context("set WAL journal mode")?;
+        if journal_mode != "wal" {
+            bail!("WAL journal mode not supported, got {journal_mode}");
+        }
+
+        let mut s = Self::from_conn(conn)?;
+
+        let mut readers = Vec::with_capacity(read_pool_size);
+        for i in 0..read_pool_size {
+            readers.push(
+                Self::open_reader(db_file).with_context(|| format!("open read connection {i}"))?,
+            );
+        }
+        s.readers = Pool::new(readers);
+
+        Ok(s)
+    }
 
+    // Database lives only as long as storage, for tests
+    pub fn new_in_memory() -> anyhow::Result<Self> {
+        let conn = Connection::open_in_memory().context("open in-memory db connection")?;
+
+        Self::from_conn(conn)
+    }
+
+    fn from_conn(conn: Connection) -> anyhow::Result<Self> {
         Self::add_custom_functions(&conn).context("add custom functions")?;
 
         let s = Self {
             conn: Mutex::new(conn),
+            readers: Pool::new(Vec::new()),
         };
 
-        s.init().context("storage init")?;
         s.apply_migrations().context("storage apply migrations")?;
 
         Ok(s)
     }
 
-    fn init(&self) -> Result<()> {
-        // Create system table if not exists
-        self.raw_execute(queries::CREATE_TABLE_SYSTEM, false, params![])
-            .context("exec create system table")
-    }
+    fn open_reader(db_file: &Path) -> anyhow::Result<Connection> {
+        let conn = Connection::open_with_flags(
+            db_file,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .context("open read-only db connection")?;
+        conn.busy_timeout(BUSY_TIMEOUT)
+            .context("set reader busy timeout")?;
+        Self::add_custom_functions(&conn).context("add custom functions")?;
 
-    fn apply_migrations(&self) -> Result<()> {
-        let last_migration_id = self
-            .get_last_migration_id()
-            .context("get last migration id")?;
+        Ok(conn)
+    }
 
+    // Database migrated by newer binary or with changed migrations is refused
+    fn apply_migrations(&self) -> anyhow::Result<()> {
         let mut conn = self.conn.lock().unwrap();
-        migrations::apply(&mut conn, last_migration_id).context("apply list of migrations")
+        migrations::init(&mut conn).context("init migrations")?;
+        migrations::check(&conn).context("check migrations")?;
+        migrations::up(&mut conn, migrations::latest_id()).context("apply list of migrations")
     }
 
-    fn raw_query<P>(&self, query: &str, params: P) -> Result<Vec<HashMap<String, Value>>>
+    fn query_map<T, P, F>(&self, query: &str, params: P, f: F) -> anyhow::Result<Vec<T>>
     where
         P: Params,
+        F: FnMut(&Row) -> rusqlite::Result<T>,
     {
-        let mut conn = self.conn.lock().unwrap();
-        let tx = conn
-            .transaction()
-            .context("failed to get transaction")
-            .unwrap();
+        if self.readers.size() == 0 {
+            let conn = self.conn.lock().unwrap();
+            return Self::query_map_conn(&conn, query, params, f);
+        }
+
+        let conn = self.readers.get();
+        Self::query_map_conn(&conn, query, params, f)
+    }
+
+    fn query_opt<T, P, F>(&self, query: &str, params: P, f: F) -> anyhow::Result<Option<T>>
+    where
+        P: Params,
+        F: FnOnce(&Row) -> rusqlite::Result<T>,
+    {
+        if self.readers.size() == 0 {
+            let conn = self.conn.lock().unwrap();
+            return Self::query_opt_conn(&conn, query, params, f);
+        }
 
-        // tx when dropped is rollbacked - it's Ok for query
-        Self::raw_query_tx(&tx, query, params)
+        let conn = self.readers.get();
+        Self::query_opt_conn(&conn, query, params, f)
     }
 
-    fn raw_execute<P>(&self, query: &str, batch: bool, params: P) -> Result<()>
+    // Transaction derefs to connection, so it works inside transactions too
+    fn query_map_conn<T, P, F>(
+        conn: &Connection,
+        query: &str,
+        params: P,
+        f: F,
+    ) -> anyhow::Result<Vec<T>>
+    where
+        P: Params,
+        F: FnMut(&Row) -> rusqlite::Result<T>,
+    {
+        let mut stmt = conn.prepare_cached(query).context("prepare query")?;
+        let rows = stmt.query_map(params, f).context("quering query")?;
+
+        rows.collect::<rusqlite::Result<Vec<T>>>()
+            .context("map query rows")
+    }
+
+    fn query_opt_conn<T, P, F>(
+        conn: &Connection,
+        query: &str,
+        params: P,
+        f: F,
+    ) -> anyhow::Result<Option<T>>
+    where
+        P: Params,
+        F: FnOnce(&Row) -> rusqlite::Result<T>,
+    {
+        let mut stmt = conn.prepare_cached(query).context("prepare query")?;
+
+        stmt.query_row(params, f)
+            .optional()
+            .context("quering single row query")
+    }
+
+    fn raw_execute<P>(&self, query: &str, batch: bool, params: P) -> anyhow::Result<()>
     where
         P: Params,
     {
@@ -94,79 +201,127 @@ impl StorageSqlite {
         Ok(())
     }
 
-    fn get_last_migration_id(&self) -> Result<i64> {
-        let res = self
-            .raw_query(queries::SELECT_MIGRATION_ID, params![])
-            .context("query last migration")?;
-        if res.is_empty() {
-            return Ok(0);
-        }
-
-        let row = res.first().unwrap();
-        if let Some(Value::Integer(val)) = row.get("migration_id") {
-            return Ok(*val);
-        }
-
-        bail!("migration_id not found");
-    }
-
-    fn raw_query_tx<P>(
+    fn raw_execute_tx<P>(
         tx: &Transaction,
         query: &str,
+        batch: bool,
         params: P,
-    ) -> Result<Vec<HashMap<String, Value>>>
+    ) -> anyhow::Result<()>
     where
         P: Params,
     {
-        let mut stmt = tx.prepare(query).context("prepare raw query")?;
-        let mut rows = stmt.query(params).context("quering raw query")?;
-
-        let mut col_names: Vec<String> = Default::default();
-        let mut col_cnt: usize = Default::default();
-        let mut first = true;
-
-        let mut res = Vec::new();
-        while let Some(row) = rows.next().context("get next row")? {
-            if first {
-                col_names = row
-                    .as_ref()
-                    .column_names()
-                    .iter()
-                    .map(|&s| s.to_string())
-                    .collect();
-                col_cnt = col_names.len();
-                first = false;
-            }
-
-            let mut res_row = HashMap::with_capacity(col_cnt);
-            for i in 0..col_cnt {
-                res_row.insert(
-                    col_names.get(i).unwrap().clone(),
-                    Value::from(
-                        row.get_ref(i)
-                            .with_context(|| anyhow!(format!("get column {i}")))?,
-                    ),
-                );
-            }
-            res.push(res_row);
+        if !batch {
+            tx.prepare_cached(query)
+                .context("prepare execute query")?
+                .execute(params)
+                .context("raw execute query")?;
+        } else {
+            tx.execute_batch(query).context("raw execute batch query")?;
         }
-        Ok(res)
+
+        Ok(())
     }
 
-    fn raw_execute_tx<P>(tx: &Transaction, query: &str, batch: bool, params: P) -> Result<()>
+    // Runs mutation in transaction with record of prior and resulting state of affected
+    // rows to change log of user, mutation that changed nothing is not recorded.
+    // Affected rows are given as table name with JSON object of filter columns.
+    fn execute_change<F>(
+        &self,
+        user_id: i64,
+        action: ChangeAction,
+        key: &str,
+        affected: &[(&str, JsonValue)],
+        f: F,
+    ) -> Result<()>
     where
-        P: Params,
+        F: FnOnce(&Transaction) -> Result<()>,
     {
-        if !batch {
-            tx.execute(query, params).context("raw execute query")?;
-        } else {
-            tx.execute_batch(query).context("raw execute batch query")?;
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().context("failed to get transaction")?;
+
+        let capture = |tx: &Transaction| -> anyhow::Result<Vec<changes::RowSet>> {
+            affected
+                .iter()
+                .map(|(table, filter)| {
+                    changes::capture(tx, table, filter)
+                        .with_context(|| format!("capture {table} rows"))
+                })
+                .collect()
+        };
+
+        let prior = capture(&tx)?;
+        f(&tx)?;
+
+        let current = capture(&tx)?;
+        if current != prior {
+            Self::insert_audit_tx(
+                &tx,
+                user_id,
+                action.entity(),
+                key,
+                AuditAction::from(action),
+                &prior,
+                &current,
+            )?;
+
+            let prior = serde_json::to_string(&prior).context("convert change rows to JSON")?;
+            let after = serde_json::to_string(&current).context("convert change rows to JSON")?;
+            Self::raw_execute_tx(
+                &tx,
+                queries::INSERT_CHANGE,
+                false,
+                params![
+                    user_id,
+                    Timestamp::now().unix_millis(),
+                    u8::from(action),
+                    key,
+                    prior,
+                    after
+                ],
+            )
+            .context("exec insert change")?;
+            Self::raw_execute_tx(
+                &tx,
+                queries::DELETE_OLD_CHANGES,
+                false,
+                params![user_id, CHANGE_LOG_SIZE],
+            )
+            .context("exec delete old changes")?;
         }
 
+        tx.commit().context("failed to commit transaction")?;
+
         Ok(())
     }
 
-    fn add_custom_functions(conn: &Connection) -> Result<()> {
+    // Audit log is written in transaction of mutation, so it can't miss committed change
+    fn insert_audit_tx(
+        tx: &Transaction,
+        user_id: i64,
+        entity: &str,
+        key: &str,
+        action: AuditAction,
+        old: &[changes::RowSet],
+        new: &[changes::RowSet],
+    ) -> anyhow::Result<()> {
+        Self::raw_execute_tx(
+            tx,
+            queries::INSERT_AUDIT,
+            false,
+            params![
+                user_id,
+                Timestamp::now().unix_millis(),
+                entity,
+                key,
+                u8::from(action),
+                changes::rows_json(old)?,
+                changes::rows_json(new)?
+            ],
+        )
+        .context("exec insert audit")
+    }
+
+    fn add_custom_functions(conn: &Connection) -> anyhow::Result<()> {
         conn.create_scalar_function(
             "r_upper",
             1,
@@ -179,40 +334,190 @@ impl StorageSqlite {
         .map_err(|e| anyhow!(e))
     }
 
-    fn get_timestamp(row: &HashMap<String, Value>, field: &str) -> Result<Timestamp> {
-        let Some(Value::Integer(ts)) = row.get(field) else {
-            bail!("failed to get \"{field}\" field");
-        };
+    // Converts column value, conversion error is reported like any other column error
+    fn get_with<T, U, E, F>(row: &Row, col: &str, f: F) -> rusqlite::Result<U>
+    where
+        T: FromSql,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+        F: FnOnce(T) -> std::result::Result<U, E>,
+    {
+        let idx = row.as_ref().column_index(col)?;
+        f(row.get(idx)?).map_err(|err| {
+            let data_type = row.get_ref(idx).map_or(Type::Null, |v| v.data_type());
+            FromSqlConversionFailure(idx, data_type, err.into())
+        })
+    }
 
-        let Some(ts) = Timestamp::from_unix_millis(*ts) else {
-            bail!("failed to parse \"{field}\" field");
-        };
+    fn get_timestamp(row: &Row, col: &str) -> rusqlite::Result<Timestamp> {
+        Self::get_with(row, col, |v: i64| {
+            Timestamp::from_unix_millis(v).ok_or_else(|| anyhow!("wrong timestamp {v}"))
+        })
+    }
+
+    fn get_timestamp_opt(row: &Row, col: &str) -> rusqlite::Result<Option<Timestamp>> {
+        Self::get_with(row, col, |v: Option<i64>| {
+            v.map(|v| Timestamp::from_unix_millis(v).ok_or_else(|| anyhow!("wrong timestamp {v}")))
+                .transpose()
+        })
+    }
+
+    fn get_json<T: DeserializeOwned>(row: &Row, col: &str) -> rusqlite::Result<T> {
+        Self::get_with(row, col, |v: String| serde_json::from_str(&v))
+    }
 
-        Ok(ts)
+    fn food_from_row(row: &Row) -> rusqlite::Result<Food> {
+        Ok(Food {
+            key: row.get("key")?,
+            name: row.get("name")?,
+            brand: row.get("brand")?,
+            cal100: row.get("cal100")?,
+            prot100: row.get("prot100")?,
+            fat100: row.get("fat100")?,
+            carb100: row.get("carb100")?,
+            comment: row.get("comment")?,
+        })
     }
 
-    fn get_float(row: &HashMap<String, Value>, field: &str) -> Result<f64> {
-        let Some(Value::Real(val)) = row.get(field) else {
-            bail!("failed to get \"{field}\" field")
+    fn bundle_row(row: &Row) -> rusqlite::Result<BundleRow> {
+        let item = match row.get::<_, Option<String>>("item_key")? {
+            Some(k) => Some((k, row.get("weight")?)),
+            None => None,
         };
 
-        Ok(*val)
+        Ok((row.get("user_id")?, row.get("key")?, item))
     }
 
-    fn get_integer(row: &HashMap<String, Value>, field: &str) -> Result<i64> {
-        let Some(Value::Integer(val)) = row.get(field) else {
-            bail!("failed to get \"{field}\" field")
-        };
+    // Rows must be ordered by bundle
+    fn group_bundle_rows(rows: Vec<BundleRow>) -> Vec<(i64, Bundle)> {
+        let mut res: Vec<(i64, Bundle)> = Vec::new();
+        for (user_id, key, item) in rows {
+            let same = matches!(res.last(), Some((u, b)) if *u == user_id && b.key == key);
+            if !same {
+                res.push((
+                    user_id,
+                    Bundle {
+                        key,
+                        data: HashMap::new(),
+                    },
+                ));
+            }
 
-        Ok(*val)
+            if let (Some((k, v)), Some((_, bndl))) = (item, res.last_mut()) {
+                bndl.data.insert(k, v);
+            }
+        }
+
+        res
     }
 
-    fn get_string(row: &HashMap<String, Value>, field: &str) -> Result<String> {
-        let Some(Value::Text(val)) = row.get(field) else {
-            bail!("failed to get \"{field}\" field")
-        };
+    fn sport_from_row(row: &Row) -> rusqlite::Result<Sport> {
+        Ok(Sport {
+            key: row.get("key")?,
+            name: row.get("name")?,
+            comment: row.get("comment")?,
+        })
+    }
+
+    fn metric_from_row(row: &Row) -> rusqlite::Result<Metric> {
+        Ok(Metric {
+            key: row.get("key")?,
+            name: row.get("name")?,
+            unit: row.get("unit")?,
+            fields: Self::get_json(row, "fields")?,
+        })
+    }
+
+    fn change_from_row(row: &Row) -> rusqlite::Result<Change> {
+        Ok(Change {
+            timestamp: Self::get_timestamp(row, "timestamp")?,
+            action: Self::get_with(row, "action", ChangeAction::new)?,
+            key: row.get("key")?,
+        })
+    }
+
+    fn is_foreign_key_error(err: &Error) -> bool {
+        err.chain().any(|cause| {
+            matches!(
+                cause.downcast_ref::<rusqlite::Error>(),
+                Some(SqliteFailure(_, Some(val))) if val == "FOREIGN KEY constraint failed"
+            )
+        })
+    }
+
+    fn get_bundle_conn(conn: &Connection, user_id: i64, key: &str) -> Result<Bundle> {
+        let rows = Self::query_map_conn(
+            conn,
+            queries::SELECT_BUNDLE,
+            params![user_id, key],
+            Self::bundle_row,
+        )
+        .context("get bundle query")?;
+
+        match Self::group_bundle_rows(rows).pop() {
+            Some((_, bndl)) => Ok(bndl),
+            None => Err(StorageError::BundleNotFound(key.into())),
+        }
+    }
+
+    // Replaces bundle items, items reference existing rows by foreign keys
+    fn set_bundle_tx(tx: &Transaction, user_id: i64, bndl: &Bundle) -> Result<()> {
+        Self::raw_execute_tx(
+            tx,
+            queries::INSERT_BUNDLE,
+            false,
+            params![user_id, bndl.key],
+        )?;
+        Self::raw_execute_tx(
+            tx,
+            queries::DELETE_BUNDLE_ITEMS,
+            false,
+            params![user_id, bndl.key],
+        )?;
+
+        for (k, v) in &bndl.data {
+            let is_bundle = *v == 0.0;
+            let (food_key, child_key, weight) = if is_bundle {
+                (None, Some(k), None)
+            } else {
+                (Some(k), None, Some(*v))
+            };
+
+            match Self::raw_execute_tx(
+                tx,
+                queries::INSERT_BUNDLE_ITEM,
+                false,
+                params![user_id, bndl.key, food_key, child_key, weight],
+            ) {
+                Err(err) if Self::is_foreign_key_error(&err) && is_bundle => {
+                    return Err(StorageError::BundleDepBundleNotFound(k.clone()))
+                }
+                Err(err) if Self::is_foreign_key_error(&err) => {
+                    return Err(StorageError::BundleDepFoodNotFound(k.clone()))
+                }
+                Err(err) => return Err(err.into()),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    // Bundle rows go first, so items may reference any restored bundle
+    fn restore_bundles_tx(tx: &Transaction, bundles: &[BundleBackup]) -> Result<()> {
+        for b in bundles {
+            Self::raw_execute_tx(tx, queries::INSERT_BUNDLE, false, params![b.user_id, b.key])
+                .context("exec insert backup bundle")?;
+        }
 
-        Ok(val.clone())
+        for b in bundles {
+            let bndl = Bundle {
+                key: b.key.clone(),
+                data: serde_json::from_str(&b.data).context("convert bundle data from JSON")?,
+            };
+            Self::set_bundle_tx(tx, b.user_id, &bndl)?;
+        }
+
+        Ok(())
     }
 
     fn get_bundle_food_items(
@@ -225,40 +530,17 @@ impl StorageSqlite {
         let mut i = 0;
 
         while i < bundles.len() {
-            // Get next bundle
-            let db_res = Self::raw_query_tx(
-                tx,
-                queries::SELECT_BUNDLE,
-                params![user_id, bundles.get(i).unwrap()],
-            )
-            .context("get bundle query")?;
-
-            if db_res.is_empty() {
-                bail!(StorageError::BundleNotFound)
-            }
-
-            // Parse bundle data
-            let json_data = Self::get_string(db_res.first().unwrap(), "data")
-                .context("get bundle data field")?;
-            let data: HashMap<String, f64> =
-                serde_json::from_str(&json_data).context("convert bundle data from JSON")?;
+            // Get next bundle, food items exist by foreign key
+            let bndl = Self::get_bundle_conn(tx, user_id, &bundles[i])?;
 
-            for (k, v) in &data {
-                if *v == 0.0 {
+            for (k, v) in bndl.data {
+                if v == 0.0 {
                     // Add bundle next bundle
-                    bundles.push(k.clone());
+                    bundles.push(k);
                     continue;
                 }
 
-                // Check if food exists add to result map
-                let db_res = Self::raw_query_tx(tx, queries::SELECT_FOOD, params![k])
-                    .context("get food query")?;
-
-                if db_res.is_empty() {
-                    bail!(StorageError::FoodNotFound)
-                }
-
-                res.insert(k.clone(), *v);
+                res.insert(k, v);
             }
 
             i += 1;
@@ -266,6 +548,20 @@ impl StorageSqlite {
 
         Ok(res)
     }
+    // Bundle row goes first, so undo restores it before its items
+    fn bundle_rows(user_id: i64, key: &str) -> [(&'static str, JsonValue); 2] {
+        [
+            ("bundle", json!({"user_id": user_id, "key": key})),
+            (
+                "bundle_item",
+                json!({"user_id": user_id, "bundle_key": key}),
+            ),
+        ]
+    }
+
+    fn schedule_key(kind: ScheduleKind, hour: u8, minute: u8) -> String {
+        format!("{} {hour:02}:{minute:02}", kind.key())
+    }
 }
 
 impl Storage for StorageSqlite {
@@ -274,134 +570,107 @@ impl Storage for StorageSqlite {
     //
 
     fn get_food(&self, key: &str) -> Result<Food> {
-        let db_res = self
-            .raw_query(queries::SELECT_FOOD, params![key])
-            .context("get food query")?;
-
-        ensure!(!db_res.is_empty(), StorageError::FoodNotFound);
-
-        let row = db_res.first().unwrap();
-
-        Ok(Food {
-            key: Self::get_string(row, "key").context("get food key field")?,
-            name: Self::get_string(row, "name").context("get food name field")?,
-            brand: Self::get_string(row, "brand").context("get food brand field")?,
-            cal100: Self::get_float(row, "cal100").context("get food cal100 field")?,
-            prot100: Self::get_float(row, "prot100").context("get food prot100 field")?,
-            fat100: Self::get_float(row, "fat100").context("get food fat100 field")?,
-            carb100: Self::get_float(row, "carb100").context("get food carb100 field")?,
-            comment: Self::get_string(row, "comment").context("get food comment field")?,
-        })
+        self.query_opt(queries::SELECT_FOOD, params![key], Self::food_from_row)
+            .context("get food query")?
+            .ok_or_else(|| StorageError::FoodNotFound(key.into()))
     }
 
     fn get_food_list(&self) -> Result<Vec<Food>> {
-        let db_res = self
-            .raw_query(queries::SELECT_FOOD_LIST, params![])
+        let food_list = self
+            .query_map(queries::SELECT_FOOD_LIST, params![], Self::food_from_row)
             .context("get food list query")?;
 
-        ensure!(!db_res.is_empty(), StorageError::EmptyResult);
-
-        let mut food_list = Vec::with_capacity(db_res.len());
-        for row in &db_res {
-            food_list.push(Food {
-                key: Self::get_string(row, "key").context("get food key field")?,
-                name: Self::get_string(row, "name").context("get food name field")?,
-                brand: Self::get_string(row, "brand").context("get food brand field")?,
-                cal100: Self::get_float(row, "cal100").context("get food cal100 field")?,
-                prot100: Self::get_float(row, "prot100").context("get food prot100 field")?,
-                fat100: Self::get_float(row, "fat100").context("get food fat100 field")?,
-                carb100: Self::get_float(row, "carb100").context("get food carb100 field")?,
-                comment: Self::get_string(row, "comment").context("get food comment field")?,
-            });
+        if food_list.is_empty() {
+            return Err(StorageError::EmptyResult);
         }
 
         Ok(food_list)
     }
 
-    fn set_food(&self, food: &Food) -> Result<()> {
-        ensure!(food.validate(), StorageError::FoodInvalid);
+    fn set_food(&self, user_id: i64, food: &Food) -> Result<()> {
+        if !food.validate() {
+            return Err(StorageError::FoodInvalid);
+        }
 
-        self.raw_execute(
-            queries::UPSERT_FOOD,
-            false,
-            params![
-                food.key,
-                food.name,
-                food.brand,
-                food.cal100,
-                food.prot100,
-                food.fat100,
-                food.carb100,
-                food.comment
-            ],
+        self.execute_change(
+            user_id,
+            ChangeAction::SetFood,
+            &food.key,
+            &[("food", json!({"key": food.key}))],
+            |tx| {
+                Self::raw_execute_tx(
+                    tx,
+                    queries::UPSERT_FOOD,
+                    false,
+                    params![
+                        food.key,
+                        food.name,
+                        food.brand,
+                        food.cal100,
+                        food.prot100,
+                        food.fat100,
+                        food.carb100,
+                        food.comment
+                    ],
+                )
+                .context("exec upsert food")?;
+
+                Ok(())
+            },
         )
-        .context("exec upsert food")
     }
 
     fn find_food(&self, pattern: &str) -> Result<Vec<Food>> {
-        let db_res = self
-            .raw_query(queries::FIND_FOOD, params![pattern.to_uppercase()])
+        let food_list = self
+            .query_map(
+                queries::FIND_FOOD,
+                params![pattern.to_uppercase()],
+                Self::food_from_row,
+            )
             .context("find food list query")?;
 
-        ensure!(!db_res.is_empty(), StorageError::EmptyResult);
-
-        let mut food_list = Vec::with_capacity(db_res.len());
-        for row in &db_res {
-            food_list.push(Food {
-                key: Self::get_string(row, "key").context("get food key field")?,
-                name: Self::get_string(row, "name").context("get food name field")?,
-                brand: Self::get_string(row, "brand").context("get food brand field")?,
-                cal100: Self::get_float(row, "cal100").context("get food cal100 field")?,
-                prot100: Self::get_float(row, "prot100").context("get food prot100 field")?,
-                fat100: Self::get_float(row, "fat100").context("get food fat100 field")?,
-                carb100: Self::get_float(row, "carb100").context("get food carb100 field")?,
-                comment: Self::get_string(row, "comment").context("get food comment field")?,
-            });
+        if food_list.is_empty() {
+            return Err(StorageError::EmptyResult);
         }
 
         Ok(food_list)
     }
 
-    fn delete_food(&self, key: &str) -> Result<()> {
-        let mut conn = self.conn.lock().unwrap();
-        let tx = conn.transaction().context("failed to get transaction")?;
-
-        // Check that food not used in bundle
-        let db_res = Self::raw_query_tx(&tx, queries::SELECT_ALL_BUNDLES, params![])
-            .context("get all bundles query")?;
-
-        for row in &db_res {
-            let json_data = Self::get_string(row, "data").context("get bundle data field")?;
-            let data: HashMap<String, f64> =
-                serde_json::from_str(&json_data).context("convert bundle data from JSON")?;
+    fn get_unused_food_list(&self) -> Result<Vec<Food>> {
+        let food_list = self
+            .query_map(
+                queries::SELECT_UNUSED_FOOD_LIST,
+                params![],
+                Self::food_from_row,
+            )
+            .context("get unused food list query")?;
 
-            for (k, v) in &data {
-                if *v > 0.0 && k == key {
-                    bail!(StorageError::FoodIsUsed)
-                }
-            }
+        if food_list.is_empty() {
+            return Err(StorageError::EmptyResult);
         }
 
-        // Delete food
-        if let Err(err) = Self::raw_execute_tx(&tx, queries::DELETE_FOOD, false, params![key])
-            .context("exec delete food")
-        {
-            for cause in err.chain() {
-                if let Some(SqliteFailure(_, Some(val))) = cause.downcast_ref::<rusqlite::Error>() {
-                    if val == "FOREIGN KEY constraint failed" {
-                        bail!(StorageError::FoodIsUsed)
-                    };
-
-                    bail!(err);
-                }
-            }
-
-            bail!(err);
-        };
-
-        tx.commit().context("failed to commit transaction")?;
+        Ok(food_list)
+    }
 
-        Ok(())
+    fn delete_food(&self, user_id: i64, key: &str) -> Result<()> {
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteFood,
+            key,
+            &[("food", json!({"key": key}))],
+            |tx| {
+                // Food used in journal or bundle is protected by foreign keys
+                match Self::raw_execute_tx(tx, queries::DELETE_FOOD, false, params![key])
+                    .context("exec delete food")
+                {
+                    Err(err) if Self::is_foreign_key_error(&err) => {
+                        Err(StorageError::FoodIsUsed(key.into()))
+                    }
+                    Err(err) => Err(err.into()),
+                    _ => Ok(()),
+                }
+            },
+        )
     }
 
     //
@@ -409,163 +678,163 @@ impl Storage for StorageSqlite {
     //
 
     fn get_bundle(&self, user_id: i64, key: &str) -> Result<Bundle> {
-        let db_res = self
-            .raw_query(queries::SELECT_BUNDLE, params![user_id, key])
-            .context("get bundle query")?;
-
-        ensure!(!db_res.is_empty(), StorageError::BundleNotFound);
-
-        let row = db_res.first().unwrap();
-
-        let json_data = Self::get_string(row, "data").context("get bundle data field")?;
-        let data: HashMap<String, f64> =
-            serde_json::from_str(&json_data).context("convert bundle data from JSON")?;
+        if self.readers.size() == 0 {
+            let conn = self.conn.lock().unwrap();
+            return Self::get_bundle_conn(&conn, user_id, key);
+        }
 
-        Ok(Bundle {
-            key: Self::get_string(row, "key").context("get bundle key field")?,
-            data,
-        })
+        let conn = self.readers.get();
+        Self::get_bundle_conn(&conn, user_id, key)
     }
 
     fn get_bundle_list(&self, user_id: i64) -> Result<Vec<Bundle>> {
-        let db_res = self
-            .raw_query(queries::SELECT_BUNDLE_LIST, params![user_id])
+        let rows = self
+            .query_map(
+                queries::SELECT_BUNDLE_LIST,
+                params![user_id],
+                Self::bundle_row,
+            )
             .context("get bundle list query")?;
 
-        ensure!(!db_res.is_empty(), StorageError::EmptyResult);
-
-        let mut res = Vec::with_capacity(db_res.len());
-        for row in &db_res {
-            let json_data = Self::get_string(row, "data").context("get bundle data field")?;
-            let data: HashMap<String, f64> =
-                serde_json::from_str(&json_data).context("convert bundle data from JSON")?;
-
-            res.push(Bundle {
-                key: Self::get_string(row, "key").context("get bundle key field")?,
-                data,
-            });
+        if rows.is_empty() {
+            return Err(StorageError::EmptyResult);
         }
 
-        Ok(res)
+        Ok(Self::group_bundle_rows(rows)
+            .into_iter()
+            .map(|(_, bndl)| bndl)
+            .collect())
     }
 
     fn set_bundle(&self, user_id: i64, bndl: &Bundle) -> Result<()> {
-        ensure!(bndl.validate(), StorageError::BundleInvalid);
+        if !bndl.validate() {
+            return Err(StorageError::BundleInvalid);
+        }
 
-        let mut conn = self.conn.lock().unwrap();
-        let tx = conn.transaction().context("failed to get transaction")?;
+        if bndl.data.get(&bndl.key) == Some(&0.0) {
+            return Err(StorageError::BundleDepRecursive(bndl.key.clone()));
+        }
 
-        // Check bundle data
-        for (k, v) in &bndl.data {
-            if *v == 0.0 {
-                // Dependent bundle
-                if *k == bndl.key {
-                    bail!(StorageError::BundleDepRecursive)
+        self.execute_change(
+            user_id,
+            ChangeAction::SetBundle,
+            &bndl.key,
+            &Self::bundle_rows(user_id, &bndl.key),
+            |tx| Self::set_bundle_tx(tx, user_id, bndl),
+        )
+    }
+
+    fn delete_bundle(&self, user_id: i64, key: &str) -> Result<()> {
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteBundle,
+            key,
+            &Self::bundle_rows(user_id, key),
+            |tx| {
+                // Bundle used in other bundles is protected by foreign key
+                match Self::raw_execute_tx(tx, queries::DELETE_BUNDLE, false, params![user_id, key])
+                {
+                    Err(err) if Self::is_foreign_key_error(&err) => {
+                        let used_in = Self::query_opt_conn(
+                            tx,
+                            queries::SELECT_BUNDLE_PARENT,
+                            params![user_id, key],
+                            |row| row.get("bundle_key"),
+                        )
+                        .context("get bundle parent query")?
+                        .unwrap_or_default();
+
+                        Err(StorageError::BundleIsUsed {
+                            key: key.into(),
+                            used_in,
+                        })
+                    }
+                    Err(err) => Err(err.into()),
+                    _ => Ok(()),
                 }
+            },
+        )
+    }
 
-                let db_res = Self::raw_query_tx(&tx, queries::SELECT_BUNDLE, params![user_id, k])
-                    .context("get bundle query")?;
+    //
+    // Weight
+    //
 
-                if db_res.is_empty() {
-                    bail!(StorageError::BundleDepBundleNotFound)
-                }
-            } else {
-                // Dependent food
-                let db_res = Self::raw_query_tx(&tx, queries::SELECT_FOOD, params![k])
-                    .context("get food query")?;
+    fn get_weight_list(&self, user_id: i64, from: Timestamp, to: Timestamp) -> Result<Vec<Weight>> {
+        let res = self
+            .query_map(
+                queries::SELECT_WEIGHT_LIST,
+                params![user_id, from.unix_millis(), to.unix_millis()],
+                |row| {
+                    Ok(Weight {
+                        timestamp: Self::get_timestamp(row, "timestamp")?,
+                        value: row.get("value")?,
+                    })
+                },
+            )
+            .context("weight list query")?;
 
-                if db_res.is_empty() {
-                    bail!(StorageError::BundleDepFoodNotFound)
-                }
-            }
+        if res.is_empty() {
+            return Err(StorageError::EmptyResult);
         }
 
-        // Set bundle
-        let data =
-            serde_json::to_string(&json!(bndl.data)).context("convert bundle data to JSON")?;
+        Ok(res)
+    }
 
-        Self::raw_execute_tx(
-            &tx,
-            queries::UPSERT_BUNDLE,
-            false,
-            params![user_id, bndl.key, data],
-        )?;
-        tx.commit().context("failed to commit transaction")?;
-
-        Ok(())
-    }
-
-    fn delete_bundle(&self, user_id: i64, key: &str) -> Result<()> {
-        let mut conn = self.conn.lock().unwrap();
-        let tx = conn.transaction().context("failed to get transaction")?;
-
-        // Check that bundle not used in other bundles
-        let db_res = Self::raw_query_tx(&tx, queries::SELECT_BUNDLE_LIST, params![user_id])
-            .context("get bundle list query")?;
-
-        for row in &db_res {
-            let json_data = Self::get_string(row, "data").context("get bundle data field")?;
-            let data: HashMap<String, f64> =
-                serde_json::from_str(&json_data).context("convert bundle data from JSON")?;
-
-            for (k, v) in &data {
-                if *v == 0.0 && k == key {
-                    bail!(StorageError::BundleIsUsed)
-                }
-            }
-        }
-
-        // Delete bundle
-        Self::raw_execute_tx(&tx, queries::DELETE_BUNDLE, false, params![user_id, key])?;
-        tx.commit().context("failed to commit transaction")?;
-
-        Ok(())
-    }
-
-    //
-    // Weight
-    //
-
-    fn get_weight_list(&self, user_id: i64, from: Timestamp, to: Timestamp) -> Result<Vec<Weight>> {
-        let db_res = self
-            .raw_query(
-                queries::SELECT_WEIGHT_LIST,
-                params![user_id, from.unix_millis(), to.unix_millis()],
-            )
-            .context("weight list query")?;
-
-        ensure!(!db_res.is_empty(), StorageError::EmptyResult);
-
-        let mut res = Vec::with_capacity(db_res.len());
-        for row in &db_res {
-            res.push(Weight {
-                timestamp: Self::get_timestamp(row, "timestamp")
-                    .context("get weight timestamp field")?,
-                value: Self::get_float(row, "value").context("get weight value field")?,
-            });
-        }
-
-        Ok(res)
+    fn get_last_weight(&self, user_id: i64, to: Timestamp) -> Result<Weight> {
+        self.query_opt(
+            queries::SELECT_LAST_WEIGHT,
+            params![user_id, to.unix_millis()],
+            |row| {
+                Ok(Weight {
+                    timestamp: Self::get_timestamp(row, "timestamp")?,
+                    value: row.get("value")?,
+                })
+            },
+        )
+        .context("last weight query")?
+        .ok_or(StorageError::EmptyResult)
     }
 
     fn set_weight(&self, user_id: i64, weight: &Weight) -> Result<()> {
-        ensure!(weight.validate(), StorageError::WeightInvalid);
+        if !weight.validate() {
+            return Err(StorageError::WeightInvalid);
+        }
 
-        self.raw_execute(
-            queries::UPSERT_WEIGHT,
-            false,
-            params![user_id, weight.timestamp.unix_millis(), weight.value],
+        let ts = weight.timestamp.unix_millis();
+        self.execute_change(
+            user_id,
+            ChangeAction::SetWeight,
+            "",
+            &[("weight", json!({"user_id": user_id, "timestamp": ts}))],
+            |tx| {
+                Self::raw_execute_tx(
+                    tx,
+                    queries::UPSERT_WEIGHT,
+                    false,
+                    params![user_id, ts, weight.value],
+                )
+                .context("exec upsert weight")?;
+
+                Ok(())
+            },
         )
-        .context("exec upsert weight")
     }
 
     fn delete_weight(&self, user_id: i64, timestamp: Timestamp) -> Result<()> {
-        self.raw_execute(
-            queries::DELETE_WEIGHT,
-            false,
-            params![user_id, timestamp.unix_millis()],
+        let ts = timestamp.unix_millis();
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteWeight,
+            "",
+            &[("weight", json!({"user_id": user_id, "timestamp": ts}))],
+            |tx| {
+                Self::raw_execute_tx(tx, queries::DELETE_WEIGHT, false, params![user_id, ts])
+                    .context("exec delete weight")?;
+
+                Ok(())
+            },
         )
-        .context("exec delete weight")
     }
 
     //
@@ -573,28 +842,46 @@ impl Storage for StorageSqlite {
     //
 
     fn get_user_settings(&self, user_id: i64) -> Result<UserSettings> {
-        let db_res = self
-            .raw_query(queries::SELECT_USER_SETTINGS, params![user_id])
-            .context("get user settings query")?;
-
-        ensure!(!db_res.is_empty(), StorageError::UserSettingsNotFound);
-
-        let row = db_res.first().unwrap();
-
-        Ok(UserSettings {
-            cal_limit: Self::get_float(row, "cal_limit").context("get cal_limit field")?,
+        self.query_opt(queries::SELECT_USER_SETTINGS, params![user_id], |row| {
+            Ok(UserSettings {
+                cal_limit: row.get("cal_limit")?,
+                sex: Self::get_with(row, "sex", |v: Option<u8>| v.map(Sex::new).transpose())?,
+                height: row.get("height")?,
+                birth_date: Self::get_timestamp_opt(row, "birth_date")?,
+            })
         })
+        .context("get user settings query")?
+        .ok_or(StorageError::UserSettingsNotFound)
     }
 
     fn set_user_settings(&self, user_id: i64, settings: &UserSettings) -> Result<()> {
-        ensure!(settings.validate(), StorageError::UserSettingsInvalid);
+        if !settings.validate() {
+            return Err(StorageError::UserSettingsInvalid);
+        }
 
-        self.raw_execute(
-            queries::UPSERT_USER_SETTINGS,
-            false,
-            params![user_id, settings.cal_limit],
+        self.execute_change(
+            user_id,
+            ChangeAction::SetUserSettings,
+            "",
+            &[("user_settings", json!({"user_id": user_id}))],
+            |tx| {
+                Self::raw_execute_tx(
+                    tx,
+                    queries::UPSERT_USER_SETTINGS,
+                    false,
+                    params![
+                        user_id,
+                        settings.cal_limit,
+                        settings.sex.map(u8::from),
+                        settings.height,
+                        settings.birth_date.as_ref().map(|v| v.unix_millis())
+                    ],
+                )
+                .context("exec upsert user settings")?;
+
+                Ok(())
+            },
         )
-        .context("exec upsert user settings")
     }
 
     //
@@ -602,36 +889,32 @@ impl Storage for StorageSqlite {
     //
 
     fn set_journal(&self, user_id: i64, journal: &Journal) -> Result<()> {
-        ensure!(journal.validate(), StorageError::JournalInvalid);
+        if !journal.validate() {
+            return Err(StorageError::JournalInvalid);
+        }
 
-        match self.raw_execute(
-            queries::UPSERT_JOURNAL,
-            false,
-            params![
-                user_id,
-                journal.timestamp.unix_millis(),
-                u8::from(journal.meal),
-                journal.food_key,
-                journal.food_weight,
-            ],
-        ) {
-            Err(err) => {
-                for cause in err.chain() {
-                    if let Some(SqliteFailure(_, Some(val))) =
-                        cause.downcast_ref::<rusqlite::Error>()
-                    {
-                        if val == "FOREIGN KEY constraint failed" {
-                            bail!(StorageError::FoodNotFound)
-                        };
-
-                        bail!(err);
-                    }
+        let (ts, meal) = (journal.timestamp.unix_millis(), u8::from(journal.meal));
+        self.execute_change(
+            user_id,
+            ChangeAction::SetJournal,
+            &journal.food_key,
+            &[(
+                "journal",
+                json!({"user_id": user_id, "timestamp": ts, "meal": meal, "foodkey": journal.food_key}),
+            )],
+            |tx| match Self::raw_execute_tx(
+                tx,
+                queries::UPSERT_JOURNAL,
+                false,
+                params![user_id, ts, meal, journal.food_key, journal.food_weight],
+            ) {
+                Err(err) if Self::is_foreign_key_error(&err) => {
+                    Err(StorageError::FoodNotFound(journal.food_key.clone()))
                 }
-
-                bail!(err);
-            }
-            _ => Ok(()),
-        }
+                Err(err) => Err(err.into()),
+                _ => Ok(()),
+            },
+        )
     }
 
     fn set_journal_bundle(
@@ -641,20 +924,29 @@ impl Storage for StorageSqlite {
         meal: Meal,
         bndl_key: &str,
     ) -> Result<()> {
-        let mut conn = self.conn.lock().unwrap();
-        let tx = conn.transaction().context("failed to get transaction")?;
-
-        let food_items = Self::get_bundle_food_items(&tx, user_id, bndl_key)?;
-        for (k, v) in food_items {
-            Self::raw_execute_tx(
-                &tx,
-                queries::UPSERT_JOURNAL,
-                false,
-                params![user_id, timestamp.unix_millis(), u8::from(meal), k, v],
-            )?;
-        }
+        let (ts, meal) = (timestamp.unix_millis(), u8::from(meal));
+        self.execute_change(
+            user_id,
+            ChangeAction::SetJournalBundle,
+            bndl_key,
+            &[(
+                "journal",
+                json!({"user_id": user_id, "timestamp": ts, "meal": meal}),
+            )],
+            |tx| {
+                let food_items = Self::get_bundle_food_items(tx, user_id, bndl_key)?;
+                for (k, v) in food_items {
+                    Self::raw_execute_tx(
+                        tx,
+                        queries::UPSERT_JOURNAL,
+                        false,
+                        params![user_id, ts, meal, k, v],
+                    )?;
+                }
 
-        tx.commit().context("failed to commit transaction")
+                Ok(())
+            },
+        )
     }
 
     fn delete_journal(
@@ -664,21 +956,51 @@ impl Storage for StorageSqlite {
         meal: Meal,
         food_key: &str,
     ) -> Result<()> {
-        self.raw_execute(
-            queries::DELETE_JOURNAL,
-            false,
-            params![user_id, timestamp.unix_millis(), u8::from(meal), food_key],
+        let (ts, meal) = (timestamp.unix_millis(), u8::from(meal));
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteJournal,
+            food_key,
+            &[(
+                "journal",
+                json!({"user_id": user_id, "timestamp": ts, "meal": meal, "foodkey": food_key}),
+            )],
+            |tx| {
+                Self::raw_execute_tx(
+                    tx,
+                    queries::DELETE_JOURNAL,
+                    false,
+                    params![user_id, ts, meal, food_key],
+                )
+                .context("exec delete journal")?;
+
+                Ok(())
+            },
         )
-        .context("exec delete journal")
     }
 
     fn delete_journal_meal(&self, user_id: i64, timestamp: Timestamp, meal: Meal) -> Result<()> {
-        self.raw_execute(
-            queries::DELETE_JOURNAL_MEAL,
-            false,
-            params![user_id, timestamp.unix_millis(), u8::from(meal)],
+        let ts = timestamp.unix_millis();
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteJournalMeal,
+            &String::from(meal),
+            &[(
+                "journal",
+                json!({"user_id": user_id, "timestamp": ts, "meal": u8::from(meal)}),
+            )],
+            |tx| {
+                Self::raw_execute_tx(
+                    tx,
+                    queries::DELETE_JOURNAL_MEAL,
+                    false,
+                    params![user_id, ts, u8::from(meal)],
+                )
+                .context("exec delete journal")?;
+
+                Ok(())
+            },
         )
-        .context("exec delete journal")
     }
 
     fn get_journal_report(
@@ -687,30 +1009,29 @@ impl Storage for StorageSqlite {
         from: Timestamp,
         to: Timestamp,
     ) -> Result<Vec<JournalReport>> {
-        let db_res = self
-            .raw_query(
+        let report = self
+            .query_map(
                 queries::JOURNAL_REPORT,
                 params![user_id, from.unix_millis(), to.unix_millis()],
+                |row| {
+                    Ok(JournalReport {
+                        timestamp: Self::get_timestamp(row, "timestamp")?,
+                        meal: Self::get_with(row, "meal", Meal::new)?,
+                        food_key: row.get("foodkey")?,
+                        food_name: row.get("foodname")?,
+                        food_brand: row.get("foodbrand")?,
+                        food_weight: row.get("foodweight")?,
+                        cal: row.get("cal")?,
+                        prot: row.get("prot")?,
+                        fat: row.get("fat")?,
+                        carb: row.get("carb")?,
+                    })
+                },
             )
-            .context("get journal reportl query")?;
-
-        ensure!(!db_res.is_empty(), StorageError::EmptyResult);
-
-        let mut report = Vec::with_capacity(db_res.len());
-        for row in &db_res {
-            report.push(JournalReport {
-                timestamp: Self::get_timestamp(row, "timestamp").context("get timestamp field")?,
-                meal: Meal::new(Self::get_integer(row, "meal").context("get meal field")? as u8)
-                    .context("wrong meal")?,
-                food_key: Self::get_string(row, "foodkey").context("get foodkey field")?,
-                food_name: Self::get_string(row, "foodname").context("get foodname field")?,
-                food_brand: Self::get_string(row, "foodbrand").context("get foodbrand field")?,
-                food_weight: Self::get_float(row, "foodweight").context("get foodweight field")?,
-                cal: Self::get_float(row, "cal").context("get cal field")?,
-                prot: Self::get_float(row, "prot").context("get prot field")?,
-                fat: Self::get_float(row, "fat").context("get fat field")?,
-                carb: Self::get_float(row, "carb").context("get carb field")?,
-            });
+            .context("get journal report query")?;
+
+        if report.is_empty() {
+            return Err(StorageError::EmptyResult);
         }
 
         Ok(report)
@@ -723,15 +1044,76 @@ impl Storage for StorageSqlite {
         from: Timestamp,
         to: Timestamp,
     ) -> Result<f64> {
-        let db_res = self
-            .raw_query(
+        let avg = self
+            .query_opt(
                 queries::JOURNAL_FOOD_AVG_WEIGHT,
                 params![user_id, food_key, from.unix_millis(), to.unix_millis()],
+                |row| row.get("avg_food_weight"),
+            )
+            .context("get journal food avg weight query")?
+            .context("get avg_food_weight field")?;
+
+        Ok(avg)
+    }
+
+    fn get_journal_food_usage(
+        &self,
+        user_id: i64,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<Vec<FoodUsage>> {
+        let usage = self
+            .query_map(
+                queries::JOURNAL_FOOD_USAGE,
+                params![user_id, from.unix_millis(), to.unix_millis()],
+                |row| {
+                    Ok(FoodUsage {
+                        food_key: row.get("foodkey")?,
+                        food_name: row.get("foodname")?,
+                        food_brand: row.get("foodbrand")?,
+                        count: row.get("cnt")?,
+                        food_weight: row.get("foodweight")?,
+                        cal: row.get("cal")?,
+                        prot: row.get("prot")?,
+                    })
+                },
+            )
+            .context("get journal food usage query")?;
+
+        if usage.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        Ok(usage)
+    }
+
+    fn get_journal_food_meal_usage(
+        &self,
+        user_id: i64,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<Vec<FoodMealUsage>> {
+        let usage = self
+            .query_map(
+                queries::JOURNAL_FOOD_MEAL_USAGE,
+                params![user_id, from.unix_millis(), to.unix_millis()],
+                |row| {
+                    Ok(FoodMealUsage {
+                        food_key: row.get("foodkey")?,
+                        food_name: row.get("foodname")?,
+                        food_brand: row.get("foodbrand")?,
+                        meal: Self::get_with(row, "meal", Meal::new)?,
+                        count: row.get("cnt")?,
+                    })
+                },
             )
-            .context("get journal food avg weight query")?;
+            .context("get journal food meal usage query")?;
 
-        Self::get_float(db_res.first().unwrap(), "avg_food_weight")
-            .context("get avg_food_weight field")
+        if usage.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        Ok(usage)
     }
 
     //
@@ -739,73 +1121,63 @@ impl Storage for StorageSqlite {
     //
 
     fn get_sport(&self, key: &str) -> Result<Sport> {
-        let db_res = self
-            .raw_query(queries::SELECT_SPORT, params![key])
-            .context("get sport query")?;
-
-        ensure!(!db_res.is_empty(), StorageError::SportNotFound);
-
-        let row = db_res.first().unwrap();
-
-        Ok(Sport {
-            key: Self::get_string(row, "key").context("get sport key field")?,
-            name: Self::get_string(row, "name").context("get food sport field")?,
-            comment: Self::get_string(row, "comment").context("get sport comment field")?,
-        })
+        self.query_opt(queries::SELECT_SPORT, params![key], Self::sport_from_row)
+            .context("get sport query")?
+            .ok_or_else(|| StorageError::SportNotFound(key.into()))
     }
 
     fn get_sport_list(&self) -> Result<Vec<Sport>> {
-        let db_res = self
-            .raw_query(queries::SELECT_SPORT_LIST, params![])
+        let sport_list = self
+            .query_map(queries::SELECT_SPORT_LIST, params![], Self::sport_from_row)
             .context("get sport list query")?;
 
-        ensure!(!db_res.is_empty(), StorageError::EmptyResult);
-
-        let mut sport_list = Vec::with_capacity(db_res.len());
-        for row in &db_res {
-            sport_list.push(Sport {
-                key: Self::get_string(row, "key").context("get sport key field")?,
-                name: Self::get_string(row, "name").context("get sport name field")?,
-                comment: Self::get_string(row, "comment").context("get sport comment field")?,
-            });
+        if sport_list.is_empty() {
+            return Err(StorageError::EmptyResult);
         }
 
         Ok(sport_list)
     }
 
-    fn set_sport(&self, sport: &Sport) -> Result<()> {
-        ensure!(sport.validate(), StorageError::SportInvalid);
+    fn set_sport(&self, user_id: i64, sport: &Sport) -> Result<()> {
+        if !sport.validate() {
+            return Err(StorageError::SportInvalid);
+        }
 
-        self.raw_execute(
-            queries::UPSERT_SPORT,
-            false,
-            params![sport.key, sport.name, sport.comment],
+        self.execute_change(
+            user_id,
+            ChangeAction::SetSport,
+            &sport.key,
+            &[("sport", json!({"key": sport.key}))],
+            |tx| {
+                Self::raw_execute_tx(
+                    tx,
+                    queries::UPSERT_SPORT,
+                    false,
+                    params![sport.key, sport.name, sport.comment],
+                )
+                .context("exec upsert sport")?;
+
+                Ok(())
+            },
         )
-        .context("exec upsert sport")
-    }
-
-    fn delete_sport(&self, key: &str) -> Result<()> {
-        match self
-            .raw_execute(queries::DELETE_SPORT, false, params![key])
-            .context("exec delete sport")
-        {
-            Err(err) => {
-                for cause in err.chain() {
-                    if let Some(SqliteFailure(_, Some(val))) =
-                        cause.downcast_ref::<rusqlite::Error>()
-                    {
-                        if val == "FOREIGN KEY constraint failed" {
-                            bail!(StorageError::SportIsUsedViolation)
-                        };
-
-                        bail!(err);
-                    }
-                }
+    }
 
-                bail!(err);
-            }
-            _ => Ok(()),
-        }
+    fn delete_sport(&self, user_id: i64, key: &str) -> Result<()> {
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteSport,
+            key,
+            &[("sport", json!({"key": key}))],
+            |tx| match Self::raw_execute_tx(tx, queries::DELETE_SPORT, false, params![key])
+                .context("exec delete sport")
+            {
+                Err(err) if Self::is_foreign_key_error(&err) => {
+                    Err(StorageError::SportIsUsed(key.into()))
+                }
+                Err(err) => Err(err.into()),
+                _ => Ok(()),
+            },
+        )
     }
 
     //
@@ -813,39 +1185,36 @@ impl Storage for StorageSqlite {
     //
 
     fn set_sport_activity(&self, user_id: i64, act: &SportActivity) -> Result<()> {
-        ensure!(act.validate(), StorageError::SportActivityInvalid);
+        if !act.validate() {
+            return Err(StorageError::SportActivityInvalid);
+        }
 
         // Convert sets to JSON array
         let str_sets = serde_json::to_string(&json!(act.sets))
             .context("convert sport activity sets to JSON")?;
 
-        match self.raw_execute(
-            queries::UPSERT_SPORT_ACTIVITY,
-            false,
-            params![
-                user_id,
-                act.timestamp.unix_millis(),
-                act.sport_key,
-                str_sets
-            ],
-        ) {
-            Err(err) => {
-                for cause in err.chain() {
-                    if let Some(SqliteFailure(_, Some(val))) =
-                        cause.downcast_ref::<rusqlite::Error>()
-                    {
-                        if val == "FOREIGN KEY constraint failed" {
-                            bail!(StorageError::SportInvalid)
-                        };
-
-                        bail!(err);
-                    }
+        let ts = act.timestamp.unix_millis();
+        self.execute_change(
+            user_id,
+            ChangeAction::SetSportActivity,
+            &act.sport_key,
+            &[(
+                "sport_activity",
+                json!({"user_id": user_id, "timestamp": ts, "sport_key": act.sport_key}),
+            )],
+            |tx| match Self::raw_execute_tx(
+                tx,
+                queries::UPSERT_SPORT_ACTIVITY,
+                false,
+                params![user_id, ts, act.sport_key, str_sets],
+            ) {
+                Err(err) if Self::is_foreign_key_error(&err) => {
+                    Err(StorageError::SportNotFound(act.sport_key.clone()))
                 }
-
-                bail!(err);
-            }
-            _ => Ok(()),
-        }
+                Err(err) => Err(err.into()),
+                _ => Ok(()),
+            },
+        )
     }
 
     fn delete_sport_activity(
@@ -854,12 +1223,27 @@ impl Storage for StorageSqlite {
         timestamp: Timestamp,
         sport_key: &str,
     ) -> Result<()> {
-        self.raw_execute(
-            queries::DELETE_SPORT_ACTIVITY,
-            false,
-            params![user_id, timestamp.unix_millis(), sport_key],
+        let ts = timestamp.unix_millis();
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteSportActivity,
+            sport_key,
+            &[(
+                "sport_activity",
+                json!({"user_id": user_id, "timestamp": ts, "sport_key": sport_key}),
+            )],
+            |tx| {
+                Self::raw_execute_tx(
+                    tx,
+                    queries::DELETE_SPORT_ACTIVITY,
+                    false,
+                    params![user_id, ts, sport_key],
+                )
+                .context("exec delete sport activity")?;
+
+                Ok(())
+            },
         )
-        .context("exec delete sport activity")
     }
 
     fn get_sport_activity_report(
@@ -868,26 +1252,433 @@ impl Storage for StorageSqlite {
         from: Timestamp,
         to: Timestamp,
     ) -> Result<Vec<SportActivityReport>> {
-        let db_res = self
-            .raw_query(
+        let res = self
+            .query_map(
                 queries::SELECT_SPORT_ACTIVITY_REPORT,
                 params![user_id, from.unix_millis(), to.unix_millis()],
+                |row| {
+                    Ok(SportActivityReport {
+                        sport_name: row.get("sport_name")?,
+                        timestamp: Self::get_timestamp(row, "timestamp")?,
+                        sets: Self::get_json(row, "sets")?,
+                    })
+                },
             )
             .context("sport activity report query")?;
 
-        ensure!(!db_res.is_empty(), StorageError::EmptyResult);
+        if res.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        Ok(res)
+    }
 
-        let mut res = Vec::with_capacity(db_res.len());
-        for row in &db_res {
-            let json_sets = Self::get_string(row, "sets").context("get sets field")?;
-            let sets: Vec<i64> =
-                serde_json::from_str(&json_sets).context("convert sets from JSON")?;
+    //
+    // Metric
+    //
 
-            res.push(SportActivityReport {
-                sport_name: Self::get_string(row, "sport_name").context("get sport name field")?,
-                timestamp: Self::get_timestamp(row, "timestamp").context("get timestamp field")?,
-                sets,
-            });
+    fn get_metric(&self, user_id: i64, key: &str) -> Result<Metric> {
+        self.query_opt(
+            queries::SELECT_METRIC,
+            params![user_id, key],
+            Self::metric_from_row,
+        )
+        .context("get metric query")?
+        .ok_or_else(|| StorageError::MetricNotFound(key.into()))
+    }
+
+    fn get_metric_list(&self, user_id: i64) -> Result<Vec<Metric>> {
+        let res = self
+            .query_map(
+                queries::SELECT_METRIC_LIST,
+                params![user_id],
+                Self::metric_from_row,
+            )
+            .context("get metric list query")?;
+
+        if res.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        Ok(res)
+    }
+
+    fn set_metric(&self, user_id: i64, metric: &Metric) -> Result<()> {
+        if !metric.validate() {
+            return Err(StorageError::MetricInvalid);
+        }
+
+        let fields = serde_json::to_string(&json!(metric.fields))
+            .context("convert metric fields to JSON")?;
+
+        self.execute_change(
+            user_id,
+            ChangeAction::SetMetric,
+            &metric.key,
+            &[("metric", json!({"user_id": user_id, "key": metric.key}))],
+            |tx| {
+                Self::raw_execute_tx(
+                    tx,
+                    queries::UPSERT_METRIC,
+                    false,
+                    params![user_id, metric.key, metric.name, metric.unit, fields],
+                )
+                .context("exec upsert metric")?;
+
+                Ok(())
+            },
+        )
+    }
+
+    fn delete_metric(&self, user_id: i64, key: &str) -> Result<()> {
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteMetric,
+            key,
+            &[("metric", json!({"user_id": user_id, "key": key}))],
+            |tx| match Self::raw_execute_tx(
+                tx,
+                queries::DELETE_METRIC,
+                false,
+                params![user_id, key],
+            )
+            .context("exec delete metric")
+            {
+                Err(err) if Self::is_foreign_key_error(&err) => {
+                    Err(StorageError::MetricIsUsed(key.into()))
+                }
+                Err(err) => Err(err.into()),
+                _ => Ok(()),
+            },
+        )
+    }
+
+    //
+    // Metric value
+    //
+
+    fn set_metric_value(&self, user_id: i64, val: &MetricValue) -> Result<()> {
+        if !val.validate() {
+            return Err(StorageError::MetricValueInvalid);
+        }
+
+        let ts = val.timestamp.unix_millis();
+        self.execute_change(
+            user_id,
+            ChangeAction::SetMetricValue,
+            &val.metric_key,
+            &[(
+                "metric_value",
+                json!({"user_id": user_id, "timestamp": ts, "metric_key": val.metric_key}),
+            )],
+            |tx| {
+                // Check that values match metric fields
+                let Some(fields) = Self::query_opt_conn(
+                    tx,
+                    queries::SELECT_METRIC,
+                    params![user_id, val.metric_key],
+                    |row| Self::get_json::<Vec<String>>(row, "fields"),
+                )
+                .context("get metric query")?
+                else {
+                    return Err(StorageError::MetricNotFound(val.metric_key.clone()));
+                };
+
+                if fields.len() != val.values.len() {
+                    return Err(StorageError::MetricValueInvalid);
+                }
+
+                // Set metric value
+                let vals = serde_json::to_string(&json!(val.values))
+                    .context("convert metric values to JSON")?;
+
+                Self::raw_execute_tx(
+                    tx,
+                    queries::UPSERT_METRIC_VALUE,
+                    false,
+                    params![user_id, ts, val.metric_key, vals],
+                )?;
+
+                Ok(())
+            },
+        )
+    }
+
+    fn delete_metric_value(
+        &self,
+        user_id: i64,
+        timestamp: Timestamp,
+        metric_key: &str,
+    ) -> Result<()> {
+        let ts = timestamp.unix_millis();
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteMetricValue,
+            metric_key,
+            &[(
+                "metric_value",
+                json!({"user_id": user_id, "timestamp": ts, "metric_key": metric_key}),
+            )],
+            |tx| {
+                Self::raw_execute_tx(
+                    tx,
+                    queries::DELETE_METRIC_VALUE,
+                    false,
+                    params![user_id, ts, metric_key],
+                )
+                .context("exec delete metric value")?;
+
+                Ok(())
+            },
+        )
+    }
+
+    fn get_metric_value_list(
+        &self,
+        user_id: i64,
+        metric_key: &str,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<Vec<MetricValue>> {
+        let res = self
+            .query_map(
+                queries::SELECT_METRIC_VALUE_LIST,
+                params![user_id, metric_key, from.unix_millis(), to.unix_millis()],
+                |row| {
+                    Ok(MetricValue {
+                        metric_key: row.get("metric_key")?,
+                        timestamp: Self::get_timestamp(row, "timestamp")?,
+                        values: Self::get_json(row, "vals")?,
+                    })
+                },
+            )
+            .context("metric value list query")?;
+
+        if res.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        Ok(res)
+    }
+
+    //
+    // Schedule
+    //
+
+    fn get_schedule_list(&self, user_id: i64) -> Result<Vec<Schedule>> {
+        let res = self
+            .query_map(queries::SELECT_SCHEDULE_LIST, params![user_id], |row| {
+                Ok(Schedule {
+                    kind: Self::get_with(row, "kind", ScheduleKind::new)?,
+                    hour: row.get("hour")?,
+                    minute: row.get("minute")?,
+                    tz: row.get("tz")?,
+                    last_run: Self::get_timestamp_opt(row, "last_run")?,
+                })
+            })
+            .context("schedule list query")?;
+
+        if res.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        Ok(res)
+    }
+
+    fn set_schedule(&self, user_id: i64, schedule: &Schedule) -> Result<()> {
+        if !schedule.validate() {
+            return Err(StorageError::ScheduleInvalid);
+        }
+
+        let kind = u8::from(schedule.kind);
+        self.execute_change(
+            user_id,
+            ChangeAction::SetSchedule,
+            &Self::schedule_key(schedule.kind, schedule.hour, schedule.minute),
+            &[(
+                "schedule",
+                json!({"user_id": user_id, "kind": kind, "hour": schedule.hour, "minute": schedule.minute}),
+            )],
+            |tx| {
+                Self::raw_execute_tx(
+                    tx,
+                    queries::UPSERT_SCHEDULE,
+                    false,
+                    params![user_id, kind, schedule.hour, schedule.minute, schedule.tz],
+                )
+                .context("exec upsert schedule")?;
+
+                Ok(())
+            },
+        )
+    }
+
+    fn set_schedule_last_run(
+        &self,
+        user_id: i64,
+        schedule: &Schedule,
+        last_run: Timestamp,
+    ) -> Result<()> {
+        self.raw_execute(
+            queries::UPDATE_SCHEDULE_LAST_RUN,
+            false,
+            params![
+                user_id,
+                u8::from(schedule.kind),
+                schedule.hour,
+                schedule.minute,
+                last_run.unix_millis()
+            ],
+        )
+        .context("exec update schedule last run")?;
+
+        Ok(())
+    }
+
+    fn delete_schedule(
+        &self,
+        user_id: i64,
+        kind: ScheduleKind,
+        hour: u8,
+        minute: u8,
+    ) -> Result<()> {
+        self.execute_change(
+            user_id,
+            ChangeAction::DeleteSchedule,
+            &Self::schedule_key(kind, hour, minute),
+            &[(
+                "schedule",
+                json!({"user_id": user_id, "kind": u8::from(kind), "hour": hour, "minute": minute}),
+            )],
+            |tx| {
+                Self::raw_execute_tx(
+                    tx,
+                    queries::DELETE_SCHEDULE,
+                    false,
+                    params![user_id, u8::from(kind), hour, minute],
+                )
+                .context("exec delete schedule")?;
+
+                Ok(())
+            },
+        )
+    }
+
+    //
+    // Change log
+    //
+
+    fn get_change_list(&self, user_id: i64, limit: usize) -> Result<Vec<Change>> {
+        let res = self
+            .query_map(
+                queries::SELECT_CHANGE_LIST,
+                params![user_id, limit],
+                Self::change_from_row,
+            )
+            .context("change list query")?;
+
+        if res.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        Ok(res)
+    }
+
+    fn undo(&self, user_id: i64, count: usize) -> Result<Vec<Change>> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().context("failed to get transaction")?;
+
+        let changes = Self::query_map_conn(
+            &tx,
+            queries::SELECT_CHANGE_LIST,
+            params![user_id, count],
+            |row| {
+                Ok((
+                    row.get::<_, i64>("id")?,
+                    Self::change_from_row(row)?,
+                    Self::get_json::<Vec<changes::RowSet>>(row, "prior")?,
+                    Self::get_with(row, "after", |v: Option<String>| {
+                        v.map(|v| serde_json::from_str::<Vec<changes::RowSet>>(&v))
+                            .transpose()
+                    })?,
+                ))
+            },
+        )
+        .context("change list query")?;
+
+        if changes.is_empty() {
+            return Err(StorageError::EmptyResult);
+        }
+
+        // Newest first, so each change is reverted on top of the state it made
+        let mut res = Vec::with_capacity(changes.len());
+        for (id, change, prior, after) in changes {
+            let current = prior
+                .iter()
+                .map(|set| changes::recapture(&tx, set))
+                .collect::<anyhow::Result<Vec<_>>>()
+                .context("capture changed rows")?;
+
+            // Rows changed later by other user, e.g. shared food, are not overwritten.
+            // Changes recorded before state after change was kept are not checked.
+            if after.is_some_and(|after| after != current) {
+                return Err(StorageError::UndoConflict);
+            }
+
+            for set in &prior {
+                match changes::restore(&tx, set) {
+                    Err(err) if Self::is_foreign_key_error(&err) => {
+                        return Err(StorageError::UndoConflict)
+                    }
+                    Err(err) => return Err(err.context("restore changed rows").into()),
+                    _ => {}
+                }
+            }
+
+            Self::insert_audit_tx(
+                &tx,
+                user_id,
+                change.action.entity(),
+                &change.key,
+                AuditAction::Undo,
+                &current,
+                &prior,
+            )?;
+            Self::raw_execute_tx(&tx, queries::DELETE_CHANGE, false, params![id])
+                .context("exec delete change")?;
+            res.push(change);
+        }
+
+        tx.commit().context("failed to commit transaction")?;
+
+        Ok(res)
+    }
+
+    //
+    // Audit log
+    //
+
+    fn get_audit_list(&self, from: Timestamp, to: Timestamp) -> Result<Vec<AuditEntry>> {
+        let res = self
+            .query_map(
+                queries::SELECT_AUDIT_LIST,
+                params![from.unix_millis(), to.unix_millis()],
+                |row| {
+                    Ok(AuditEntry {
+                        timestamp: Self::get_timestamp(row, "timestamp")?,
+                        user_id: row.get("user_id")?,
+                        entity: row.get("entity")?,
+                        key: row.get("key")?,
+                        action: Self::get_with(row, "action", AuditAction::new)?,
+                        old_value: row.get("old_value")?,
+                        new_value: row.get("new_value")?,
+                    })
+                },
+            )
+            .context("audit list query")?;
+
+        if res.is_empty() {
+            return Err(StorageError::EmptyResult);
         }
 
         Ok(res)
@@ -899,117 +1690,160 @@ impl Storage for StorageSqlite {
 
     fn backup(&self, user_id: i64) -> Result<Backup> {
         // Weight
-        let db_res = self
-            .raw_query(queries::SELECT_WEIGHT_FOR_BACKUP, params![])
+        let weight_backup = self
+            .query_map(queries::SELECT_WEIGHT_FOR_BACKUP, params![], |row| {
+                Ok(WeightBackup {
+                    user_id: row.get("user_id")?,
+                    timestamp: Self::get_timestamp(row, "timestamp")?.unix_millis(),
+                    value: row.get("value")?,
+                })
+            })
             .context("select weight backup query")?;
 
-        let mut weight_backup = Vec::with_capacity(db_res.len());
-        for row in db_res {
-            weight_backup.push(WeightBackup {
-                user_id: Self::get_integer(&row, "user_id").context("get user_id field")?,
-                timestamp: Self::get_timestamp(&row, "timestamp")
-                    .context("get timestamp field")?
-                    .unix_millis(),
-                value: Self::get_float(&row, "value").context("get value field")?,
-            });
-        }
-
         // Food
-        let db_res = self
-            .raw_query(queries::SELECT_FOOD_FOR_BACKUP, params![])
+        let food_backup = self
+            .query_map(queries::SELECT_FOOD_FOR_BACKUP, params![], |row| {
+                Ok(FoodBackup {
+                    user_id,
+                    key: row.get("key")?,
+                    name: row.get("name")?,
+                    brand: row.get("brand")?,
+                    cal100: row.get("cal100")?,
+                    prot100: row.get("prot100")?,
+                    fat100: row.get("fat100")?,
+                    carb100: row.get("carb100")?,
+                    comment: row.get("comment")?,
+                })
+            })
             .context("select food backup query")?;
 
-        let mut food_backup = Vec::with_capacity(db_res.len());
-        for row in db_res {
-            food_backup.push(FoodBackup {
-                user_id,
-                key: Self::get_string(&row, "key").context("get key field")?,
-                name: Self::get_string(&row, "name").context("get name field")?,
-                brand: Self::get_string(&row, "brand").context("get brand field")?,
-                cal100: Self::get_float(&row, "cal100").context("get cal100 field")?,
-                prot100: Self::get_float(&row, "prot100").context("get prot100 field")?,
-                fat100: Self::get_float(&row, "fat100").context("get fat100 field")?,
-                carb100: Self::get_float(&row, "carb100").context("get carb100 field")?,
-                comment: Self::get_string(&row, "comment").context("get comment field")?,
-            });
-        }
-
         // User settings
-        let db_res = self
-            .raw_query(queries::SELECT_USER_SETTINGS_FOR_BACKUP, params![])
+        let us_backup = self
+            .query_map(queries::SELECT_USER_SETTINGS_FOR_BACKUP, params![], |row| {
+                Ok(UserSettingsBackup {
+                    user_id: row.get("user_id")?,
+                    cal_limit: row.get("cal_limit")?,
+                    sex: row.get("sex")?,
+                    height: row.get("height")?,
+                    birth_date: row.get("birth_date")?,
+                })
+            })
             .context("select user settings backup query")?;
 
-        let mut us_backup = Vec::with_capacity(db_res.len());
-        for row in db_res {
-            us_backup.push(UserSettingsBackup {
-                user_id: Self::get_integer(&row, "user_id").context("get user_id field")?,
-                cal_limit: Self::get_float(&row, "cal_limit").context("get cal_limit field")?,
-            });
-        }
-
         // Bundles
-        let db_res: Vec<HashMap<String, Value>> = self
-            .raw_query(queries::SELECT_BUNDLES_FOR_BACKUP, params![])
+        let rows = self
+            .query_map(
+                queries::SELECT_BUNDLES_FOR_BACKUP,
+                params![],
+                Self::bundle_row,
+            )
             .context("select bundles backup query")?;
 
-        let mut bundle_backup = Vec::with_capacity(db_res.len());
-        for row in db_res {
+        let mut bundle_backup = Vec::with_capacity(rows.len());
+        for (user_id, bndl) in Self::group_bundle_rows(rows) {
             bundle_backup.push(BundleBackup {
-                user_id: Self::get_integer(&row, "user_id").context("get user_id field")?,
-                key: Self::get_string(&row, "key").context("get key field")?,
-                data: Self::get_string(&row, "data").context("get bundle data field")?,
+                user_id,
+                key: bndl.key,
+                // Sorted keys keep backup stable
+                data: serde_json::to_string(&bndl.data.iter().collect::<BTreeMap<_, _>>())
+                    .context("convert bundle data to JSON")?,
             });
         }
 
         // Journal
-        let db_res: Vec<HashMap<String, Value>> = self
-            .raw_query(queries::SELECT_JOURNAL_FOR_BACKUP, params![])
+        let journal_backup = self
+            .query_map(queries::SELECT_JOURNAL_FOR_BACKUP, params![], |row| {
+                Ok(JournalBackup {
+                    user_id: row.get("user_id")?,
+                    timestamp: Self::get_timestamp(row, "timestamp")?.unix_millis(),
+                    meal: row.get("meal")?,
+                    food_key: row.get("foodkey")?,
+                    food_weight: row.get("foodweight")?,
+                })
+            })
             .context("select journal backup query")?;
 
-        let mut journal_backup = Vec::with_capacity(db_res.len());
-        for row in db_res {
-            journal_backup.push(JournalBackup {
-                user_id: Self::get_integer(&row, "user_id").context("get user_id field")?,
-                timestamp: Self::get_timestamp(&row, "timestamp")
-                    .context("get timestamp field")?
-                    .unix_millis(),
-                meal: Self::get_integer(&row, "meal").context("get meal field")? as u8,
-                food_key: Self::get_string(&row, "foodkey").context("get foodkey field")?,
-                food_weight: Self::get_float(&row, "foodweight").context("get foodweight field")?,
-            });
-        }
-
         // Sport
-        let db_res: Vec<HashMap<String, Value>> = self
-            .raw_query(queries::SELECT_SPORT_FOR_BACKUP, params![])
+        let sport_backup = self
+            .query_map(queries::SELECT_SPORT_FOR_BACKUP, params![], |row| {
+                Ok(SportBackup {
+                    user_id,
+                    key: row.get("key")?,
+                    name: row.get("name")?,
+                    comment: row.get("comment")?,
+                })
+            })
             .context("select sport backup query")?;
 
-        let mut sport_backup = Vec::with_capacity(db_res.len());
-        for row in db_res {
-            sport_backup.push(SportBackup {
-                user_id,
-                key: Self::get_string(&row, "key").context("get key field")?,
-                name: Self::get_string(&row, "name").context("get name field")?,
-                comment: Self::get_string(&row, "comment").context("get comment field")?,
-            });
-        }
-
         // Sport activity
-        let db_res: Vec<HashMap<String, Value>> = self
-            .raw_query(queries::SELECT_SPORT_ACTIVITY_FOR_BACKUP, params![])
+        let sa_backup = self
+            .query_map(
+                queries::SELECT_SPORT_ACTIVITY_FOR_BACKUP,
+                params![],
+                |row| {
+                    Ok(SportActivityBackup {
+                        user_id: row.get("user_id")?,
+                        timestamp: Self::get_timestamp(row, "timestamp")?.unix_millis(),
+                        sport_key: row.get("sport_key")?,
+                        sets: row.get("sets")?,
+                    })
+                },
+            )
             .context("select sport activity backup query")?;
 
-        let mut sa_backup = Vec::with_capacity(db_res.len());
-        for row in db_res {
-            sa_backup.push(SportActivityBackup {
-                user_id: Self::get_integer(&row, "user_id").context("get user_id field")?,
-                timestamp: Self::get_timestamp(&row, "timestamp")
-                    .context("get timestamp field")?
-                    .unix_millis(),
-                sport_key: Self::get_string(&row, "sport_key").context("get sport_key field")?,
-                sets: Self::get_string(&row, "sets").context("get sets field")?,
-            });
-        }
+        // Metric
+        let metric_backup = self
+            .query_map(queries::SELECT_METRIC_FOR_BACKUP, params![], |row| {
+                Ok(MetricBackup {
+                    user_id: row.get("user_id")?,
+                    key: row.get("key")?,
+                    name: row.get("name")?,
+                    unit: row.get("unit")?,
+                    fields: row.get("fields")?,
+                })
+            })
+            .context("select metric backup query")?;
+
+        // Metric value
+        let mv_backup = self
+            .query_map(queries::SELECT_METRIC_VALUE_FOR_BACKUP, params![], |row| {
+                Ok(MetricValueBackup {
+                    user_id: row.get("user_id")?,
+                    timestamp: Self::get_timestamp(row, "timestamp")?.unix_millis(),
+                    metric_key: row.get("metric_key")?,
+                    values: row.get("vals")?,
+                })
+            })
+            .context("select metric value backup query")?;
+
+        // Schedule
+        let schedule_backup = self
+            .query_map(queries::SELECT_SCHEDULE_FOR_BACKUP, params![], |row| {
+                Ok(ScheduleBackup {
+                    user_id: row.get("user_id")?,
+                    kind: row.get("kind")?,
+                    hour: row.get("hour")?,
+                    minute: row.get("minute")?,
+                    tz: row.get("tz")?,
+                })
+            })
+            .context("select schedule backup query")?;
+
+        // Audit log
+        let audit_backup = self
+            .query_map(queries::SELECT_AUDIT_LOG_FOR_BACKUP, params![], |row| {
+                Ok(AuditLogBackup {
+                    id: row.get("id")?,
+                    user_id: row.get("user_id")?,
+                    timestamp: Self::get_timestamp(row, "timestamp")?.unix_millis(),
+                    entity: row.get("entity")?,
+                    key: row.get("key")?,
+                    action: row.get("action")?,
+                    old_value: row.get("old_value")?,
+                    new_value: row.get("new_value")?,
+                })
+            })
+            .context("select audit log backup query")?;
 
         Ok(Backup {
             timestamp: Timestamp::now().unix_millis(),
@@ -1020,12 +1854,20 @@ impl Storage for StorageSqlite {
             journal: journal_backup,
             sport: sport_backup,
             sport_activity: sa_backup,
+            metric: metric_backup,
+            metric_value: mv_backup,
+            schedule: schedule_backup,
+            audit_log: audit_backup,
         })
     }
 
     fn restore(&self, backup: &Backup) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().context("failed to get transaction")?;
+
         for w in &backup.weight {
-            self.raw_execute(
+            Self::raw_execute_tx(
+                &tx,
                 queries::UPSERT_WEIGHT,
                 false,
                 params![w.user_id, w.timestamp, w.value],
@@ -1034,7 +1876,8 @@ impl Storage for StorageSqlite {
         }
 
         for f in &backup.food {
-            self.raw_execute(
+            Self::raw_execute_tx(
+                &tx,
                 queries::UPSERT_FOOD,
                 false,
                 params![
@@ -1045,25 +1888,20 @@ impl Storage for StorageSqlite {
         }
 
         for us in &backup.user_settings {
-            self.raw_execute(
+            Self::raw_execute_tx(
+                &tx,
                 queries::UPSERT_USER_SETTINGS,
                 false,
-                params![us.user_id, us.cal_limit],
+                params![us.user_id, us.cal_limit, us.sex, us.height, us.birth_date],
             )
             .context("exec upsert backup user settings")?;
         }
 
-        for b in &backup.bundle {
-            self.raw_execute(
-                queries::UPSERT_BUNDLE,
-                false,
-                params![b.user_id, b.key, b.data],
-            )
-            .context("exec upsert backup bundle")?;
-        }
+        Self::restore_bundles_tx(&tx, &backup.bundle)?;
 
         for j in &backup.journal {
-            self.raw_execute(
+            Self::raw_execute_tx(
+                &tx,
                 queries::UPSERT_JOURNAL,
                 false,
                 params![j.user_id, j.timestamp, j.meal, j.food_key, j.food_weight],
@@ -1072,7 +1910,8 @@ impl Storage for StorageSqlite {
         }
 
         for s in &backup.sport {
-            self.raw_execute(
+            Self::raw_execute_tx(
+                &tx,
                 queries::UPSERT_SPORT,
                 false,
                 params![s.key, s.name, s.comment],
@@ -1081,7 +1920,8 @@ impl Storage for StorageSqlite {
         }
 
         for sa in &backup.sport_activity {
-            self.raw_execute(
+            Self::raw_execute_tx(
+                &tx,
                 queries::UPSERT_SPORT_ACTIVITY,
                 false,
                 params![sa.user_id, sa.timestamp, sa.sport_key, sa.sets],
@@ -1089,18 +1929,72 @@ impl Storage for StorageSqlite {
             .context("exec upsert backup sport activity")?;
         }
 
-        Ok(())
-    }
+        for m in &backup.metric {
+            Self::raw_execute_tx(
+                &tx,
+                queries::UPSERT_METRIC,
+                false,
+                params![m.user_id, m.key, m.name, m.unit, m.fields],
+            )
+            .context("exec upsert backup metric")?;
+        }
 
-    //
-    // Error
-    //
+        for mv in &backup.metric_value {
+            Self::raw_execute_tx(
+                &tx,
+                queries::UPSERT_METRIC_VALUE,
+                false,
+                params![mv.user_id, mv.timestamp, mv.metric_key, mv.values],
+            )
+            .context("exec upsert backup metric value")?;
+        }
+
+        for sc in &backup.schedule {
+            Self::raw_execute_tx(
+                &tx,
+                queries::UPSERT_SCHEDULE,
+                false,
+                params![sc.user_id, sc.kind, sc.hour, sc.minute, sc.tz],
+            )
+            .context("exec upsert backup schedule")?;
+        }
 
-    fn is_storage_error(&self, stg_err: StorageError, err: &Error) -> bool {
-        stg_err
-            == *err
-                .root_cause()
-                .downcast_ref::<StorageError>()
-                .unwrap_or(&StorageError::default())
+        for a in &backup.audit_log {
+            Self::raw_execute_tx(
+                &tx,
+                queries::INSERT_AUDIT_FOR_BACKUP,
+                false,
+                params![
+                    a.user_id,
+                    a.timestamp,
+                    a.entity,
+                    a.key,
+                    a.action,
+                    a.old_value,
+                    a.new_value
+                ],
+            )
+            .context("exec insert backup audit log")?;
+        }
+
+        Self::raw_execute_tx(
+            &tx,
+            queries::INSERT_AUDIT,
+            false,
+            params![
+                SYSTEM_USER_ID,
+                Timestamp::now().unix_millis(),
+                "backup",
+                "",
+                u8::from(AuditAction::Restore),
+                Option::<String>::None,
+                restore_audit_value(backup)
+            ],
+        )
+        .context("exec insert restore audit")?;
+
+        tx.commit().context("failed to commit transaction")?;
+
+        Ok(())
     }
 }
@@ -0,0 +1,262 @@
+use anyhow::{anyhow, bail, Context, Result};
+use rusqlite::{
+    params_from_iter,
+    types::{Type, Value, ValueRef},
+    Connection,
+    Error::FromSqlConversionFailure,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value as JsonValue};
+
+// Tables that may be changed with their primary key columns.
+// Table without primary key has its affected rows replaced as a whole.
+const TABLES: &[(&str, Option<&[&str]>)] = &[
+    ("food", Some(&["key"])),
+    ("bundle", Some(&["user_id", "key"])),
+    ("bundle_item", None),
+    ("weight", Some(&["user_id", "timestamp"])),
+    ("user_settings", Some(&["user_id"])),
+    (
+        "journal",
+        Some(&["user_id", "timestamp", "meal", "foodkey"]),
+    ),
+    ("sport", Some(&["key"])),
+    (
+        "sport_activity",
+        Some(&["user_id", "timestamp", "sport_key"]),
+    ),
+    ("metric", Some(&["user_id", "key"])),
+    (
+        "metric_value",
+        Some(&["user_id", "timestamp", "metric_key"]),
+    ),
+    ("schedule", Some(&["user_id", "kind", "hour", "minute"])),
+];
+
+// Columns maintained outside of changes, they are neither captured nor restored.
+// Schedule last run is kept as is, so undo doesn't make scheduler repeat sent job.
+const UNTRACKED: &[(&str, &str)] = &[("schedule", "last_run")];
+
+type Columns = Map<String, JsonValue>;
+
+// Rows of table matching filter columns, as they were at the moment of capture
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct RowSet {
+    table: String,
+    filter: Columns,
+    rows: Vec<Columns>,
+}
+
+// Filter is JSON object of column values, e.g. {"user_id": 1, "key": "key1"}
+pub fn capture(conn: &Connection, table: &str, filter: &JsonValue) -> Result<RowSet> {
+    let JsonValue::Object(filter) = filter else {
+        bail!("filter of {table} rows is not an object");
+    };
+
+    Ok(RowSet {
+        table: table.into(),
+        rows: select_rows(conn, table, filter)?,
+        filter: filter.clone(),
+    })
+}
+
+// Same rows with their current state
+pub fn recapture(conn: &Connection, set: &RowSet) -> Result<RowSet> {
+    Ok(RowSet {
+        table: set.table.clone(),
+        rows: select_rows(conn, &set.table, &set.filter)?,
+        filter: set.filter.clone(),
+    })
+}
+
+// Rows of sets grouped by table as JSON object, e.g. {"food": [{"key": "key1", ...}]},
+// None if there are no rows
+pub fn rows_json(sets: &[RowSet]) -> Result<Option<String>> {
+    let mut res = Columns::new();
+    for set in sets.iter().filter(|s| !s.rows.is_empty()) {
+        let rows = res
+            .entry(set.table.clone())
+            .or_insert_with(|| JsonValue::Array(Vec::new()));
+        if let JsonValue::Array(rows) = rows {
+            rows.extend(set.rows.iter().cloned().map(JsonValue::Object));
+        }
+    }
+    if res.is_empty() {
+        return Ok(None);
+    }
+
+    serde_json::to_string(&res)
+        .map(Some)
+        .context("convert rows to JSON")
+}
+
+// Returns rows of set to captured state: rows created after capture are deleted,
+// changed and deleted rows are written back
+pub fn restore(conn: &Connection, set: &RowSet) -> Result<()> {
+    let Some(pk) = primary_key(&set.table)? else {
+        let (cond, vals) = where_clause(&set.filter)?;
+        execute(
+            conn,
+            &format!("DELETE FROM {} WHERE {cond}", set.table),
+            vals,
+        )?;
+        for row in &set.rows {
+            insert(conn, &set.table, row, None)?;
+        }
+
+        return Ok(());
+    };
+
+    for row in select_rows(conn, &set.table, &set.filter)? {
+        let captured = set
+            .rows
+            .iter()
+            .any(|r| pk.iter().all(|c| r.get(*c) == row.get(*c)));
+        if captured {
+            continue;
+        }
+
+        let key: Columns = pk
+            .iter()
+            .map(|c| (c.to_string(), row.get(*c).cloned().unwrap_or_default()))
+            .collect();
+        let (cond, vals) = where_clause(&key)?;
+        execute(
+            conn,
+            &format!("DELETE FROM {} WHERE {cond}", set.table),
+            vals,
+        )?;
+    }
+
+    for row in &set.rows {
+        insert(conn, &set.table, row, Some(pk))?;
+    }
+
+    Ok(())
+}
+
+fn primary_key(table: &str) -> Result<Option<&'static [&'static str]>> {
+    TABLES
+        .iter()
+        .find(|(t, _)| *t == table)
+        .map(|(_, pk)| *pk)
+        .ok_or_else(|| anyhow!("table {table} can't be changed"))
+}
+
+// Column names come from database, but are checked anyway since they get into query text
+fn column(name: &str) -> Result<&str> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        bail!("wrong column name {name}");
+    }
+
+    Ok(name)
+}
+
+fn where_clause(filter: &Columns) -> Result<(String, Vec<Value>)> {
+    let mut cond = Vec::with_capacity(filter.len());
+    let mut vals = Vec::with_capacity(filter.len());
+    for (i, (k, v)) in filter.iter().enumerate() {
+        cond.push(format!("{} = ?{}", column(k)?, i + 1));
+        vals.push(to_sql(v)?);
+    }
+    if cond.is_empty() {
+        bail!("empty filter");
+    }
+
+    Ok((cond.join(" AND "), vals))
+}
+
+fn select_rows(conn: &Connection, table: &str, filter: &Columns) -> Result<Vec<Columns>> {
+    primary_key(table)?;
+    let (cond, vals) = where_clause(filter)?;
+
+    let mut stmt = conn
+        .prepare_cached(&format!("SELECT * FROM {table} WHERE {cond}"))
+        .with_context(|| format!("prepare select {table} rows query"))?;
+    let names: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let rows = stmt
+        .query_map(params_from_iter(vals), |row| {
+            let mut res = Columns::new();
+            for (i, name) in names.iter().enumerate() {
+                if UNTRACKED.contains(&(table, name.as_str())) {
+                    continue;
+                }
+                res.insert(name.clone(), from_sql(i, row.get_ref(i)?)?);
+            }
+
+            Ok(res)
+        })
+        .with_context(|| format!("query {table} rows"))?;
+
+    rows.collect::<rusqlite::Result<_>>()
+        .with_context(|| format!("map {table} rows"))
+}
+
+// Upserts row by primary key, or just inserts it into table without primary key
+fn insert(conn: &Connection, table: &str, row: &Columns, pk: Option<&[&str]>) -> Result<()> {
+    let mut cols = Vec::with_capacity(row.len());
+    let mut vals = Vec::with_capacity(row.len());
+    for (k, v) in row {
+        cols.push(column(k)?);
+        vals.push(to_sql(v)?);
+    }
+
+    let placeholders: Vec<String> = (1..=cols.len()).map(|i| format!("?{i}")).collect();
+    let mut query = format!(
+        "INSERT INTO {table} ({}) VALUES ({})",
+        cols.join(", "),
+        placeholders.join(", ")
+    );
+    if let Some(pk) = pk {
+        let updates: Vec<String> = cols
+            .iter()
+            .filter(|c| !pk.contains(c))
+            .map(|c| format!("{c} = excluded.{c}"))
+            .collect();
+        query.push_str(&format!(" ON CONFLICT ({}) DO ", pk.join(", ")));
+        if updates.is_empty() {
+            query.push_str("NOTHING");
+        } else {
+            query.push_str(&format!("UPDATE SET {}", updates.join(", ")));
+        }
+    }
+
+    execute(conn, &query, vals)
+}
+
+fn execute(conn: &Connection, query: &str, vals: Vec<Value>) -> Result<()> {
+    conn.prepare_cached(query)
+        .context("prepare restore query")?
+        .execute(params_from_iter(vals))
+        .context("exec restore query")?;
+
+    Ok(())
+}
+
+fn from_sql(idx: usize, v: ValueRef) -> rusqlite::Result<JsonValue> {
+    Ok(match v {
+        ValueRef::Null => JsonValue::Null,
+        ValueRef::Integer(v) => JsonValue::Number(v.into()),
+        ValueRef::Real(v) => Number::from_f64(v).map_or(JsonValue::Null, JsonValue::Number),
+        ValueRef::Text(v) => JsonValue::String(String::from_utf8_lossy(v).into()),
+        ValueRef::Blob(_) => {
+            return Err(FromSqlConversionFailure(
+                idx,
+                Type::Blob,
+                "blob columns are not supported".into(),
+            ))
+        }
+    })
+}
+
+fn to_sql(v: &JsonValue) -> Result<Value> {
+    Ok(match v {
+        JsonValue::Null => Value::Null,
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(v) => Value::Integer(v),
+            None => Value::Real(n.as_f64().context("wrong number")?),
+        },
+        JsonValue::String(v) => Value::Text(v.clone()),
+        _ => bail!("wrong column value {v}"),
+    })
+}
@@ -1,96 +1,450 @@
-use super::queries;
-use anyhow::{Context, Result};
-use rusqlite::{Connection, Transaction};
+use std::collections::BTreeMap;
+use std::path::Path;
 
-type MigrationFn = fn(&Transaction) -> Result<()>;
-type Migrations = Vec<(i64, MigrationFn)>;
+use super::{queries, BUSY_TIMEOUT};
+use anyhow::{bail, Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use sha2::{Digest, Sha256};
+use types::timestamp::Timestamp;
 
-pub fn apply(conn: &mut Connection, last_migration_id: i64) -> Result<()> {
-    for (id, f) in get_all_migrations() {
-        if id <= last_migration_id {
-            continue;
+// Schema change, applied and reverted in single transaction
+struct Migration {
+    id: i64,
+    name: &'static str,
+    up: &'static [&'static str],
+    down: &'static [&'static str],
+    // Verifies data before applying, e.g. fails listing data it can't convert
+    check: Option<fn(&Transaction) -> Result<()>>,
+}
+
+impl Migration {
+    // Only applied SQL matters, down can be fixed after migration was applied
+    fn checksum(&self) -> String {
+        let mut hasher = Sha256::new();
+        for query in self.up {
+            hasher.update(query.as_bytes());
         }
 
-        let tx = conn
-            .transaction()
-            .with_context(|| format!("start migration [{}] transaction", id))?;
+        format!("{:x}", hasher.finalize())
+    }
+}
 
-        f(&tx).with_context(|| format!("exec migration [{}] transaction", id))?;
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        id: 1,
+        name: "insert_initial_migration_id",
+        up: &[queries::INSERT_INITIAL_MIGRATION_ID],
+        down: &[queries::DELETE_INITIAL_MIGRATION_ID],
+        check: None,
+    },
+    Migration {
+        id: 2,
+        name: "create_tables_weight_food",
+        up: &[queries::CREATE_TABLE_WEIGHT, queries::CREATE_TABLE_FOOD],
+        down: &[queries::DROP_TABLE_FOOD, queries::DROP_TABLE_WEIGHT],
+        check: None,
+    },
+    Migration {
+        id: 3,
+        name: "create_table_sport",
+        up: &[queries::CREATE_TABLE_SPORT],
+        down: &[queries::DROP_TABLE_SPORT],
+        check: None,
+    },
+    Migration {
+        id: 4,
+        name: "create_table_sport_activity",
+        up: &[queries::CREATE_TABLE_SPORT_ACTIVITY],
+        down: &[queries::DROP_TABLE_SPORT_ACTIVITY],
+        check: None,
+    },
+    Migration {
+        id: 5,
+        name: "create_table_user_settings",
+        up: &[queries::CREATE_TABLE_USER_SETTINGS],
+        down: &[queries::DROP_TABLE_USER_SETTINGS],
+        check: None,
+    },
+    Migration {
+        id: 6,
+        name: "create_table_bundle",
+        up: &[queries::CREATE_TABLE_BUNDLE],
+        down: &[queries::DROP_TABLE_BUNDLE],
+        check: None,
+    },
+    Migration {
+        id: 7,
+        name: "create_table_journal",
+        up: &[queries::CREATE_TABLE_JOURNAL],
+        down: &[queries::DROP_TABLE_JOURNAL],
+        check: None,
+    },
+    Migration {
+        id: 8,
+        name: "create_tables_metric",
+        up: &[
+            queries::CREATE_TABLE_METRIC,
+            queries::CREATE_TABLE_METRIC_VALUE,
+        ],
+        down: &[queries::DROP_TABLE_METRIC_VALUE, queries::DROP_TABLE_METRIC],
+        check: None,
+    },
+    Migration {
+        id: 9,
+        name: "alter_table_user_settings_add_profile",
+        up: &[queries::ALTER_TABLE_USER_SETTINGS_ADD_PROFILE],
+        down: &[queries::ALTER_TABLE_USER_SETTINGS_DROP_PROFILE],
+        check: None,
+    },
+    Migration {
+        id: 10,
+        name: "create_table_schedule",
+        up: &[queries::CREATE_TABLE_SCHEDULE],
+        down: &[queries::DROP_TABLE_SCHEDULE],
+        check: None,
+    },
+    Migration {
+        id: 11,
+        name: "create_table_bundle_item",
+        up: &[
+            queries::CREATE_TABLE_BUNDLE_ITEM,
+            queries::MIGRATE_BUNDLE_DATA_TO_ITEMS,
+        ],
+        down: &[
+            queries::MIGRATE_BUNDLE_ITEMS_TO_DATA,
+            queries::DROP_TABLE_BUNDLE_ITEM,
+        ],
+        check: Some(check_dangling_bundle_items),
+    },
+    Migration {
+        id: 12,
+        name: "create_table_change_log",
+        up: &[queries::CREATE_TABLE_CHANGE_LOG],
+        down: &[queries::DROP_TABLE_CHANGE_LOG],
+        check: None,
+    },
+    Migration {
+        id: 13,
+        name: "create_table_audit_log",
+        up: &[queries::CREATE_TABLE_AUDIT_LOG],
+        down: &[queries::DROP_TABLE_AUDIT_LOG],
+        check: None,
+    },
+    Migration {
+        id: 14,
+        name: "alter_table_change_log_add_after",
+        up: &[queries::ALTER_TABLE_CHANGE_LOG_ADD_AFTER],
+        down: &[queries::ALTER_TABLE_CHANGE_LOG_DROP_AFTER],
+        check: None,
+    },
+];
 
-        update_migration_id(&tx, id)
-            .with_context(|| format!("update migration id for migration [{}]", id))?;
+// Bundle references to missing food or bundle can't become bundle items,
+// user has to fix such bundles before migration
+fn check_dangling_bundle_items(tx: &Transaction) -> Result<()> {
+    let mut stmt = tx
+        .prepare(queries::SELECT_BUNDLE_DATA_DANGLING)
+        .context("prepare dangling bundle items query")?;
+    let dangling = stmt
+        .query_map([], |row| {
+            Ok(format!(
+                "user {} bundle {} item {}",
+                row.get::<_, i64>("user_id")?,
+                row.get::<_, String>("bundle_key")?,
+                row.get::<_, String>("item_key")?
+            ))
+        })
+        .context("query dangling bundle items")?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .context("map dangling bundle items")?;
 
-        tx.commit()
-            .with_context(|| format!("commit migration [{}] transaction", id))?;
+    if !dangling.is_empty() {
+        bail!(
+            "bundles reference missing food or bundle, fix them before migration: {}",
+            dangling.join(", ")
+        );
     }
 
     Ok(())
 }
 
-fn update_migration_id(tx: &Transaction, migration_id: i64) -> Result<()> {
-    tx.execute(queries::UPDATE_MIGRATION_ID, [migration_id])
-        .context("exec update migration id query")?;
-    Ok(())
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MigrationState {
+    Applied,
+    Pending,
+    // Applied SQL differs from SQL of this binary
+    Changed,
+    // Applied by newer binary
+    Unknown,
 }
 
-fn get_all_migrations() -> Migrations {
-    vec![
-        (1, insert_initial_migration_id),
-        (2, create_tables_weight_food),
-        (3, create_table_sport),
-        (4, create_table_sport_activity),
-        (5, create_table_user_settings),
-        (6, create_table_bundle),
-        (7, create_table_journal),
-    ]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationStatus {
+    pub id: i64,
+    pub name: String,
+    // None if applied before metadata was recorded
+    pub applied_at: Option<Timestamp>,
+    pub state: MigrationState,
 }
 
-fn insert_initial_migration_id(tx: &Transaction) -> Result<()> {
-    tx.execute(queries::INSERT_INITIAL_MIGRATION_ID, [])
-        .context("exec initial migration id query")?;
-    Ok(())
+// Migration control of database file, opening doesn't apply migrations
+pub struct Migrator {
+    conn: Connection,
 }
 
-fn create_tables_weight_food(tx: &Transaction) -> Result<()> {
-    tx.execute(queries::CREATE_TABLE_WEIGHT, [])
-        .context("exec create table weight")?;
-    tx.execute(queries::CREATE_TABLE_FOOD, [])
-        .context("exec create table food")?;
+impl Migrator {
+    pub fn open(db_file: &Path) -> Result<Self> {
+        let mut conn = Connection::open(db_file).context("open db connection")?;
+        conn.busy_timeout(BUSY_TIMEOUT)
+            .context("set busy timeout")?;
+        init(&mut conn).context("init migrations")?;
 
-    Ok(())
+        Ok(Self { conn })
+    }
+
+    pub fn latest_id(&self) -> i64 {
+        latest_id()
+    }
+
+    pub fn current_id(&self) -> Result<i64> {
+        current_id(&self.conn)
+    }
+
+    pub fn status(&self) -> Result<Vec<MigrationStatus>> {
+        status(&self.conn)
+    }
+
+    // Database refused by storage is refused here too, unless forced
+    pub fn up(&mut self, target_id: i64, force: bool) -> Result<()> {
+        if !force {
+            check(&self.conn)?;
+        }
+        up(&mut self.conn, target_id)
+    }
+
+    pub fn down(&mut self, target_id: i64, force: bool) -> Result<()> {
+        if !force {
+            check(&self.conn)?;
+        }
+        down(&mut self.conn, target_id)
+    }
 }
 
-fn create_table_sport(tx: &Transaction) -> Result<()> {
-    tx.execute(queries::CREATE_TABLE_SPORT, [])
-        .context("exec create table sport")?;
+pub fn latest_id() -> i64 {
+    MIGRATIONS.last().map_or(0, |m| m.id)
+}
 
-    Ok(())
+pub fn current_id(conn: &Connection) -> Result<i64> {
+    let id = conn
+        .query_row(queries::SELECT_MIGRATION_ID, [], |row| {
+            row.get::<_, Option<i64>>("migration_id")
+        })
+        .optional()
+        .context("query last migration")?;
+
+    Ok(id.flatten().unwrap_or(0))
+}
+
+// Creates system tables, records metadata of migrations applied before it was kept
+pub fn init(conn: &mut Connection) -> Result<()> {
+    let tx = conn.transaction().context("start init transaction")?;
+    tx.execute(queries::CREATE_TABLE_SYSTEM, [])
+        .context("exec create system table")?;
+    tx.execute(queries::CREATE_TABLE_MIGRATION, [])
+        .context("exec create migration table")?;
+
+    let current_id = current_id(&tx)?;
+    let applied = get_applied(&tx)?;
+    for m in MIGRATIONS {
+        if m.id <= current_id && !applied.contains_key(&m.id) {
+            insert_migration(&tx, m, None)
+                .with_context(|| format!("record migration [{}]", m.id))?;
+        }
+    }
+
+    tx.commit().context("commit init transaction")
+}
+
+pub fn status(conn: &Connection) -> Result<Vec<MigrationStatus>> {
+    let mut applied = get_applied(conn)?;
+
+    let mut res = Vec::with_capacity(MIGRATIONS.len());
+    for m in MIGRATIONS {
+        let (applied_at, state) = match applied.remove(&m.id) {
+            Some(a) if a.checksum == m.checksum() => (a.applied_at, MigrationState::Applied),
+            Some(a) => (a.applied_at, MigrationState::Changed),
+            None => (None, MigrationState::Pending),
+        };
+
+        res.push(MigrationStatus {
+            id: m.id,
+            name: m.name.into(),
+            applied_at,
+            state,
+        });
+    }
+
+    for (id, a) in applied {
+        res.push(MigrationStatus {
+            id,
+            name: a.name,
+            applied_at: a.applied_at,
+            state: MigrationState::Unknown,
+        });
+    }
+
+    Ok(res)
 }
 
-fn create_table_sport_activity(tx: &Transaction) -> Result<()> {
-    tx.execute(queries::CREATE_TABLE_SPORT_ACTIVITY, [])
-        .context("exec create table sport activity")?;
+// Refuses database, that was migrated by newer binary or with other migrations
+pub fn check(conn: &Connection) -> Result<()> {
+    let current_id = current_id(conn)?;
+    if current_id > latest_id() {
+        bail!(
+            "database migration {current_id} is newer than latest known {}",
+            latest_id()
+        );
+    }
+
+    for s in status(conn)? {
+        match s.state {
+            MigrationState::Changed => {
+                bail!(
+                    "migration [{}] {} changed after it was applied",
+                    s.id,
+                    s.name
+                )
+            }
+            MigrationState::Unknown => {
+                bail!("migration [{}] {} is unknown", s.id, s.name)
+            }
+            _ => {}
+        }
+    }
 
     Ok(())
 }
 
-fn create_table_user_settings(tx: &Transaction) -> Result<()> {
-    tx.execute(queries::CREATE_TABLE_USER_SETTINGS, [])
-        .context("exec create table user settings")?;
+// Applies migrations after current one, up to target id inclusive
+pub fn up(conn: &mut Connection, target_id: i64) -> Result<()> {
+    if target_id > latest_id() {
+        bail!("unknown target migration {target_id}");
+    }
+
+    let current_id = current_id(conn)?;
+    for m in MIGRATIONS {
+        if m.id <= current_id || m.id > target_id {
+            continue;
+        }
+
+        let tx = conn
+            .transaction()
+            .with_context(|| format!("start migration [{}] transaction", m.id))?;
+
+        if let Some(check) = m.check {
+            check(&tx).with_context(|| format!("check migration [{}]", m.id))?;
+        }
+
+        for query in m.up {
+            tx.execute_batch(query)
+                .with_context(|| format!("exec migration [{}] transaction", m.id))?;
+        }
+
+        insert_migration(&tx, m, Some(Timestamp::now()))
+            .with_context(|| format!("record migration [{}]", m.id))?;
+        update_migration_id(&tx, m.id)
+            .with_context(|| format!("update migration id for migration [{}]", m.id))?;
+
+        tx.commit()
+            .with_context(|| format!("commit migration [{}] transaction", m.id))?;
+    }
 
     Ok(())
 }
 
-fn create_table_bundle(tx: &Transaction) -> Result<()> {
-    tx.execute(queries::CREATE_TABLE_BUNDLE, [])
-        .context("exec create table bundle")?;
+// Reverts migrations down to target id, target itself stays applied
+pub fn down(conn: &mut Connection, target_id: i64) -> Result<()> {
+    if target_id < 0 {
+        bail!("unknown target migration {target_id}");
+    }
+
+    let current_id = current_id(conn)?;
+    if current_id > latest_id() {
+        bail!("can't revert migration {current_id} unknown to this binary");
+    }
+
+    for (i, m) in MIGRATIONS.iter().enumerate().rev() {
+        if m.id > current_id || m.id <= target_id {
+            continue;
+        }
+
+        let tx = conn
+            .transaction()
+            .with_context(|| format!("start revert [{}] transaction", m.id))?;
+
+        for query in m.down {
+            tx.execute_batch(query)
+                .with_context(|| format!("exec revert [{}] transaction", m.id))?;
+        }
+
+        tx.execute(queries::DELETE_MIGRATION, [m.id])
+            .with_context(|| format!("delete migration [{}] record", m.id))?;
+        let prev_id = if i > 0 { MIGRATIONS[i - 1].id } else { 0 };
+        update_migration_id(&tx, prev_id)
+            .with_context(|| format!("update migration id for revert [{}]", m.id))?;
+
+        tx.commit()
+            .with_context(|| format!("commit revert [{}] transaction", m.id))?;
+    }
 
     Ok(())
 }
 
-fn create_table_journal(tx: &Transaction) -> Result<()> {
-    tx.execute(queries::CREATE_TABLE_JOURNAL, [])
-        .context("exec create table journal")?;
+struct AppliedMigration {
+    name: String,
+    applied_at: Option<Timestamp>,
+    checksum: String,
+}
+
+fn get_applied(conn: &Connection) -> Result<BTreeMap<i64, AppliedMigration>> {
+    let mut stmt = conn
+        .prepare(queries::SELECT_MIGRATION_LIST)
+        .context("prepare migration list query")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>("id")?,
+                AppliedMigration {
+                    name: row.get("name")?,
+                    applied_at: row
+                        .get::<_, Option<i64>>("applied_at")?
+                        .and_then(Timestamp::from_unix_millis),
+                    checksum: row.get("checksum")?,
+                },
+            ))
+        })
+        .context("query migration list")?;
 
+    rows.collect::<rusqlite::Result<_>>()
+        .context("map migration list rows")
+}
+
+fn insert_migration(tx: &Transaction, m: &Migration, applied_at: Option<Timestamp>) -> Result<()> {
+    tx.execute(
+        queries::INSERT_MIGRATION,
+        params![
+            m.id,
+            m.name,
+            applied_at.map(|v| v.unix_millis()),
+            m.checksum()
+        ],
+    )
+    .context("exec insert migration query")?;
+    Ok(())
+}
+
+fn update_migration_id(tx: &Transaction, migration_id: i64) -> Result<()> {
+    tx.execute(queries::UPDATE_MIGRATION_ID, [migration_id])
+        .context("exec update migration id query")?;
     Ok(())
 }
@@ -0,0 +1,70 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex};
+
+use rusqlite::Connection;
+
+// Fixed set of connections, caller waits while all of them are taken
+pub struct Pool {
+    conns: Mutex<Vec<Connection>>,
+    released: Condvar,
+    size: usize,
+}
+
+impl Pool {
+    pub fn new(conns: Vec<Connection>) -> Self {
+        let size = conns.len();
+
+        Self {
+            conns: Mutex::new(conns),
+            released: Condvar::new(),
+            size,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // Must not be called on empty pool, it would wait forever
+    pub fn get(&self) -> PooledConnection<'_> {
+        let mut conns = self.conns.lock().unwrap();
+        loop {
+            if let Some(conn) = conns.pop() {
+                return PooledConnection {
+                    pool: self,
+                    conn: Some(conn),
+                };
+            }
+            conns = self.released.wait(conns).unwrap();
+        }
+    }
+}
+
+// Connection taken from pool, returned back when dropped
+pub struct PooledConnection<'a> {
+    pool: &'a Pool,
+    conn: Option<Connection>,
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledConnection<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            self.pool.conns.lock().unwrap().push(conn);
+            self.pool.released.notify_one();
+        }
+    }
+}
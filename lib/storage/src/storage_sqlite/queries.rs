@@ -12,6 +12,10 @@ pub const INSERT_INITIAL_MIGRATION_ID: &str = "
     INSERT INTO system(migration_id) VALUES(0)
 ";
 
+pub const DELETE_INITIAL_MIGRATION_ID: &str = "
+    DELETE FROM system
+";
+
 pub const SELECT_MIGRATION_ID: &str = "
     SELECT migration_id FROM system LIMIT 1
 ";
@@ -20,6 +24,35 @@ pub const UPDATE_MIGRATION_ID: &str = "
     UPDATE system SET migration_id = ?1
 ";
 
+// Metadata of applied migrations, applied_at is unknown for migrations
+// applied before metadata was recorded
+pub const CREATE_TABLE_MIGRATION: &str = "
+    CREATE TABLE IF NOT EXISTS migration (
+        id         INTEGER NOT NULL PRIMARY KEY,
+        name       TEXT    NOT NULL,
+        applied_at INTEGER NULL,
+        checksum   TEXT    NOT NULL
+    )
+";
+
+pub const SELECT_MIGRATION_LIST: &str = "
+    SELECT id, name, applied_at, checksum
+    FROM migration
+    ORDER BY id
+";
+
+pub const INSERT_MIGRATION: &str = "
+    INSERT INTO migration (
+        id, name, applied_at, checksum
+    )
+    VALUES (?1, ?2, ?3, ?4)
+";
+
+pub const DELETE_MIGRATION: &str = "
+    DELETE FROM migration
+    WHERE id = ?1
+";
+
 //
 // Weight
 //
@@ -33,6 +66,10 @@ pub const CREATE_TABLE_WEIGHT: &str = "
     )
 ";
 
+pub const DROP_TABLE_WEIGHT: &str = "
+    DROP TABLE weight
+";
+
 pub const SELECT_WEIGHT_LIST: &str = "
     SELECT timestamp, value
     FROM weight
@@ -44,6 +81,17 @@ pub const SELECT_WEIGHT_LIST: &str = "
         timestamp
 ";
 
+pub const SELECT_LAST_WEIGHT: &str = "
+    SELECT timestamp, value
+    FROM weight
+    WHERE
+        user_id = ?1 AND
+        timestamp <= ?2
+    ORDER BY
+        timestamp DESC
+    LIMIT 1
+";
+
 pub const SELECT_WEIGHT_FOR_BACKUP: &str = "
     SELECT user_id, timestamp, value
     FROM weight
@@ -81,6 +129,10 @@ pub const CREATE_TABLE_FOOD: &str = "
     )
 ";
 
+pub const DROP_TABLE_FOOD: &str = "
+    DROP TABLE food
+";
+
 pub const UPSERT_FOOD: &str = "
     INSERT INTO food (
         key, name, brand, cal100,
@@ -123,6 +175,22 @@ pub const DELETE_FOOD: &str = "
     WHERE key = ?1
 ";
 
+// Foods not used in journal of any user and in any bundle
+pub const SELECT_UNUSED_FOOD_LIST: &str = "
+    SELECT 
+        f.key, f.name, f.brand, f.cal100,
+        f.prot100, f.fat100, f.carb100, f.comment
+    FROM food f
+    WHERE
+        NOT EXISTS (
+            SELECT 1 FROM journal j WHERE j.foodkey = f.key
+        ) AND
+        NOT EXISTS (
+            SELECT 1 FROM bundle_item i WHERE i.food_key = f.key
+        )
+    ORDER BY f.name, f.key
+";
+
 pub const FIND_FOOD: &str = "
     SELECT 
         key, name, brand, cal100,
@@ -152,6 +220,10 @@ pub const CREATE_TABLE_JOURNAL: &str = "
     )
 ";
 
+pub const DROP_TABLE_JOURNAL: &str = "
+    DROP TABLE journal
+";
+
 pub const UPSERT_JOURNAL: &str = "
     INSERT INTO journal (
         user_id, timestamp, meal, foodkey, foodweight
@@ -211,6 +283,50 @@ pub const JOURNAL_FOOD_AVG_WEIGHT: &str = "
         j.timestamp <= ?4
 ";
 
+pub const JOURNAL_FOOD_USAGE: &str = "
+    SELECT
+        j.foodkey,
+        f.name AS foodname,
+        f.brand AS foodbrand,
+        count(*) AS cnt,
+        sum(j.foodweight) AS foodweight,
+        sum(j.foodweight / 100 * f.cal100) AS cal,
+        sum(j.foodweight / 100 * f.prot100) AS prot
+    FROM journal j, food f
+    WHERE
+        j.foodkey = f.key AND
+        j.user_id = ?1 AND
+        j.timestamp >= ?2 AND
+        j.timestamp <= ?3
+    GROUP BY
+        j.foodkey
+    ORDER BY
+        cal DESC,
+        f.name
+";
+
+pub const JOURNAL_FOOD_MEAL_USAGE: &str = "
+    SELECT
+        j.foodkey,
+        f.name AS foodname,
+        f.brand AS foodbrand,
+        j.meal,
+        count(*) AS cnt
+    FROM journal j, food f
+    WHERE
+        j.foodkey = f.key AND
+        j.user_id = ?1 AND
+        j.timestamp >= ?2 AND
+        j.timestamp <= ?3
+    GROUP BY
+        j.foodkey,
+        j.meal
+    ORDER BY
+        f.name,
+        j.foodkey,
+        j.meal
+";
+
 pub const SELECT_JOURNAL_FOR_BACKUP: &str = "
     SELECT user_id, timestamp, meal, foodkey, foodweight
     from journal
@@ -230,38 +346,57 @@ pub const CREATE_TABLE_BUNDLE: &str = "
     )
 ";
 
+pub const DROP_TABLE_BUNDLE: &str = "
+    DROP TABLE bundle
+";
+
+// Each bundle row is joined with its items, bundle without items gives single row
+// with NULL item_key. Child bundle item has 0 weight, as in Bundle model.
 pub const SELECT_BUNDLE: &str = "
-    SELECT key, data
-    FROM bundle
-    WHERE user_id = ?1 AND key = ?2
+    SELECT
+        b.user_id, b.key,
+        coalesce(i.food_key, i.child_key) AS item_key,
+        coalesce(i.weight, 0.0) AS weight
+    FROM bundle b
+    LEFT JOIN bundle_item i ON i.user_id = b.user_id AND i.bundle_key = b.key
+    WHERE b.user_id = ?1 AND b.key = ?2
 ";
 
 pub const SELECT_BUNDLE_LIST: &str = "
-    SELECT key, data
-    FROM bundle
-    WHERE user_id = ?1
-    ORDER by key
+    SELECT
+        b.user_id, b.key,
+        coalesce(i.food_key, i.child_key) AS item_key,
+        coalesce(i.weight, 0.0) AS weight
+    FROM bundle b
+    LEFT JOIN bundle_item i ON i.user_id = b.user_id AND i.bundle_key = b.key
+    WHERE b.user_id = ?1
+    ORDER BY b.key
 ";
 
-pub const SELECT_ALL_BUNDLES: &str = "
-    SELECT key, data
-    FROM bundle
+pub const SELECT_BUNDLES_FOR_BACKUP: &str = "
+    SELECT
+        b.user_id, b.key,
+        coalesce(i.food_key, i.child_key) AS item_key,
+        coalesce(i.weight, 0.0) AS weight
+    FROM bundle b
+    LEFT JOIN bundle_item i ON i.user_id = b.user_id AND i.bundle_key = b.key
+    ORDER BY b.user_id, b.key
 ";
 
-pub const SELECT_BUNDLES_FOR_BACKUP: &str = "
-    SELECT user_id, key, data
-    FROM bundle
-    ORDER BY user_id, key
+pub const SELECT_BUNDLE_PARENT: &str = "
+    SELECT bundle_key
+    FROM bundle_item
+    WHERE user_id = ?1 AND child_key = ?2
+    ORDER BY bundle_key
+    LIMIT 1
 ";
 
-pub const UPSERT_BUNDLE: &str = "
+pub const INSERT_BUNDLE: &str = "
     INSERT INTO bundle (
-        user_id, key, data
+        user_id, key
     )
-    VALUES (?1, ?2, ?3)
-    ON CONFLICT (user_id, key) DO
-    UPDATE SET
-        data = ?3
+    VALUES (?1, ?2)
+    ON CONFLICT (user_id, key) DO NOTHING
 ";
 
 pub const DELETE_BUNDLE: &str = "
@@ -269,6 +404,97 @@ pub const DELETE_BUNDLE: &str = "
     WHERE user_id = ?1 AND key = ?2
 ";
 
+//
+// Bundle item
+//
+
+// Item references either food with positive weight or child bundle of same user
+pub const CREATE_TABLE_BUNDLE_ITEM: &str = "
+    CREATE TABLE bundle_item (
+        user_id    INTEGER NOT NULL,
+        bundle_key TEXT    NOT NULL,
+        food_key   TEXT    NULL,
+        child_key  TEXT    NULL,
+        weight     REAL    NULL,
+        UNIQUE (user_id, bundle_key, food_key),
+        UNIQUE (user_id, bundle_key, child_key),
+        CHECK (
+            (food_key IS NOT NULL AND child_key IS NULL AND weight > 0) OR
+            (food_key IS NULL AND child_key IS NOT NULL AND weight IS NULL)
+        ),
+        FOREIGN KEY (user_id, bundle_key) REFERENCES bundle(user_id, key) ON DELETE CASCADE,
+        FOREIGN KEY (food_key) REFERENCES food(key) ON DELETE RESTRICT,
+        FOREIGN KEY (user_id, child_key) REFERENCES bundle(user_id, key) ON DELETE RESTRICT
+    );
+    CREATE INDEX bundle_item_food_key ON bundle_item(food_key);
+    CREATE INDEX bundle_item_child_key ON bundle_item(user_id, child_key);
+";
+
+// References to missing food or bundle can't be kept with foreign keys
+pub const SELECT_BUNDLE_DATA_DANGLING: &str = "
+    SELECT b.user_id, b.key AS bundle_key, d.key AS item_key
+    FROM bundle b, json_each(b.data) d
+    WHERE NOT (
+        (d.value > 0 AND EXISTS (
+            SELECT 1 FROM food f WHERE f.key = d.key
+        )) OR
+        (d.value = 0 AND EXISTS (
+            SELECT 1 FROM bundle c WHERE c.user_id = b.user_id AND c.key = d.key
+        ))
+    )
+    ORDER BY b.user_id, b.key, d.key
+";
+
+// Data with dangling references is rejected by migration check beforehand
+pub const MIGRATE_BUNDLE_DATA_TO_ITEMS: &str = "
+    INSERT INTO bundle_item (
+        user_id, bundle_key, food_key, child_key, weight
+    )
+    SELECT
+        b.user_id, b.key,
+        CASE WHEN d.value > 0 THEN d.key END,
+        CASE WHEN d.value = 0 THEN d.key END,
+        CASE WHEN d.value > 0 THEN d.value END
+    FROM bundle b, json_each(b.data) d
+    WHERE
+        (d.value > 0 AND EXISTS (
+            SELECT 1 FROM food f WHERE f.key = d.key
+        )) OR
+        (d.value = 0 AND EXISTS (
+            SELECT 1 FROM bundle c WHERE c.user_id = b.user_id AND c.key = d.key
+        ));
+    ALTER TABLE bundle DROP COLUMN data;
+";
+
+// Child bundle item gets 0 weight, as before bundle_item table
+pub const MIGRATE_BUNDLE_ITEMS_TO_DATA: &str = "
+    ALTER TABLE bundle ADD COLUMN data TEXT NOT NULL DEFAULT '{}';
+    UPDATE bundle SET data = (
+        SELECT json_group_object(
+            coalesce(i.food_key, i.child_key),
+            coalesce(i.weight, 0.0)
+        )
+        FROM bundle_item i
+        WHERE i.user_id = bundle.user_id AND i.bundle_key = bundle.key
+    );
+";
+
+pub const DROP_TABLE_BUNDLE_ITEM: &str = "
+    DROP TABLE bundle_item
+";
+
+pub const INSERT_BUNDLE_ITEM: &str = "
+    INSERT INTO bundle_item (
+        user_id, bundle_key, food_key, child_key, weight
+    )
+    VALUES (?1, ?2, ?3, ?4, ?5)
+";
+
+pub const DELETE_BUNDLE_ITEMS: &str = "
+    DELETE FROM bundle_item
+    WHERE user_id = ?1 AND bundle_key = ?2
+";
+
 //
 // User settings
 //
@@ -280,26 +506,42 @@ pub const CREATE_TABLE_USER_SETTINGS: &str = "
     )
 ";
 
+pub const DROP_TABLE_USER_SETTINGS: &str = "
+    DROP TABLE user_settings
+";
+
+pub const ALTER_TABLE_USER_SETTINGS_ADD_PROFILE: &str = "
+    ALTER TABLE user_settings ADD COLUMN sex INTEGER NULL;
+    ALTER TABLE user_settings ADD COLUMN height REAL NULL;
+    ALTER TABLE user_settings ADD COLUMN birth_date INTEGER NULL;
+";
+
+pub const ALTER_TABLE_USER_SETTINGS_DROP_PROFILE: &str = "
+    ALTER TABLE user_settings DROP COLUMN sex;
+    ALTER TABLE user_settings DROP COLUMN height;
+    ALTER TABLE user_settings DROP COLUMN birth_date;
+";
+
 pub const SELECT_USER_SETTINGS: &str = "
-    SELECT cal_limit
+    SELECT cal_limit, sex, height, birth_date
     FROM user_settings
     WHERE user_id = ?1
 ";
 
 pub const SELECT_USER_SETTINGS_FOR_BACKUP: &str = "
-    SELECT user_id, cal_limit
+    SELECT user_id, cal_limit, sex, height, birth_date
     FROM user_settings
     ORDER BY user_id
 ";
 
 pub const UPSERT_USER_SETTINGS: &str = "
     INSERT INTO user_settings (
-        user_id, cal_limit
+        user_id, cal_limit, sex, height, birth_date
     )
-    VALUES (?1, ?2)
+    VALUES (?1, ?2, ?3, ?4, ?5)
     ON CONFLICT (user_id) DO
     UPDATE SET
-        cal_limit = ?2
+        cal_limit = ?2, sex = ?3, height = ?4, birth_date = ?5
 ";
 
 //
@@ -314,6 +556,10 @@ pub const CREATE_TABLE_SPORT: &str = "
     )
 ";
 
+pub const DROP_TABLE_SPORT: &str = "
+    DROP TABLE sport
+";
+
 pub const SELECT_SPORT: &str = "
     SELECT 
         key, name, comment
@@ -365,6 +611,10 @@ pub const CREATE_TABLE_SPORT_ACTIVITY: &str = "
     )
 ";
 
+pub const DROP_TABLE_SPORT_ACTIVITY: &str = "
+    DROP TABLE sport_activity
+";
+
 pub const UPSERT_SPORT_ACTIVITY: &str = "
     INSERT INTO sport_activity (
         user_id, timestamp, sport_key, sets
@@ -403,3 +653,309 @@ pub const DELETE_SPORT_ACTIVITY: &str = "
         timestamp = ?2 AND
         sport_key = ?3
 ";
+
+//
+// Metric
+//
+
+pub const CREATE_TABLE_METRIC: &str = "
+    CREATE TABLE metric (
+        user_id INTEGER NOT NULL,
+        key     TEXT NOT NULL,
+        name    TEXT NOT NULL,
+        unit    TEXT NOT NULL,
+        fields  TEXT NOT NULL,
+        PRIMARY KEY (user_id, key)
+    )
+";
+
+pub const DROP_TABLE_METRIC: &str = "
+    DROP TABLE metric
+";
+
+pub const SELECT_METRIC: &str = "
+    SELECT key, name, unit, fields
+    FROM metric
+    WHERE user_id = ?1 AND key = ?2
+";
+
+pub const SELECT_METRIC_LIST: &str = "
+    SELECT key, name, unit, fields
+    FROM metric
+    WHERE user_id = ?1
+    ORDER BY name, key
+";
+
+pub const SELECT_METRIC_FOR_BACKUP: &str = "
+    SELECT user_id, key, name, unit, fields
+    FROM metric
+    ORDER BY user_id, key
+";
+
+pub const UPSERT_METRIC: &str = "
+    INSERT INTO metric (
+        user_id, key, name, unit, fields
+    )
+    VALUES (?1, ?2, ?3, ?4, ?5)
+    ON CONFLICT (user_id, key) DO
+    UPDATE SET
+        name = ?3, unit = ?4, fields = ?5
+";
+
+pub const DELETE_METRIC: &str = "
+    DELETE FROM metric
+    WHERE user_id = ?1 AND key = ?2
+";
+
+//
+// Metric value
+//
+
+pub const CREATE_TABLE_METRIC_VALUE: &str = "
+    CREATE TABLE metric_value (
+        user_id    INTEGER NOT NULL,
+        timestamp  INTEGER NOT NULL,
+        metric_key TEXT NOT NULL,
+        vals       TEXT NOT NULL,
+        PRIMARY KEY (user_id, timestamp, metric_key),
+        FOREIGN KEY (user_id, metric_key) REFERENCES metric(user_id, key) ON DELETE RESTRICT
+    )
+";
+
+pub const DROP_TABLE_METRIC_VALUE: &str = "
+    DROP TABLE metric_value
+";
+
+pub const UPSERT_METRIC_VALUE: &str = "
+    INSERT INTO metric_value (
+        user_id, timestamp, metric_key, vals
+    )
+    VALUES (?1, ?2, ?3, ?4)
+    ON CONFLICT (user_id, timestamp, metric_key) DO
+    UPDATE SET
+        vals = ?4
+";
+
+pub const SELECT_METRIC_VALUE_LIST: &str = "
+    SELECT timestamp, metric_key, vals
+    FROM metric_value
+    WHERE
+        user_id = ?1 AND
+        metric_key = ?2 AND
+        timestamp >= ?3 AND
+        timestamp <= ?4
+    ORDER BY
+        timestamp
+";
+
+pub const SELECT_METRIC_VALUE_FOR_BACKUP: &str = "
+    SELECT user_id, timestamp, metric_key, vals
+    FROM metric_value
+    ORDER BY user_id, timestamp, metric_key
+";
+
+pub const DELETE_METRIC_VALUE: &str = "
+    DELETE FROM metric_value
+    WHERE
+        user_id = ?1 AND
+        timestamp = ?2 AND
+        metric_key = ?3
+";
+
+//
+// Schedule
+//
+
+pub const CREATE_TABLE_SCHEDULE: &str = "
+    CREATE TABLE schedule (
+        user_id  INTEGER NOT NULL,
+        kind     INTEGER NOT NULL,
+        hour     INTEGER NOT NULL,
+        minute   INTEGER NOT NULL,
+        tz       TEXT NOT NULL,
+        last_run INTEGER NULL,
+        PRIMARY KEY (user_id, kind, hour, minute)
+    )
+";
+
+pub const DROP_TABLE_SCHEDULE: &str = "
+    DROP TABLE schedule
+";
+
+pub const SELECT_SCHEDULE_LIST: &str = "
+    SELECT kind, hour, minute, tz, last_run
+    FROM schedule
+    WHERE user_id = ?1
+    ORDER BY hour, minute, kind
+";
+
+pub const SELECT_SCHEDULE_FOR_BACKUP: &str = "
+    SELECT user_id, kind, hour, minute, tz
+    FROM schedule
+    ORDER BY user_id, kind, hour, minute
+";
+
+pub const UPSERT_SCHEDULE: &str = "
+    INSERT INTO schedule (
+        user_id, kind, hour, minute, tz
+    )
+    VALUES (?1, ?2, ?3, ?4, ?5)
+    ON CONFLICT (user_id, kind, hour, minute) DO
+    UPDATE SET
+        tz = ?5
+";
+
+pub const UPDATE_SCHEDULE_LAST_RUN: &str = "
+    UPDATE schedule
+    SET last_run = ?5
+    WHERE
+        user_id = ?1 AND
+        kind = ?2 AND
+        hour = ?3 AND
+        minute = ?4
+";
+
+pub const DELETE_SCHEDULE: &str = "
+    DELETE FROM schedule
+    WHERE
+        user_id = ?1 AND
+        kind = ?2 AND
+        hour = ?3 AND
+        minute = ?4
+";
+
+//
+// Change log
+//
+
+// Prior is JSON list of affected row sets, as they were before change
+pub const CREATE_TABLE_CHANGE_LOG: &str = "
+    CREATE TABLE change_log (
+        id        INTEGER NOT NULL PRIMARY KEY,
+        user_id   INTEGER NOT NULL,
+        timestamp INTEGER NOT NULL,
+        action    INTEGER NOT NULL,
+        key       TEXT    NOT NULL,
+        prior     TEXT    NOT NULL
+    );
+    CREATE INDEX change_log_user_id ON change_log (user_id, id);
+";
+
+pub const DROP_TABLE_CHANGE_LOG: &str = "
+    DROP TABLE change_log
+";
+
+// State of affected rows right after change, undo is refused if they changed since
+pub const ALTER_TABLE_CHANGE_LOG_ADD_AFTER: &str = "
+    ALTER TABLE change_log ADD COLUMN after TEXT NULL
+";
+
+pub const ALTER_TABLE_CHANGE_LOG_DROP_AFTER: &str = "
+    ALTER TABLE change_log DROP COLUMN after
+";
+
+pub const SELECT_CHANGE_LIST: &str = "
+    SELECT id, timestamp, action, key, prior, after
+    FROM change_log
+    WHERE user_id = ?1
+    ORDER BY id DESC
+    LIMIT ?2
+";
+
+pub const INSERT_CHANGE: &str = "
+    INSERT INTO change_log (
+        user_id, timestamp, action, key, prior, after
+    )
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+";
+
+pub const DELETE_CHANGE: &str = "
+    DELETE FROM change_log
+    WHERE id = ?1
+";
+
+// Keeps only ?2 newest changes of user
+pub const DELETE_OLD_CHANGES: &str = "
+    DELETE FROM change_log
+    WHERE
+        user_id = ?1 AND
+        id <= (
+            SELECT id
+            FROM change_log
+            WHERE user_id = ?1
+            ORDER BY id DESC
+            LIMIT 1 OFFSET ?2
+        )
+";
+
+//
+// Audit log
+//
+
+// Old and new values are JSON of affected rows, rows are never updated or deleted
+pub const CREATE_TABLE_AUDIT_LOG: &str = "
+    CREATE TABLE audit_log (
+        id        INTEGER NOT NULL PRIMARY KEY,
+        user_id   INTEGER NOT NULL,
+        timestamp INTEGER NOT NULL,
+        entity    TEXT    NOT NULL,
+        key       TEXT    NOT NULL,
+        action    INTEGER NOT NULL,
+        old_value TEXT    NULL,
+        new_value TEXT    NULL
+    );
+    CREATE INDEX audit_log_timestamp ON audit_log (timestamp);
+    CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON audit_log
+    BEGIN
+        SELECT RAISE(ABORT, 'audit log is append-only');
+    END;
+    CREATE TRIGGER audit_log_no_delete BEFORE DELETE ON audit_log
+    BEGIN
+        SELECT RAISE(ABORT, 'audit log is append-only');
+    END;
+";
+
+pub const DROP_TABLE_AUDIT_LOG: &str = "
+    DROP TABLE audit_log
+";
+
+pub const SELECT_AUDIT_LIST: &str = "
+    SELECT timestamp, user_id, entity, key, action, old_value, new_value
+    FROM audit_log
+    WHERE timestamp >= ?1 AND timestamp <= ?2
+    ORDER BY id
+";
+
+pub const INSERT_AUDIT: &str = "
+    INSERT INTO audit_log (
+        user_id, timestamp, entity, key, action, old_value, new_value
+    )
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+";
+
+pub const SELECT_AUDIT_LOG_FOR_BACKUP: &str = "
+    SELECT id, user_id, timestamp, entity, key, action, old_value, new_value
+    FROM audit_log
+    ORDER BY id
+";
+
+// Restored entries get new ids, entry equal to one already present is skipped,
+// so restoring same backup twice doesn't duplicate history
+pub const INSERT_AUDIT_FOR_BACKUP: &str = "
+    INSERT INTO audit_log (
+        user_id, timestamp, entity, key, action, old_value, new_value
+    )
+    SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7
+    WHERE NOT EXISTS (
+        SELECT 1
+        FROM audit_log
+        WHERE
+            timestamp = ?2 AND
+            user_id = ?1 AND
+            entity = ?3 AND
+            key = ?4 AND
+            action = ?5 AND
+            old_value IS ?6 AND
+            new_value IS ?7
+    )
+";
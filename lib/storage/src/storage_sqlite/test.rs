@@ -1,8 +1,8 @@
-use std::vec;
+// SQLite specific tests, storage behaviour is checked by conformance tests
 
 use super::*;
+use crate::Storage;
 use anyhow::Result;
-use model::backup::{BundleBackup, FoodBackup, JournalBackup, UserSettingsBackup, WeightBackup};
 use tempfile::NamedTempFile;
 
 //
@@ -14,2175 +14,381 @@ fn test_migrations_apply() -> Result<()> {
     let db_file = NamedTempFile::new()?;
     let stg = StorageSqlite::new(db_file.path())?;
 
-    assert_eq!(7, stg.get_last_migration_id().unwrap());
+    let conn = stg.conn.lock().unwrap();
+    assert_eq!(14, migrations::current_id(&conn)?);
+
+    let status = migrations::status(&conn)?;
+    assert_eq!(14, status.len());
+    for s in status {
+        assert_eq!(MigrationState::Applied, s.state);
+        assert!(s.applied_at.is_some());
+    }
 
     Ok(())
 }
 
-//
-// Weight
-//
-
 #[test]
-fn test_get_weight_list() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Check EmptyList error
-    let res = stg.get_weight_list(
-        1,
-        Timestamp::from_unix_millis(0).unwrap(),
-        Timestamp::from_unix_millis(10).unwrap(),
-    );
-
-    assert!(stg.is_storage_error(StorageError::EmptyResult, &res.unwrap_err()));
-
-    // Add test data
-    stg.raw_execute(
-        "
-            INSERT INTO weight(user_id, timestamp, value)
-            VALUES 
-                (1, 1, 1.1),
-                (1, 2, 2.2),
-                (1, 3, 3.3),
-                (2, 4, 4.4)
-            ;
-        ",
-        false,
-        params![],
-    )?;
+fn test_migrations_apply_in_memory() -> Result<()> {
+    let stg = StorageSqlite::new_in_memory()?;
 
-    // Check weight list for user 1
-    let res = stg.get_weight_list(
-        1,
-        Timestamp::from_unix_millis(0).unwrap(),
-        Timestamp::from_unix_millis(10).unwrap(),
-    );
-    assert_eq!(
-        vec![
-            Weight {
-                timestamp: Timestamp::from_unix_millis(1).unwrap(),
-                value: 1.1
-            },
-            Weight {
-                timestamp: Timestamp::from_unix_millis(2).unwrap(),
-                value: 2.2
-            },
-            Weight {
-                timestamp: Timestamp::from_unix_millis(3).unwrap(),
-                value: 3.3
-            },
-        ],
-        res.unwrap()
-    );
-
-    // Check weight list for user 2
-    let res = stg.get_weight_list(
-        2,
-        Timestamp::from_unix_millis(0).unwrap(),
-        Timestamp::from_unix_millis(10).unwrap(),
-    );
-    assert_eq!(
-        vec![Weight {
-            timestamp: Timestamp::from_unix_millis(4).unwrap(),
-            value: 4.4
-        },],
-        res.unwrap()
-    );
+    assert_eq!(14, migrations::current_id(&stg.conn.lock().unwrap())?);
 
     Ok(())
 }
 
 #[test]
-fn test_delete_weight() -> Result<()> {
+fn test_migrations_down_up() -> Result<()> {
     let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
+    drop(StorageSqlite::new(db_file.path())?);
 
-    // Add test data
-    stg.raw_execute(
-        "
-            INSERT INTO weight(user_id, timestamp, value)
-            VALUES 
-                (1, 1, 1.1),
-                (2, 4, 4.4)
-            ;
-        ",
-        false,
-        params![],
+    let mut migrator = Migrator::open(db_file.path())?;
+    migrator.down(0, false)?;
+    assert_eq!(0, migrator.current_id()?);
+    for s in migrator.status()? {
+        assert_eq!(MigrationState::Pending, s.state);
+    }
+
+    let tables: i64 = Connection::open(db_file.path())?.query_row(
+        "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name NOT IN ('system', 'migration')",
+        [],
+        |row| row.get(0),
     )?;
+    assert_eq!(0, tables);
 
-    // Delete for user 2
-    stg.delete_weight(2, Timestamp::from_unix_millis(4).unwrap())?;
-    let res = stg.get_weight_list(
-        2,
-        Timestamp::from_unix_millis(0).unwrap(),
-        Timestamp::from_unix_millis(10).unwrap(),
-    );
-    assert!(stg.is_storage_error(StorageError::EmptyResult, &res.unwrap_err()));
+    migrator.up(5, false)?;
+    assert_eq!(5, migrator.current_id()?);
+    migrator.up(migrator.latest_id(), false)?;
+    assert_eq!(14, migrator.current_id()?);
 
-    // Delete for user 1 record that not exists (timestamp=4)
-    stg.delete_weight(1, Timestamp::from_unix_millis(4).unwrap())?;
-    assert_eq!(
-        1,
-        stg.get_weight_list(
-            1,
-            Timestamp::from_unix_millis(0).unwrap(),
-            Timestamp::from_unix_millis(10).unwrap(),
-        )?
-        .len()
-    );
+    // Unknown target
+    assert!(migrator.up(15, false).is_err());
 
     Ok(())
 }
 
 #[test]
-fn test_set_weight() -> Result<()> {
+fn test_migrations_down_keeps_bundles() -> Result<()> {
     let db_file = NamedTempFile::new()?;
     let stg = StorageSqlite::new(db_file.path())?;
-
-    // Set invalid weight
-    let res = stg.set_weight(
-        1,
-        &Weight {
-            timestamp: Timestamp::from_unix_millis(1734876557).unwrap(),
-            value: -1.1,
-        },
-    );
-    assert!(stg.is_storage_error(StorageError::WeightInvalid, &res.unwrap_err()));
-
-    // Set weight
-    stg.set_weight(
+    stg.set_food(
         1,
-        &Weight {
-            timestamp: Timestamp::from_unix_millis(1734876557).unwrap(),
-            value: 1.1,
+        &Food {
+            key: "food1".into(),
+            name: "name".into(),
+            brand: "brand".into(),
+            cal100: 1.1,
+            prot100: 2.2,
+            fat100: 3.3,
+            carb100: 4.4,
+            comment: "comment".into(),
         },
     )?;
-
-    // Check in DB
-    let res = stg.raw_query(
-        "SELECT timestamp, value FROM weight WHERE user_id = 1",
-        params![],
-    )?;
-
-    assert_eq!(1, res.len());
-    assert_eq!(
-        Timestamp::from_unix_millis(1734876557).unwrap(),
-        StorageSqlite::get_timestamp(res.first().unwrap(), "timestamp").unwrap()
-    );
-    assert_eq!(
-        1.1,
-        StorageSqlite::get_float(res.first().unwrap(), "value").unwrap()
-    );
-
-    // Update weight
-    stg.set_weight(
+    stg.set_bundle(
         1,
-        &Weight {
-            timestamp: Timestamp::from_unix_millis(1734876557).unwrap(),
-            value: 2.2,
+        &Bundle {
+            key: "child".into(),
+            data: HashMap::from([("food1".into(), 1.5)]),
         },
     )?;
+    let parent = Bundle {
+        key: "parent".into(),
+        data: HashMap::from([("child".into(), 0.0), ("food1".into(), 2.0)]),
+    };
+    stg.set_bundle(1, &parent)?;
+    drop(stg);
 
-    // Check in DB
-    let res = stg.raw_query(
-        "SELECT timestamp, value FROM weight WHERE user_id = 1",
-        params![],
+    // Bundle items go back to JSON data and return
+    let mut migrator = Migrator::open(db_file.path())?;
+    migrator.down(10, false)?;
+    let data: String = Connection::open(db_file.path())?.query_row(
+        "SELECT data FROM bundle WHERE key = 'parent'",
+        [],
+        |row| row.get(0),
     )?;
+    let data: HashMap<String, f64> = serde_json::from_str(&data)?;
+    assert_eq!(parent.data, data);
+    drop(migrator);
 
-    assert_eq!(1, res.len());
-    assert_eq!(
-        Timestamp::from_unix_millis(1734876557).unwrap(),
-        StorageSqlite::get_timestamp(res.first().unwrap(), "timestamp").unwrap()
-    );
-    assert_eq!(
-        2.2,
-        StorageSqlite::get_float(res.first().unwrap(), "value").unwrap()
-    );
+    let stg = StorageSqlite::new(db_file.path())?;
+    assert_eq!(parent, stg.get_bundle(1, "parent")?);
 
     Ok(())
 }
 
-//
-// Food
-//
-
 #[test]
-fn test_set_food() -> Result<()> {
+fn test_migrations_refuse_newer_db() -> Result<()> {
     let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
+    drop(StorageSqlite::new(db_file.path())?);
 
-    // Set invalid food
-    let res = stg.set_food(&Food {
-        key: "".into(),
-        name: "name".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    });
-    assert!(stg.is_storage_error(StorageError::FoodInvalid, &res.unwrap_err()));
-
-    // Set food
-    stg.set_food(&Food {
-        key: "key".into(),
-        name: "name".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    })?;
-
-    // Check in DB
-    let res = stg.raw_query(
-        r#"
-            SELECT
-                key, name, brand, cal100,
-                prot100, fat100, carb100, comment
-            FROM food
-        "#,
-        params![],
+    // Migration applied by newer binary
+    Connection::open(db_file.path())?.execute_batch(
+        "INSERT INTO migration (id, name, applied_at, checksum) VALUES (15, 'next', 0, 'x');
+         UPDATE system SET migration_id = 15;",
     )?;
-
-    assert_eq!(1, res.len());
-    assert_eq!(
-        String::from("key"),
-        StorageSqlite::get_string(res.first().unwrap(), "key").unwrap()
-    );
-    assert_eq!(
-        String::from("name"),
-        StorageSqlite::get_string(res.first().unwrap(), "name").unwrap()
-    );
-    assert_eq!(
-        String::from("brand"),
-        StorageSqlite::get_string(res.first().unwrap(), "brand").unwrap()
-    );
     assert_eq!(
-        1.1,
-        StorageSqlite::get_float(res.first().unwrap(), "cal100").unwrap()
-    );
-    assert_eq!(
-        2.2,
-        StorageSqlite::get_float(res.first().unwrap(), "prot100").unwrap()
-    );
-    assert_eq!(
-        3.3,
-        StorageSqlite::get_float(res.first().unwrap(), "fat100").unwrap()
-    );
-    assert_eq!(
-        4.4,
-        StorageSqlite::get_float(res.first().unwrap(), "carb100").unwrap()
-    );
-    assert_eq!(
-        String::from("comment"),
-        StorageSqlite::get_string(res.first().unwrap(), "comment").unwrap()
+        Some(MigrationState::Unknown),
+        Migrator::open(db_file.path())?
+            .status()?
+            .last()
+            .map(|s| s.state)
     );
 
-    // Update food
-    stg.set_food(&Food {
-        key: "key".into(),
-        name: "name".into(),
-        brand: "".into(),
-        cal100: 5.5,
-        prot100: 6.6,
-        fat100: 7.7,
-        carb100: 8.8,
-        comment: "".into(),
-    })?;
-
-    // Check in DB
-    let res = stg.raw_query(
-        r#"
-            SELECT
-                key, name, brand, cal100,
-                prot100, fat100, carb100, comment
-            FROM food
-        "#,
-        params![],
-    )?;
+    let err = StorageSqlite::new(db_file.path()).err().unwrap();
+    assert!(format!("{err:#}").contains("database migration 15 is newer than latest known 14"));
 
-    assert_eq!(1, res.len());
-    assert_eq!(
-        String::from("key"),
-        StorageSqlite::get_string(res.first().unwrap(), "key").unwrap()
-    );
-    assert_eq!(
-        String::from("name"),
-        StorageSqlite::get_string(res.first().unwrap(), "name").unwrap()
-    );
-    assert_eq!(
-        String::from(""),
-        StorageSqlite::get_string(res.first().unwrap(), "brand").unwrap()
-    );
-    assert_eq!(
-        5.5,
-        StorageSqlite::get_float(res.first().unwrap(), "cal100").unwrap()
-    );
-    assert_eq!(
-        6.6,
-        StorageSqlite::get_float(res.first().unwrap(), "prot100").unwrap()
-    );
-    assert_eq!(
-        7.7,
-        StorageSqlite::get_float(res.first().unwrap(), "fat100").unwrap()
-    );
-    assert_eq!(
-        8.8,
-        StorageSqlite::get_float(res.first().unwrap(), "carb100").unwrap()
-    );
-    assert_eq!(
-        String::from(""),
-        StorageSqlite::get_string(res.first().unwrap(), "comment").unwrap()
-    );
+    let mut migrator = Migrator::open(db_file.path())?;
+    assert!(migrator.down(0, false).is_err());
 
     Ok(())
 }
 
 #[test]
-fn test_get_food() -> Result<()> {
+fn test_migrations_refuse_changed() -> Result<()> {
     let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Get food that not exists
-    let res = stg.get_food("key");
-    assert!(stg.is_storage_error(StorageError::FoodNotFound, &res.unwrap_err()));
-
-    // Set food
-    let f = Food {
-        key: "key".into(),
-        name: "name".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    };
-    stg.set_food(&f)?;
-
-    // Get food
-    assert_eq!(f, stg.get_food("key").unwrap());
+    drop(StorageSqlite::new(db_file.path())?);
 
-    Ok(())
-}
+    Connection::open(db_file.path())?
+        .execute("UPDATE migration SET checksum = 'x' WHERE id = 2", [])?;
+    assert_eq!(
+        MigrationState::Changed,
+        Migrator::open(db_file.path())?.status()?[1].state
+    );
 
-#[test]
-fn test_get_food_list() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
+    let err = StorageSqlite::new(db_file.path()).err().unwrap();
+    assert!(format!("{err:#}").contains("migration [2] create_tables_weight_food changed"));
 
-    // Get empty food list
-    let res = stg.get_food_list();
-    assert!(stg.is_storage_error(StorageError::EmptyResult, &res.unwrap_err()));
-
-    // Set food
-    let f1 = Food {
-        key: "key1".into(),
-        name: "name1".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    };
-    stg.set_food(&f1)?;
-
-    let f2 = Food {
-        key: "key2".into(),
-        name: "name2".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    };
-    stg.set_food(&f2)?;
+    // Migrator refuses it too, unless forced
+    let mut migrator = Migrator::open(db_file.path())?;
+    let err = migrator.down(10, false).err().unwrap();
+    assert!(format!("{err:#}").contains("migration [2] create_tables_weight_food changed"));
+    assert_eq!(14, migrator.current_id()?);
 
-    // Get food list
-    assert_eq!(vec![f1, f2], stg.get_food_list().unwrap());
+    migrator.down(10, true)?;
+    assert_eq!(10, migrator.current_id()?);
+    let err = migrator.up(14, false).err().unwrap();
+    assert!(format!("{err:#}").contains("migration [2] create_tables_weight_food changed"));
+    migrator.up(14, true)?;
+    assert_eq!(14, migrator.current_id()?);
 
     Ok(())
 }
 
 #[test]
-fn test_delete_food() -> Result<()> {
+fn test_migrations_metadata_for_old_db() -> Result<()> {
     let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Set food
-    let f1 = Food {
-        key: "key1".into(),
-        name: "name1".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    };
-    stg.set_food(&f1)?;
-
-    let f2 = Food {
-        key: "key2".into(),
-        name: "name2".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    };
-    stg.set_food(&f2)?;
-
-    // Get food list
-    assert_eq!(vec![f1, f2.clone()], stg.get_food_list().unwrap());
-
-    // Delete food1
-    stg.delete_food("key1")?;
-
-    // Get food list
-    assert_eq!(vec![f2], stg.get_food_list().unwrap());
-
-    // Delete food2
-    stg.delete_food("key2")?;
-
-    // Get food list
-    let res = stg.get_food_list();
-    assert!(stg.is_storage_error(StorageError::EmptyResult, &res.unwrap_err()));
+    drop(StorageSqlite::new(db_file.path())?);
+
+    // Database of binary, that didn't record metadata
+    let conn = Connection::open(db_file.path())?;
+    conn.execute("DROP TABLE migration", [])?;
+    drop(conn);
+
+    let migrator = Migrator::open(db_file.path())?;
+    let status = migrator.status()?;
+    assert_eq!(14, status.len());
+    for s in status {
+        assert_eq!(MigrationState::Applied, s.state);
+        assert_eq!(None, s.applied_at);
+    }
 
     Ok(())
 }
 
 #[test]
-fn test_delete_food_with_bundle() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
+fn test_migration_bundle_items() -> Result<()> {
+    let mut conn = Connection::open_in_memory()?;
+    migrations::init(&mut conn)?;
+    migrations::up(&mut conn, 10)?;
 
-    // Set food
-    stg.set_food(&Food {
-        key: "key1".into(),
-        name: "name1".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    })?;
-
-    stg.set_food(&Food {
-        key: "key2".into(),
-        name: "name2".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    })?;
-
-    // Set bundle
-    stg.set_bundle(
-        1,
-        &Bundle {
-            key: "bndl_key".into(),
-            data: HashMap::from([("key1".into(), 123.123)]),
-        },
+    // Bundle contents as JSON
+    conn.execute_batch(
+        r#"
+        INSERT INTO food (key, name, brand, cal100, prot100, fat100, carb100, comment)
+        VALUES ('food1', 'name', 'brand', 1.1, 2.2, 3.3, 4.4, 'comment');
+        INSERT INTO bundle (user_id, key, data) VALUES
+            (1, 'child', '{"food1": 1.5}'),
+            (1, 'parent', '{"child": 0, "food1": 2}');
+        "#,
     )?;
 
-    // Check delete food, that is used in bundle
-    let res = stg.delete_food("key1");
-    assert!(stg.is_storage_error(StorageError::FoodIsUsed, &res.unwrap_err()));
+    let stg = StorageSqlite::from_conn(conn)?;
+
+    assert_eq!(14, migrations::current_id(&stg.conn.lock().unwrap())?);
+    assert_eq!(
+        Bundle {
+            key: "parent".into(),
+            data: HashMap::from([("child".into(), 0.0), ("food1".into(), 2.0)]),
+        },
+        stg.get_bundle(1, "parent")?
+    );
+    assert_eq!(
+        Bundle {
+            key: "child".into(),
+            data: HashMap::from([("food1".into(), 1.5)]),
+        },
+        stg.get_bundle(1, "child")?
+    );
 
-    // Delete food that not used
-    stg.delete_food("key2")?;
+    // Old data column is dropped
+    let conn = stg.conn.lock().unwrap();
+    assert!(conn.prepare("SELECT data FROM bundle").is_err());
 
     Ok(())
 }
 
 #[test]
-fn test_find_food() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Find empty result
-    let res = stg.find_food("some food");
-    assert!(stg.is_storage_error(StorageError::EmptyResult, &res.unwrap_err()));
-
-    // Set food
-    let f1 = Food {
-        key: "key1".into(),
-        name: "name1".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    };
-    stg.set_food(&f1)?;
-
-    let f2 = Food {
-        key: "key2".into(),
-        name: "name2".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    };
-    stg.set_food(&f2)?;
-
-    let f3 = Food {
-        key: "key3".into(),
-        name: "Сырок Дружба".into(),
-        brand: "Вкусвилл".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "Вкусный".into(),
-    };
-    stg.set_food(&f3)?;
-
-    // Find food
-    assert_eq!(
-        vec![f1.clone(), f2.clone(), f3.clone()],
-        stg.find_food("kEy").unwrap()
-    );
-    assert_eq!(vec![f2], stg.find_food("NAMe2").unwrap());
-    assert_eq!(vec![f3.clone()], stg.find_food("дружба").unwrap());
-    assert_eq!(vec![f3.clone()], stg.find_food("вкусВиЛЛ").unwrap());
-    assert_eq!(vec![f3.clone()], stg.find_food("нЫЙ").unwrap());
+fn test_migration_bundle_items_dangling() -> Result<()> {
+    let mut conn = Connection::open_in_memory()?;
+    migrations::init(&mut conn)?;
+    migrations::up(&mut conn, 10)?;
+
+    let data = r#"{"deleted_food": 3, "deleted_bundle": 0}"#;
+    conn.execute(
+        "INSERT INTO bundle (user_id, key, data) VALUES (1, 'parent', ?1)",
+        [data],
+    )?;
+
+    // Migration fails listing references to missing rows, data stays as it was
+    let err = migrations::up(&mut conn, 11).err().unwrap();
+    assert!(format!("{err:#}").contains(
+        "user 1 bundle parent item deleted_bundle, user 1 bundle parent item deleted_food"
+    ));
+    assert_eq!(10, migrations::current_id(&conn)?);
+    let res: String = conn.query_row("SELECT data FROM bundle", [], |row| row.get(0))?;
+    assert_eq!(data, res);
 
     Ok(())
 }
 
 //
-// Sport
+// Bundle items
 //
 
 #[test]
-fn test_set_sport() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Set invalid sport
-    let res = stg.set_sport(&Sport {
-        key: "".into(),
-        name: "name".into(),
-        comment: "comment".into(),
+fn test_bundle_item_foreign_keys() -> Result<()> {
+    let stg = StorageSqlite::new_in_memory()?;
+
+    // Restore doesn't accept references to missing rows
+    let mut backup = stg.backup(1)?;
+    backup.bundle.push(BundleBackup {
+        user_id: 1,
+        key: "bundle".into(),
+        data: r#"{"missing": 1.1}"#.into(),
     });
-    assert!(stg.is_storage_error(StorageError::SportInvalid, &res.unwrap_err()));
-
-    // Set sport
-    stg.set_sport(&Sport {
-        key: "key".into(),
-        name: "name".into(),
-        comment: "comment".into(),
-    })?;
-
-    // Check in DB
-    let res = stg.raw_query(
-        r#"
-            SELECT
-                key, name, comment
-            FROM sport
-        "#,
-        params![],
-    )?;
-
-    assert_eq!(1, res.len());
-    assert_eq!(
-        String::from("key"),
-        StorageSqlite::get_string(res.first().unwrap(), "key").unwrap()
-    );
-    assert_eq!(
-        String::from("name"),
-        StorageSqlite::get_string(res.first().unwrap(), "name").unwrap()
-    );
-    assert_eq!(
-        String::from("comment"),
-        StorageSqlite::get_string(res.first().unwrap(), "comment").unwrap()
-    );
-
-    // Update sport
-    stg.set_sport(&Sport {
-        key: "key".into(),
-        name: "name".into(),
-        comment: "".into(),
-    })?;
-
-    // Check in DB
-    let res = stg.raw_query(
-        r#"
-            SELECT
-                key, name, comment
-            FROM sport
-        "#,
-        params![],
-    )?;
-
-    assert_eq!(1, res.len());
-    assert_eq!(
-        String::from("key"),
-        StorageSqlite::get_string(res.first().unwrap(), "key").unwrap()
-    );
-    assert_eq!(
-        String::from("name"),
-        StorageSqlite::get_string(res.first().unwrap(), "name").unwrap()
-    );
-    assert_eq!(
-        String::from(""),
-        StorageSqlite::get_string(res.first().unwrap(), "comment").unwrap()
-    );
+    assert!(matches!(
+        stg.restore(&backup),
+        Err(StorageError::BundleDepFoodNotFound(k)) if k == "missing"
+    ));
+
+    backup.bundle[0].data = r#"{"missing": 0}"#.into();
+    assert!(matches!(
+        stg.restore(&backup),
+        Err(StorageError::BundleDepBundleNotFound(k)) if k == "missing"
+    ));
+    assert!(matches!(
+        stg.get_bundle(1, "bundle"),
+        Err(StorageError::BundleNotFound(_))
+    ));
 
     Ok(())
 }
 
-#[test]
-fn test_get_sport() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Get sport that not exists
-    let res = stg.get_sport("key");
-    assert!(stg.is_storage_error(StorageError::SportNotFound, &res.unwrap_err()));
-
-    // Set sport
-    let s = Sport {
-        key: "key".into(),
-        name: "name".into(),
-        comment: "comment".into(),
-    };
-    stg.set_sport(&s)?;
-
-    // Get sport
-    assert_eq!(s, stg.get_sport("key").unwrap());
-
-    Ok(())
-}
+//
+// WAL
+//
 
 #[test]
-fn test_get_sport_list() -> Result<()> {
+fn test_read_during_write_transaction() -> Result<()> {
     let db_file = NamedTempFile::new()?;
     let stg = StorageSqlite::new(db_file.path())?;
+    stg.set_food(
+        1,
+        &Food {
+            key: "key".into(),
+            name: "name".into(),
+            brand: "brand".into(),
+            cal100: 1.1,
+            prot100: 2.2,
+            fat100: 3.3,
+            carb100: 4.4,
+            comment: "comment".into(),
+        },
+    )?;
 
-    // Get empty sport list
-    let res = stg.get_sport_list();
-    assert!(stg.is_storage_error(StorageError::EmptyResult, &res.unwrap_err()));
-
-    // Set sport
-    let s1 = Sport {
-        key: "key1".into(),
-        name: "name1".into(),
-        comment: "comment".into(),
-    };
-    stg.set_sport(&s1)?;
-
-    let s2 = Sport {
-        key: "key2".into(),
-        name: "name2".into(),
-        comment: "comment".into(),
-    };
-    stg.set_sport(&s2)?;
+    // Writer keeps transaction open, readers don't wait and see last commit
+    let mut conn = stg.conn.lock().unwrap();
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM food", [])?;
+    assert_eq!("name", stg.get_food("key")?.name);
+    tx.commit()?;
+    drop(conn);
 
-    // Get sport list
-    assert_eq!(vec![s1, s2], stg.get_sport_list().unwrap());
+    assert!(matches!(
+        stg.get_food("key"),
+        Err(StorageError::FoodNotFound(k)) if k == "key"
+    ));
 
     Ok(())
 }
 
-#[test]
-fn test_delete_sport() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Set sport
-    let s1 = Sport {
-        key: "key1".into(),
-        name: "name1".into(),
-        comment: "comment".into(),
-    };
-    stg.set_sport(&s1)?;
-
-    let s2 = Sport {
-        key: "key2".into(),
-        name: "name2".into(),
-        comment: "comment".into(),
-    };
-    stg.set_sport(&s2)?;
-
-    // Get sport list
-    assert_eq!(vec![s1, s2.clone()], stg.get_sport_list().unwrap());
-
-    // Delete sport1
-    stg.delete_sport("key1")?;
+//
+// Row mapping
+//
 
-    // Get sport list
-    assert_eq!(vec![s2], stg.get_sport_list().unwrap());
+#[test]
+fn test_wrong_column_value() -> Result<()> {
+    let stg = StorageSqlite::new_in_memory()?;
 
-    // Delete sport2
-    stg.delete_sport("key2")?;
+    // Values bypassing validation must fail mapping, not be truncated or panic
+    stg.raw_execute(
+        "INSERT INTO weight (user_id, timestamp, value) VALUES (1, 1000, 'heavy')",
+        false,
+        params![],
+    )?;
+    assert!(matches!(
+        stg.get_weight_list(
+            1,
+            Timestamp::from_unix_millis(0).unwrap(),
+            Timestamp::from_unix_millis(2000).unwrap()
+        ),
+        Err(StorageError::Internal(_))
+    ));
 
-    // Get sport list
-    let res = stg.get_sport_list();
-    assert!(stg.is_storage_error(StorageError::EmptyResult, &res.unwrap_err()));
+    stg.raw_execute(
+        "INSERT INTO schedule (user_id, kind, hour, minute, tz) VALUES (1, 99, 8, 0, 'UTC')",
+        false,
+        params![],
+    )?;
+    assert!(matches!(
+        stg.get_schedule_list(1),
+        Err(StorageError::Internal(_))
+    ));
 
     Ok(())
 }
 
 //
-// Sport activity
+// Audit log
 //
 
 #[test]
-fn test_set_sport_activity() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Set invalid sport activity
-    let res = stg.set_sport_activity(
-        1,
-        &SportActivity {
-            sport_key: "test".into(),
-            timestamp: Timestamp::now(),
-            sets: vec![],
-        },
-    );
-    assert!(stg.is_storage_error(StorageError::SportActivityInvalid, &res.unwrap_err()));
-
-    // Set sport activity for sport that not exists
-    let res = stg.set_sport_activity(
-        1,
-        &SportActivity {
-            sport_key: "test".into(),
-            timestamp: Timestamp::now(),
-            sets: vec![1, 2, 3],
-        },
-    );
-    assert!(stg.is_storage_error(StorageError::SportInvalid, &res.unwrap_err()));
-
-    // Set sport
-    stg.set_sport(&Sport {
-        key: "test".into(),
-        name: "test".into(),
-        comment: "".into(),
-    })?;
-
-    // Set sport activity
-    stg.set_sport_activity(
+fn test_audit_log_append_only() -> Result<()> {
+    let stg = StorageSqlite::new_in_memory()?;
+    stg.set_weight(
         1,
-        &SportActivity {
-            sport_key: "test".into(),
+        &Weight {
             timestamp: Timestamp::from_unix_millis(1).unwrap(),
-            sets: vec![1],
+            value: 80.0,
         },
     )?;
 
-    // Check in DB
-    let res = stg.raw_query(
-        r#"
-            SELECT
-                timestamp, sport_key, sets
-            FROM sport_activity
-            WHERE user_id = 1
-        "#,
-        params![],
-    )?;
-
-    assert_eq!(1, res.len());
-    assert_eq!(
-        Timestamp::from_unix_millis(1).unwrap(),
-        StorageSqlite::get_timestamp(res.first().unwrap(), "timestamp").unwrap()
-    );
-    assert_eq!(
-        String::from("test"),
-        StorageSqlite::get_string(res.first().unwrap(), "sport_key").unwrap()
-    );
-    assert_eq!(
-        String::from("[1]"),
-        StorageSqlite::get_string(res.first().unwrap(), "sets").unwrap()
-    );
-
-    // Update sport activity
-    stg.set_sport_activity(
-        1,
-        &SportActivity {
-            sport_key: "test".into(),
-            timestamp: Timestamp::from_unix_millis(1).unwrap(),
-            sets: vec![1, 2, 3],
-        },
-    )?;
-
-    // Check in DB
-    let res = stg.raw_query(
-        r#"
-            SELECT
-                timestamp, sport_key, sets
-            FROM sport_activity
-            WHERE user_id = 1
-        "#,
-        params![],
-    )?;
-
-    assert_eq!(1, res.len());
-    assert_eq!(
-        Timestamp::from_unix_millis(1).unwrap(),
-        StorageSqlite::get_timestamp(res.first().unwrap(), "timestamp").unwrap()
-    );
-    assert_eq!(
-        String::from("test"),
-        StorageSqlite::get_string(res.first().unwrap(), "sport_key").unwrap()
-    );
-    assert_eq!(
-        String::from("[1,2,3]"),
-        StorageSqlite::get_string(res.first().unwrap(), "sets").unwrap()
-    );
-
-    Ok(())
-}
-
-#[test]
-fn test_get_sport_activity_report() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Get empty report
-    let res = stg.get_sport_activity_report(
-        1,
-        Timestamp::from_unix_millis(1).unwrap(),
-        Timestamp::from_unix_millis(2).unwrap(),
-    );
-    assert!(stg.is_storage_error(StorageError::EmptyResult, &res.unwrap_err()));
-
-    // Set data
-    stg.set_sport(&Sport {
-        key: "sport1".into(),
-        name: "Sport 1".into(),
-        comment: "".into(),
-    })?;
-    stg.set_sport(&Sport {
-        key: "sport2".into(),
-        name: "Sport 2".into(),
-        comment: "".into(),
-    })?;
-
-    stg.set_sport_activity(
-        1,
-        &SportActivity {
-            sport_key: "sport2".into(),
-            timestamp: Timestamp::from_unix_millis(1).unwrap(),
-            sets: vec![1],
-        },
-    )?;
-    stg.set_sport_activity(
-        1,
-        &SportActivity {
-            sport_key: "sport1".into(),
-            timestamp: Timestamp::from_unix_millis(1).unwrap(),
-            sets: vec![1, 2],
-        },
-    )?;
-    stg.set_sport_activity(
-        1,
-        &SportActivity {
-            sport_key: "sport1".into(),
-            timestamp: Timestamp::from_unix_millis(3).unwrap(),
-            sets: vec![1, 2, 3],
-        },
-    )?;
-
-    // Get report
-    let res = stg.get_sport_activity_report(
-        1,
-        Timestamp::from_unix_millis(1).unwrap(),
-        Timestamp::from_unix_millis(3).unwrap(),
-    )?;
-
-    assert_eq!(
-        vec![
-            SportActivityReport {
-                sport_name: "Sport 1".into(),
-                timestamp: Timestamp::from_unix_millis(1).unwrap(),
-                sets: vec![1, 2],
-            },
-            SportActivityReport {
-                sport_name: "Sport 2".into(),
-                timestamp: Timestamp::from_unix_millis(1).unwrap(),
-                sets: vec![1],
-            },
-            SportActivityReport {
-                sport_name: "Sport 1".into(),
-                timestamp: Timestamp::from_unix_millis(3).unwrap(),
-                sets: vec![1, 2, 3],
-            }
-        ],
-        res
-    );
-
-    Ok(())
-}
-
-#[test]
-fn test_delete_sport_activity() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Set data
-    stg.set_sport(&Sport {
-        key: "sport1".into(),
-        name: "Sport 1".into(),
-        comment: "".into(),
-    })?;
-
-    stg.set_sport_activity(
-        1,
-        &SportActivity {
-            sport_key: "sport1".into(),
-            timestamp: Timestamp::from_unix_millis(1).unwrap(),
-            sets: vec![1],
-        },
-    )?;
-
-    // Check sport activity report
-    let res = stg.get_sport_activity_report(
-        1,
-        Timestamp::from_unix_millis(1).unwrap(),
-        Timestamp::from_unix_millis(3).unwrap(),
-    )?;
-    assert_eq!(
-        vec![SportActivityReport {
-            sport_name: "Sport 1".into(),
-            timestamp: Timestamp::from_unix_millis(1).unwrap(),
-            sets: vec![1],
-        }],
-        res
-    );
-
-    // Delete sport activity
-    stg.delete_sport_activity(1, Timestamp::from_unix_millis(1).unwrap(), "sport1")?;
-
-    // Check empty report
-    let res = stg.get_sport_activity_report(
-        1,
-        Timestamp::from_unix_millis(1).unwrap(),
-        Timestamp::from_unix_millis(2).unwrap(),
-    );
-    assert!(stg.is_storage_error(StorageError::EmptyResult, &res.unwrap_err()));
-
-    Ok(())
-}
-
-#[test]
-fn test_delete_sport_with_activity() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Set data
-    stg.set_sport(&Sport {
-        key: "sport1".into(),
-        name: "Sport 1".into(),
-        comment: "".into(),
-    })?;
-
-    stg.set_sport_activity(
-        1,
-        &SportActivity {
-            sport_key: "sport1".into(),
-            timestamp: Timestamp::from_unix_millis(1).unwrap(),
-            sets: vec![1],
-        },
-    )?;
-
-    // Delet sport
-    let res = stg.delete_sport("sport1");
-    assert!(stg.is_storage_error(StorageError::SportIsUsedViolation, &res.unwrap_err()));
-
-    Ok(())
-}
-
-//
-// User settings
-//
-
-#[test]
-fn set_user_settings() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Set invalid user settings
-    let res = stg.set_user_settings(1, &UserSettings { cal_limit: 0.0 });
-    assert!(stg.is_storage_error(StorageError::UserSettingsInvalid, &res.unwrap_err()));
-
-    // Set user settings
-    stg.set_user_settings(1, &UserSettings { cal_limit: 100.0 })?;
-
-    // Check in DB
-    let res = stg.raw_query(
-        r#"
-            SELECT cal_limit
-            FROM user_settings
-            WHERE user_id = 1
-        "#,
-        params![],
-    )?;
-
-    assert_eq!(1, res.len());
-    assert_eq!(
-        100.0,
-        StorageSqlite::get_float(res.first().unwrap(), "cal_limit").unwrap()
-    );
-
-    // Upser user settings
-    stg.set_user_settings(1, &UserSettings { cal_limit: 200.0 })?;
-
-    // Check in DB
-    let res = stg.raw_query(
-        r#"
-            SELECT cal_limit
-            FROM user_settings
-            WHERE user_id = 1
-        "#,
-        params![],
-    )?;
-
-    assert_eq!(1, res.len());
-    assert_eq!(
-        200.0,
-        StorageSqlite::get_float(res.first().unwrap(), "cal_limit").unwrap()
-    );
-
-    Ok(())
-}
-
-#[test]
-fn get_user_settings() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Get settings that not exists
-    let res = stg.get_user_settings(1);
-    assert!(stg.is_storage_error(StorageError::UserSettingsNotFound, &res.unwrap_err()));
-
-    // Set settings
-    let s = UserSettings { cal_limit: 200.0 };
-    stg.set_user_settings(1, &s)?;
-
-    // Get settings
-    let res = stg.get_user_settings(1)?;
-    assert_eq!(s, res);
-
-    Ok(())
-}
-
-//
-// Bundle
-//
-
-#[test]
-fn test_get_bundle() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Get not existing bundle
-    let res = stg.get_bundle(1, "test");
-    assert!(stg.is_storage_error(StorageError::BundleNotFound, &res.unwrap_err()));
-
-    // Add bundle to DB
-    stg.raw_execute(
-        r#"
-            INSERT INTO bundle(user_id, key, data)
-            VALUES 
-                (1, 'test', '{"bundle1": 0, "food1": 1.1}')
-            ;
-        "#,
-        false,
-        params![],
-    )?;
-
-    // Get bundle
-    let res = stg.get_bundle(1, "test")?;
-    assert_eq!(
-        Bundle {
-            key: "test".into(),
-            data: HashMap::from([("bundle1".into(), 0.0), ("food1".into(), 1.1)]),
-        },
-        res
-    );
-
-    Ok(())
-}
-
-#[test]
-fn test_get_bundle_list() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Get empty bundle list
-    let res = stg.get_bundle_list(1);
-    assert!(stg.is_storage_error(StorageError::EmptyResult, &res.unwrap_err()));
-
-    // Add bundle to DB
-    stg.raw_execute(
-        r#"
-            INSERT INTO bundle(user_id, key, data)
-            VALUES 
-                (1, 'test', '{"bundle1": 0, "food1": 1.1}'),
-                (1, 'test2', '{"bundle2": 0}')
-            ;
-        "#,
-        false,
-        params![],
-    )?;
-
-    // Get bundle list
-    let res = stg.get_bundle_list(1)?;
-    assert_eq!(
-        vec![
-            Bundle {
-                key: "test".into(),
-                data: HashMap::from([("bundle1".into(), 0.0), ("food1".into(), 1.1)]),
-            },
-            Bundle {
-                key: "test2".into(),
-                data: HashMap::from([("bundle2".into(), 0.0)]),
-            }
-        ],
-        res
-    );
-
-    Ok(())
-}
-
-#[test]
-fn test_set_bundle() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Check invalid bundle
-    for b in [
-        &Bundle {
-            key: "".into(),
-            data: HashMap::new(),
-        },
-        &Bundle {
-            key: "key".into(),
-            data: HashMap::new(),
-        },
-        &Bundle {
-            key: "key".into(),
-            data: HashMap::from([("food1".into(), -1.0)]),
-        },
-    ] {
-        let res = stg.set_bundle(1, b);
-        assert!(stg.is_storage_error(StorageError::BundleInvalid, &res.unwrap_err()));
-    }
-
-    // Check errors
-    let res = stg.set_bundle(
-        1,
-        &Bundle {
-            key: "bndl_key".into(),
-            data: HashMap::from([("bndl_key".into(), 0.0)]),
-        },
-    );
-    assert!(stg.is_storage_error(StorageError::BundleDepRecursive, &res.unwrap_err()));
-
-    let res = stg.set_bundle(
-        1,
-        &Bundle {
-            key: "bndl_key".into(),
-            data: HashMap::from([("bndl_key2".into(), 0.0)]),
-        },
-    );
-    assert!(stg.is_storage_error(StorageError::BundleDepBundleNotFound, &res.unwrap_err()));
-
-    let res = stg.set_bundle(
-        1,
-        &Bundle {
-            key: "bndl_key".into(),
-            data: HashMap::from([("food_key".into(), 1.0)]),
-        },
-    );
-    assert!(stg.is_storage_error(StorageError::BundleDepFoodNotFound, &res.unwrap_err()));
-
-    // Set initial data
-    stg.set_food(&Food {
-        key: "food_key".into(),
-        name: "name".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    })?;
-
-    // Set bundle
-    stg.set_bundle(
-        1,
-        &Bundle {
-            key: "bndl_key".into(),
-            data: HashMap::from([("food_key".into(), 123.123)]),
-        },
-    )?;
-
-    // Set another bundle
-    stg.set_bundle(
-        1,
-        &Bundle {
-            key: "bndl_key_2".into(),
-            data: HashMap::from([("food_key".into(), 123.123)]),
-        },
-    )?;
-
-    // Update bundle
-    stg.set_bundle(
-        1,
-        &Bundle {
-            key: "bndl_key".into(),
-            data: HashMap::from([("food_key".into(), 123.123), ("bndl_key_2".into(), 0.0)]),
-        },
-    )?;
-
-    // Get bundle list
-    let res = stg.get_bundle_list(1)?;
-    assert_eq!(
-        vec![
-            Bundle {
-                key: "bndl_key".into(),
-                data: HashMap::from([("food_key".into(), 123.123), ("bndl_key_2".into(), 0.0)]),
-            },
-            Bundle {
-                key: "bndl_key_2".into(),
-                data: HashMap::from([("food_key".into(), 123.123)]),
-            }
-        ],
-        res
-    );
-
-    Ok(())
-}
-
-#[test]
-fn test_delete_bundle() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Set initial data
-    stg.set_food(&Food {
-        key: "food_key".into(),
-        name: "name".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    })?;
-
-    stg.set_bundle(
-        1,
-        &Bundle {
-            key: "bndl_key_2".into(),
-            data: HashMap::from([("food_key".into(), 123.123)]),
-        },
-    )?;
-
-    stg.set_bundle(
-        1,
-        &Bundle {
-            key: "bndl_key".into(),
-            data: HashMap::from([("food_key".into(), 123.123), ("bndl_key_2".into(), 0.0)]),
-        },
-    )?;
-
-    // Try delete when used
-    let res = stg.delete_bundle(1, "bndl_key_2");
-    assert!(stg.is_storage_error(StorageError::BundleIsUsed, &res.unwrap_err()));
-
-    // Delete correct
-    stg.delete_bundle(1, "bndl_key")?;
-    stg.delete_bundle(1, "bndl_key_2")?;
-
-    Ok(())
-}
-
-//
-// Journal
-//
-
-#[test]
-fn test_set_journal() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Set invalid journal
-    for j in [
-        &Journal {
-            timestamp: Timestamp::from_unix_millis(1).unwrap(),
-            meal: Meal::Breakfast,
-            food_key: "".into(),
-            food_weight: 0.0,
-        },
-        &Journal {
-            timestamp: Timestamp::from_unix_millis(1).unwrap(),
-            meal: Meal::Breakfast,
-            food_key: "food".into(),
-            food_weight: 0.0,
-        },
-    ] {
-        let res = stg.set_journal(1, j);
-        assert!(stg.is_storage_error(StorageError::JournalInvalid, &res.unwrap_err()));
-    }
-
-    // Set journal with food not exists
-    let res = stg.set_journal(
-        1,
-        &Journal {
-            timestamp: Timestamp::from_unix_millis(1).unwrap(),
-            meal: Meal::Breakfast,
-            food_key: "food".into(),
-            food_weight: 1.0,
-        },
-    );
-    assert!(stg.is_storage_error(StorageError::FoodNotFound, &res.unwrap_err()));
-
-    // Set food
-    stg.set_food(&Food {
-        key: "food".into(),
-        name: "name".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    })?;
-
-    // Set journal
-    stg.set_journal(
-        1,
-        &Journal {
-            timestamp: Timestamp::from_unix_millis(1).unwrap(),
-            meal: Meal::Breakfast,
-            food_key: "food".into(),
-            food_weight: 1.0,
-        },
-    )?;
-
-    // Check in DB
-    let res = stg.raw_query(
-        r#"
-            SELECT timestamp, meal, foodkey, foodweight
-            FROM journal
-            WHERE user_id = 1
-        "#,
-        params![],
-    )?;
-
-    assert_eq!(1, res.len());
-    assert_eq!(
-        Timestamp::from_unix_millis(1).unwrap(),
-        StorageSqlite::get_timestamp(res.first().unwrap(), "timestamp").unwrap()
-    );
-    assert_eq!(
-        0,
-        StorageSqlite::get_integer(res.first().unwrap(), "meal").unwrap()
-    );
-    assert_eq!(
-        String::from("food"),
-        StorageSqlite::get_string(res.first().unwrap(), "foodkey").unwrap()
-    );
-    assert_eq!(
-        1.0,
-        StorageSqlite::get_float(res.first().unwrap(), "foodweight").unwrap()
-    );
-
-    Ok(())
-}
-
-#[test]
-fn test_set_journal_bundle() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Set initial data
-    stg.set_food(&Food {
-        key: "food".into(),
-        name: "name".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    })?;
-    stg.set_food(&Food {
-        key: "food2".into(),
-        name: "name".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    })?;
-    stg.set_bundle(
-        1,
-        &Bundle {
-            key: "bndl2".into(),
-            data: HashMap::from([("food2".into(), 123.123)]),
-        },
-    )?;
-    stg.set_bundle(
-        1,
-        &Bundle {
-            key: "bndl1".into(),
-            data: HashMap::from([("food".into(), 456.456), ("bndl2".into(), 0.0)]),
-        },
-    )?;
-
-    // Set journal bundle not exists
-    let res = stg.set_journal_bundle(
-        1,
-        Timestamp::from_unix_millis(1).unwrap(),
-        Meal::Breakfast,
-        "test",
-    );
-    assert!(stg.is_storage_error(StorageError::BundleNotFound, &res.unwrap_err()));
-
-    // Set journal bundle
-    stg.set_journal_bundle(
-        1,
-        Timestamp::from_unix_millis(1).unwrap(),
-        Meal::Breakfast,
-        "bndl1",
-    )?;
-
-    // Check in DB
-    let res = stg.raw_query(
-        r#"
-            SELECT timestamp, meal, foodkey, foodweight
-            FROM journal
-            WHERE user_id = 1
-        "#,
-        params![],
-    )?;
-
-    assert_eq!(2, res.len());
-    assert_eq!(
-        Timestamp::from_unix_millis(1).unwrap(),
-        StorageSqlite::get_timestamp(res.first().unwrap(), "timestamp").unwrap()
-    );
-    assert_eq!(
-        0,
-        StorageSqlite::get_integer(res.first().unwrap(), "meal").unwrap()
-    );
-    assert_eq!(
-        String::from("food"),
-        StorageSqlite::get_string(res.first().unwrap(), "foodkey").unwrap()
-    );
-    assert_eq!(
-        456.456,
-        StorageSqlite::get_float(res.first().unwrap(), "foodweight").unwrap()
-    );
-    //
-    assert_eq!(
-        Timestamp::from_unix_millis(1).unwrap(),
-        StorageSqlite::get_timestamp(res.get(1).unwrap(), "timestamp").unwrap()
-    );
-    assert_eq!(
-        0,
-        StorageSqlite::get_integer(res.get(1).unwrap(), "meal").unwrap()
-    );
-    assert_eq!(
-        String::from("food2"),
-        StorageSqlite::get_string(res.get(1).unwrap(), "foodkey").unwrap()
-    );
-    assert_eq!(
-        123.123,
-        StorageSqlite::get_float(res.get(1).unwrap(), "foodweight").unwrap()
-    );
-
-    Ok(())
-}
-
-#[test]
-fn test_delete_journal() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Set inital data
-    stg.set_food(&Food {
-        key: "food".into(),
-        name: "name".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    })?;
-    stg.set_food(&Food {
-        key: "food2".into(),
-        name: "name".into(),
-        brand: "brand".into(),
-        cal100: 1.1,
-        prot100: 2.2,
-        fat100: 3.3,
-        carb100: 4.4,
-        comment: "comment".into(),
-    })?;
-
-    stg.set_journal(
-        1,
-        &Journal {
-            timestamp: Timestamp::from_unix_millis(1).unwrap(),
-            meal: Meal::Breakfast,
-            food_key: "food".into(),
-            food_weight: 1.0,
-        },
-    )?;
-    stg.set_journal(
-        1,
-        &Journal {
-            timestamp: Timestamp::from_unix_millis(1).unwrap(),
-            meal: Meal::Dinner,
-            food_key: "food".into(),
-            food_weight: 1.0,
-        },
-    )?;
-    stg.set_journal(
-        1,
-        &Journal {
-            timestamp: Timestamp::from_unix_millis(1).unwrap(),
-            meal: Meal::Dinner,
-            food_key: "food2".into(),
-            food_weight: 2.0,
-        },
-    )?;
-
-    // Check in DB
-    let res = stg.raw_query(
-        r#"
-            SELECT *
-            FROM journal
-            WHERE user_id = 1
-        "#,
-        params![],
-    )?;
-    assert_eq!(3, res.len());
-
-    // Delete
-    stg.delete_journal(
-        1,
-        Timestamp::from_unix_millis(1).unwrap(),
-        Meal::Breakfast,
-        "food",
-    )?;
-    stg.delete_journal_meal(1, Timestamp::from_unix_millis(1).unwrap(), Meal::Dinner)?;
-
-    // Check in DB
-    let res = stg.raw_query(
-        r#"
-            SELECT *
-            FROM journal
-            WHERE user_id = 1
-        "#,
-        params![],
-    )?;
-    assert_eq!(0, res.len());
-
-    Ok(())
-}
-
-#[test]
-fn test_get_journal_report_and_food_avg_weight() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    // Get empty report
-    let res = stg.get_journal_report(
-        1,
-        Timestamp::from_unix_millis(1).unwrap(),
-        Timestamp::from_unix_millis(1).unwrap(),
-    );
-    assert!(stg.is_storage_error(StorageError::EmptyResult, &res.unwrap_err()));
-
-    // Get empty avg weight
-    let res = stg.get_journal_food_avg_weight(
-        1,
-        "food",
-        Timestamp::from_unix_millis(1).unwrap(),
-        Timestamp::from_unix_millis(1).unwrap(),
-    )?;
-    assert_eq!(0.0, res);
-
-    // Set data
-    stg.set_food(&Food {
-        key: "key_aaa".into(),
-        name: "aaa".into(),
-        brand: "brand_aaa".into(),
-        cal100: 1.0,
-        prot100: 2.0,
-        fat100: 3.0,
-        carb100: 4.0,
-        comment: "comment".into(),
-    })?;
-    stg.set_food(&Food {
-        key: "key_bbb".into(),
-        name: "bbb".into(),
-        brand: "brand_bbb".into(),
-        cal100: 1.0,
-        prot100: 2.0,
-        fat100: 3.0,
-        carb100: 4.0,
-        comment: "comment".into(),
-    })?;
-    stg.set_food(&Food {
-        key: "key_ccc".into(),
-        name: "ccc".into(),
-        brand: "brand_ccc".into(),
-        cal100: 1.0,
-        prot100: 2.0,
-        fat100: 3.0,
-        carb100: 4.0,
-        comment: "comment".into(),
-    })?;
-    stg.set_food(&Food {
-        key: "key_ddd".into(),
-        name: "Еда ЯЯЯ".into(),
-        brand: "brand_ddd".into(),
-        cal100: 1.0,
-        prot100: 2.0,
-        fat100: 3.0,
-        carb100: 4.0,
-        comment: "comment".into(),
-    })?;
-    stg.set_food(&Food {
-        key: "key_eee".into(),
-        name: "Еда ААА".into(),
-        brand: "brand_eee".into(),
-        cal100: 1.0,
-        prot100: 2.0,
-        fat100: 3.0,
-        carb100: 4.0,
-        comment: "comment".into(),
-    })?;
-
-    stg.set_journal(
-        1,
-        &Journal {
-            timestamp: Timestamp::from_unix_millis(1).unwrap(),
-            meal: Meal::new_str("ужин").unwrap(),
-            food_key: "key_aaa".into(),
-            food_weight: 100.0,
-        },
-    )?;
-    stg.set_journal(
-        1,
-        &Journal {
-            timestamp: Timestamp::from_unix_millis(1).unwrap(),
-            meal: Meal::new_str("обед").unwrap(),
-            food_key: "key_ccc".into(),
-            food_weight: 200.0,
-        },
-    )?;
-    stg.set_journal(
-        1,
-        &Journal {
-            timestamp: Timestamp::from_unix_millis(1).unwrap(),
-            meal: Meal::new_str("обед").unwrap(),
-            food_key: "key_bbb".into(),
-            food_weight: 100.0,
-        },
-    )?;
-    stg.set_journal(
-        1,
-        &Journal {
-            timestamp: Timestamp::from_unix_millis(2).unwrap(),
-            meal: Meal::new_str("завтрак").unwrap(),
-            food_key: "key_ddd".into(),
-            food_weight: 100.0,
-        },
-    )?;
-    stg.set_journal(
-        1,
-        &Journal {
-            timestamp: Timestamp::from_unix_millis(2).unwrap(),
-            meal: Meal::new_str("завтрак").unwrap(),
-            food_key: "key_eee".into(),
-            food_weight: 100.0,
-        },
-    )?;
-
-    // Get report
-    let res = stg.get_journal_report(
-        1,
-        Timestamp::from_unix_millis(1).unwrap(),
-        Timestamp::from_unix_millis(2).unwrap(),
-    )?;
-    assert_eq!(
-        vec![
-            JournalReport {
-                timestamp: Timestamp::from_unix_millis(1).unwrap(),
-                meal: Meal::new_str("обед").unwrap(),
-                food_key: "key_bbb".into(),
-                food_name: "bbb".into(),
-                food_brand: "brand_bbb".into(),
-                food_weight: 100.0,
-                cal: 1.0,
-                prot: 2.0,
-                fat: 3.0,
-                carb: 4.0,
-            },
-            JournalReport {
-                timestamp: Timestamp::from_unix_millis(1).unwrap(),
-                meal: Meal::new_str("обед").unwrap(),
-                food_key: "key_ccc".into(),
-                food_name: "ccc".into(),
-                food_brand: "brand_ccc".into(),
-                food_weight: 200.0,
-                cal: 2.0,
-                prot: 4.0,
-                fat: 6.0,
-                carb: 8.0,
-            },
-            JournalReport {
-                timestamp: Timestamp::from_unix_millis(1).unwrap(),
-                meal: Meal::new_str("ужин").unwrap(),
-                food_key: "key_aaa".into(),
-                food_name: "aaa".into(),
-                food_brand: "brand_aaa".into(),
-                food_weight: 100.0,
-                cal: 1.0,
-                prot: 2.0,
-                fat: 3.0,
-                carb: 4.0,
-            },
-            JournalReport {
-                timestamp: Timestamp::from_unix_millis(2).unwrap(),
-                meal: Meal::new_str("завтрак").unwrap(),
-                food_key: "key_eee".into(),
-                food_name: "Еда ААА".into(),
-                food_brand: "brand_eee".into(),
-                food_weight: 100.0,
-                cal: 1.0,
-                prot: 2.0,
-                fat: 3.0,
-                carb: 4.0,
-            },
-            JournalReport {
-                timestamp: Timestamp::from_unix_millis(2).unwrap(),
-                meal: Meal::new_str("завтрак").unwrap(),
-                food_key: "key_ddd".into(),
-                food_name: "Еда ЯЯЯ".into(),
-                food_brand: "brand_ddd".into(),
-                food_weight: 100.0,
-                cal: 1.0,
-                prot: 2.0,
-                fat: 3.0,
-                carb: 4.0,
-            }
-        ],
-        res
-    );
-
-    // Get avg weight
-    let res = stg.get_journal_food_avg_weight(
-        1,
-        "key_aaa",
-        Timestamp::from_unix_millis(1).unwrap(),
-        Timestamp::from_unix_millis(2).unwrap(),
-    )?;
-    assert_eq!(100.0, res);
-
-    Ok(())
-}
-
-//
-// Restore/backup
-//
-
-#[test]
-fn test_backup_restore() -> Result<()> {
-    let db_file = NamedTempFile::new()?;
-    let stg = StorageSqlite::new(db_file.path())?;
-
-    let backup = Backup {
-        timestamp: 1,
-        weight: vec![
-            WeightBackup {
-                timestamp: 1,
-                user_id: 1,
-                value: 1.1,
-            },
-            WeightBackup {
-                timestamp: 2,
-                user_id: 1,
-                value: 2.2,
-            },
-            WeightBackup {
-                timestamp: 3,
-                user_id: 1,
-                value: 3.3,
-            },
-            WeightBackup {
-                timestamp: 4,
-                user_id: 2,
-                value: 4.4,
-            },
-        ],
-        food: vec![
-            FoodBackup {
-                user_id: 1,
-                key: "key1".into(),
-                name: "Food 1".into(),
-                brand: "Brand 1".into(),
-                cal100: 1.1,
-                prot100: 2.2,
-                fat100: 3.3,
-                carb100: 4.4,
-                comment: "Comment1".into(),
-            },
-            FoodBackup {
-                user_id: 1,
-                key: "key2".into(),
-                name: "Food 2".into(),
-                brand: "Brand2".into(),
-                cal100: 5.5,
-                prot100: 6.6,
-                fat100: 7.7,
-                carb100: 8.8,
-                comment: "Comment2".into(),
-            },
-            FoodBackup {
-                user_id: 1,
-                key: "key3".into(),
-                name: "Еда 3".into(),
-                brand: "Брэнд 3".into(),
-                cal100: 10.10,
-                prot100: 20.20,
-                fat100: 30.30,
-                carb100: 40.40,
-                comment: "Комментарий 3".into(),
-            },
-            FoodBackup {
-                user_id: 1,
-                key: "key4".into(),
-                name: "Еда 4".into(),
-                brand: "Брэнд 4".into(),
-                cal100: 100.100,
-                prot100: 200.200,
-                fat100: 300.300,
-                carb100: 400.400,
-                comment: "Комментарий 4".into(),
-            },
-        ],
-        user_settings: vec![
-            UserSettingsBackup {
-                user_id: 1,
-                cal_limit: 1.0,
-            },
-            UserSettingsBackup {
-                user_id: 2,
-                cal_limit: 2.0,
-            },
-        ],
-        bundle: vec![
-            BundleBackup {
-                user_id: 1,
-                key: "bundle1".into(),
-                data: r#"{"key1":100.0}"#.into(),
-            },
-            BundleBackup {
-                user_id: 1,
-                key: "bundle2".into(),
-                data: r#"{"key2":100.0,"bundle1":0.0}"#.into(),
-            },
-        ],
-        journal: vec![
-            JournalBackup {
-                user_id: 1,
-                timestamp: 1,
-                meal: 1,
-                food_key: "key1".into(),
-                food_weight: 100.0,
-            },
-            JournalBackup {
-                user_id: 1,
-                timestamp: 1,
-                meal: 2,
-                food_key: "key2".into(),
-                food_weight: 200.0,
-            },
-        ],
-        sport: vec![
-            SportBackup {
-                user_id: 1,
-                key: "sport1".into(),
-                name: "Sport 1".into(),
-                comment: "Sport 1".into(),
-            },
-            SportBackup {
-                user_id: 1,
-                key: "sport2".into(),
-                name: "Sport 2".into(),
-                comment: "Sport 2".into(),
-            },
-            SportBackup {
-                user_id: 1,
-                key: "sport3".into(),
-                name: "Sport 3".into(),
-                comment: "Sport 3".into(),
-            },
-        ],
-        sport_activity: vec![
-            SportActivityBackup {
-                user_id: 1,
-                sport_key: "sport1".into(),
-                timestamp: 1,
-                sets: "[1,2,3]".into(),
-            },
-            SportActivityBackup {
-                user_id: 1,
-                sport_key: "sport2".into(),
-                timestamp: 1,
-                sets: "[4,5,6]".into(),
-            },
-            SportActivityBackup {
-                user_id: 2,
-                sport_key: "sport3".into(),
-                timestamp: 2,
-                sets: "[10]".into(),
-            },
-        ],
-    };
-
-    // Do restore
-    stg.restore(&backup)?;
-
-    // Check weight list for user 1
-    let res = stg.get_weight_list(
-        1,
-        Timestamp::from_unix_millis(0).unwrap(),
-        Timestamp::from_unix_millis(10).unwrap(),
-    )?;
-    assert_eq!(
-        vec![
-            Weight {
-                timestamp: Timestamp::from_unix_millis(1).unwrap(),
-                value: 1.1
-            },
-            Weight {
-                timestamp: Timestamp::from_unix_millis(2).unwrap(),
-                value: 2.2
-            },
-            Weight {
-                timestamp: Timestamp::from_unix_millis(3).unwrap(),
-                value: 3.3
-            },
-        ],
-        res
-    );
-
-    // Check weight list for user 2
-    let res = stg.get_weight_list(
-        2,
-        Timestamp::from_unix_millis(0).unwrap(),
-        Timestamp::from_unix_millis(10).unwrap(),
-    )?;
-    assert_eq!(
-        vec![Weight {
-            timestamp: Timestamp::from_unix_millis(4).unwrap(),
-            value: 4.4
-        },],
-        res
-    );
-
-    // Check food
-    let res = stg.get_food_list()?;
-    assert_eq!(
-        vec![
-            Food {
-                key: "key1".into(),
-                name: "Food 1".into(),
-                brand: "Brand 1".into(),
-                cal100: 1.1,
-                prot100: 2.2,
-                fat100: 3.3,
-                carb100: 4.4,
-                comment: "Comment1".into(),
-            },
-            Food {
-                key: "key2".into(),
-                name: "Food 2".into(),
-                brand: "Brand2".into(),
-                cal100: 5.5,
-                prot100: 6.6,
-                fat100: 7.7,
-                carb100: 8.8,
-                comment: "Comment2".into(),
-            },
-            Food {
-                key: "key3".into(),
-                name: "Еда 3".into(),
-                brand: "Брэнд 3".into(),
-                cal100: 10.10,
-                prot100: 20.20,
-                fat100: 30.30,
-                carb100: 40.40,
-                comment: "Комментарий 3".into(),
-            },
-            Food {
-                key: "key4".into(),
-                name: "Еда 4".into(),
-                brand: "Брэнд 4".into(),
-                cal100: 100.100,
-                prot100: 200.200,
-                fat100: 300.300,
-                carb100: 400.400,
-                comment: "Комментарий 4".into(),
-            }
-        ],
-        res
-    );
-
-    // Check user settings
-    let res = stg.get_user_settings(1)?;
-    assert_eq!(1.0, res.cal_limit);
-
-    let res = stg.get_user_settings(2)?;
-    assert_eq!(2.0, res.cal_limit);
-
-    // Check bundles
-    let res = stg.get_bundle_list(1)?;
-    assert_eq!(
-        vec![
-            Bundle {
-                key: "bundle1".into(),
-                data: HashMap::from([("key1".into(), 100.0)]),
-            },
-            Bundle {
-                key: "bundle2".into(),
-                data: HashMap::from([("key2".into(), 100.0), ("bundle1".into(), 0.0)]),
-            },
-        ],
-        res
-    );
-
-    // Check journal
-    let res = stg.get_journal_report(
-        1,
-        Timestamp::from_unix_millis(1).unwrap(),
-        Timestamp::from_unix_millis(1).unwrap(),
-    )?;
-    assert_eq!(
-        vec![
-            JournalReport {
-                timestamp: Timestamp::from_unix_millis(1).unwrap(),
-                meal: Meal::new_str("до обеда").unwrap(),
-                food_key: "key1".into(),
-                food_name: "Food 1".into(),
-                food_brand: "Brand 1".into(),
-                food_weight: 100.0,
-                cal: 1.1,
-                prot: 2.2,
-                fat: 3.3,
-                carb: 4.4,
-            },
-            JournalReport {
-                timestamp: Timestamp::from_unix_millis(1).unwrap(),
-                meal: Meal::Dinner,
-                food_key: "key2".into(),
-                food_name: "Food 2".into(),
-                food_brand: "Brand2".into(),
-                food_weight: 200.0,
-                cal: 11.0,
-                prot: 13.2,
-                fat: 15.4,
-                carb: 17.6
-            },
-        ],
-        res
-    );
-
-    // Check sport
-    let res = stg.get_sport_list()?;
-    assert_eq!(
-        vec![
-            Sport {
-                key: "sport1".into(),
-                name: "Sport 1".into(),
-                comment: "Sport 1".into(),
-            },
-            Sport {
-                key: "sport2".into(),
-                name: "Sport 2".into(),
-                comment: "Sport 2".into(),
-            },
-            Sport {
-                key: "sport3".into(),
-                name: "Sport 3".into(),
-                comment: "Sport 3".into(),
-            }
-        ],
-        res
-    );
-
-    // Check sport activity
-    let res = stg.get_sport_activity_report(
-        1,
-        Timestamp::from_unix_millis(1).unwrap(),
-        Timestamp::from_unix_millis(2).unwrap(),
-    )?;
-    assert_eq!(
-        vec![
-            SportActivityReport {
-                timestamp: Timestamp::from_unix_millis(1).unwrap(),
-                sport_name: "Sport 1".into(),
-                sets: vec![1, 2, 3],
-            },
-            SportActivityReport {
-                timestamp: Timestamp::from_unix_millis(1).unwrap(),
-                sport_name: "Sport 2".into(),
-                sets: vec![4, 5, 6],
-            }
-        ],
-        res
-    );
-    let res = stg.get_sport_activity_report(
-        2,
-        Timestamp::from_unix_millis(1).unwrap(),
-        Timestamp::from_unix_millis(2).unwrap(),
-    )?;
-    assert_eq!(
-        vec![SportActivityReport {
-            timestamp: Timestamp::from_unix_millis(2).unwrap(),
-            sport_name: "Sport 3".into(),
-            sets: vec![10],
-        },],
-        res
-    );
+    // Written in transaction of mutation
+    let conn = stg.conn.lock().unwrap();
+    let count: i64 = conn.query_row("SELECT count(*) FROM audit_log", [], |row| row.get(0))?;
+    assert_eq!(1, count);
 
-    // Check backup
-    let backup2 = stg.backup(1)?;
-    assert_eq!(backup.food, backup2.food);
-    assert_eq!(backup.weight, backup2.weight);
-    assert_eq!(backup.user_settings, backup2.user_settings);
-    assert_eq!(backup.bundle, backup2.bundle);
-    assert_eq!(backup.journal, backup2.journal);
-    assert_eq!(backup.sport, backup2.sport);
-    assert_eq!(backup.sport_activity, backup2.sport_activity);
+    assert!(conn.execute("UPDATE audit_log SET key = 'x'", []).is_err());
+    assert!(conn.execute("DELETE FROM audit_log", []).is_err());
 
     Ok(())
 }
@@ -0,0 +1,3173 @@
+// Conformance tests, every storage implementation must pass them
+
+use std::collections::HashMap;
+
+use super::*;
+use crate::{storage_memory::StorageMemory, storage_sqlite::StorageSqlite};
+use anyhow::Result;
+use model::{
+    backup::{
+        AuditLogBackup, BundleBackup, FoodBackup, JournalBackup, MetricBackup, MetricValueBackup,
+        ScheduleBackup, SportActivityBackup, SportBackup, UserSettingsBackup, WeightBackup,
+    },
+    AuditAction, ChangeAction, Sex,
+};
+use tempfile::NamedTempFile;
+
+// Runs each test against every storage implementation
+macro_rules! conformance_tests {
+    ($($name:ident),* $(,)?) => {
+        mod sqlite {
+            use super::*;
+
+            $(
+                #[test]
+                fn $name() -> Result<()> {
+                    let db_file = NamedTempFile::new()?;
+                    let stg = StorageSqlite::new(db_file.path())?;
+
+                    super::$name(&stg)
+                }
+            )*
+        }
+
+        mod memory {
+            use super::*;
+
+            $(
+                #[test]
+                fn $name() -> Result<()> {
+                    let stg = StorageMemory::new();
+
+                    super::$name(&stg)
+                }
+            )*
+        }
+    };
+}
+
+conformance_tests!(
+    test_get_weight_list,
+    test_get_last_weight,
+    test_delete_weight,
+    test_set_weight,
+    test_set_food,
+    test_get_food,
+    test_get_food_list,
+    test_delete_food,
+    test_delete_food_with_bundle,
+    test_delete_food_with_journal,
+    test_find_food,
+    test_set_sport,
+    test_get_sport,
+    test_get_sport_list,
+    test_delete_sport,
+    test_set_sport_activity,
+    test_get_sport_activity_report,
+    test_delete_sport_activity,
+    test_delete_sport_with_activity,
+    set_user_settings,
+    get_user_settings,
+    test_get_bundle,
+    test_get_bundle_list,
+    test_set_bundle,
+    test_delete_bundle,
+    test_set_journal,
+    test_set_journal_bundle,
+    test_delete_journal,
+    test_get_journal_report_and_food_avg_weight,
+    test_get_journal_food_usage,
+    test_set_metric,
+    test_get_metric_list,
+    test_set_metric_value,
+    test_get_metric_value_list_and_delete,
+    test_schedule,
+    test_get_change_list,
+    test_undo,
+    test_undo_conflict,
+    test_undo_shared_conflict,
+    test_undo_schedule_keeps_last_run,
+    test_get_audit_list,
+    test_audit_bundle_rows,
+    test_backup_restore,
+);
+
+//
+// Weight
+//
+
+fn test_get_weight_list(stg: &dyn Storage) -> Result<()> {
+    // Check EmptyList error
+    let res = stg.get_weight_list(
+        1,
+        Timestamp::from_unix_millis(0).unwrap(),
+        Timestamp::from_unix_millis(10).unwrap(),
+    );
+
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    // Add test data
+    for (user_id, ts, value) in [(1, 1, 1.1), (1, 2, 2.2), (1, 3, 3.3), (2, 4, 4.4)] {
+        stg.set_weight(
+            user_id,
+            &Weight {
+                timestamp: Timestamp::from_unix_millis(ts).unwrap(),
+                value,
+            },
+        )?;
+    }
+
+    // Check weight list for user 1
+    let res = stg.get_weight_list(
+        1,
+        Timestamp::from_unix_millis(0).unwrap(),
+        Timestamp::from_unix_millis(10).unwrap(),
+    );
+    assert_eq!(
+        vec![
+            Weight {
+                timestamp: Timestamp::from_unix_millis(1).unwrap(),
+                value: 1.1
+            },
+            Weight {
+                timestamp: Timestamp::from_unix_millis(2).unwrap(),
+                value: 2.2
+            },
+            Weight {
+                timestamp: Timestamp::from_unix_millis(3).unwrap(),
+                value: 3.3
+            },
+        ],
+        res.unwrap()
+    );
+
+    // Check weight list for user 2
+    let res = stg.get_weight_list(
+        2,
+        Timestamp::from_unix_millis(0).unwrap(),
+        Timestamp::from_unix_millis(10).unwrap(),
+    );
+    assert_eq!(
+        vec![Weight {
+            timestamp: Timestamp::from_unix_millis(4).unwrap(),
+            value: 4.4
+        },],
+        res.unwrap()
+    );
+
+    Ok(())
+}
+
+fn test_get_last_weight(stg: &dyn Storage) -> Result<()> {
+    let res = stg.get_last_weight(1, Timestamp::from_unix_millis(10).unwrap());
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    for (user_id, ts, value) in [(1, 1, 1.1), (1, 3, 3.3), (1, 20, 20.2), (2, 5, 5.5)] {
+        stg.set_weight(
+            user_id,
+            &Weight {
+                timestamp: Timestamp::from_unix_millis(ts).unwrap(),
+                value,
+            },
+        )?;
+    }
+
+    // Latest before period end, other user is not seen
+    assert_eq!(
+        Weight {
+            timestamp: Timestamp::from_unix_millis(3).unwrap(),
+            value: 3.3
+        },
+        stg.get_last_weight(1, Timestamp::from_unix_millis(10).unwrap())?
+    );
+    assert_eq!(
+        Weight {
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            value: 1.1
+        },
+        stg.get_last_weight(1, Timestamp::from_unix_millis(1).unwrap())?
+    );
+
+    let res = stg.get_last_weight(2, Timestamp::from_unix_millis(4).unwrap());
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    Ok(())
+}
+
+fn test_delete_weight(stg: &dyn Storage) -> Result<()> {
+    // Add test data
+    for (user_id, ts, value) in [(1, 1, 1.1), (2, 4, 4.4)] {
+        stg.set_weight(
+            user_id,
+            &Weight {
+                timestamp: Timestamp::from_unix_millis(ts).unwrap(),
+                value,
+            },
+        )?;
+    }
+
+    // Delete for user 2
+    stg.delete_weight(2, Timestamp::from_unix_millis(4).unwrap())?;
+    let res = stg.get_weight_list(
+        2,
+        Timestamp::from_unix_millis(0).unwrap(),
+        Timestamp::from_unix_millis(10).unwrap(),
+    );
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    // Delete for user 1 record that not exists (timestamp=4)
+    stg.delete_weight(1, Timestamp::from_unix_millis(4).unwrap())?;
+    assert_eq!(
+        1,
+        stg.get_weight_list(
+            1,
+            Timestamp::from_unix_millis(0).unwrap(),
+            Timestamp::from_unix_millis(10).unwrap(),
+        )?
+        .len()
+    );
+
+    Ok(())
+}
+
+fn test_set_weight(stg: &dyn Storage) -> Result<()> {
+    // Set invalid weight
+    let res = stg.set_weight(
+        1,
+        &Weight {
+            timestamp: Timestamp::from_unix_millis(1734876557).unwrap(),
+            value: -1.1,
+        },
+    );
+    assert!(matches!(res, Err(StorageError::WeightInvalid)));
+
+    // Set weight
+    stg.set_weight(
+        1,
+        &Weight {
+            timestamp: Timestamp::from_unix_millis(1734876557).unwrap(),
+            value: 1.1,
+        },
+    )?;
+
+    // Check stored weight
+    assert_eq!(
+        vec![Weight {
+            timestamp: Timestamp::from_unix_millis(1734876557).unwrap(),
+            value: 1.1,
+        }],
+        stg.get_weight_list(
+            1,
+            Timestamp::from_unix_millis(0).unwrap(),
+            Timestamp::from_unix_millis(1734876557).unwrap(),
+        )?
+    );
+
+    // Update weight
+    stg.set_weight(
+        1,
+        &Weight {
+            timestamp: Timestamp::from_unix_millis(1734876557).unwrap(),
+            value: 2.2,
+        },
+    )?;
+
+    // Check stored weight
+    assert_eq!(
+        vec![Weight {
+            timestamp: Timestamp::from_unix_millis(1734876557).unwrap(),
+            value: 2.2,
+        }],
+        stg.get_weight_list(
+            1,
+            Timestamp::from_unix_millis(0).unwrap(),
+            Timestamp::from_unix_millis(1734876557).unwrap(),
+        )?
+    );
+
+    Ok(())
+}
+
+//
+// Food
+//
+
+fn test_set_food(stg: &dyn Storage) -> Result<()> {
+    // Set invalid food
+    let res = stg.set_food(
+        1,
+        &Food {
+            key: "".into(),
+            name: "name".into(),
+            brand: "brand".into(),
+            cal100: 1.1,
+            prot100: 2.2,
+            fat100: 3.3,
+            carb100: 4.4,
+            comment: "comment".into(),
+        },
+    );
+    assert!(matches!(res, Err(StorageError::FoodInvalid)));
+
+    // Set food
+    stg.set_food(
+        1,
+        &Food {
+            key: "key".into(),
+            name: "name".into(),
+            brand: "brand".into(),
+            cal100: 1.1,
+            prot100: 2.2,
+            fat100: 3.3,
+            carb100: 4.4,
+            comment: "comment".into(),
+        },
+    )?;
+
+    // Check stored food
+    assert_eq!(
+        vec![Food {
+            key: "key".into(),
+            name: "name".into(),
+            brand: "brand".into(),
+            cal100: 1.1,
+            prot100: 2.2,
+            fat100: 3.3,
+            carb100: 4.4,
+            comment: "comment".into(),
+        }],
+        stg.get_food_list()?
+    );
+
+    // Update food
+    stg.set_food(
+        1,
+        &Food {
+            key: "key".into(),
+            name: "name".into(),
+            brand: "".into(),
+            cal100: 5.5,
+            prot100: 6.6,
+            fat100: 7.7,
+            carb100: 8.8,
+            comment: "".into(),
+        },
+    )?;
+
+    // Check stored food
+    assert_eq!(
+        vec![Food {
+            key: "key".into(),
+            name: "name".into(),
+            brand: "".into(),
+            cal100: 5.5,
+            prot100: 6.6,
+            fat100: 7.7,
+            carb100: 8.8,
+            comment: "".into(),
+        }],
+        stg.get_food_list()?
+    );
+
+    Ok(())
+}
+
+fn test_get_food(stg: &dyn Storage) -> Result<()> {
+    // Get food that not exists
+    let res = stg.get_food("key");
+    assert!(matches!(res, Err(StorageError::FoodNotFound(k)) if k == "key"));
+
+    // Set food
+    let f = Food {
+        key: "key".into(),
+        name: "name".into(),
+        brand: "brand".into(),
+        cal100: 1.1,
+        prot100: 2.2,
+        fat100: 3.3,
+        carb100: 4.4,
+        comment: "comment".into(),
+    };
+    stg.set_food(1, &f)?;
+
+    // Get food
+    assert_eq!(f, stg.get_food("key").unwrap());
+
+    Ok(())
+}
+
+fn test_get_food_list(stg: &dyn Storage) -> Result<()> {
+    // Get empty food list
+    let res = stg.get_food_list();
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    // Set food
+    let f1 = Food {
+        key: "key1".into(),
+        name: "name1".into(),
+        brand: "brand".into(),
+        cal100: 1.1,
+        prot100: 2.2,
+        fat100: 3.3,
+        carb100: 4.4,
+        comment: "comment".into(),
+    };
+    stg.set_food(1, &f1)?;
+
+    let f2 = Food {
+        key: "key2".into(),
+        name: "name2".into(),
+        brand: "brand".into(),
+        cal100: 1.1,
+        prot100: 2.2,
+        fat100: 3.3,
+        carb100: 4.4,
+        comment: "comment".into(),
+    };
+    stg.set_food(1, &f2)?;
+
+    // Get food list
+    assert_eq!(vec![f1, f2], stg.get_food_list().unwrap());
+
+    Ok(())
+}
+
+fn test_delete_food(stg: &dyn Storage) -> Result<()> {
+    // Set food
+    let f1 = Food {
+        key: "key1".into(),
+        name: "name1".into(),
+        brand: "brand".into(),
+        cal100: 1.1,
+        prot100: 2.2,
+        fat100: 3.3,
+        carb100: 4.4,
+        comment: "comment".into(),
+    };
+    stg.set_food(1, &f1)?;
+
+    let f2 = Food {
+        key: "key2".into(),
+        name: "name2".into(),
+        brand: "brand".into(),
+        cal100: 1.1,
+        prot100: 2.2,
+        fat100: 3.3,
+        carb100: 4.4,
+        comment: "comment".into(),
+    };
+    stg.set_food(1, &f2)?;
+
+    // Get food list
+    assert_eq!(vec![f1, f2.clone()], stg.get_food_list().unwrap());
+
+    // Delete food1
+    stg.delete_food(1, "key1")?;
+
+    // Get food list
+    assert_eq!(vec![f2], stg.get_food_list().unwrap());
+
+    // Delete food2
+    stg.delete_food(1, "key2")?;
+
+    // Get food list
+    let res = stg.get_food_list();
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    Ok(())
+}
+
+fn test_delete_food_with_bundle(stg: &dyn Storage) -> Result<()> {
+    // Set food
+    stg.set_food(
+        1,
+        &Food {
+            key: "key1".into(),
+            name: "name1".into(),
+            brand: "brand".into(),
+            cal100: 1.1,
+            prot100: 2.2,
+            fat100: 3.3,
+            carb100: 4.4,
+            comment: "comment".into(),
+        },
+    )?;
+
+    stg.set_food(
+        1,
+        &Food {
+            key: "key2".into(),
+            name: "name2".into(),
+            brand: "brand".into(),
+            cal100: 1.1,
+            prot100: 2.2,
+            fat100: 3.3,
+            carb100: 4.4,
+            comment: "comment".into(),
+        },
+    )?;
+
+    // Set bundle
+    stg.set_bundle(
+        1,
+        &Bundle {
+            key: "bndl_key".into(),
+            data: HashMap::from([("key1".into(), 123.123)]),
+        },
+    )?;
+
+    // Check delete food, that is used in bundle
+    let res = stg.delete_food(1, "key1");
+    assert!(matches!(res, Err(StorageError::FoodIsUsed(k)) if k == "key1"));
+
+    // Delete food that not used
+    stg.delete_food(1, "key2")?;
+
+    Ok(())
+}
+
+fn test_delete_food_with_journal(stg: &dyn Storage) -> Result<()> {
+    // Set food
+    stg.set_food(
+        1,
+        &Food {
+            key: "key1".into(),
+            name: "name1".into(),
+            brand: "brand".into(),
+            cal100: 1.1,
+            prot100: 2.2,
+            fat100: 3.3,
+            carb100: 4.4,
+            comment: "comment".into(),
+        },
+    )?;
+
+    // Set journal of other user
+    stg.set_journal(
+        2,
+        &Journal {
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            meal: Meal::Breakfast,
+            food_key: "key1".into(),
+            food_weight: 100.0,
+        },
+    )?;
+
+    // Check delete food, that is used in journal
+    let res = stg.delete_food(1, "key1");
+    assert!(matches!(res, Err(StorageError::FoodIsUsed(k)) if k == "key1"));
+
+    // Delete food after journal
+    stg.delete_journal(
+        2,
+        Timestamp::from_unix_millis(1).unwrap(),
+        Meal::Breakfast,
+        "key1",
+    )?;
+    stg.delete_food(1, "key1")?;
+
+    Ok(())
+}
+
+fn test_find_food(stg: &dyn Storage) -> Result<()> {
+    // Find empty result
+    let res = stg.find_food("some food");
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    // Set food
+    let f1 = Food {
+        key: "key1".into(),
+        name: "name1".into(),
+        brand: "brand".into(),
+        cal100: 1.1,
+        prot100: 2.2,
+        fat100: 3.3,
+        carb100: 4.4,
+        comment: "comment".into(),
+    };
+    stg.set_food(1, &f1)?;
+
+    let f2 = Food {
+        key: "key2".into(),
+        name: "name2".into(),
+        brand: "brand".into(),
+        cal100: 1.1,
+        prot100: 2.2,
+        fat100: 3.3,
+        carb100: 4.4,
+        comment: "comment".into(),
+    };
+    stg.set_food(1, &f2)?;
+
+    let f3 = Food {
+        key: "key3".into(),
+        name: "Сырок Дружба".into(),
+        brand: "Вкусвилл".into(),
+        cal100: 1.1,
+        prot100: 2.2,
+        fat100: 3.3,
+        carb100: 4.4,
+        comment: "Вкусный".into(),
+    };
+    stg.set_food(1, &f3)?;
+
+    // Find food
+    assert_eq!(
+        vec![f1.clone(), f2.clone(), f3.clone()],
+        stg.find_food("kEy").unwrap()
+    );
+    assert_eq!(vec![f2], stg.find_food("NAMe2").unwrap());
+    assert_eq!(vec![f3.clone()], stg.find_food("дружба").unwrap());
+    assert_eq!(vec![f3.clone()], stg.find_food("вкусВиЛЛ").unwrap());
+    assert_eq!(vec![f3.clone()], stg.find_food("нЫЙ").unwrap());
+
+    Ok(())
+}
+
+//
+// Sport
+//
+
+fn test_set_sport(stg: &dyn Storage) -> Result<()> {
+    // Set invalid sport
+    let res = stg.set_sport(
+        1,
+        &Sport {
+            key: "".into(),
+            name: "name".into(),
+            comment: "comment".into(),
+        },
+    );
+    assert!(matches!(res, Err(StorageError::SportInvalid)));
+
+    // Set sport
+    stg.set_sport(
+        1,
+        &Sport {
+            key: "key".into(),
+            name: "name".into(),
+            comment: "comment".into(),
+        },
+    )?;
+
+    // Check stored sport
+    assert_eq!(
+        vec![Sport {
+            key: "key".into(),
+            name: "name".into(),
+            comment: "comment".into(),
+        }],
+        stg.get_sport_list()?
+    );
+
+    // Update sport
+    stg.set_sport(
+        1,
+        &Sport {
+            key: "key".into(),
+            name: "name".into(),
+            comment: "".into(),
+        },
+    )?;
+
+    // Check stored sport
+    assert_eq!(
+        vec![Sport {
+            key: "key".into(),
+            name: "name".into(),
+            comment: "".into(),
+        }],
+        stg.get_sport_list()?
+    );
+
+    Ok(())
+}
+
+fn test_get_sport(stg: &dyn Storage) -> Result<()> {
+    // Get sport that not exists
+    let res = stg.get_sport("key");
+    assert!(matches!(res, Err(StorageError::SportNotFound(k)) if k == "key"));
+
+    // Set sport
+    let s = Sport {
+        key: "key".into(),
+        name: "name".into(),
+        comment: "comment".into(),
+    };
+    stg.set_sport(1, &s)?;
+
+    // Get sport
+    assert_eq!(s, stg.get_sport("key").unwrap());
+
+    Ok(())
+}
+
+fn test_get_sport_list(stg: &dyn Storage) -> Result<()> {
+    // Get empty sport list
+    let res = stg.get_sport_list();
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    // Set sport
+    let s1 = Sport {
+        key: "key1".into(),
+        name: "name1".into(),
+        comment: "comment".into(),
+    };
+    stg.set_sport(1, &s1)?;
+
+    let s2 = Sport {
+        key: "key2".into(),
+        name: "name2".into(),
+        comment: "comment".into(),
+    };
+    stg.set_sport(1, &s2)?;
+
+    // Get sport list
+    assert_eq!(vec![s1, s2], stg.get_sport_list().unwrap());
+
+    Ok(())
+}
+
+fn test_delete_sport(stg: &dyn Storage) -> Result<()> {
+    // Set sport
+    let s1 = Sport {
+        key: "key1".into(),
+        name: "name1".into(),
+        comment: "comment".into(),
+    };
+    stg.set_sport(1, &s1)?;
+
+    let s2 = Sport {
+        key: "key2".into(),
+        name: "name2".into(),
+        comment: "comment".into(),
+    };
+    stg.set_sport(1, &s2)?;
+
+    // Get sport list
+    assert_eq!(vec![s1, s2.clone()], stg.get_sport_list().unwrap());
+
+    // Delete sport1
+    stg.delete_sport(1, "key1")?;
+
+    // Get sport list
+    assert_eq!(vec![s2], stg.get_sport_list().unwrap());
+
+    // Delete sport2
+    stg.delete_sport(1, "key2")?;
+
+    // Get sport list
+    let res = stg.get_sport_list();
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    Ok(())
+}
+
+//
+// Sport activity
+//
+
+fn test_set_sport_activity(stg: &dyn Storage) -> Result<()> {
+    // Set invalid sport activity
+    let res = stg.set_sport_activity(
+        1,
+        &SportActivity {
+            sport_key: "test".into(),
+            timestamp: Timestamp::now(),
+            sets: vec![],
+        },
+    );
+    assert!(matches!(res, Err(StorageError::SportActivityInvalid)));
+
+    // Set sport activity for sport that not exists
+    let res = stg.set_sport_activity(
+        1,
+        &SportActivity {
+            sport_key: "test".into(),
+            timestamp: Timestamp::now(),
+            sets: vec![1, 2, 3],
+        },
+    );
+    assert!(matches!(res, Err(StorageError::SportNotFound(k)) if k == "test"));
+
+    // Set sport
+    stg.set_sport(
+        1,
+        &Sport {
+            key: "test".into(),
+            name: "test".into(),
+            comment: "".into(),
+        },
+    )?;
+
+    // Set sport activity
+    stg.set_sport_activity(
+        1,
+        &SportActivity {
+            sport_key: "test".into(),
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            sets: vec![1],
+        },
+    )?;
+
+    // Check stored sport activity
+    assert_eq!(
+        vec![SportActivityBackup {
+            user_id: 1,
+            timestamp: 1,
+            sport_key: "test".into(),
+            sets: "[1]".into(),
+        }],
+        stg.backup(1)?.sport_activity
+    );
+
+    // Update sport activity
+    stg.set_sport_activity(
+        1,
+        &SportActivity {
+            sport_key: "test".into(),
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            sets: vec![1, 2, 3],
+        },
+    )?;
+
+    // Check stored sport activity
+    assert_eq!(
+        vec![SportActivityBackup {
+            user_id: 1,
+            timestamp: 1,
+            sport_key: "test".into(),
+            sets: "[1,2,3]".into(),
+        }],
+        stg.backup(1)?.sport_activity
+    );
+
+    Ok(())
+}
+
+fn test_get_sport_activity_report(stg: &dyn Storage) -> Result<()> {
+    // Get empty report
+    let res = stg.get_sport_activity_report(
+        1,
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(2).unwrap(),
+    );
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    // Set data
+    stg.set_sport(
+        1,
+        &Sport {
+            key: "sport1".into(),
+            name: "Sport 1".into(),
+            comment: "".into(),
+        },
+    )?;
+    stg.set_sport(
+        1,
+        &Sport {
+            key: "sport2".into(),
+            name: "Sport 2".into(),
+            comment: "".into(),
+        },
+    )?;
+
+    stg.set_sport_activity(
+        1,
+        &SportActivity {
+            sport_key: "sport2".into(),
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            sets: vec![1],
+        },
+    )?;
+    stg.set_sport_activity(
+        1,
+        &SportActivity {
+            sport_key: "sport1".into(),
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            sets: vec![1, 2],
+        },
+    )?;
+    stg.set_sport_activity(
+        1,
+        &SportActivity {
+            sport_key: "sport1".into(),
+            timestamp: Timestamp::from_unix_millis(3).unwrap(),
+            sets: vec![1, 2, 3],
+        },
+    )?;
+
+    // Get report
+    let res = stg.get_sport_activity_report(
+        1,
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(3).unwrap(),
+    )?;
+
+    assert_eq!(
+        vec![
+            SportActivityReport {
+                sport_name: "Sport 1".into(),
+                timestamp: Timestamp::from_unix_millis(1).unwrap(),
+                sets: vec![1, 2],
+            },
+            SportActivityReport {
+                sport_name: "Sport 2".into(),
+                timestamp: Timestamp::from_unix_millis(1).unwrap(),
+                sets: vec![1],
+            },
+            SportActivityReport {
+                sport_name: "Sport 1".into(),
+                timestamp: Timestamp::from_unix_millis(3).unwrap(),
+                sets: vec![1, 2, 3],
+            }
+        ],
+        res
+    );
+
+    Ok(())
+}
+
+fn test_delete_sport_activity(stg: &dyn Storage) -> Result<()> {
+    // Set data
+    stg.set_sport(
+        1,
+        &Sport {
+            key: "sport1".into(),
+            name: "Sport 1".into(),
+            comment: "".into(),
+        },
+    )?;
+
+    stg.set_sport_activity(
+        1,
+        &SportActivity {
+            sport_key: "sport1".into(),
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            sets: vec![1],
+        },
+    )?;
+
+    // Check sport activity report
+    let res = stg.get_sport_activity_report(
+        1,
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(3).unwrap(),
+    )?;
+    assert_eq!(
+        vec![SportActivityReport {
+            sport_name: "Sport 1".into(),
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            sets: vec![1],
+        }],
+        res
+    );
+
+    // Delete sport activity
+    stg.delete_sport_activity(1, Timestamp::from_unix_millis(1).unwrap(), "sport1")?;
+
+    // Check empty report
+    let res = stg.get_sport_activity_report(
+        1,
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(2).unwrap(),
+    );
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    Ok(())
+}
+
+fn test_delete_sport_with_activity(stg: &dyn Storage) -> Result<()> {
+    // Set data
+    stg.set_sport(
+        1,
+        &Sport {
+            key: "sport1".into(),
+            name: "Sport 1".into(),
+            comment: "".into(),
+        },
+    )?;
+
+    stg.set_sport_activity(
+        1,
+        &SportActivity {
+            sport_key: "sport1".into(),
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            sets: vec![1],
+        },
+    )?;
+
+    // Delet sport
+    let res = stg.delete_sport(1, "sport1");
+    assert!(matches!(res, Err(StorageError::SportIsUsed(k)) if k == "sport1"));
+
+    Ok(())
+}
+
+//
+// User settings
+//
+
+fn set_user_settings(stg: &dyn Storage) -> Result<()> {
+    // Set invalid user settings
+    let res = stg.set_user_settings(
+        1,
+        &UserSettings {
+            cal_limit: 0.0,
+            ..Default::default()
+        },
+    );
+    assert!(matches!(res, Err(StorageError::UserSettingsInvalid)));
+
+    // Set user settings
+    stg.set_user_settings(
+        1,
+        &UserSettings {
+            cal_limit: 100.0,
+            ..Default::default()
+        },
+    )?;
+
+    // Check stored user settings
+    assert_eq!(100.0, stg.get_user_settings(1)?.cal_limit);
+
+    // Upser user settings
+    stg.set_user_settings(
+        1,
+        &UserSettings {
+            cal_limit: 200.0,
+            ..Default::default()
+        },
+    )?;
+
+    // Check stored user settings
+    assert_eq!(200.0, stg.get_user_settings(1)?.cal_limit);
+
+    Ok(())
+}
+
+fn get_user_settings(stg: &dyn Storage) -> Result<()> {
+    // Get settings that not exists
+    let res = stg.get_user_settings(1);
+    assert!(matches!(res, Err(StorageError::UserSettingsNotFound)));
+
+    // Set settings
+    let s = UserSettings {
+        cal_limit: 200.0,
+        ..Default::default()
+    };
+    stg.set_user_settings(1, &s)?;
+
+    // Get settings
+    let res = stg.get_user_settings(1)?;
+    assert_eq!(s, res);
+
+    // Set settings with body profile
+    let s = UserSettings {
+        cal_limit: 200.0,
+        sex: Some(Sex::Female),
+        height: Some(170.0),
+        birth_date: Timestamp::from_unix_millis(1),
+    };
+    stg.set_user_settings(1, &s)?;
+
+    // Get settings
+    let res = stg.get_user_settings(1)?;
+    assert_eq!(s, res);
+
+    Ok(())
+}
+
+//
+// Bundle
+//
+
+fn bundle_backup(bundle: Vec<BundleBackup>) -> Backup {
+    Backup {
+        timestamp: 0,
+        weight: vec![],
+        food: vec![FoodBackup {
+            user_id: 1,
+            key: "food1".into(),
+            name: "name".into(),
+            brand: "brand".into(),
+            cal100: 1.1,
+            prot100: 2.2,
+            fat100: 3.3,
+            carb100: 4.4,
+            comment: "comment".into(),
+        }],
+        user_settings: vec![],
+        bundle,
+        journal: vec![],
+        sport: vec![],
+        sport_activity: vec![],
+        metric: vec![],
+        metric_value: vec![],
+        schedule: vec![],
+        audit_log: vec![],
+    }
+}
+
+fn test_get_bundle(stg: &dyn Storage) -> Result<()> {
+    // Get not existing bundle
+    let res = stg.get_bundle(1, "test");
+    assert!(matches!(res, Err(StorageError::BundleNotFound(k)) if k == "test"));
+
+    // Add bundle data from backup, child bundle goes after parent
+    stg.restore(&bundle_backup(vec![
+        BundleBackup {
+            user_id: 1,
+            key: "test".into(),
+            data: r#"{"bundle1": 0, "food1": 1.1}"#.into(),
+        },
+        BundleBackup {
+            user_id: 1,
+            key: "bundle1".into(),
+            data: r#"{"food1": 2.2}"#.into(),
+        },
+    ]))?;
+
+    // Get bundle
+    let res = stg.get_bundle(1, "test")?;
+    assert_eq!(
+        Bundle {
+            key: "test".into(),
+            data: HashMap::from([("bundle1".into(), 0.0), ("food1".into(), 1.1)]),
+        },
+        res
+    );
+
+    Ok(())
+}
+
+fn test_get_bundle_list(stg: &dyn Storage) -> Result<()> {
+    // Get empty bundle list
+    let res = stg.get_bundle_list(1);
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    // Add bundle data from backup
+    stg.restore(&bundle_backup(vec![
+        BundleBackup {
+            user_id: 1,
+            key: "test".into(),
+            data: r#"{"bundle1": 0, "food1": 1.1}"#.into(),
+        },
+        BundleBackup {
+            user_id: 1,
+            key: "test2".into(),
+            data: r#"{"bundle1": 0}"#.into(),
+        },
+        BundleBackup {
+            user_id: 1,
+            key: "bundle1".into(),
+            data: r#"{"food1": 2.2}"#.into(),
+        },
+        BundleBackup {
+            user_id: 2,
+            key: "other".into(),
+            data: r#"{"food1": 3.3}"#.into(),
+        },
+    ]))?;
+
+    // Get bundle list
+    let res = stg.get_bundle_list(1)?;
+    assert_eq!(
+        vec![
+            Bundle {
+                key: "bundle1".into(),
+                data: HashMap::from([("food1".into(), 2.2)]),
+            },
+            Bundle {
+                key: "test".into(),
+                data: HashMap::from([("bundle1".into(), 0.0), ("food1".into(), 1.1)]),
+            },
+            Bundle {
+                key: "test2".into(),
+                data: HashMap::from([("bundle1".into(), 0.0)]),
+            }
+        ],
+        res
+    );
+
+    Ok(())
+}
+
+fn test_set_bundle(stg: &dyn Storage) -> Result<()> {
+    // Check invalid bundle
+    for b in [
+        &Bundle {
+            key: "".into(),
+            data: HashMap::new(),
+        },
+        &Bundle {
+            key: "key".into(),
+            data: HashMap::new(),
+        },
+        &Bundle {
+            key: "key".into(),
+            data: HashMap::from([("food1".into(), -1.0)]),
+        },
+    ] {
+        let res = stg.set_bundle(1, b);
+        assert!(matches!(res, Err(StorageError::BundleInvalid)));
+    }
+
+    // Check errors
+    let res = stg.set_bundle(
+        1,
+        &Bundle {
+            key: "bndl_key".into(),
+            data: HashMap::from([("bndl_key".into(), 0.0)]),
+        },
+    );
+    assert!(matches!(res, Err(StorageError::BundleDepRecursive(k)) if k == "bndl_key"));
+
+    let res = stg.set_bundle(
+        1,
+        &Bundle {
+            key: "bndl_key".into(),
+            data: HashMap::from([("bndl_key2".into(), 0.0)]),
+        },
+    );
+    assert!(matches!(res, Err(StorageError::BundleDepBundleNotFound(k)) if k == "bndl_key2"));
+
+    let res = stg.set_bundle(
+        1,
+        &Bundle {
+            key: "bndl_key".into(),
+            data: HashMap::from([("food_key".into(), 1.0)]),
+        },
+    );
+    assert!(matches!(res, Err(StorageError::BundleDepFoodNotFound(k)) if k == "food_key"));
+
+    // Set initial data
+    stg.set_food(
+        1,
+        &Food {
+            key: "food_key".into(),
+            name: "name".into(),
+            brand: "brand".into(),
+            cal100: 1.1,
+            prot100: 2.2,
+            fat100: 3.3,
+            carb100: 4.4,
+            comment: "comment".into(),
+        },
+    )?;
+
+    // Set bundle
+    stg.set_bundle(
+        1,
+        &Bundle {
+            key: "bndl_key".into(),
+            data: HashMap::from([("food_key".into(), 123.123)]),
+        },
+    )?;
+
+    // Set another bundle
+    stg.set_bundle(
+        1,
+        &Bundle {
+            key: "bndl_key_2".into(),
+            data: HashMap::from([("food_key".into(), 123.123)]),
+        },
+    )?;
+
+    // Update bundle
+    stg.set_bundle(
+        1,
+        &Bundle {
+            key: "bndl_key".into(),
+            data: HashMap::from([("food_key".into(), 123.123), ("bndl_key_2".into(), 0.0)]),
+        },
+    )?;
+
+    // Get bundle list
+    let res = stg.get_bundle_list(1)?;
+    assert_eq!(
+        vec![
+            Bundle {
+                key: "bndl_key".into(),
+                data: HashMap::from([("food_key".into(), 123.123), ("bndl_key_2".into(), 0.0)]),
+            },
+            Bundle {
+                key: "bndl_key_2".into(),
+                data: HashMap::from([("food_key".into(), 123.123)]),
+            }
+        ],
+        res
+    );
+
+    Ok(())
+}
+
+fn test_delete_bundle(stg: &dyn Storage) -> Result<()> {
+    // Set initial data
+    stg.set_food(
+        1,
+        &Food {
+            key: "food_key".into(),
+            name: "name".into(),
+            brand: "brand".into(),
+            cal100: 1.1,
+            prot100: 2.2,
+            fat100: 3.3,
+            carb100: 4.4,
+            comment: "comment".into(),
+        },
+    )?;
+
+    stg.set_bundle(
+        1,
+        &Bundle {
+            key: "bndl_key_2".into(),
+            data: HashMap::from([("food_key".into(), 123.123)]),
+        },
+    )?;
+
+    stg.set_bundle(
+        1,
+        &Bundle {
+            key: "bndl_key".into(),
+            data: HashMap::from([("food_key".into(), 123.123), ("bndl_key_2".into(), 0.0)]),
+        },
+    )?;
+
+    // Try delete when used
+    let res = stg.delete_bundle(1, "bndl_key_2");
+    assert!(
+        matches!(res, Err(StorageError::BundleIsUsed { key, used_in }) if key == "bndl_key_2" && used_in == "bndl_key")
+    );
+
+    // Delete correct
+    stg.delete_bundle(1, "bndl_key")?;
+    stg.delete_bundle(1, "bndl_key_2")?;
+
+    Ok(())
+}
+
+//
+// Journal
+//
+
+fn test_set_journal(stg: &dyn Storage) -> Result<()> {
+    // Set invalid journal
+    for j in [
+        &Journal {
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            meal: Meal::Breakfast,
+            food_key: "".into(),
+            food_weight: 0.0,
+        },
+        &Journal {
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            meal: Meal::Breakfast,
+            food_key: "food".into(),
+            food_weight: 0.0,
+        },
+    ] {
+        let res = stg.set_journal(1, j);
+        assert!(matches!(res, Err(StorageError::JournalInvalid)));
+    }
+
+    // Set journal with food not exists
+    let res = stg.set_journal(
+        1,
+        &Journal {
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            meal: Meal::Breakfast,
+            food_key: "food".into(),
+            food_weight: 1.0,
+        },
+    );
+    assert!(matches!(res, Err(StorageError::FoodNotFound(k)) if k == "food"));
+
+    // Set food
+    stg.set_food(
+        1,
+        &Food {
+            key: "food".into(),
+            name: "name".into(),
+            brand: "brand".into(),
+            cal100: 1.1,
+            prot100: 2.2,
+            fat100: 3.3,
+            carb100: 4.4,
+            comment: "comment".into(),
+        },
+    )?;
+
+    // Set journal
+    stg.set_journal(
+        1,
+        &Journal {
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            meal: Meal::Breakfast,
+            food_key: "food".into(),
+            food_weight: 1.0,
+        },
+    )?;
+
+    // Check stored journal
+    assert_eq!(
+        vec![JournalBackup {
+            user_id: 1,
+            timestamp: 1,
+            meal: 0,
+            food_key: "food".into(),
+            food_weight: 1.0,
+        }],
+        stg.backup(1)?.journal
+    );
+
+    Ok(())
+}
+
+fn test_set_journal_bundle(stg: &dyn Storage) -> Result<()> {
+    // Set initial data
+    stg.set_food(
+        1,
+        &Food {
+            key: "food".into(),
+            name: "name".into(),
+            brand: "brand".into(),
+            cal100: 1.1,
+            prot100: 2.2,
+            fat100: 3.3,
+            carb100: 4.4,
+            comment: "comment".into(),
+        },
+    )?;
+    stg.set_food(
+        1,
+        &Food {
+            key: "food2".into(),
+            name: "name".into(),
+            brand: "brand".into(),
+            cal100: 1.1,
+            prot100: 2.2,
+            fat100: 3.3,
+            carb100: 4.4,
+            comment: "comment".into(),
+        },
+    )?;
+    stg.set_bundle(
+        1,
+        &Bundle {
+            key: "bndl2".into(),
+            data: HashMap::from([("food2".into(), 123.123)]),
+        },
+    )?;
+    stg.set_bundle(
+        1,
+        &Bundle {
+            key: "bndl1".into(),
+            data: HashMap::from([("food".into(), 456.456), ("bndl2".into(), 0.0)]),
+        },
+    )?;
+
+    // Set journal bundle not exists
+    let res = stg.set_journal_bundle(
+        1,
+        Timestamp::from_unix_millis(1).unwrap(),
+        Meal::Breakfast,
+        "test",
+    );
+    assert!(matches!(res, Err(StorageError::BundleNotFound(k)) if k == "test"));
+
+    // Set journal bundle
+    stg.set_journal_bundle(
+        1,
+        Timestamp::from_unix_millis(1).unwrap(),
+        Meal::Breakfast,
+        "bndl1",
+    )?;
+
+    // Check stored journal
+    assert_eq!(
+        vec![
+            JournalBackup {
+                user_id: 1,
+                timestamp: 1,
+                meal: 0,
+                food_key: "food".into(),
+                food_weight: 456.456,
+            },
+            JournalBackup {
+                user_id: 1,
+                timestamp: 1,
+                meal: 0,
+                food_key: "food2".into(),
+                food_weight: 123.123,
+            }
+        ],
+        stg.backup(1)?.journal
+    );
+
+    Ok(())
+}
+
+fn test_delete_journal(stg: &dyn Storage) -> Result<()> {
+    // Set inital data
+    stg.set_food(
+        1,
+        &Food {
+            key: "food".into(),
+            name: "name".into(),
+            brand: "brand".into(),
+            cal100: 1.1,
+            prot100: 2.2,
+            fat100: 3.3,
+            carb100: 4.4,
+            comment: "comment".into(),
+        },
+    )?;
+    stg.set_food(
+        1,
+        &Food {
+            key: "food2".into(),
+            name: "name".into(),
+            brand: "brand".into(),
+            cal100: 1.1,
+            prot100: 2.2,
+            fat100: 3.3,
+            carb100: 4.4,
+            comment: "comment".into(),
+        },
+    )?;
+
+    stg.set_journal(
+        1,
+        &Journal {
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            meal: Meal::Breakfast,
+            food_key: "food".into(),
+            food_weight: 1.0,
+        },
+    )?;
+    stg.set_journal(
+        1,
+        &Journal {
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            meal: Meal::Dinner,
+            food_key: "food".into(),
+            food_weight: 1.0,
+        },
+    )?;
+    stg.set_journal(
+        1,
+        &Journal {
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            meal: Meal::Dinner,
+            food_key: "food2".into(),
+            food_weight: 2.0,
+        },
+    )?;
+
+    // Check stored journal
+    assert_eq!(3, stg.backup(1)?.journal.len());
+
+    // Delete
+    stg.delete_journal(
+        1,
+        Timestamp::from_unix_millis(1).unwrap(),
+        Meal::Breakfast,
+        "food",
+    )?;
+    stg.delete_journal_meal(1, Timestamp::from_unix_millis(1).unwrap(), Meal::Dinner)?;
+
+    // Check stored journal
+    assert_eq!(0, stg.backup(1)?.journal.len());
+
+    Ok(())
+}
+
+fn test_get_journal_report_and_food_avg_weight(stg: &dyn Storage) -> Result<()> {
+    // Get empty report
+    let res = stg.get_journal_report(
+        1,
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(1).unwrap(),
+    );
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    // Get empty avg weight
+    let res = stg.get_journal_food_avg_weight(
+        1,
+        "food",
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(1).unwrap(),
+    )?;
+    assert_eq!(0.0, res);
+
+    // Set data
+    stg.set_food(
+        1,
+        &Food {
+            key: "key_aaa".into(),
+            name: "aaa".into(),
+            brand: "brand_aaa".into(),
+            cal100: 1.0,
+            prot100: 2.0,
+            fat100: 3.0,
+            carb100: 4.0,
+            comment: "comment".into(),
+        },
+    )?;
+    stg.set_food(
+        1,
+        &Food {
+            key: "key_bbb".into(),
+            name: "bbb".into(),
+            brand: "brand_bbb".into(),
+            cal100: 1.0,
+            prot100: 2.0,
+            fat100: 3.0,
+            carb100: 4.0,
+            comment: "comment".into(),
+        },
+    )?;
+    stg.set_food(
+        1,
+        &Food {
+            key: "key_ccc".into(),
+            name: "ccc".into(),
+            brand: "brand_ccc".into(),
+            cal100: 1.0,
+            prot100: 2.0,
+            fat100: 3.0,
+            carb100: 4.0,
+            comment: "comment".into(),
+        },
+    )?;
+    stg.set_food(
+        1,
+        &Food {
+            key: "key_ddd".into(),
+            name: "Еда ЯЯЯ".into(),
+            brand: "brand_ddd".into(),
+            cal100: 1.0,
+            prot100: 2.0,
+            fat100: 3.0,
+            carb100: 4.0,
+            comment: "comment".into(),
+        },
+    )?;
+    stg.set_food(
+        1,
+        &Food {
+            key: "key_eee".into(),
+            name: "Еда ААА".into(),
+            brand: "brand_eee".into(),
+            cal100: 1.0,
+            prot100: 2.0,
+            fat100: 3.0,
+            carb100: 4.0,
+            comment: "comment".into(),
+        },
+    )?;
+
+    stg.set_journal(
+        1,
+        &Journal {
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            meal: Meal::new_str("ужин").unwrap(),
+            food_key: "key_aaa".into(),
+            food_weight: 100.0,
+        },
+    )?;
+    stg.set_journal(
+        1,
+        &Journal {
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            meal: Meal::new_str("обед").unwrap(),
+            food_key: "key_ccc".into(),
+            food_weight: 200.0,
+        },
+    )?;
+    stg.set_journal(
+        1,
+        &Journal {
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            meal: Meal::new_str("обед").unwrap(),
+            food_key: "key_bbb".into(),
+            food_weight: 100.0,
+        },
+    )?;
+    stg.set_journal(
+        1,
+        &Journal {
+            timestamp: Timestamp::from_unix_millis(2).unwrap(),
+            meal: Meal::new_str("завтрак").unwrap(),
+            food_key: "key_ddd".into(),
+            food_weight: 100.0,
+        },
+    )?;
+    stg.set_journal(
+        1,
+        &Journal {
+            timestamp: Timestamp::from_unix_millis(2).unwrap(),
+            meal: Meal::new_str("завтрак").unwrap(),
+            food_key: "key_eee".into(),
+            food_weight: 100.0,
+        },
+    )?;
+
+    // Get report
+    let res = stg.get_journal_report(
+        1,
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(2).unwrap(),
+    )?;
+    assert_eq!(
+        vec![
+            JournalReport {
+                timestamp: Timestamp::from_unix_millis(1).unwrap(),
+                meal: Meal::new_str("обед").unwrap(),
+                food_key: "key_bbb".into(),
+                food_name: "bbb".into(),
+                food_brand: "brand_bbb".into(),
+                food_weight: 100.0,
+                cal: 1.0,
+                prot: 2.0,
+                fat: 3.0,
+                carb: 4.0,
+            },
+            JournalReport {
+                timestamp: Timestamp::from_unix_millis(1).unwrap(),
+                meal: Meal::new_str("обед").unwrap(),
+                food_key: "key_ccc".into(),
+                food_name: "ccc".into(),
+                food_brand: "brand_ccc".into(),
+                food_weight: 200.0,
+                cal: 2.0,
+                prot: 4.0,
+                fat: 6.0,
+                carb: 8.0,
+            },
+            JournalReport {
+                timestamp: Timestamp::from_unix_millis(1).unwrap(),
+                meal: Meal::new_str("ужин").unwrap(),
+                food_key: "key_aaa".into(),
+                food_name: "aaa".into(),
+                food_brand: "brand_aaa".into(),
+                food_weight: 100.0,
+                cal: 1.0,
+                prot: 2.0,
+                fat: 3.0,
+                carb: 4.0,
+            },
+            JournalReport {
+                timestamp: Timestamp::from_unix_millis(2).unwrap(),
+                meal: Meal::new_str("завтрак").unwrap(),
+                food_key: "key_eee".into(),
+                food_name: "Еда ААА".into(),
+                food_brand: "brand_eee".into(),
+                food_weight: 100.0,
+                cal: 1.0,
+                prot: 2.0,
+                fat: 3.0,
+                carb: 4.0,
+            },
+            JournalReport {
+                timestamp: Timestamp::from_unix_millis(2).unwrap(),
+                meal: Meal::new_str("завтрак").unwrap(),
+                food_key: "key_ddd".into(),
+                food_name: "Еда ЯЯЯ".into(),
+                food_brand: "brand_ddd".into(),
+                food_weight: 100.0,
+                cal: 1.0,
+                prot: 2.0,
+                fat: 3.0,
+                carb: 4.0,
+            }
+        ],
+        res
+    );
+
+    // Get avg weight
+    let res = stg.get_journal_food_avg_weight(
+        1,
+        "key_aaa",
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(2).unwrap(),
+    )?;
+    assert_eq!(100.0, res);
+
+    Ok(())
+}
+
+fn test_get_journal_food_usage(stg: &dyn Storage) -> Result<()> {
+    // Get empty usage
+    let res = stg.get_journal_food_usage(
+        1,
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(2).unwrap(),
+    );
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    let res = stg.get_journal_food_meal_usage(
+        1,
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(2).unwrap(),
+    );
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    let res = stg.get_unused_food_list();
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    // Set data
+    for (key, name, cal100) in [
+        ("key_aaa", "aaa", 100.0),
+        ("key_bbb", "bbb", 300.0),
+        ("key_ccc", "ccc", 50.0),
+        ("key_ddd", "ddd", 10.0),
+    ] {
+        stg.set_food(
+            1,
+            &Food {
+                key: key.into(),
+                name: name.into(),
+                brand: "brand".into(),
+                cal100,
+                prot100: 10.0,
+                fat100: 1.0,
+                carb100: 1.0,
+                comment: "".into(),
+            },
+        )?;
+    }
+    stg.set_bundle(
+        1,
+        &Bundle {
+            key: "bndl".into(),
+            data: HashMap::from([("key_ccc".into(), 100.0)]),
+        },
+    )?;
+
+    for (ts, meal, food_key, food_weight) in [
+        (1, "завтрак", "key_aaa", 100.0),
+        (1, "обед", "key_aaa", 200.0),
+        (1, "обед", "key_bbb", 100.0),
+        (2, "завтрак", "key_aaa", 100.0),
+        // Out of period
+        (3, "ужин", "key_bbb", 500.0),
+    ] {
+        stg.set_journal(
+            1,
+            &Journal {
+                timestamp: Timestamp::from_unix_millis(ts).unwrap(),
+                meal: Meal::new_str(meal).unwrap(),
+                food_key: food_key.into(),
+                food_weight,
+            },
+        )?;
+    }
+    // Other user
+    stg.set_journal(
+        2,
+        &Journal {
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            meal: Meal::new_str("ужин").unwrap(),
+            food_key: "key_aaa".into(),
+            food_weight: 1000.0,
+        },
+    )?;
+
+    // Get usage
+    let res = stg.get_journal_food_usage(
+        1,
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(2).unwrap(),
+    )?;
+    assert_eq!(
+        vec![
+            FoodUsage {
+                food_key: "key_aaa".into(),
+                food_name: "aaa".into(),
+                food_brand: "brand".into(),
+                count: 3,
+                food_weight: 400.0,
+                cal: 400.0,
+                prot: 40.0,
+            },
+            FoodUsage {
+                food_key: "key_bbb".into(),
+                food_name: "bbb".into(),
+                food_brand: "brand".into(),
+                count: 1,
+                food_weight: 100.0,
+                cal: 300.0,
+                prot: 10.0,
+            },
+        ],
+        res
+    );
+
+    // Get meal usage
+    let res = stg.get_journal_food_meal_usage(
+        1,
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(2).unwrap(),
+    )?;
+    assert_eq!(
+        vec![
+            FoodMealUsage {
+                food_key: "key_aaa".into(),
+                food_name: "aaa".into(),
+                food_brand: "brand".into(),
+                meal: Meal::Breakfast,
+                count: 2,
+            },
+            FoodMealUsage {
+                food_key: "key_aaa".into(),
+                food_name: "aaa".into(),
+                food_brand: "brand".into(),
+                meal: Meal::Dinner,
+                count: 1,
+            },
+            FoodMealUsage {
+                food_key: "key_bbb".into(),
+                food_name: "bbb".into(),
+                food_brand: "brand".into(),
+                meal: Meal::Dinner,
+                count: 1,
+            },
+        ],
+        res
+    );
+
+    // Get unused foods, food in bundle is used
+    let res = stg.get_unused_food_list()?;
+    assert_eq!(
+        vec!["key_ddd"],
+        res.iter().map(|f| f.key.as_str()).collect::<Vec<_>>()
+    );
+
+    Ok(())
+}
+
+//
+// Metric
+//
+
+fn test_set_metric(stg: &dyn Storage) -> Result<()> {
+    // Set invalid metric
+    let res = stg.set_metric(
+        1,
+        &Metric {
+            key: "bp".into(),
+            name: "".into(),
+            unit: "".into(),
+            fields: vec![],
+        },
+    );
+    assert!(matches!(res, Err(StorageError::MetricInvalid)));
+
+    // Set metric
+    stg.set_metric(
+        1,
+        &Metric {
+            key: "bp".into(),
+            name: "Давление".into(),
+            unit: "мм рт. ст.".into(),
+            fields: vec!["sys".into()],
+        },
+    )?;
+
+    // Check stored metric
+    assert_eq!(
+        vec![MetricBackup {
+            user_id: 1,
+            key: "bp".into(),
+            name: "Давление".into(),
+            unit: "мм рт. ст.".into(),
+            fields: r#"["sys"]"#.into(),
+        }],
+        stg.backup(1)?.metric
+    );
+
+    // Update metric
+    stg.set_metric(
+        1,
+        &Metric {
+            key: "bp".into(),
+            name: "Давление".into(),
+            unit: "мм рт. ст.".into(),
+            fields: vec!["sys".into(), "dia".into()],
+        },
+    )?;
+
+    assert_eq!(
+        Metric {
+            key: "bp".into(),
+            name: "Давление".into(),
+            unit: "мм рт. ст.".into(),
+            fields: vec!["sys".into(), "dia".into()],
+        },
+        stg.get_metric(1, "bp")?
+    );
+
+    // Metric of other user not visible
+    let res = stg.get_metric(2, "bp");
+    assert!(matches!(res, Err(StorageError::MetricNotFound(k)) if k == "bp"));
+
+    Ok(())
+}
+
+fn test_get_metric_list(stg: &dyn Storage) -> Result<()> {
+    // Get empty list
+    let res = stg.get_metric_list(1);
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    // Set data
+    stg.set_metric(
+        1,
+        &Metric {
+            key: "waist".into(),
+            name: "Талия".into(),
+            unit: "см".into(),
+            fields: vec!["value".into()],
+        },
+    )?;
+    stg.set_metric(
+        1,
+        &Metric {
+            key: "bp".into(),
+            name: "Давление".into(),
+            unit: "мм рт. ст.".into(),
+            fields: vec!["sys".into(), "dia".into()],
+        },
+    )?;
+    stg.set_metric(
+        2,
+        &Metric {
+            key: "hr".into(),
+            name: "Пульс".into(),
+            unit: "уд/мин".into(),
+            fields: vec!["value".into()],
+        },
+    )?;
+
+    assert_eq!(
+        vec![
+            Metric {
+                key: "bp".into(),
+                name: "Давление".into(),
+                unit: "мм рт. ст.".into(),
+                fields: vec!["sys".into(), "dia".into()],
+            },
+            Metric {
+                key: "waist".into(),
+                name: "Талия".into(),
+                unit: "см".into(),
+                fields: vec!["value".into()],
+            },
+        ],
+        stg.get_metric_list(1)?
+    );
+
+    Ok(())
+}
+
+fn test_set_metric_value(stg: &dyn Storage) -> Result<()> {
+    // Set invalid metric value
+    let res = stg.set_metric_value(
+        1,
+        &MetricValue {
+            metric_key: "bp".into(),
+            timestamp: Timestamp::now(),
+            values: vec![],
+        },
+    );
+    assert!(matches!(res, Err(StorageError::MetricValueInvalid)));
+
+    // Set metric value for metric that not exists
+    let res = stg.set_metric_value(
+        1,
+        &MetricValue {
+            metric_key: "bp".into(),
+            timestamp: Timestamp::now(),
+            values: vec![120.0, 80.0],
+        },
+    );
+    assert!(matches!(res, Err(StorageError::MetricNotFound(k)) if k == "bp"));
+
+    // Set metric
+    stg.set_metric(
+        1,
+        &Metric {
+            key: "bp".into(),
+            name: "Давление".into(),
+            unit: "мм рт. ст.".into(),
+            fields: vec!["sys".into(), "dia".into()],
+        },
+    )?;
+
+    // Set metric value with wrong values count
+    let res = stg.set_metric_value(
+        1,
+        &MetricValue {
+            metric_key: "bp".into(),
+            timestamp: Timestamp::now(),
+            values: vec![120.0],
+        },
+    );
+    assert!(matches!(res, Err(StorageError::MetricValueInvalid)));
+
+    // Set metric value for other user
+    let res = stg.set_metric_value(
+        2,
+        &MetricValue {
+            metric_key: "bp".into(),
+            timestamp: Timestamp::now(),
+            values: vec![120.0, 80.0],
+        },
+    );
+    assert!(matches!(res, Err(StorageError::MetricNotFound(k)) if k == "bp"));
+
+    // Set metric value
+    stg.set_metric_value(
+        1,
+        &MetricValue {
+            metric_key: "bp".into(),
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            values: vec![120.0, 80.0],
+        },
+    )?;
+
+    // Update metric value
+    stg.set_metric_value(
+        1,
+        &MetricValue {
+            metric_key: "bp".into(),
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            values: vec![125.0, 85.0],
+        },
+    )?;
+
+    // Check stored metric value
+    assert_eq!(
+        vec![MetricValueBackup {
+            user_id: 1,
+            timestamp: 1,
+            metric_key: "bp".into(),
+            values: "[125.0,85.0]".into(),
+        }],
+        stg.backup(1)?.metric_value
+    );
+
+    Ok(())
+}
+
+fn test_get_metric_value_list_and_delete(stg: &dyn Storage) -> Result<()> {
+    // Get empty list
+    let res = stg.get_metric_value_list(
+        1,
+        "hr",
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(10).unwrap(),
+    );
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    // Set data
+    stg.set_metric(
+        1,
+        &Metric {
+            key: "hr".into(),
+            name: "Пульс".into(),
+            unit: "уд/мин".into(),
+            fields: vec!["value".into()],
+        },
+    )?;
+    for (ts, v) in [(3, 62.0), (1, 60.0), (20, 70.0)] {
+        stg.set_metric_value(
+            1,
+            &MetricValue {
+                metric_key: "hr".into(),
+                timestamp: Timestamp::from_unix_millis(ts).unwrap(),
+                values: vec![v],
+            },
+        )?;
+    }
+
+    // Get list
+    let res = stg.get_metric_value_list(
+        1,
+        "hr",
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(10).unwrap(),
+    )?;
+    assert_eq!(
+        vec![
+            MetricValue {
+                metric_key: "hr".into(),
+                timestamp: Timestamp::from_unix_millis(1).unwrap(),
+                values: vec![60.0],
+            },
+            MetricValue {
+                metric_key: "hr".into(),
+                timestamp: Timestamp::from_unix_millis(3).unwrap(),
+                values: vec![62.0],
+            },
+        ],
+        res
+    );
+
+    // Delete metric that used in values
+    let res = stg.delete_metric(1, "hr");
+    assert!(matches!(res, Err(StorageError::MetricIsUsed(k)) if k == "hr"));
+
+    // Delete values
+    for ts in [1, 3, 20] {
+        stg.delete_metric_value(1, Timestamp::from_unix_millis(ts).unwrap(), "hr")?;
+    }
+    let res = stg.get_metric_value_list(
+        1,
+        "hr",
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(30).unwrap(),
+    );
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    // Delete metric
+    stg.delete_metric(1, "hr")?;
+    let res = stg.get_metric(1, "hr");
+    assert!(matches!(res, Err(StorageError::MetricNotFound(k)) if k == "hr"));
+
+    Ok(())
+}
+
+//
+// Schedule
+//
+
+fn test_schedule(stg: &dyn Storage) -> Result<()> {
+    // Get empty list
+    let res = stg.get_schedule_list(1);
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    // Set invalid
+    let res = stg.set_schedule(
+        1,
+        &Schedule {
+            kind: ScheduleKind::WeightReminder,
+            hour: 24,
+            minute: 0,
+            tz: "UTC".into(),
+            last_run: None,
+        },
+    );
+    assert!(matches!(res, Err(StorageError::ScheduleInvalid)));
+
+    // Set schedules
+    let sc_weight = Schedule {
+        kind: ScheduleKind::WeightReminder,
+        hour: 8,
+        minute: 0,
+        tz: "Europe/Moscow".into(),
+        last_run: None,
+    };
+    let sc_summary = Schedule {
+        kind: ScheduleKind::DaySummary,
+        hour: 21,
+        minute: 30,
+        tz: "Europe/Moscow".into(),
+        last_run: None,
+    };
+    stg.set_schedule(1, &sc_summary)?;
+    stg.set_schedule(1, &sc_weight)?;
+    stg.set_schedule(
+        2,
+        &Schedule {
+            kind: ScheduleKind::JournalReminder,
+            hour: 13,
+            minute: 0,
+            tz: "UTC".into(),
+            last_run: None,
+        },
+    )?;
+
+    // Get list
+    let res = stg.get_schedule_list(1)?;
+    assert_eq!(vec![sc_weight.clone(), sc_summary.clone()], res);
+
+    // Set last run
+    stg.set_schedule_last_run(1, &sc_weight, Timestamp::from_unix_millis(5).unwrap())?;
+    let res = stg.get_schedule_list(1)?;
+    assert_eq!(Timestamp::from_unix_millis(5), res[0].last_run);
+    assert_eq!(None, res[1].last_run);
+
+    // Update timezone keeps last run
+    stg.set_schedule(
+        1,
+        &Schedule {
+            tz: "UTC".into(),
+            ..sc_weight.clone()
+        },
+    )?;
+    let res = stg.get_schedule_list(1)?;
+    assert_eq!("UTC", res[0].tz);
+    assert_eq!(Timestamp::from_unix_millis(5), res[0].last_run);
+
+    // Delete
+    stg.delete_schedule(1, ScheduleKind::WeightReminder, 8, 0)?;
+    stg.delete_schedule(1, ScheduleKind::DaySummary, 21, 30)?;
+    let res = stg.get_schedule_list(1);
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+    assert_eq!(1, stg.get_schedule_list(2)?.len());
+
+    Ok(())
+}
+
+//
+// Change log
+//
+
+fn change_food(key: &str, name: &str) -> Food {
+    Food {
+        key: key.into(),
+        name: name.into(),
+        brand: "brand".into(),
+        cal100: 1.1,
+        prot100: 2.2,
+        fat100: 3.3,
+        carb100: 4.4,
+        comment: "comment".into(),
+    }
+}
+
+fn change_journal(meal_ts: i64, food_key: &str, food_weight: f64) -> Journal {
+    Journal {
+        timestamp: Timestamp::from_unix_millis(meal_ts).unwrap(),
+        meal: Meal::Dinner,
+        food_key: food_key.into(),
+        food_weight,
+    }
+}
+
+fn change_keys(changes: &[Change]) -> Vec<(ChangeAction, &str)> {
+    changes.iter().map(|c| (c.action, c.key.as_str())).collect()
+}
+
+fn test_get_change_list(stg: &dyn Storage) -> Result<()> {
+    let res = stg.get_change_list(1, 10);
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    let weight = Weight {
+        timestamp: Timestamp::from_unix_millis(1).unwrap(),
+        value: 80.0,
+    };
+    stg.set_food(1, &change_food("key1", "name1"))?;
+    stg.set_weight(1, &weight)?;
+    stg.set_weight(2, &weight)?;
+    // Nothing changed, not recorded
+    stg.set_weight(1, &weight)?;
+    stg.delete_weight(1, Timestamp::from_unix_millis(2).unwrap())?;
+    // Failed, not recorded
+    let res = stg.delete_bundle(1, "bundle1");
+    assert!(res.is_ok() || matches!(res, Err(StorageError::BundleNotFound(_))));
+
+    // Newest first
+    assert_eq!(
+        vec![
+            (ChangeAction::SetWeight, ""),
+            (ChangeAction::SetFood, "key1")
+        ],
+        change_keys(&stg.get_change_list(1, 10)?)
+    );
+    assert_eq!(
+        vec![(ChangeAction::SetWeight, "")],
+        change_keys(&stg.get_change_list(1, 1)?)
+    );
+    assert_eq!(
+        vec![(ChangeAction::SetWeight, "")],
+        change_keys(&stg.get_change_list(2, 10)?)
+    );
+
+    // Only last changes are kept
+    for i in 0..CHANGE_LOG_SIZE + 5 {
+        stg.set_weight(
+            3,
+            &Weight {
+                timestamp: Timestamp::from_unix_millis(i as i64).unwrap(),
+                value: 80.0,
+            },
+        )?;
+    }
+    assert_eq!(CHANGE_LOG_SIZE, stg.get_change_list(3, 1000)?.len());
+
+    Ok(())
+}
+
+fn test_undo(stg: &dyn Storage) -> Result<()> {
+    let res = stg.undo(1, 1);
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    // Overwritten food
+    stg.set_food(1, &change_food("key1", "name1"))?;
+    stg.set_food(1, &change_food("key1", "wrong"))?;
+    assert_eq!(
+        vec![(ChangeAction::SetFood, "key1")],
+        change_keys(&stg.undo(1, 1)?)
+    );
+    assert_eq!(change_food("key1", "name1"), stg.get_food("key1")?);
+
+    // Deleted meal
+    stg.set_food(1, &change_food("key2", "name2"))?;
+    stg.set_journal(1, &change_journal(1, "key1", 100.0))?;
+    stg.set_journal(1, &change_journal(1, "key2", 200.0))?;
+    stg.delete_journal_meal(1, Timestamp::from_unix_millis(1).unwrap(), Meal::Dinner)?;
+    let journal = |stg: &dyn Storage| -> Result<Vec<(String, f64)>> {
+        let res = stg.get_journal_report(
+            1,
+            Timestamp::from_unix_millis(0).unwrap(),
+            Timestamp::from_unix_millis(10).unwrap(),
+        );
+        if matches!(res, Err(StorageError::EmptyResult)) {
+            return Ok(Vec::new());
+        }
+
+        Ok(res?
+            .into_iter()
+            .map(|r| (r.food_key, r.food_weight))
+            .collect())
+    };
+    assert!(journal(stg)?.is_empty());
+
+    stg.undo(1, 1)?;
+    assert_eq!(
+        vec![("key1".to_string(), 100.0), ("key2".to_string(), 200.0)],
+        journal(stg)?
+    );
+
+    // Bundle changed and deleted
+    let bndl = |weight: f64| Bundle {
+        key: "bundle1".into(),
+        data: HashMap::from([("key1".into(), weight)]),
+    };
+    stg.set_bundle(1, &bndl(100.0))?;
+    stg.set_bundle(1, &bndl(200.0))?;
+    stg.delete_bundle(1, "bundle1")?;
+    assert_eq!(
+        vec![
+            (ChangeAction::DeleteBundle, "bundle1"),
+            (ChangeAction::SetBundle, "bundle1")
+        ],
+        change_keys(&stg.undo(1, 2)?)
+    );
+    assert_eq!(bndl(100.0), stg.get_bundle(1, "bundle1")?);
+
+    // Count larger than log undoes everything
+    let res = stg.undo(1, 100)?;
+    assert_eq!(
+        vec![
+            (ChangeAction::SetBundle, "bundle1"),
+            (ChangeAction::SetJournal, "key2"),
+            (ChangeAction::SetJournal, "key1"),
+            (ChangeAction::SetFood, "key2"),
+            (ChangeAction::SetFood, "key1"),
+        ],
+        change_keys(&res)
+    );
+    assert!(matches!(
+        stg.get_bundle(1, "bundle1"),
+        Err(StorageError::BundleNotFound(_))
+    ));
+    assert!(journal(stg)?.is_empty());
+    assert!(matches!(
+        stg.get_food_list(),
+        Err(StorageError::EmptyResult)
+    ));
+
+    let res = stg.undo(1, 1);
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    Ok(())
+}
+
+fn test_undo_conflict(stg: &dyn Storage) -> Result<()> {
+    // Food of user 1 is used by user 2
+    stg.set_food(1, &change_food("key1", "name1"))?;
+    stg.set_weight(
+        1,
+        &Weight {
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            value: 80.0,
+        },
+    )?;
+    stg.set_journal(2, &change_journal(1, "key1", 100.0))?;
+
+    // Whole undo is rolled back
+    let res = stg.undo(1, 2);
+    assert!(matches!(res, Err(StorageError::UndoConflict)));
+    assert_eq!(change_food("key1", "name1"), stg.get_food("key1")?);
+    assert_eq!(
+        1,
+        stg.get_weight_list(
+            1,
+            Timestamp::from_unix_millis(0).unwrap(),
+            Timestamp::from_unix_millis(10).unwrap()
+        )?
+        .len()
+    );
+    assert_eq!(2, stg.get_change_list(1, 10)?.len());
+
+    // Undo of dependent change first
+    stg.undo(2, 1)?;
+    stg.undo(1, 2)?;
+    assert!(matches!(
+        stg.get_food("key1"),
+        Err(StorageError::FoodNotFound(_))
+    ));
+
+    Ok(())
+}
+
+fn test_undo_shared_conflict(stg: &dyn Storage) -> Result<()> {
+    // Food changed by user 1, then edited by user 2
+    stg.set_food(1, &change_food("key1", "name1"))?;
+    stg.set_food(1, &change_food("key1", "name2"))?;
+    stg.set_food(2, &change_food("key1", "name3"))?;
+
+    // Undo of user 1 would overwrite edit of user 2
+    let res = stg.undo(1, 1);
+    assert!(matches!(res, Err(StorageError::UndoConflict)));
+    assert_eq!(change_food("key1", "name3"), stg.get_food("key1")?);
+    assert_eq!(2, stg.get_change_list(1, 10)?.len());
+
+    // After undo of user 2 food is as user 1 left it
+    stg.undo(2, 1)?;
+    stg.undo(1, 1)?;
+    assert_eq!(change_food("key1", "name1"), stg.get_food("key1")?);
+
+    Ok(())
+}
+
+fn test_undo_schedule_keeps_last_run(stg: &dyn Storage) -> Result<()> {
+    let sc = |tz: &str| Schedule {
+        kind: ScheduleKind::DaySummary,
+        hour: 21,
+        minute: 0,
+        tz: tz.into(),
+        last_run: None,
+    };
+    stg.set_schedule(1, &sc("UTC"))?;
+    stg.set_schedule(1, &sc("Europe/Moscow"))?;
+    // Scheduler runs job after change
+    let last_run = Timestamp::from_unix_millis(1000).unwrap();
+    stg.set_schedule_last_run(1, &sc("Europe/Moscow"), last_run.clone())?;
+
+    // Undo doesn't make job run again
+    stg.undo(1, 1)?;
+    assert_eq!(
+        vec![Schedule {
+            last_run: Some(last_run),
+            ..sc("UTC")
+        }],
+        stg.get_schedule_list(1)?
+    );
+
+    Ok(())
+}
+
+//
+// Audit log
+//
+
+fn test_get_audit_list(stg: &dyn Storage) -> Result<()> {
+    let from = Timestamp::from_unix_millis(0).unwrap();
+    let res = stg.get_audit_list(from.clone(), Timestamp::now());
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    // Shared food changed by both users
+    stg.set_food(1, &change_food("key1", "name1"))?;
+    stg.set_food(2, &change_food("key1", "name2"))?;
+    // Nothing changed, not recorded
+    stg.set_food(2, &change_food("key1", "name2"))?;
+    stg.delete_food(2, "key1")?;
+    stg.undo(2, 1)?;
+
+    let res = stg.get_audit_list(from.clone(), Timestamp::now())?;
+    assert_eq!(
+        vec![
+            (1, AuditAction::Set, false, true),
+            (2, AuditAction::Set, true, true),
+            (2, AuditAction::Delete, true, false),
+            (2, AuditAction::Undo, false, true),
+        ],
+        res.iter()
+            .map(|e| (
+                e.user_id,
+                e.action,
+                e.old_value.is_some(),
+                e.new_value.is_some()
+            ))
+            .collect::<Vec<_>>()
+    );
+    assert!(res.iter().all(|e| e.entity == "food" && e.key == "key1"));
+    assert!(res[1].old_value.as_ref().unwrap().contains("name1"));
+    assert!(res[1].new_value.as_ref().unwrap().contains("name2"));
+    assert!(res[3].new_value.as_ref().unwrap().contains("name2"));
+
+    // Entry without key
+    stg.set_weight(
+        1,
+        &Weight {
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            value: 80.0,
+        },
+    )?;
+    let res = stg.get_audit_list(from.clone(), Timestamp::now())?;
+    assert_eq!(5, res.len());
+    assert_eq!(
+        ("weight", ""),
+        (res[4].entity.as_str(), res[4].key.as_str())
+    );
+
+    // Out of period
+    let res = stg.get_audit_list(from.clone(), from);
+    assert!(matches!(res, Err(StorageError::EmptyResult)));
+
+    Ok(())
+}
+
+// Both storages record bundle as bundle row and its item rows
+fn test_audit_bundle_rows(stg: &dyn Storage) -> Result<()> {
+    stg.set_food(1, &change_food("food1", "name1"))?;
+    stg.set_bundle(
+        1,
+        &Bundle {
+            key: "child".into(),
+            data: HashMap::from([("food1".into(), 1.5)]),
+        },
+    )?;
+    stg.set_bundle(
+        1,
+        &Bundle {
+            key: "parent".into(),
+            data: HashMap::from([("child".into(), 0.0), ("food1".into(), 2.0)]),
+        },
+    )?;
+    stg.delete_bundle(1, "parent")?;
+
+    // Item rows order is not defined
+    let rows = |value: &Option<String>| -> Result<serde_json::Value> {
+        let mut res: serde_json::Value = serde_json::from_str(value.as_deref().unwrap())?;
+        if let Some(items) = res["bundle_item"].as_array_mut() {
+            items.sort_by_key(|item| item.to_string());
+        }
+        Ok(res)
+    };
+    let expected = serde_json::json!({
+        "bundle": [{"user_id": 1, "key": "parent"}],
+        "bundle_item": [
+            {"user_id": 1, "bundle_key": "parent", "food_key": null, "child_key": "child", "weight": null},
+            {"user_id": 1, "bundle_key": "parent", "food_key": "food1", "child_key": null, "weight": 2.0},
+        ],
+    });
+
+    let res = stg.get_audit_list(Timestamp::from_unix_millis(0).unwrap(), Timestamp::now())?;
+    assert_eq!(4, res.len());
+    assert_eq!(expected, rows(&res[2].new_value)?);
+    assert_eq!(expected, rows(&res[3].old_value)?);
+    assert_eq!(None, res[3].new_value);
+
+    // Undo brings back bundle with its items
+    stg.undo(1, 1)?;
+    assert_eq!(
+        HashMap::from([("child".into(), 0.0), ("food1".into(), 2.0)]),
+        stg.get_bundle(1, "parent")?.data
+    );
+
+    Ok(())
+}
+
+//
+// Restore/backup
+//
+
+fn test_backup_restore(stg: &dyn Storage) -> Result<()> {
+    let backup = Backup {
+        timestamp: 1,
+        weight: vec![
+            WeightBackup {
+                timestamp: 1,
+                user_id: 1,
+                value: 1.1,
+            },
+            WeightBackup {
+                timestamp: 2,
+                user_id: 1,
+                value: 2.2,
+            },
+            WeightBackup {
+                timestamp: 3,
+                user_id: 1,
+                value: 3.3,
+            },
+            WeightBackup {
+                timestamp: 4,
+                user_id: 2,
+                value: 4.4,
+            },
+        ],
+        food: vec![
+            FoodBackup {
+                user_id: 1,
+                key: "key1".into(),
+                name: "Food 1".into(),
+                brand: "Brand 1".into(),
+                cal100: 1.1,
+                prot100: 2.2,
+                fat100: 3.3,
+                carb100: 4.4,
+                comment: "Comment1".into(),
+            },
+            FoodBackup {
+                user_id: 1,
+                key: "key2".into(),
+                name: "Food 2".into(),
+                brand: "Brand2".into(),
+                cal100: 5.5,
+                prot100: 6.6,
+                fat100: 7.7,
+                carb100: 8.8,
+                comment: "Comment2".into(),
+            },
+            FoodBackup {
+                user_id: 1,
+                key: "key3".into(),
+                name: "Еда 3".into(),
+                brand: "Брэнд 3".into(),
+                cal100: 10.10,
+                prot100: 20.20,
+                fat100: 30.30,
+                carb100: 40.40,
+                comment: "Комментарий 3".into(),
+            },
+            FoodBackup {
+                user_id: 1,
+                key: "key4".into(),
+                name: "Еда 4".into(),
+                brand: "Брэнд 4".into(),
+                cal100: 100.100,
+                prot100: 200.200,
+                fat100: 300.300,
+                carb100: 400.400,
+                comment: "Комментарий 4".into(),
+            },
+        ],
+        user_settings: vec![
+            UserSettingsBackup {
+                user_id: 1,
+                cal_limit: 1.0,
+                sex: Some(0),
+                height: Some(180.0),
+                birth_date: Some(1),
+            },
+            UserSettingsBackup {
+                user_id: 2,
+                cal_limit: 2.0,
+                sex: None,
+                height: None,
+                birth_date: None,
+            },
+        ],
+        bundle: vec![
+            BundleBackup {
+                user_id: 1,
+                key: "bundle1".into(),
+                data: r#"{"key1":100.0}"#.into(),
+            },
+            BundleBackup {
+                user_id: 1,
+                key: "bundle2".into(),
+                data: r#"{"bundle1":0.0,"key2":100.0}"#.into(),
+            },
+        ],
+        journal: vec![
+            JournalBackup {
+                user_id: 1,
+                timestamp: 1,
+                meal: 1,
+                food_key: "key1".into(),
+                food_weight: 100.0,
+            },
+            JournalBackup {
+                user_id: 1,
+                timestamp: 1,
+                meal: 2,
+                food_key: "key2".into(),
+                food_weight: 200.0,
+            },
+        ],
+        sport: vec![
+            SportBackup {
+                user_id: 1,
+                key: "sport1".into(),
+                name: "Sport 1".into(),
+                comment: "Sport 1".into(),
+            },
+            SportBackup {
+                user_id: 1,
+                key: "sport2".into(),
+                name: "Sport 2".into(),
+                comment: "Sport 2".into(),
+            },
+            SportBackup {
+                user_id: 1,
+                key: "sport3".into(),
+                name: "Sport 3".into(),
+                comment: "Sport 3".into(),
+            },
+        ],
+        sport_activity: vec![
+            SportActivityBackup {
+                user_id: 1,
+                sport_key: "sport1".into(),
+                timestamp: 1,
+                sets: "[1,2,3]".into(),
+            },
+            SportActivityBackup {
+                user_id: 1,
+                sport_key: "sport2".into(),
+                timestamp: 1,
+                sets: "[4,5,6]".into(),
+            },
+            SportActivityBackup {
+                user_id: 2,
+                sport_key: "sport3".into(),
+                timestamp: 2,
+                sets: "[10]".into(),
+            },
+        ],
+        metric: vec![MetricBackup {
+            user_id: 1,
+            key: "bp".into(),
+            name: "Давление".into(),
+            unit: "мм рт. ст.".into(),
+            fields: r#"["sys","dia"]"#.into(),
+        }],
+        metric_value: vec![MetricValueBackup {
+            user_id: 1,
+            metric_key: "bp".into(),
+            timestamp: 1,
+            values: "[120.0,80.0]".into(),
+        }],
+        schedule: vec![ScheduleBackup {
+            user_id: 1,
+            kind: 2,
+            hour: 21,
+            minute: 0,
+            tz: "Europe/Moscow".into(),
+        }],
+        audit_log: vec![AuditLogBackup {
+            id: 1,
+            user_id: 2,
+            timestamp: 1,
+            entity: "food".into(),
+            key: "key1".into(),
+            action: 0,
+            old_value: None,
+            new_value: Some(r#"{"food":[{"key":"key1"}]}"#.into()),
+        }],
+    };
+
+    // Do restore
+    stg.restore(&backup)?;
+
+    // Check weight list for user 1
+    let res = stg.get_weight_list(
+        1,
+        Timestamp::from_unix_millis(0).unwrap(),
+        Timestamp::from_unix_millis(10).unwrap(),
+    )?;
+    assert_eq!(
+        vec![
+            Weight {
+                timestamp: Timestamp::from_unix_millis(1).unwrap(),
+                value: 1.1
+            },
+            Weight {
+                timestamp: Timestamp::from_unix_millis(2).unwrap(),
+                value: 2.2
+            },
+            Weight {
+                timestamp: Timestamp::from_unix_millis(3).unwrap(),
+                value: 3.3
+            },
+        ],
+        res
+    );
+
+    // Check weight list for user 2
+    let res = stg.get_weight_list(
+        2,
+        Timestamp::from_unix_millis(0).unwrap(),
+        Timestamp::from_unix_millis(10).unwrap(),
+    )?;
+    assert_eq!(
+        vec![Weight {
+            timestamp: Timestamp::from_unix_millis(4).unwrap(),
+            value: 4.4
+        },],
+        res
+    );
+
+    // Check food
+    let res = stg.get_food_list()?;
+    assert_eq!(
+        vec![
+            Food {
+                key: "key1".into(),
+                name: "Food 1".into(),
+                brand: "Brand 1".into(),
+                cal100: 1.1,
+                prot100: 2.2,
+                fat100: 3.3,
+                carb100: 4.4,
+                comment: "Comment1".into(),
+            },
+            Food {
+                key: "key2".into(),
+                name: "Food 2".into(),
+                brand: "Brand2".into(),
+                cal100: 5.5,
+                prot100: 6.6,
+                fat100: 7.7,
+                carb100: 8.8,
+                comment: "Comment2".into(),
+            },
+            Food {
+                key: "key3".into(),
+                name: "Еда 3".into(),
+                brand: "Брэнд 3".into(),
+                cal100: 10.10,
+                prot100: 20.20,
+                fat100: 30.30,
+                carb100: 40.40,
+                comment: "Комментарий 3".into(),
+            },
+            Food {
+                key: "key4".into(),
+                name: "Еда 4".into(),
+                brand: "Брэнд 4".into(),
+                cal100: 100.100,
+                prot100: 200.200,
+                fat100: 300.300,
+                carb100: 400.400,
+                comment: "Комментарий 4".into(),
+            }
+        ],
+        res
+    );
+
+    // Check user settings
+    let res = stg.get_user_settings(1)?;
+    assert_eq!(1.0, res.cal_limit);
+    assert_eq!(Some(Sex::Male), res.sex);
+    assert_eq!(Some(180.0), res.height);
+
+    let res = stg.get_user_settings(2)?;
+    assert_eq!(2.0, res.cal_limit);
+
+    // Check bundles
+    let res = stg.get_bundle_list(1)?;
+    assert_eq!(
+        vec![
+            Bundle {
+                key: "bundle1".into(),
+                data: HashMap::from([("key1".into(), 100.0)]),
+            },
+            Bundle {
+                key: "bundle2".into(),
+                data: HashMap::from([("key2".into(), 100.0), ("bundle1".into(), 0.0)]),
+            },
+        ],
+        res
+    );
+
+    // Check journal
+    let res = stg.get_journal_report(
+        1,
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(1).unwrap(),
+    )?;
+    assert_eq!(
+        vec![
+            JournalReport {
+                timestamp: Timestamp::from_unix_millis(1).unwrap(),
+                meal: Meal::new_str("до обеда").unwrap(),
+                food_key: "key1".into(),
+                food_name: "Food 1".into(),
+                food_brand: "Brand 1".into(),
+                food_weight: 100.0,
+                cal: 1.1,
+                prot: 2.2,
+                fat: 3.3,
+                carb: 4.4,
+            },
+            JournalReport {
+                timestamp: Timestamp::from_unix_millis(1).unwrap(),
+                meal: Meal::Dinner,
+                food_key: "key2".into(),
+                food_name: "Food 2".into(),
+                food_brand: "Brand2".into(),
+                food_weight: 200.0,
+                cal: 11.0,
+                prot: 13.2,
+                fat: 15.4,
+                carb: 17.6
+            },
+        ],
+        res
+    );
+
+    // Check sport
+    let res = stg.get_sport_list()?;
+    assert_eq!(
+        vec![
+            Sport {
+                key: "sport1".into(),
+                name: "Sport 1".into(),
+                comment: "Sport 1".into(),
+            },
+            Sport {
+                key: "sport2".into(),
+                name: "Sport 2".into(),
+                comment: "Sport 2".into(),
+            },
+            Sport {
+                key: "sport3".into(),
+                name: "Sport 3".into(),
+                comment: "Sport 3".into(),
+            }
+        ],
+        res
+    );
+
+    // Check sport activity
+    let res = stg.get_sport_activity_report(
+        1,
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(2).unwrap(),
+    )?;
+    assert_eq!(
+        vec![
+            SportActivityReport {
+                timestamp: Timestamp::from_unix_millis(1).unwrap(),
+                sport_name: "Sport 1".into(),
+                sets: vec![1, 2, 3],
+            },
+            SportActivityReport {
+                timestamp: Timestamp::from_unix_millis(1).unwrap(),
+                sport_name: "Sport 2".into(),
+                sets: vec![4, 5, 6],
+            }
+        ],
+        res
+    );
+    let res = stg.get_sport_activity_report(
+        2,
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(2).unwrap(),
+    )?;
+    assert_eq!(
+        vec![SportActivityReport {
+            timestamp: Timestamp::from_unix_millis(2).unwrap(),
+            sport_name: "Sport 3".into(),
+            sets: vec![10],
+        },],
+        res
+    );
+
+    // Check metric
+    let res = stg.get_metric_value_list(
+        1,
+        "bp",
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(1).unwrap(),
+    )?;
+    assert_eq!(
+        vec![MetricValue {
+            metric_key: "bp".into(),
+            timestamp: Timestamp::from_unix_millis(1).unwrap(),
+            values: vec![120.0, 80.0],
+        }],
+        res
+    );
+
+    // Check schedule
+    let res = stg.get_schedule_list(1)?;
+    assert_eq!(
+        vec![Schedule {
+            kind: ScheduleKind::DaySummary,
+            hour: 21,
+            minute: 0,
+            tz: "Europe/Moscow".into(),
+            last_run: None,
+        }],
+        res
+    );
+
+    // Check backup
+    let backup2 = stg.backup(1)?;
+    assert_eq!(backup.food, backup2.food);
+    assert_eq!(backup.weight, backup2.weight);
+    assert_eq!(backup.user_settings, backup2.user_settings);
+    assert_eq!(backup.bundle, backup2.bundle);
+    assert_eq!(backup.journal, backup2.journal);
+    assert_eq!(backup.sport, backup2.sport);
+    assert_eq!(backup.sport_activity, backup2.sport_activity);
+    assert_eq!(backup.metric, backup2.metric);
+    assert_eq!(backup.metric_value, backup2.metric_value);
+    assert_eq!(backup.schedule, backup2.schedule);
+    assert_eq!(backup.audit_log[..], backup2.audit_log[..1]);
+
+    // Restore itself is recorded
+    let restore_entry = &backup2.audit_log[1];
+    assert_eq!(
+        (SYSTEM_USER_ID, "backup", u8::from(AuditAction::Restore)),
+        (
+            restore_entry.user_id,
+            restore_entry.entity.as_str(),
+            restore_entry.action
+        )
+    );
+    assert!(restore_entry
+        .new_value
+        .as_ref()
+        .unwrap()
+        .contains(r#""food":4"#));
+
+    // Entries of backup colliding by id with existing ones are kept under new ids,
+    // entries already present are not duplicated
+    let mut backup3 = stg.backup(1)?;
+    backup3.audit_log[0].key = "key2".into();
+    stg.restore(&backup3)?;
+    stg.restore(&backup3)?;
+    let entry = |key: &str| AuditEntry {
+        timestamp: Timestamp::from_unix_millis(1).unwrap(),
+        user_id: 2,
+        entity: "food".into(),
+        key: key.into(),
+        action: AuditAction::Set,
+        old_value: None,
+        new_value: Some(r#"{"food":[{"key":"key1"}]}"#.into()),
+    };
+    let res = stg.get_audit_list(
+        Timestamp::from_unix_millis(1).unwrap(),
+        Timestamp::from_unix_millis(1).unwrap(),
+    )?;
+    assert_eq!(vec![entry("key1"), entry("key2")], res);
+
+    let res = stg.get_audit_list(Timestamp::from_unix_millis(2).unwrap(), Timestamp::now())?;
+    assert_eq!(
+        vec![AuditAction::Restore; 3],
+        res.iter().map(|e| e.action).collect::<Vec<_>>()
+    );
+
+    Ok(())
+}
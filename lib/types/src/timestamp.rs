@@ -1,6 +1,6 @@
 use anyhow::{anyhow, Context, Result};
 
-use chrono::{DateTime, Duration, FixedOffset, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDateTime, NaiveTime, TimeZone, Utc};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Timestamp(DateTime<FixedOffset>);
@@ -26,6 +26,14 @@ impl Timestamp {
             .map(|v| v.fixed_offset().into())
     }
 
+    pub fn parse_datetime<TZ: TimeZone>(input: &str, format: &str, tz: TZ) -> Result<Self> {
+        let dt = NaiveDateTime::parse_from_str(input, format).context("parse naive datetime")?;
+        tz.from_local_datetime(&dt)
+            .single()
+            .ok_or(anyhow!("bad datetime"))
+            .map(|v| v.fixed_offset().into())
+    }
+
     pub fn unix_millis(&self) -> i64 {
         self.0.timestamp_millis()
     }
@@ -42,9 +50,45 @@ impl Timestamp {
         self.0.with_time(NaiveTime::MIN).unwrap().into()
     }
 
+    pub fn end_of_day(&self) -> Self {
+        self.start_of_day()
+            .add(Duration::days(1))
+            .sub(Duration::milliseconds(1))
+    }
+
+    pub fn start_of_week(&self) -> Self {
+        let days = self.0.weekday().num_days_from_monday();
+        self.start_of_day().sub(Duration::days(days as i64))
+    }
+
+    pub fn start_of_month(&self) -> Self {
+        let days = self.0.day0();
+        self.start_of_day().sub(Duration::days(days as i64))
+    }
+
+    pub fn end_of_month(&self) -> Self {
+        // Any month is shorter than 32 days
+        self.start_of_month()
+            .add(Duration::days(32))
+            .start_of_month()
+            .sub(Duration::milliseconds(1))
+    }
+
+    pub fn full_years_until(&self, to: &Timestamp) -> Option<u32> {
+        to.0.date_naive().years_since(self.0.date_naive())
+    }
+
     pub fn sub(&self, dt: Duration) -> Self {
         (self.0 - dt).into()
     }
+
+    pub fn add(&self, dt: Duration) -> Self {
+        (self.0 + dt).into()
+    }
+
+    pub fn checked_add(&self, dt: Duration) -> Option<Self> {
+        self.0.checked_add_signed(dt).map(Into::into)
+    }
 }
 
 impl From<DateTime<FixedOffset>> for Timestamp {
@@ -95,4 +139,92 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_parse_datetime() -> Result<()> {
+        assert_eq!(
+            1734773400000,
+            Timestamp::parse_datetime(
+                "21.12.2024 12:30",
+                "%d.%m.%Y %H:%M",
+                chrono_tz::Europe::Moscow
+            )?
+            .unix_millis()
+        );
+        assert!(Timestamp::parse_datetime("21.12.2024", "%d.%m.%Y %H:%M", chrono_tz::UTC).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_end_of_day() -> Result<()> {
+        let ts1 = Timestamp::parse_date("21.12.2024", "%d.%m.%Y", chrono_tz::Europe::Moscow)?;
+
+        assert_eq!(ts1.add(Duration::days(1)).unix_millis(), 1734814800000);
+        assert_eq!(ts1.end_of_day().unix_millis(), 1734814799999);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_week_month() -> Result<()> {
+        let ts = Timestamp::parse_datetime(
+            "19.02.2024 12:30",
+            "%d.%m.%Y %H:%M",
+            chrono_tz::Europe::Moscow,
+        )?;
+
+        assert_eq!(
+            "19.02.2024 00:00",
+            ts.start_of_week().format("%d.%m.%Y %H:%M")
+        );
+        assert_eq!(
+            "12.02.2024 00:00",
+            ts.sub(Duration::days(1))
+                .start_of_week()
+                .format("%d.%m.%Y %H:%M")
+        );
+        assert_eq!(
+            "01.02.2024 00:00",
+            ts.start_of_month().format("%d.%m.%Y %H:%M")
+        );
+        assert_eq!(
+            "29.02.2024 23:59:59",
+            ts.end_of_month().format("%d.%m.%Y %H:%M:%S")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_full_years_until() -> Result<()> {
+        let birth = Timestamp::parse_date("21.12.1990", "%d.%m.%Y", chrono_tz::UTC)?;
+
+        assert_eq!(
+            Some(33),
+            birth.full_years_until(&Timestamp::parse_date(
+                "20.12.2024",
+                "%d.%m.%Y",
+                chrono_tz::UTC
+            )?)
+        );
+        assert_eq!(
+            Some(34),
+            birth.full_years_until(&Timestamp::parse_date(
+                "21.12.2024",
+                "%d.%m.%Y",
+                chrono_tz::UTC
+            )?)
+        );
+        assert_eq!(
+            None,
+            birth.full_years_until(&Timestamp::parse_date(
+                "21.12.1980",
+                "%d.%m.%Y",
+                chrono_tz::UTC
+            )?)
+        );
+
+        Ok(())
+    }
 }
@@ -2,13 +2,15 @@ use std::{fs, io::Read, path::Path, sync::Arc};
 
 use env_logger::{Builder, Env};
 use flate2::read::GzDecoder;
+use html::AssetMode;
 use model::backup::Backup;
-use storage::{storage_sqlite::StorageSqlite, Storage};
+use storage::{storage_async::StorageAsync, storage_sqlite::StorageSqlite, Storage};
 use teloxide::prelude::*;
 
 use super::args::ArgsCli;
 use super::cmd;
 use super::config::Config;
+use super::scheduler;
 use anyhow::{Context, Result};
 use chrono_tz::Tz;
 
@@ -72,6 +74,15 @@ impl service::Service for App {
 
         log::info!("starting MyHealth bot...");
 
+        let mut asset_mode = self.config.asset_mode;
+        if asset_mode == AssetMode::Inline && !html::assets_embedded() {
+            log::warn!(
+                "HTML assets are not embedded (no `embed-assets` feature), linking from CDN"
+            );
+            asset_mode = AssetMode::Cdn;
+        }
+        html::set_asset_mode(asset_mode);
+
         let bot = Bot::new(self.config.token.clone());
 
         let handler = dptree::entry().branch(
@@ -83,16 +94,16 @@ impl service::Service for App {
         let tz: Tz = self.config.tz.parse().context("tz parse")?;
 
         // Init storage
-        let stg: Arc<Box<dyn Storage>> = Arc::new(Box::new(
-            StorageSqlite::new(Path::new(&self.config.db_file_path))
-                .context("new sqlite storage")?,
-        ));
+        let sqlite = StorageSqlite::new(Path::new(&self.config.db_file_path))
+            .context("new sqlite storage")?;
 
         if let Some(backup) = self.try_get_backup().context("try get backup")? {
             log::info!("found backup, restoring...");
-            stg.restore(&backup).context("storage backup")?;
+            sqlite.restore(&backup).context("storage backup")?;
         }
 
+        let stg = StorageAsync::new(Box::new(sqlite));
+
         // Init runtime
 
         let runtime = tokio::runtime::Builder::new_multi_thread()
@@ -101,6 +112,12 @@ impl service::Service for App {
             .context("build tokio runtime")?;
 
         runtime.block_on(async {
+            tokio::spawn(scheduler::run(
+                bot.clone(),
+                stg.clone(),
+                self.config.allowed_user_ids.clone(),
+            ));
+
             Dispatcher::builder(bot, handler)
                 .dependencies(dptree::deps![
                     stg.clone(),
@@ -17,4 +17,11 @@ pub struct ArgsCli {
 
     #[arg(short = 'b', action, help = "Debug mode")]
     pub debug: bool,
+
+    #[arg(
+        short = 'c',
+        action,
+        help = "Link CSS/JS assets from CDN instead of inlining into HTML reports"
+    )]
+    pub cdn_assets: bool,
 }
@@ -1,131 +1,139 @@
 mod bundle;
 mod cal_calc;
+mod calendar;
+mod compare;
+pub mod digest;
 mod food;
+mod food_stats;
 mod journal;
 mod maintenance;
+mod metric;
+mod registry;
+mod schedule;
 mod sport;
+pub mod summary;
+mod tdee;
 mod user_settings;
 mod weight;
 
+#[cfg(test)]
+mod test;
+
 use super::messages;
+use chart::{render::render_png, ChartData};
 use chrono_tz::Tz;
+use registry::{Arg, ArgKind, Args, Command, Group, Registry};
 use std::sync::Arc;
-use storage::Storage;
+use storage::{storage_async::StorageAsync, StorageError};
 use teloxide::prelude::*;
 use types::timestamp::Timestamp;
 
-use crate::HandlerResult;
+use crate::{
+    output::{Output, TelegramSink},
+    HandlerResult,
+};
+
+// Handler context, user of the message being processed and replies to its chat
+#[derive(Clone)]
+pub struct Ctx {
+    pub out: Output,
+    pub user_id: i64,
+    pub stg: StorageAsync,
+    pub tz: Tz,
+}
+
+const HELP_GROUP: Group = Group {
+    name: "h",
+    title: "Справка",
+    commands: &[Command {
+        name: "",
+        aliases: &[],
+        args: &[
+            Arg::opt("command", ArgKind::Key),
+            Arg::opt("subcommand", ArgKind::Key),
+        ],
+        description: "Список команд или справка по команде",
+        handler: |ctx, args| Box::pin(help(ctx, args)),
+    }],
+};
+
+static REGISTRY: Registry = Registry::new(&[
+    food::GROUP,
+    bundle::GROUP,
+    journal::GROUP,
+    user_settings::GROUP,
+    weight::GROUP,
+    sport::GROUP,
+    metric::GROUP,
+    schedule::GROUP,
+    digest::GROUP,
+    calendar::GROUP,
+    compare::GROUP,
+    cal_calc::GROUP,
+    tdee::GROUP,
+    maintenance::GROUP,
+    HELP_GROUP,
+]);
 
 pub async fn process_command(
     bot: Bot,
     msg: Message,
-    stg: Arc<Box<dyn Storage>>,
+    stg: StorageAsync,
     tz: Tz,
     debug: bool,
 ) -> HandlerResult {
     // Get user_id (unwrap - because we filtered message before and there should be a user)
     let user_id = msg.from.clone().unwrap().id.0 as i64;
+    let out = Output::new(Arc::new(TelegramSink::new(bot)), msg.chat.id);
 
     if debug {
-        bot.send_message(msg.chat.id, messages::DEBUG_MODE).await?;
+        out.text(messages::DEBUG_MODE).await?;
     }
 
-    match msg.text() {
-        None => {
-            bot.send_message(msg.chat.id, messages::ERR_WRONG_COMMAND)
-                .await?;
+    let Some(input) = msg.text() else {
+        out.text(messages::ERR_WRONG_COMMAND).await?;
+        return Ok(());
+    };
+
+    execute(
+        Ctx {
+            out,
+            user_id,
+            stg,
+            tz,
+        },
+        input,
+    )
+    .await
+}
+
+// Resolves command by message text and runs its handler
+pub async fn execute(ctx: Ctx, input: &str) -> HandlerResult {
+    let parts: Vec<&str> = input.split(",").map(|v| v.trim()).collect();
+    match REGISTRY.resolve(&parts, ctx.tz) {
+        Ok((handler, args)) => handler(ctx, args).await?,
+        Err(text) => {
+            log::error!("wrong command: {input}");
+            ctx.out.html(text).await?;
         }
-        Some(input) => {
-            let parts: Vec<&str> = input.split(",").map(|v| v.trim()).collect();
-
-            if parts.is_empty() {
-                log::error!("empty command");
-                bot.send_message(msg.chat.id, messages::ERR_WRONG_COMMAND)
-                    .await?;
-            } else {
-                match parts[0] {
-                    "b" => {
-                        bundle::process_bundle_command(
-                            bot,
-                            user_id,
-                            msg.chat.id,
-                            parts[1..].to_vec(),
-                            stg,
-                        )
-                        .await?;
-                    }
-                    "f" => {
-                        food::process_food_command(bot, msg.chat.id, parts[1..].to_vec(), stg)
-                            .await?;
-                    }
-                    "j" => {
-                        journal::process_journal_command(
-                            bot,
-                            user_id,
-                            msg.chat.id,
-                            parts[1..].to_vec(),
-                            stg,
-                            tz,
-                        )
-                        .await?;
-                    }
-                    "u" => {
-                        user_settings::process_user_settings_command(
-                            bot,
-                            user_id,
-                            msg.chat.id,
-                            parts[1..].to_vec(),
-                            stg,
-                        )
-                        .await?;
-                    }
-                    "w" => {
-                        weight::process_weight_command(
-                            bot,
-                            user_id,
-                            msg.chat.id,
-                            parts[1..].to_vec(),
-                            stg,
-                            tz,
-                        )
-                        .await?;
-                    }
-                    "s" => {
-                        sport::process_sport_command(
-                            bot,
-                            user_id,
-                            msg.chat.id,
-                            parts[1..].to_vec(),
-                            stg,
-                            tz,
-                        )
-                        .await?;
-                    }
-                    "cc" => {
-                        cal_calc::process_cal_calc_command(bot, msg.chat.id, parts[1..].to_vec())
-                            .await?;
-                    }
-                    "m" => {
-                        maintenance::process_maintenance(
-                            bot,
-                            user_id,
-                            msg.chat.id,
-                            parts[1..].to_vec(),
-                            stg,
-                            tz,
-                        )
-                        .await?;
-                    }
-                    _ => {
-                        log::error!("unknown command");
-                        bot.send_message(msg.chat.id, messages::ERR_WRONG_COMMAND)
-                            .await?;
-                    }
-                }
-            }
+    };
+
+    Ok(())
+}
+
+async fn help(ctx: Ctx, args: Args) -> HandlerResult {
+    let text = match args.opt::<String>("command") {
+        None => REGISTRY.help(),
+        Some(cmd) => {
+            let sub: Option<String> = args.opt("subcommand");
+            REGISTRY
+                .command_help(&cmd, sub.as_deref())
+                .unwrap_or_else(|| messages::ERR_UNKNOWN_COMMAND.into())
         }
     };
 
+    ctx.out.html(text).await?;
+
     Ok(())
 }
 
@@ -137,6 +145,74 @@ pub fn parse_timestamp(input: &str, tz: Tz) -> anyhow::Result<Timestamp> {
     }
 }
 
+pub fn parse_datetime(input: &str, tz: Tz) -> anyhow::Result<Timestamp> {
+    if input.is_empty() {
+        Ok(Timestamp::now().with_timezone(tz))
+    } else if input.contains(' ') {
+        Timestamp::parse_datetime(input, "%d.%m.%Y %H:%M", tz)
+    } else {
+        Timestamp::parse_date(input, "%d.%m.%Y", tz)
+    }
+}
+
 pub fn format_timestamp(ts: &Timestamp, format: &str, tz: Tz) -> String {
     ts.with_timezone(tz).format(format)
 }
+
+// Empty list instead of EmptyResult error, for reports combining several sources
+pub fn or_empty<T>(res: storage::Result<Vec<T>>) -> storage::Result<Vec<T>> {
+    match res {
+        Err(StorageError::EmptyResult) => Ok(Vec::new()),
+        v => v,
+    }
+}
+
+// Message to user for storage error, internal details are only logged
+pub fn storage_error_message(err: &StorageError) -> String {
+    use messages::*;
+
+    match err {
+        StorageError::EmptyResult => ERR_EMPTY.into(),
+        StorageError::WeightInvalid
+        | StorageError::FoodInvalid
+        | StorageError::SportInvalid
+        | StorageError::SportActivityInvalid
+        | StorageError::UserSettingsInvalid
+        | StorageError::BundleInvalid
+        | StorageError::JournalInvalid
+        | StorageError::MetricInvalid
+        | StorageError::MetricValueInvalid
+        | StorageError::ScheduleInvalid => ERR_WRONG_COMMAND.into(),
+        StorageError::FoodNotFound(key) => format!("{ERR_FOOD_NOT_FOUND}: {key}"),
+        StorageError::FoodIsUsed(key) => format!("{ERR_FOOD_IS_USED}: {key}"),
+        StorageError::SportNotFound(key) => format!("{ERR_SPORT_NOT_FOUND}: {key}"),
+        StorageError::SportIsUsed(key) => format!("{ERR_SPORT_IS_USED}: {key}"),
+        StorageError::UserSettingsNotFound => ERR_USER_SETTINGS_NOT_FOUND.into(),
+        StorageError::BundleNotFound(key) => format!("{ERR_BUNDLE_NOT_FOUND}: {key}"),
+        StorageError::BundleIsUsed { key, used_in } => {
+            format!("{ERR_BUNDLE_IS_USED}: {key} -> {used_in}")
+        }
+        StorageError::BundleDepBundleNotFound(key) => {
+            format!("{ERR_DEP_BUNDLE_NOT_FOUND}: {key}")
+        }
+        StorageError::BundleDepFoodNotFound(key) => format!("{ERR_DEP_FOOD_NOT_FOUND}: {key}"),
+        StorageError::BundleDepRecursive(key) => format!("{ERR_DEP_BUNDLE_RECURSIVE}: {key}"),
+        StorageError::MetricNotFound(key) => format!("{ERR_METRIC_NOT_FOUND}: {key}"),
+        StorageError::MetricIsUsed(key) => format!("{ERR_METRIC_IS_USED}: {key}"),
+        StorageError::UndoConflict => ERR_UNDO_CONFLICT.into(),
+        StorageError::Internal(_) => ERR_INTERNAL.into(),
+    }
+}
+
+// Sends chart rendered to PNG as photo, render errors are only logged
+// because chart is an addition to the main report. Rendering is CPU bound,
+// so it runs off the executor like storage calls.
+pub async fn send_chart_photo(out: &Output, data: ChartData, file_name: &str) -> HandlerResult {
+    match tokio::task::spawn_blocking(move || render_png(&data)).await {
+        Ok(Ok(png)) => out.photo(file_name, png).await?,
+        Ok(Err(err)) => log::error!("render chart error: {err}"),
+        Err(err) => log::error!("render chart task error: {err}"),
+    };
+
+    Ok(())
+}
@@ -7,144 +7,91 @@ use html::{
     table::{Table, Td, Tr},
 };
 use model::Bundle;
-use std::{collections::HashMap, sync::Arc};
-use storage::{Storage, StorageError};
-use teloxide::{prelude::*, types::InputFile};
+use std::collections::HashMap;
 
-use crate::{
-    messages::{
-        ERR_BUNDLE_IS_USED, ERR_BUNDLE_NOT_FOUND, ERR_DEP_BUNDLE_NOT_FOUND,
-        ERR_DEP_BUNDLE_RECURSIVE, ERR_DEP_FOOD_NOT_FOUND, ERR_EMPTY, ERR_INTERNAL,
-        ERR_WRONG_COMMAND, OK,
-    },
-    HandlerResult,
-};
+use crate::{messages::OK, HandlerResult};
 
-pub async fn process_bundle_command(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-) -> HandlerResult {
-    if args.is_empty() {
-        log::error!("empty args");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
-
-    match *args.first().unwrap() {
-        "set" => {
-            bundle_set(bot, user_id, chat_id, args[1..].to_vec(), stg).await?;
-        }
-        "st" => {
-            bundle_set_template(bot, user_id, chat_id, args[1..].to_vec(), stg).await?;
-        }
-        "list" => {
-            bundle_list(bot, user_id, chat_id, stg).await?;
-        }
-        "del" => {
-            bundle_del(bot, user_id, chat_id, args[1..].to_vec(), stg).await?;
-        }
-        _ => {
-            log::error!("unknown command");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        }
-    };
-
-    Ok(())
-}
-
-async fn bundle_set(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-) -> HandlerResult {
-    if args.len() < 2 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
-
-    let key = args.first().unwrap().to_string();
-    let mut data = HashMap::with_capacity(args.len() - 1);
+use super::{
+    registry::{Arg, ArgKind, Args, Command, Group},
+    storage_error_message, Ctx,
+};
 
-    for i in 1..args.len() {
-        let arg = args.get(i).unwrap();
-        if arg.contains(":") {
-            // Add dependant food
-            let parts: Vec<&str> = arg.split(":").map(|v| v.trim()).collect();
-            if parts.len() != 2 {
-                log::error!("wrong bundle dep food args count");
-                bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-                return Ok(());
-            }
+pub const GROUP: Group = Group {
+    name: "b",
+    title: "Бандлы",
+    commands: &[
+        Command {
+            name: "set",
+            aliases: &[],
+            args: &[
+                Arg::req("key", ArgKind::Key),
+                Arg::many("items", ArgKind::BundleItem),
+            ],
+            description: "Установить бандл",
+            handler: |ctx, args| Box::pin(bundle_set(ctx, args)),
+        },
+        Command {
+            name: "st",
+            aliases: &[],
+            args: &[Arg::req("key", ArgKind::Key)],
+            description: "Шаблон команды установки бандла",
+            handler: |ctx, args| Box::pin(bundle_set_template(ctx, args)),
+        },
+        Command {
+            name: "list",
+            aliases: &[],
+            args: &[],
+            description: "Список бандлов",
+            handler: |ctx, _| Box::pin(bundle_list(ctx)),
+        },
+        Command {
+            name: "del",
+            aliases: &[],
+            args: &[Arg::req("key", ArgKind::Key)],
+            description: "Удалить бандл",
+            handler: |ctx, args| Box::pin(bundle_del(ctx, args)),
+        },
+    ],
+};
 
-            let weight = match parts.get(1).unwrap().parse::<f64>() {
-                Ok(v) => v,
-                Err(err) => {
-                    log::error!("parse bundle dep food weight error: {err}");
-                    bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-                    return Ok(());
-                }
-            };
+async fn bundle_set(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
 
-            data.insert(parts.first().unwrap().to_string(), weight);
-        } else {
-            // Add dependant bundle
-            data.insert(arg.to_string(), 0.0);
-        }
-    }
+    let key: String = args.get("key");
+    let data: HashMap<String, f64> = args.many("items").into_iter().collect();
 
     // Call storage
-    match stg.set_bundle(user_id, &Bundle { key, data }) {
+    match stg
+        .call(move |s| s.set_bundle(user_id, &Bundle { key, data }))
+        .await
+    {
         Ok(_) => {
-            bot.send_message(chat_id, OK).await?;
+            out.text(OK).await?;
         }
         Err(err) => {
             log::error!("set bundle error: {err}");
-            if stg.is_storage_error(StorageError::BundleInvalid, &err) {
-                bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            } else if stg.is_storage_error(StorageError::BundleDepBundleNotFound, &err) {
-                bot.send_message(chat_id, ERR_DEP_BUNDLE_NOT_FOUND).await?;
-            } else if stg.is_storage_error(StorageError::BundleDepFoodNotFound, &err) {
-                bot.send_message(chat_id, ERR_DEP_FOOD_NOT_FOUND).await?;
-            } else if stg.is_storage_error(StorageError::BundleDepRecursive, &err) {
-                bot.send_message(chat_id, ERR_DEP_BUNDLE_RECURSIVE).await?;
-            } else {
-                bot.send_message(chat_id, ERR_INTERNAL).await?;
-            }
+            out.text(storage_error_message(&err)).await?;
         }
     };
 
     Ok(())
 }
 
-async fn bundle_set_template(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-) -> HandlerResult {
-    if args.len() != 1 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn bundle_set_template(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
+
+    let key: String = args.get("key");
 
     // Call storage
-    let bndl = match stg.get_bundle(user_id, args.first().unwrap()) {
+    let bndl = match stg.call(move |s| s.get_bundle(user_id, &key)).await {
         Ok(v) => v,
         Err(err) => {
             log::error!("get bundle error: {err}");
-            if stg.is_storage_error(StorageError::BundleNotFound, &err) {
-                bot.send_message(chat_id, ERR_BUNDLE_NOT_FOUND).await?;
-            } else {
-                bot.send_message(chat_id, ERR_INTERNAL).await?;
-            }
+            out.text(storage_error_message(&err)).await?;
             return Ok(());
         }
     };
@@ -159,26 +106,21 @@ async fn bundle_set_template(
         }
     }
 
-    bot.send_message(chat_id, res).await?;
+    out.text(res).await?;
 
     Ok(())
 }
 
-async fn bundle_list(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    stg: Arc<Box<dyn Storage>>,
-) -> HandlerResult {
+async fn bundle_list(ctx: Ctx) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
+
     // Call storage
-    let b_lst = match stg.get_bundle_list(user_id) {
+    let b_lst = match stg.call(move |s| s.get_bundle_list(user_id)).await {
         Err(err) => {
             log::error!("bundle list error: {err}");
-            if stg.is_storage_error(StorageError::EmptyResult, &err) {
-                bot.send_message(chat_id, ERR_EMPTY).await?;
-            } else {
-                bot.send_message(chat_id, ERR_INTERNAL).await?;
-            }
+            out.text(storage_error_message(&err)).await?;
             return Ok(());
         }
         Ok(lst) => lst,
@@ -223,40 +165,26 @@ async fn bundle_list(
             .as_box(),
     );
 
-    bot.send_document(
-        chat_id,
-        InputFile::memory(doc.build()).file_name("bundles.html"),
-    )
-    .await?;
+    out.document("bundles.html", doc.build()).await?;
 
     Ok(())
 }
 
-async fn bundle_del(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-) -> HandlerResult {
-    if args.len() != 1 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn bundle_del(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
+
+    let key: String = args.get("key");
 
     // Call storage
-    if let Err(err) = stg.delete_bundle(user_id, args.first().unwrap()) {
+    if let Err(err) = stg.call(move |s| s.delete_bundle(user_id, &key)).await {
         log::error!("del bundle error: {err}");
-        if stg.is_storage_error(StorageError::BundleIsUsed, &err) {
-            bot.send_message(chat_id, ERR_BUNDLE_IS_USED).await?;
-        } else {
-            bot.send_message(chat_id, ERR_INTERNAL).await?;
-        }
+        out.text(storage_error_message(&err)).await?;
         return Ok(());
     };
 
-    bot.send_message(chat_id, OK).await?;
+    out.text(OK).await?;
 
     Ok(())
 }
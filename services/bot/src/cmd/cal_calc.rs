@@ -1,83 +1,142 @@
-use teloxide::{
-    prelude::*,
-    types::{ChatId, ParseMode},
-    Bot,
+use analytics::bmr::{
+    apply_goal, harris_benedict, katch_mcardle, mifflin_st_jeor, Formula, ACTIVITY_LEVELS,
 };
+use storage::StorageError;
+use types::timestamp::Timestamp;
 
-use crate::{messages::ERR_WRONG_COMMAND, HandlerResult};
+use crate::{
+    messages::{ERR_BODY_FAT_REQUIRED, ERR_PROFILE_NOT_SET, ERR_WEIGHT_NOT_FOUND},
+    HandlerResult,
+};
+
+use super::{
+    registry::{Arg, ArgKind, Args, Command, Group},
+    storage_error_message, Ctx,
+};
+
+pub const GROUP: Group = Group {
+    name: "cc",
+    title: "Расчет калорий",
+    commands: &[Command {
+        name: "",
+        aliases: &[],
+        args: &[
+            Arg::opt("formula", ArgKind::Choice(&["msj", "hb", "km"])),
+            Arg::opt("goal", ArgKind::Float),
+            Arg::opt("body_fat", ArgKind::Float),
+        ],
+        description: "Расчет нормы калорий по формуле",
+        handler: |ctx, args| Box::pin(cal_calc(ctx, args)),
+    }],
+};
+
+async fn cal_calc(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
+
+    let formula = args
+        .opt::<String>("formula")
+        .and_then(|v| Formula::new(&v))
+        .unwrap_or(Formula::MifflinStJeor);
 
-pub async fn process_cal_calc_command(bot: Bot, chat_id: ChatId, args: Vec<&str>) -> HandlerResult {
-    if args.len() != 4 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
+    let goal: f64 = args.opt("goal").unwrap_or_default();
+    if goal <= -100.0 {
+        log::error!("wrong goal: {goal}");
+        out.html(args.wrong_arg("goal", "должно быть больше -100"))
+            .await?;
         return Ok(());
     }
 
-    let gender = args.first().unwrap();
-    if !(*gender == "m" || *gender == "f") {
-        log::error!("wrong gender");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
+    let body_fat: Option<f64> = args.opt("body_fat");
+    if body_fat.is_some_and(|v| v <= 0.0 || v >= 100.0) {
+        log::error!("wrong body fat: {body_fat:?}");
+        out.html(args.wrong_arg("body_fat", "должно быть от 0 до 100"))
+            .await?;
         return Ok(());
     }
 
-    let weight = match args.get(1).unwrap().parse::<f64>() {
+    if formula == Formula::KatchMcArdle && body_fat.is_none() {
+        out.text(ERR_BODY_FAT_REQUIRED).await?;
+        return Ok(());
+    }
+
+    // Call storage
+    let us = match stg.call(move |s| s.get_user_settings(user_id)).await {
         Ok(v) => v,
         Err(err) => {
-            log::error!("parse weight error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
+            log::error!("get user settings error: {err}");
+            out.text(storage_error_message(&err)).await?;
             return Ok(());
         }
     };
-    let height = match args.get(2).unwrap().parse::<f64>() {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse height error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
+
+    let now = Timestamp::now().with_timezone(tz);
+    let (Some(sex), Some(height), Some(age)) = (
+        us.sex,
+        us.height,
+        us.birth_date.and_then(|v| v.full_years_until(&now)),
+    ) else {
+        out.text(ERR_PROFILE_NOT_SET).await?;
+        return Ok(());
+    };
+
+    let weight = match stg
+        .call(move |s| s.get_last_weight(user_id, now.end_of_day()))
+        .await
+    {
+        Ok(w) => w.value,
+        Err(StorageError::EmptyResult) => {
+            out.text(ERR_WEIGHT_NOT_FOUND).await?;
             return Ok(());
         }
-    };
-    let age = match args.get(3).unwrap().parse::<f64>() {
-        Ok(v) => v,
         Err(err) => {
-            log::error!("parse age error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
+            log::error!("get last weight error: {err}");
+            out.text(storage_error_message(&err)).await?;
             return Ok(());
         }
     };
 
-    if weight <= 0.0 || height <= 0.0 || age <= 0.0 {
-        log::error!("error <= 0: weight={weight}, height={height}, age={age}");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+    let age = age as f64;
+    let bmr = match formula {
+        Formula::MifflinStJeor => mifflin_st_jeor(sex, weight, height, age),
+        Formula::HarrisBenedict => harris_benedict(sex, weight, height, age),
+        Formula::KatchMcArdle => katch_mcardle(weight, body_fat.unwrap()),
+    };
 
-    let mut ubm = 10.0 * weight + 6.25 * height - 5.0 * age;
-    if *gender == "m" {
-        ubm += 5.0
-    } else {
-        ubm -= 161.0
+    let mut res = String::new();
+    res.push_str(&format!("<b>Формула:</b> {}\n", formula.name()));
+    res.push_str(&format!(
+        "<b>Вес:</b> {weight} кг, <b>рост:</b> {height} см, <b>возраст:</b> {age}\n"
+    ));
+    if let Some(body_fat) = body_fat {
+        res.push_str(&format!("<b>Процент жира:</b> {body_fat}\n"));
+    }
+    if goal != 0.0 {
+        res.push_str(&format!("<b>Цель:</b> {goal:+}%\n"));
     }
+    res.push('\n');
 
-    let mut res = String::new();
     res.push_str("<b>Уровень Базального Метаболизма (УБМ)</b>\n");
-    res.push_str(&format!("{} ккал\n\n", ubm as i64));
+    res.push_str(&format!("{} ккал\n\n", bmr as i64));
 
     res.push_str("<b>Усредненные значения по активностям</b>\n\n");
-    for (name, k) in [
-        ("Сидячая активность", 1.2),
-        ("Легкая активность", 1.375),
-        ("Средняя активность", 1.55),
-        ("Полноценная активность", 1.725),
-        ("Супер активность", 1.9),
-    ] {
+    for (name, k) in ACTIVITY_LEVELS {
         res.push_str(&format!("<b>{}</b>\n", name));
-        let norm = (ubm * k) as i64;
-        res.push_str(&format!("ККал: {norm}\n\n"));
+        let norm = bmr * k;
+        res.push_str(&format!("ККал: {}\n", norm as i64));
+
+        let target = apply_goal(norm, goal).round() as i64;
+        if goal != 0.0 {
+            res.push_str(&format!("ККал с учетом цели: {target}\n"));
+        }
+        res.push_str(&format!("Установить: u,set,{target}\n\n"));
     }
 
-    bot.send_message(chat_id, res)
-        .parse_mode(ParseMode::Html)
-        .await?;
+    out.html(res).await?;
 
     Ok(())
 }
@@ -0,0 +1,296 @@
+use analytics::calendar::{month_calendar, CalendarDay};
+use anyhow::Result;
+use chart::{with_alpha, CHART_COLOR_BLUE, CHART_COLOR_GREEN, CHART_COLOR_RED};
+use chrono_tz::Tz;
+use html::{
+    a::A,
+    attrs::Attrs,
+    b::B,
+    div::Div,
+    h::H,
+    s::S,
+    script::Script,
+    span::Span,
+    table::{Table, Td, Tr},
+    Asset, Element,
+};
+use model::{JournalReport, Meal, SportActivityReport};
+use std::collections::BTreeSet;
+use storage::Storage;
+use types::timestamp::Timestamp;
+
+use crate::{
+    messages::{ERR_EMPTY, ERR_INTERNAL},
+    HandlerResult,
+};
+
+use super::{
+    digest::load_period,
+    format_timestamp,
+    registry::{Arg, ArgKind, Args, Command, Group},
+    Ctx,
+};
+
+const WEEKDAYS: [&str; 7] = ["Пн", "Вт", "Ср", "Чт", "Пт", "Сб", "Вс"];
+const MARK_WEIGHT: &str = "⚖";
+const MARK_WORKOUT: &str = "🏋";
+
+pub const GROUP: Group = Group {
+    name: "cal",
+    title: "Календарь",
+    commands: &[Command {
+        name: "",
+        aliases: &[],
+        args: &[Arg::opt("timestamp", ArgKind::Date)],
+        description: "Календарь соблюдения лимита за месяц, содержащий дату",
+        handler: |ctx, args| Box::pin(calendar(ctx, args)),
+    }],
+};
+
+async fn calendar(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
+
+    let timestamp: Timestamp = args.get("timestamp");
+
+    match stg
+        .call(move |s| build_calendar(user_id, s, &timestamp, tz))
+        .await
+    {
+        Ok(Some((file_name, doc))) => {
+            out.document(file_name, doc).await?;
+        }
+        Ok(None) => {
+            out.text(ERR_EMPTY).await?;
+        }
+        Err(err) => {
+            log::error!("build calendar error: {err}");
+            out.text(ERR_INTERNAL).await?;
+        }
+    };
+
+    Ok(())
+}
+
+// Builds calendar HTML document for month containing timestamp.
+// Returns file name and document, None if there is no data for month.
+fn build_calendar(
+    user_id: i64,
+    stg: &dyn Storage,
+    timestamp: &Timestamp,
+    tz: Tz,
+) -> Result<Option<(String, String)>> {
+    let month = timestamp.with_timezone(tz);
+    let (ts_from, ts_to) = (month.start_of_month(), month.end_of_month());
+
+    // Call storage
+    let pd = load_period(user_id, stg, &ts_from, &ts_to)?;
+    let (rep, weights, activities, cal_limit) =
+        (&pd.rep, &pd.weights, &pd.activities, pd.cal_limit);
+
+    if rep.is_empty() && weights.is_empty() && activities.is_empty() {
+        return Ok(None);
+    }
+
+    let weeks = month_calendar(&month, rep, weights, activities);
+    let month_str = format_timestamp(&month, "%m.%Y", tz);
+
+    // Generate HTML
+    let mut tbl = Table::new(WEEKDAYS.iter().map(|d| d.to_string()).collect());
+    for week in &weeks {
+        let mut tr = Tr::new();
+        for day in week {
+            tr = tr.add_td(day_cell(day, cal_limit, tz));
+        }
+        tbl.add_row(tr);
+    }
+
+    let mut legend = String::from("Число в ячейке - разница с лимитом, ккал. ");
+    if cal_limit.is_none() {
+        legend = String::from("Число в ячейке - потреблено, ккал. Лимит не установлен. ");
+    }
+    legend.push_str(&format!(
+        "{MARK_WEIGHT} - взвешивание, {MARK_WORKOUT} - тренировка."
+    ));
+
+    let mut container = Div::new_container()
+        .add_element(
+            H::new(&format!("Календарь за {}", month_str), 5)
+                .set_attr(Attrs::from_items(vec![("align", "center")].into_iter()))
+                .as_box(),
+        )
+        .add_element(tbl.as_box())
+        .add_element(
+            Div::new("small mb-3")
+                .add_element(S::create(&legend))
+                .as_box(),
+        );
+
+    // Day details
+    for day in weeks.iter().flatten().filter(|d| d.in_month && has_data(d)) {
+        container = container.add_element(day_details(day, rep, activities, cal_limit, tz));
+    }
+
+    let doc = html::Builder::new("Календарь")
+        .add_element(container.as_box())
+        .add_element(Script::create_asset(Asset::JsBootstrap));
+
+    Ok(Some((format!("calendar_{}.html", month_str), doc.build())))
+}
+
+fn has_data(day: &CalendarDay) -> bool {
+    day.cal.is_some() || day.weight.is_some() || day.workout
+}
+
+fn day_anchor(day: &CalendarDay, tz: Tz) -> String {
+    format!("day-{}", format_timestamp(&day.day, "%Y-%m-%d", tz))
+}
+
+// Green under the limit, red over it, more intense with bigger difference
+fn day_color(day: &CalendarDay, cal_limit: Option<f64>) -> Option<String> {
+    let cal = day.cal?;
+    let Some(cal_limit) = cal_limit else {
+        return Some(with_alpha(CHART_COLOR_BLUE, 0.4));
+    };
+
+    let alpha = 0.2 + 0.8 * day.deviation(cal_limit).unwrap_or_default();
+    let color = if cal <= cal_limit {
+        CHART_COLOR_GREEN
+    } else {
+        CHART_COLOR_RED
+    };
+
+    Some(with_alpha(color, (alpha * 100.0).round() / 100.0))
+}
+
+fn day_cell(day: &CalendarDay, cal_limit: Option<f64>, tz: Tz) -> Td {
+    let num = format_timestamp(&day.day, "%d", tz);
+    if !day.in_month {
+        return Td::new(S::create(&num)).set_attrs(Attrs::from_items(
+            vec![("class", "text-muted"), ("align", "center")].into_iter(),
+        ));
+    }
+
+    let mut elements: Vec<Box<dyn Element>> = vec![B::new(&num).as_box()];
+    if let Some(cal) = day.cal {
+        let val = match cal_limit.and_then(|v| day.diff(v)) {
+            Some(diff) => format!("{:+.0}", diff),
+            None => format!("{:.0}", cal),
+        };
+        elements.push(Div::new("small").add_element(S::create(&val)).as_box());
+    }
+
+    let mut marks: Vec<String> = Vec::new();
+    if let Some(w) = day.weight {
+        marks.push(format!("{MARK_WEIGHT} {:.1}", w));
+    }
+    if day.workout {
+        marks.push(MARK_WORKOUT.into());
+    }
+    if !marks.is_empty() {
+        elements.push(
+            Div::new("small")
+                .add_element(S::create(&marks.join(" ")))
+                .as_box(),
+        );
+    }
+
+    let content = if has_data(day) {
+        A::new(&format!("#{}", day_anchor(day, tz)), elements)
+            .set_attrs(Attrs::from_items(
+                vec![("class", "link-dark text-decoration-none")].into_iter(),
+            ))
+            .as_box()
+    } else {
+        Span::create(elements)
+    };
+
+    let mut style = String::from("width: 14%");
+    if let Some(color) = day_color(day, cal_limit) {
+        style.push_str(&format!("; background-color: {color}"));
+    }
+
+    Td::new(content).set_attrs(Attrs::from_items(
+        vec![("style", style.as_str()), ("align", "center")].into_iter(),
+    ))
+}
+
+fn day_details(
+    day: &CalendarDay,
+    rep: &[JournalReport],
+    activities: &[SportActivityReport],
+    cal_limit: Option<f64>,
+    tz: Tz,
+) -> Box<dyn Element> {
+    let (from, to) = (day.day.unix_millis(), day.day.end_of_day().unix_millis());
+    let in_day = |ts: &Timestamp| (from..=to).contains(&ts.unix_millis());
+
+    // Meal subtotals
+    let mut meals: Vec<(Meal, [f64; 4])> = Vec::new();
+    for jr in rep.iter().filter(|jr| in_day(&jr.timestamp)) {
+        if meals.last().map(|(m, _)| *m) != Some(jr.meal) {
+            meals.push((jr.meal, [0.0; 4]));
+        }
+        let (_, totals) = meals.last_mut().unwrap();
+        for (t, v) in totals.iter_mut().zip([jr.cal, jr.prot, jr.fat, jr.carb]) {
+            *t += v;
+        }
+    }
+
+    let mut tbl = Table::new(vec![
+        "Прием пищи".into(),
+        "ККал".into(),
+        "Белки".into(),
+        "Жиры".into(),
+        "Углеводы".into(),
+    ]);
+    for (meal, totals) in &meals {
+        let mut tr = Tr::new().add_td(Td::new(S::create(&String::from(*meal))));
+        for v in totals {
+            tr = tr.add_td(Td::new(S::create(&format!("{:.2}", v))));
+        }
+        tbl.add_row(tr);
+    }
+
+    let mut summary: Vec<String> = Vec::new();
+    if let Some(cal) = day.cal {
+        summary.push(format!("Всего потреблено: {:.2} ккал", cal));
+    }
+    if let Some(diff) = cal_limit.and_then(|v| day.diff(v)) {
+        summary.push(format!("Разница с лимитом: {:+.2} ккал", diff));
+    }
+    if let Some(w) = day.weight {
+        summary.push(format!("Вес: {:.1} кг", w));
+    }
+    let sports: BTreeSet<&str> = activities
+        .iter()
+        .filter(|a| in_day(&a.timestamp))
+        .map(|a| a.sport_name.as_str())
+        .collect();
+    if !sports.is_empty() {
+        summary.push(format!(
+            "Тренировки: {}",
+            sports.into_iter().collect::<Vec<&str>>().join(", ")
+        ));
+    }
+
+    let mut div = Div::new("mb-4").add_element(
+        H::new(&format_timestamp(&day.day, "%d.%m.%Y", tz), 6)
+            .set_attr(Attrs::from_items(
+                vec![("id", day_anchor(day, tz).as_str())].into_iter(),
+            ))
+            .as_box(),
+    );
+    if !meals.is_empty() {
+        div = div.add_element(tbl.as_box());
+    }
+    for line in summary {
+        div = div.add_element(Div::new("").add_element(S::create(&line)).as_box());
+    }
+
+    div.as_box()
+}
@@ -0,0 +1,275 @@
+use analytics::{
+    compare::{compare, Delta, Indicator},
+    digest::{Digest, FoodTotal, TOP_FOODS},
+};
+use anyhow::Result;
+use chrono::Duration;
+use chrono_tz::Tz;
+use html::{
+    attrs::Attrs,
+    b::B,
+    div::Div,
+    h::H,
+    s::S,
+    script::Script,
+    table::{Table, Td, Tr},
+    Asset,
+};
+use storage::Storage;
+use types::timestamp::Timestamp;
+
+use crate::{
+    messages::{ERR_EMPTY, ERR_INTERNAL},
+    HandlerResult,
+};
+
+use super::{
+    digest::load_period,
+    format_timestamp,
+    registry::{Arg, ArgKind, Args, Command, Group},
+    Ctx,
+};
+
+const ARROW_UP: &str = "▲";
+const ARROW_DOWN: &str = "▼";
+// Longer periods don't fit timestamp range, and make no sense in comparison
+const MAX_DAYS: i64 = 3650;
+
+pub const GROUP: Group = Group {
+    name: "cmp",
+    title: "Сравнение периодов",
+    commands: &[
+        Command {
+            name: "",
+            aliases: &[],
+            args: &[Arg::req("days", ArgKind::Int)],
+            description: "Последние N дней с предыдущими N днями",
+            handler: |ctx, args| Box::pin(compare_days(ctx, args)),
+        },
+        Command {
+            name: "",
+            aliases: &[],
+            args: &[
+                Arg::req("from1", ArgKind::Date),
+                Arg::req("to1", ArgKind::Date),
+                Arg::req("from2", ArgKind::Date),
+                Arg::req("to2", ArgKind::Date),
+            ],
+            description: "Два произвольных периода",
+            handler: |ctx, args| Box::pin(compare_ranges(ctx, args)),
+        },
+    ],
+};
+
+type Range = (Timestamp, Timestamp);
+
+async fn compare_days(ctx: Ctx, args: Args) -> HandlerResult {
+    let days: i64 = args.get("days");
+    if !(1..=MAX_DAYS).contains(&days) {
+        log::error!("wrong days count: {days}");
+        ctx.out
+            .html(args.wrong_arg("days", &format!("должно быть от 1 до {MAX_DAYS}")))
+            .await?;
+        return Ok(());
+    }
+
+    // Last N days against N days before
+    let to = Timestamp::now().with_timezone(ctx.tz).end_of_day();
+    let from = to.sub(Duration::days(days - 1)).start_of_day();
+    let first = (from.sub(Duration::days(days)), to.sub(Duration::days(days)));
+
+    send_comparison(ctx, &first, &(from, to)).await
+}
+
+async fn compare_ranges(ctx: Ctx, args: Args) -> HandlerResult {
+    let mut ranges: Vec<Range> = Vec::with_capacity(2);
+    for (from, to) in [("from1", "to1"), ("from2", "to2")] {
+        let (ts_from, ts_to): Range = (args.get(from), args.get(to));
+        if ts_from.unix_millis() > ts_to.unix_millis() {
+            log::error!("range start is after end");
+            ctx.out
+                .html(args.wrong_arg(from, "начало периода позже конца"))
+                .await?;
+            return Ok(());
+        }
+        ranges.push((ts_from.start_of_day(), ts_to.end_of_day()));
+    }
+
+    send_comparison(ctx, &ranges[0], &ranges[1]).await
+}
+
+async fn send_comparison(ctx: Ctx, first: &Range, second: &Range) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
+
+    let (first, second) = (first.clone(), second.clone());
+    match stg
+        .call(move |s| build_comparison(user_id, s, &first, &second, tz))
+        .await
+    {
+        Ok(Some((file_name, doc))) => {
+            out.document(file_name, doc).await?;
+        }
+        Ok(None) => {
+            out.text(ERR_EMPTY).await?;
+        }
+        Err(err) => {
+            log::error!("build comparison error: {err}");
+            out.text(ERR_INTERNAL).await?;
+        }
+    };
+
+    Ok(())
+}
+
+// Builds comparison HTML document, None if there is no data for both periods
+fn build_comparison(
+    user_id: i64,
+    stg: &dyn Storage,
+    first: &Range,
+    second: &Range,
+    tz: Tz,
+) -> Result<Option<(String, String)>> {
+    // Call storage
+    let first_pd = load_period(user_id, stg, &first.0, &first.1)?;
+    let second_pd = load_period(user_id, stg, &second.0, &second.1)?;
+
+    let (first_dg, second_dg) = (first_pd.digest(TOP_FOODS), second_pd.digest(TOP_FOODS));
+    if first_dg.is_empty() && second_dg.is_empty() {
+        return Ok(None);
+    }
+
+    // Generate HTML
+    let fmt_range = |r: &Range| {
+        format!(
+            "{} - {}",
+            format_timestamp(&r.0, "%d.%m.%Y", tz),
+            format_timestamp(&r.1, "%d.%m.%Y", tz)
+        )
+    };
+    let (first_str, second_str) = (fmt_range(first), fmt_range(second));
+
+    let mut tbl = Table::new(vec![
+        "Показатель".into(),
+        first_str.clone(),
+        second_str.clone(),
+        "Изменение".into(),
+    ]);
+    for delta in compare(&first_dg, &second_dg, first_pd.cal_limit.is_some()) {
+        let precision = precision(delta.indicator);
+        tbl.add_row(
+            Tr::new()
+                .add_td(Td::new(B::new(indicator_name(delta.indicator)).as_box()))
+                .add_td(Td::new(S::create(&fmt_value(delta.first, precision))))
+                .add_td(Td::new(S::create(&fmt_value(delta.second, precision))))
+                .add_td(delta_cell(&delta, precision)),
+        );
+    }
+
+    let doc = html::Builder::new("Сравнение периодов")
+        .add_element(
+            Div::new_container()
+                .add_element(
+                    H::new("Сравнение периодов", 5)
+                        .set_attr(Attrs::from_items(vec![("align", "center")].into_iter()))
+                        .as_box(),
+                )
+                .add_element(tbl.as_box())
+                .add_element(
+                    H::new("Топ продуктов по калориям", 6)
+                        .set_attr(Attrs::from_items(vec![("align", "center")].into_iter()))
+                        .as_box(),
+                )
+                .add_element(foods_table(&first_dg, &second_dg, &first_str, &second_str).as_box())
+                .as_box(),
+        )
+        .add_element(Script::create_asset(Asset::JsBootstrap));
+
+    Ok(Some((
+        format!(
+            "compare_{}_{}.html",
+            format_timestamp(&first.0, "%d.%m.%Y", tz),
+            format_timestamp(&second.1, "%d.%m.%Y", tz)
+        ),
+        doc.build(),
+    )))
+}
+
+fn indicator_name(indicator: Indicator) -> &'static str {
+    match indicator {
+        Indicator::LoggedDays => "Дней в журнале",
+        Indicator::AvgCal => "Среднее ККал в день",
+        Indicator::AvgProt => "Среднее Белки в день",
+        Indicator::AvgFat => "Среднее Жиры в день",
+        Indicator::AvgCarb => "Среднее Углеводы в день",
+        Indicator::DaysUnder => "Дней в пределах лимита",
+        Indicator::DaysOver => "Дней выше лимита",
+        Indicator::WeightChange => "Изменение веса, кг",
+        Indicator::WeighIns => "Взвешиваний",
+        Indicator::TrainingDays => "Дней с тренировками",
+    }
+}
+
+fn precision(indicator: Indicator) -> usize {
+    match indicator {
+        Indicator::AvgCal | Indicator::AvgProt | Indicator::AvgFat | Indicator::AvgCarb => 2,
+        Indicator::WeightChange => 1,
+        _ => 0,
+    }
+}
+
+fn fmt_value(v: Option<f64>, precision: usize) -> String {
+    match v {
+        Some(v) => format!("{:.*}", precision, v),
+        None => "-".into(),
+    }
+}
+
+// Arrow with change, green for improvement, red for regression
+fn delta_cell(delta: &Delta, precision: usize) -> Td {
+    let Some(diff) = delta.diff() else {
+        return Td::new(S::create("-"));
+    };
+
+    let mut val = format!("{:+.*}", precision, diff);
+    if let Some(percent) = delta.percent() {
+        val.push_str(&format!(" ({:+.1}%)", percent));
+    }
+    if diff > 0.0 {
+        val = format!("{ARROW_UP} {val}");
+    } else if diff < 0.0 {
+        val = format!("{ARROW_DOWN} {val}");
+    }
+
+    let class = match delta.is_improvement() {
+        Some(true) => "text-success",
+        Some(false) => "text-danger",
+        None => "text-secondary",
+    };
+
+    Td::new(S::create(&val)).set_attrs(Attrs::from_items(vec![("class", class)].into_iter()))
+}
+
+fn foods_table(first: &Digest, second: &Digest, first_str: &str, second_str: &str) -> Table {
+    let mut tbl = Table::new(vec!["#".into(), first_str.into(), second_str.into()]);
+    let fmt = |ft: Option<&FoodTotal>| match ft {
+        Some(ft) if ft.brand.is_empty() => format!("{} ({:.0} ккал)", ft.name, ft.cal),
+        Some(ft) => format!("{} - {} ({:.0} ккал)", ft.name, ft.brand, ft.cal),
+        None => String::new(),
+    };
+
+    for i in 0..first.top_foods.len().max(second.top_foods.len()) {
+        tbl.add_row(
+            Tr::new()
+                .add_td(Td::new(S::create(&(i + 1).to_string())))
+                .add_td(Td::new(S::create(&fmt(first.top_foods.get(i)))))
+                .add_td(Td::new(S::create(&fmt(second.top_foods.get(i))))),
+        );
+    }
+
+    tbl
+}
@@ -0,0 +1,382 @@
+use analytics::digest::{Digest, TOP_FOODS};
+use anyhow::{Context, Result};
+use chart::{
+    get_chart_snippet, Annotation, Axis, ChartData, ChartDataset, ChartType, CHART_COLOR_BLUE,
+    CHART_COLOR_RED,
+};
+use chrono::Duration;
+use chrono_tz::Tz;
+use html::{
+    accordion::{Accordion, AccordionItem},
+    b::B,
+    canvas::Canvas,
+    div::Div,
+    raw::Raw,
+    s::S,
+    script::Script,
+    table::{Table, Td, Tr},
+    Asset,
+};
+use model::{JournalReport, SportActivityReport, Weight};
+use storage::{Storage, StorageError};
+use types::timestamp::Timestamp;
+
+use crate::{
+    messages::{ERR_EMPTY, ERR_INTERNAL},
+    HandlerResult,
+};
+
+use super::{
+    format_timestamp, or_empty,
+    registry::{Arg, ArgKind, Args, Command, Group},
+    Ctx,
+};
+
+pub const GROUP: Group = Group {
+    name: "dg",
+    title: "Отчет за период",
+    commands: &[Command {
+        name: "",
+        aliases: &[],
+        args: &[
+            Arg::req("period", ArgKind::Choice(&["w", "m"])),
+            Arg::opt("timestamp", ArgKind::Date),
+        ],
+        description: "Отчет за неделю (w) или месяц (m), содержащие дату",
+        handler: |ctx, args| Box::pin(digest(ctx, args)),
+    }],
+};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Period {
+    Week,
+    Month,
+}
+
+impl Period {
+    fn new_str(s: &str) -> Option<Self> {
+        match s {
+            "w" => Some(Self::Week),
+            "m" => Some(Self::Month),
+            _ => None,
+        }
+    }
+
+    // Period bounds containing timestamp
+    fn range(&self, ts: &Timestamp) -> (Timestamp, Timestamp) {
+        match self {
+            Self::Week => {
+                let from = ts.start_of_week();
+                let to = from.add(Duration::days(6)).end_of_day();
+                (from, to)
+            }
+            Self::Month => (ts.start_of_month(), ts.end_of_month()),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Week => "Отчет за неделю",
+            Self::Month => "Отчет за месяц",
+        }
+    }
+}
+
+async fn digest(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
+
+    let period = Period::new_str(&args.get::<String>("period")).unwrap();
+    let timestamp: Timestamp = args.get("timestamp");
+
+    match stg
+        .call(move |s| build_digest(user_id, s, period, &timestamp, tz))
+        .await
+    {
+        Ok(Some((file_name, doc))) => {
+            out.document(file_name, doc).await?;
+        }
+        Ok(None) => {
+            out.text(ERR_EMPTY).await?;
+        }
+        Err(err) => {
+            log::error!("build digest error: {err}");
+            out.text(ERR_INTERNAL).await?;
+        }
+    };
+
+    Ok(())
+}
+
+// Data of user for period
+pub struct PeriodData {
+    pub rep: Vec<JournalReport>,
+    pub weights: Vec<Weight>,
+    pub activities: Vec<SportActivityReport>,
+    pub cal_limit: Option<f64>,
+}
+
+impl PeriodData {
+    pub fn digest(&self, top_foods: usize) -> Digest {
+        Digest::new(
+            &self.rep,
+            &self.weights,
+            &self.activities,
+            self.cal_limit,
+            top_foods,
+        )
+    }
+}
+
+pub fn load_period(
+    user_id: i64,
+    stg: &dyn Storage,
+    ts_from: &Timestamp,
+    ts_to: &Timestamp,
+) -> Result<PeriodData> {
+    let rep = or_empty(stg.get_journal_report(user_id, ts_from.clone(), ts_to.clone()))
+        .context("get journal report")?;
+    let weights = or_empty(stg.get_weight_list(user_id, ts_from.clone(), ts_to.clone()))
+        .context("get weight list")?;
+    let activities =
+        or_empty(stg.get_sport_activity_report(user_id, ts_from.clone(), ts_to.clone()))
+            .context("get sport activity report")?;
+    let cal_limit = match stg.get_user_settings(user_id) {
+        Ok(us) => Some(us.cal_limit),
+        Err(StorageError::UserSettingsNotFound) => None,
+        Err(err) => return Err(err).context("get user settings"),
+    };
+
+    Ok(PeriodData {
+        rep,
+        weights,
+        activities,
+        cal_limit,
+    })
+}
+
+// Builds digest HTML document for period containing timestamp.
+// Returns file name and document, None if there is no data for period.
+pub fn build_digest(
+    user_id: i64,
+    stg: &dyn Storage,
+    period: Period,
+    timestamp: &Timestamp,
+    tz: Tz,
+) -> Result<Option<(String, String)>> {
+    let (ts_from, ts_to) = period.range(&timestamp.with_timezone(tz));
+
+    // Call storage
+    let pd = load_period(user_id, stg, &ts_from, &ts_to)?;
+    let (rep, cal_limit) = (&pd.rep, pd.cal_limit);
+
+    let dg = pd.digest(TOP_FOODS);
+    if dg.is_empty() {
+        return Ok(None);
+    }
+
+    // Generate HTML
+    let days =
+        (ts_to.unix_millis() - ts_from.unix_millis()) / Duration::days(1).num_milliseconds() + 1;
+    let ts_from = format_timestamp(&ts_from, "%d.%m.%Y", tz);
+    let ts_to = format_timestamp(&ts_to, "%d.%m.%Y", tz);
+
+    let chart_snip =
+        get_chart_snippet(&calories_chart(rep, cal_limit, tz)).context("chart snippet")?;
+
+    let mut doc = html::Builder::new(period.name());
+    let mut accrd = Accordion::new("accordionDigest");
+
+    accrd.add_item(AccordionItem::new(
+        "nutrition",
+        &format!("Питание за {} - {}", &ts_from, &ts_to),
+        Div::new("")
+            .add_element(nutrition_table(&dg, cal_limit, days).as_box())
+            .add_element(Canvas::create("chart"))
+            .as_box(),
+    ));
+    accrd.add_item(AccordionItem::new(
+        "weight",
+        &format!("Вес за {} - {}", &ts_from, &ts_to),
+        weight_table(&dg).as_box(),
+    ));
+    accrd.add_item(AccordionItem::new(
+        "sport",
+        &format!("Тренировки за {} - {}", &ts_from, &ts_to),
+        sport_table(&dg).as_box(),
+    ));
+    accrd.add_item(AccordionItem::new(
+        "food",
+        &format!("Топ продуктов по калориям за {} - {}", &ts_from, &ts_to),
+        food_table(&dg).as_box(),
+    ));
+
+    doc = doc
+        .add_element(Div::new_container().add_element(accrd.as_box()).as_box())
+        .add_element(Script::create_asset(Asset::JsBootstrap))
+        .add_element(Script::create_asset(Asset::JsChart))
+        .add_element(Raw::create(&chart_snip));
+
+    Ok(Some((
+        format!("digest_{}_{}.html", &ts_from, &ts_to),
+        doc.build(),
+    )))
+}
+
+// Calories by day with the limit line
+fn calories_chart(rep: &[JournalReport], cal_limit: Option<f64>, tz: Tz) -> ChartData {
+    let mut x_labels: Vec<String> = Vec::new();
+    let mut data: Vec<f64> = Vec::new();
+    for jr in rep {
+        let ts = format_timestamp(&jr.timestamp, "%d.%m", tz);
+        if x_labels.last() != Some(&ts) {
+            x_labels.push(ts);
+            data.push(0.0);
+        }
+        *data.last_mut().unwrap() += jr.cal;
+    }
+
+    ChartData {
+        elem_id: "chart".into(),
+        x_labels,
+        ctype: ChartType::Bar,
+        datasets: vec![ChartDataset {
+            data,
+            label: "ККал".into(),
+            color: CHART_COLOR_BLUE.into(),
+            ..Default::default()
+        }],
+        axes: vec![Axis {
+            unit: "ккал".into(),
+            ..Default::default()
+        }],
+        annotations: cal_limit
+            .map(|v| Annotation {
+                label: "Лимит".into(),
+                value: v,
+                color: CHART_COLOR_RED.into(),
+                axis: None,
+            })
+            .into_iter()
+            .collect(),
+        ..Default::default()
+    }
+}
+
+fn kv_table(rows: Vec<(&str, String)>) -> Table {
+    let mut tbl = Table::new(vec!["Показатель".into(), "Значение".into()]);
+    for (name, val) in rows {
+        tbl.add_row(
+            Tr::new()
+                .add_td(Td::new(B::new(name).as_box()))
+                .add_td(Td::new(S::create(&val))),
+        );
+    }
+
+    tbl
+}
+
+fn nutrition_table(dg: &Digest, cal_limit: Option<f64>, days: i64) -> Table {
+    let pfc = dg.avg_prot + dg.avg_fat + dg.avg_carb;
+    let pfc_share = |v: f64| {
+        if pfc == 0.0 {
+            format!("{:.2}", v)
+        } else {
+            format!("{:.2} ({:.2}%)", v, v / pfc * 100.0)
+        }
+    };
+
+    let mut rows = vec![
+        ("Дней в журнале", format!("{} из {}", dg.logged_days, days)),
+        ("Среднее ККал в день", format!("{:.2}", dg.avg_cal)),
+    ];
+    if let Some(cal_limit) = cal_limit {
+        rows.push(("Лимит ККал", format!("{:.2}", cal_limit)));
+        rows.push((
+            "Отклонение от лимита в день",
+            format!("{:+.2}", dg.avg_cal - cal_limit),
+        ));
+        rows.push(("Дней в пределах лимита", dg.days_under.to_string()));
+        rows.push(("Дней выше лимита", dg.days_over.to_string()));
+    }
+    rows.push(("Среднее Белки в день", pfc_share(dg.avg_prot)));
+    rows.push(("Среднее Жиры в день", pfc_share(dg.avg_fat)));
+    rows.push(("Среднее Углеводы в день", pfc_share(dg.avg_carb)));
+
+    kv_table(rows)
+}
+
+fn weight_table(dg: &Digest) -> Table {
+    let fmt = |v: Option<f64>, sign: bool| match v {
+        Some(v) if sign => format!("{:+.1}", v),
+        Some(v) => format!("{:.1}", v),
+        None => "-".into(),
+    };
+
+    kv_table(vec![
+        ("Взвешиваний", dg.weigh_ins.to_string()),
+        ("Вес в начале периода, кг", fmt(dg.weight_first, false)),
+        ("Вес в конце периода, кг", fmt(dg.weight_last, false)),
+        ("Изменение, кг", fmt(dg.weight_change(), true)),
+    ])
+}
+
+fn sport_table(dg: &Digest) -> Table {
+    let mut tbl = Table::new(vec![
+        "Спорт".into(),
+        "Тренировок".into(),
+        "Подходов".into(),
+        "Повторений".into(),
+    ]);
+
+    for st in &dg.sports {
+        tbl.add_row(
+            Tr::new()
+                .add_td(Td::new(S::create(&st.name)))
+                .add_td(Td::new(S::create(&st.sessions.to_string())))
+                .add_td(Td::new(S::create(&st.sets.to_string())))
+                .add_td(Td::new(S::create(&st.reps.to_string()))),
+        );
+    }
+
+    tbl.add_row(
+        Tr::new()
+            .add_td(Td::new(B::new("Дней с тренировками").as_box()))
+            .add_td(Td::new(S::create(&dg.training_days.to_string())))
+            .add_td(Td::new(S::create("")))
+            .add_td(Td::new(S::create(""))),
+    );
+
+    tbl
+}
+
+fn food_table(dg: &Digest) -> Table {
+    let mut tbl = Table::new(vec![
+        "Еда".into(),
+        "Вес, г".into(),
+        "ККал".into(),
+        "ККал в день".into(),
+    ]);
+
+    let days = dg.logged_days.max(1) as f64;
+    for f in &dg.top_foods {
+        let name = if f.brand.is_empty() {
+            f.name.clone()
+        } else {
+            format!("{} - {}", f.name, f.brand)
+        };
+        tbl.add_row(
+            Tr::new()
+                .add_td(Td::new(S::create(&name)))
+                .add_td(Td::new(S::create(&format!("{:.1}", f.weight))))
+                .add_td(Td::new(S::create(&format!("{:.2}", f.cal))))
+                .add_td(Td::new(S::create(&format!("{:.2}", f.cal / days)))),
+        );
+    }
+
+    tbl
+}
@@ -6,186 +6,146 @@ use html::{
     table::{Table, Td, Tr},
 };
 use model::Food;
-use std::sync::Arc;
-use storage::{Storage, StorageError};
-use teloxide::{
-    prelude::*,
-    types::{InputFile, ParseMode},
-};
 
-use crate::{
-    messages::{
-        ERR_EMPTY, ERR_FOOD_IS_USED, ERR_FOOD_NOT_FOUND, ERR_INTERNAL, ERR_WRONG_COMMAND, OK,
-    },
-    HandlerResult,
-};
+use crate::{messages::OK, HandlerResult};
 
-pub async fn process_food_command(
-    bot: Bot,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-) -> HandlerResult {
-    if args.is_empty() {
-        log::error!("empty args");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
-
-    match *args.first().unwrap() {
-        "set" => {
-            food_set(bot, chat_id, args[1..].to_vec(), stg).await?;
-        }
-        "st" => {
-            food_set_template(bot, chat_id, args[1..].to_vec(), stg).await?;
-        }
-        "list" => {
-            food_list(bot, chat_id, stg).await?;
-        }
-        "find" => {
-            food_find(bot, chat_id, args[1..].to_vec(), stg).await?;
-        }
-        "del" => {
-            food_del(bot, chat_id, args[1..].to_vec(), stg).await?;
-        }
-        _ => {
-            log::error!("unknown command");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        }
-    };
+use super::{
+    registry::{Arg, ArgKind, Args, Command, Group},
+    storage_error_message, Ctx,
+};
 
-    Ok(())
-}
+pub const GROUP: Group = Group {
+    name: "f",
+    title: "Еда",
+    commands: &[
+        Command {
+            name: "set",
+            aliases: &[],
+            args: &[
+                Arg::req("key", ArgKind::Key),
+                Arg::req("name", ArgKind::Text),
+                Arg::req("brand", ArgKind::Text),
+                Arg::req("cal100", ArgKind::Float),
+                Arg::req("prot100", ArgKind::Float),
+                Arg::req("fat100", ArgKind::Float),
+                Arg::req("carb100", ArgKind::Float),
+                Arg::req("comment", ArgKind::Text),
+            ],
+            description: "Установить еду",
+            handler: |ctx, args| Box::pin(food_set(ctx, args)),
+        },
+        Command {
+            name: "st",
+            aliases: &[],
+            args: &[Arg::req("key", ArgKind::Key)],
+            description: "Шаблон команды установки еды",
+            handler: |ctx, args| Box::pin(food_set_template(ctx, args)),
+        },
+        Command {
+            name: "list",
+            aliases: &[],
+            args: &[],
+            description: "Список еды",
+            handler: |ctx, _| Box::pin(food_list(ctx)),
+        },
+        Command {
+            name: "find",
+            aliases: &[],
+            args: &[Arg::req("pattern", ArgKind::Key)],
+            description: "Поиск еды",
+            handler: |ctx, args| Box::pin(food_find(ctx, args)),
+        },
+        Command {
+            name: "del",
+            aliases: &[],
+            args: &[Arg::req("key", ArgKind::Key)],
+            description: "Удалить еду",
+            handler: |ctx, args| Box::pin(food_del(ctx, args)),
+        },
+    ],
+};
 
-async fn food_set(
-    bot: Bot,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-) -> HandlerResult {
-    if args.len() != 8 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn food_set(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
 
-    let key = args.first().unwrap().to_string();
-    let name = args.get(1).unwrap().to_string();
-    let brand = args.get(2).unwrap().to_string();
-    let comment = args.get(7).unwrap().to_string();
-
-    let cal100 = match args.get(3).unwrap().parse::<f64>() {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse cal100 error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
-    let prot100 = match args.get(4).unwrap().parse::<f64>() {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse prot100 error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
-    let fat100 = match args.get(5).unwrap().parse::<f64>() {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse fat100 error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
-    let carb100 = match args.get(6).unwrap().parse::<f64>() {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse carb100 error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
+    let key: String = args.get("key");
+    let name: String = args.get("name");
+    let brand: String = args.get("brand");
+    let comment: String = args.get("comment");
+    let cal100: f64 = args.get("cal100");
+    let prot100: f64 = args.get("prot100");
+    let fat100: f64 = args.get("fat100");
+    let carb100: f64 = args.get("carb100");
 
     // Call storage
-    if let Err(err) = stg.set_food(&Food {
-        key,
-        name,
-        brand,
-        cal100,
-        prot100,
-        fat100,
-        carb100,
-        comment,
-    }) {
+    let res = stg
+        .call(move |s| {
+            s.set_food(
+                user_id,
+                &Food {
+                    key,
+                    name,
+                    brand,
+                    cal100,
+                    prot100,
+                    fat100,
+                    carb100,
+                    comment,
+                },
+            )
+        })
+        .await;
+    if let Err(err) = res {
         log::error!("set food error: {err}");
-        if stg.is_storage_error(StorageError::FoodInvalid, &err) {
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        } else {
-            bot.send_message(chat_id, ERR_INTERNAL).await?;
-        }
+        out.text(storage_error_message(&err)).await?;
     } else {
-        bot.send_message(chat_id, OK).await?;
+        out.text(OK).await?;
     }
 
     Ok(())
 }
 
-async fn food_set_template(
-    bot: Bot,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-) -> HandlerResult {
-    if args.len() != 1 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn food_set_template(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx { out, stg, .. } = ctx;
+
+    let key: String = args.get("key");
 
     // Call storage
-    let food = match stg.get_food(args.first().unwrap()) {
+    let food = match stg.call(move |s| s.get_food(&key)).await {
         Err(err) => {
             log::error!("get food error: {err}");
-            if stg.is_storage_error(StorageError::FoodNotFound, &err) {
-                bot.send_message(chat_id, ERR_FOOD_NOT_FOUND).await?;
-            } else {
-                bot.send_message(chat_id, ERR_INTERNAL).await?;
-            }
+            out.text(storage_error_message(&err)).await?;
             return Ok(());
         }
         Ok(f) => f,
     };
 
-    bot.send_message(
-        chat_id,
-        format!(
-            "f,set,{},{},{},{:.2},{:.2},{:.2},{:.2},{}",
-            food.key,
-            food.name,
-            food.brand,
-            food.cal100,
-            food.prot100,
-            food.fat100,
-            food.carb100,
-            food.comment
-        ),
-    )
+    out.text(format!(
+        "f,set,{},{},{},{:.2},{:.2},{:.2},{:.2},{}",
+        food.key,
+        food.name,
+        food.brand,
+        food.cal100,
+        food.prot100,
+        food.fat100,
+        food.carb100,
+        food.comment
+    ))
     .await?;
 
     Ok(())
 }
 
-async fn food_list(bot: Bot, chat_id: ChatId, stg: Arc<Box<dyn Storage>>) -> HandlerResult {
+async fn food_list(ctx: Ctx) -> HandlerResult {
+    let Ctx { out, stg, .. } = ctx;
+
     // Call storage
-    let f_lst = match stg.get_food_list() {
+    let f_lst = match stg.call(|s| s.get_food_list()).await {
         Err(err) => {
             log::error!("food list error: {err}");
-            if stg.is_storage_error(StorageError::EmptyResult, &err) {
-                bot.send_message(chat_id, ERR_EMPTY).await?;
-            } else {
-                bot.send_message(chat_id, ERR_INTERNAL).await?;
-            }
+            out.text(storage_error_message(&err)).await?;
             return Ok(());
         }
         Ok(lst) => lst,
@@ -228,36 +188,21 @@ async fn food_list(bot: Bot, chat_id: ChatId, stg: Arc<Box<dyn Storage>>) -> Han
             .as_box(),
     );
 
-    bot.send_document(
-        chat_id,
-        InputFile::memory(doc.build()).file_name("food.html"),
-    )
-    .await?;
+    out.document("food.html", doc.build()).await?;
 
     Ok(())
 }
 
-async fn food_find(
-    bot: Bot,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-) -> HandlerResult {
-    if args.len() != 1 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn food_find(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx { out, stg, .. } = ctx;
+
+    let pattern: String = args.get("pattern");
 
     // Call storage
-    let food = match stg.find_food(args.first().unwrap()) {
+    let food = match stg.call(move |s| s.find_food(&pattern)).await {
         Err(err) => {
             log::error!("find food error: {err}");
-            if stg.is_storage_error(StorageError::EmptyResult, &err) {
-                bot.send_message(chat_id, ERR_EMPTY).await?;
-            } else {
-                bot.send_message(chat_id, ERR_INTERNAL).await?;
-            }
+            out.text(storage_error_message(&err)).await?;
             return Ok(());
         }
         Ok(f) => f,
@@ -279,37 +224,26 @@ async fn food_find(
         }
     }
 
-    bot.send_message(chat_id, res)
-        .parse_mode(ParseMode::Html)
-        .await?;
+    out.html(res).await?;
 
     Ok(())
 }
 
-async fn food_del(
-    bot: Bot,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-) -> HandlerResult {
-    if args.len() != 1 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn food_del(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
+
+    let key: String = args.get("key");
 
     // Call storage
-    if let Err(err) = stg.delete_food(args.first().unwrap()) {
+    if let Err(err) = stg.call(move |s| s.delete_food(user_id, &key)).await {
         log::error!("del food error: {err}");
-        if stg.is_storage_error(StorageError::FoodIsUsed, &err) {
-            bot.send_message(chat_id, ERR_FOOD_IS_USED).await?;
-        } else {
-            bot.send_message(chat_id, ERR_INTERNAL).await?;
-        }
+        out.text(storage_error_message(&err)).await?;
         return Ok(());
     };
 
-    bot.send_message(chat_id, OK).await?;
+    out.text(OK).await?;
 
     Ok(())
 }
@@ -0,0 +1,220 @@
+use analytics::{
+    digest::TOP_FOODS,
+    food_stats::{FoodStats, MEALS},
+};
+use anyhow::{Context, Result};
+use chrono::Duration;
+use chrono_tz::Tz;
+use html::{
+    attrs::Attrs,
+    div::Div,
+    h::H,
+    s::S,
+    script::Script,
+    table::{Table, Td, Tr},
+    Asset,
+};
+use model::{FoodUsage, Meal};
+use storage::Storage;
+use types::timestamp::Timestamp;
+
+use crate::{
+    messages::{ERR_EMPTY, ERR_INTERNAL},
+    HandlerResult,
+};
+
+use super::{format_timestamp, or_empty, registry::Args, Ctx};
+
+// Default period in days
+const DEFAULT_DAYS: i64 = 30;
+
+pub async fn journal_food_stats(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
+
+    // Last days by default or explicit range
+    let (ts_from, ts_to) = match (
+        args.opt::<Timestamp>("ts_from"),
+        args.opt::<Timestamp>("ts_to"),
+    ) {
+        (Some(from), Some(to)) => (from.start_of_day(), to.end_of_day()),
+        _ => {
+            let to = Timestamp::now().with_timezone(tz).end_of_day();
+            (to.sub(Duration::days(DEFAULT_DAYS - 1)).start_of_day(), to)
+        }
+    };
+    if ts_from.unix_millis() > ts_to.unix_millis() {
+        log::error!("range start is after end");
+        out.html(args.wrong_arg("ts_from", "начало периода позже конца"))
+            .await?;
+        return Ok(());
+    }
+
+    match stg
+        .call(move |s| build_food_stats(user_id, s, &ts_from, &ts_to, tz))
+        .await
+    {
+        Ok(Some((file_name, doc))) => {
+            out.document(file_name, doc).await?;
+        }
+        Ok(None) => {
+            out.text(ERR_EMPTY).await?;
+        }
+        Err(err) => {
+            log::error!("build food stats error: {err}");
+            out.text(ERR_INTERNAL).await?;
+        }
+    };
+
+    Ok(())
+}
+
+// Builds food statistics HTML document, None if journal is empty for the period
+fn build_food_stats(
+    user_id: i64,
+    stg: &dyn Storage,
+    ts_from: &Timestamp,
+    ts_to: &Timestamp,
+    tz: Tz,
+) -> Result<Option<(String, String)>> {
+    // Call storage
+    let usage = or_empty(stg.get_journal_food_usage(user_id, ts_from.clone(), ts_to.clone()))
+        .context("get journal food usage")?;
+    if usage.is_empty() {
+        return Ok(None);
+    }
+
+    let meal_usage =
+        or_empty(stg.get_journal_food_meal_usage(user_id, ts_from.clone(), ts_to.clone()))
+            .context("get journal food meal usage")?;
+
+    let unused = or_empty(stg.get_unused_food_list()).context("get unused food list")?;
+
+    let fs = FoodStats::new(&usage, &meal_usage, TOP_FOODS);
+
+    // Generate HTML
+    let period_str = format!(
+        "{} - {}",
+        format_timestamp(ts_from, "%d.%m.%Y", tz),
+        format_timestamp(ts_to, "%d.%m.%Y", tz)
+    );
+    let header = |text: &str, size: u8| {
+        H::new(text, size)
+            .set_attr(Attrs::from_items(vec![("align", "center")].into_iter()))
+            .as_box()
+    };
+
+    let mut container = Div::new_container()
+        .add_element(header(&format!("Статистика продуктов за {period_str}"), 5))
+        .add_element(header("Топ по калориям", 6))
+        .add_element(usage_table(&fs.by_cal).as_box())
+        .add_element(header("Топ по частоте", 6))
+        .add_element(usage_table(&fs.by_count).as_box())
+        .add_element(header("Топ по белкам", 6))
+        .add_element(usage_table(&fs.by_prot).as_box())
+        .add_element(header("Частота по приемам пищи", 6));
+
+    let mut meals_tbl = Table::new(
+        std::iter::once("Наименование".to_string())
+            .chain(meals().map(String::from))
+            .chain(std::iter::once("Всего".to_string()))
+            .collect(),
+    );
+    for fm in &fs.meals {
+        let mut tr = Tr::new().add_td(Td::new(S::create(&food_title(
+            &fm.food_name,
+            &fm.food_brand,
+        ))));
+        for meal in meals() {
+            let count = fm.count(meal);
+            let val = if count > 0 {
+                count.to_string()
+            } else {
+                String::new()
+            };
+            tr = tr.add_td(Td::new(S::create(&val)));
+        }
+        tr = tr.add_td(Td::new(S::create(&fm.total().to_string())));
+        meals_tbl.add_row(tr);
+    }
+    container = container.add_element(meals_tbl.as_box());
+
+    // Unused foods are common for all users
+    container = container.add_element(header("Неиспользуемые продукты", 6));
+    if unused.is_empty() {
+        container = container.add_element(
+            Div::new("mb-3")
+                .add_element(S::create("Все продукты используются"))
+                .as_box(),
+        );
+    } else {
+        let mut unused_tbl = Table::new(vec![
+            "Ключ".into(),
+            "Наименование".into(),
+            "ККал/100".into(),
+        ]);
+        for f in &unused {
+            unused_tbl.add_row(
+                Tr::new()
+                    .add_td(Td::new(S::create(&f.key)))
+                    .add_td(Td::new(S::create(&food_title(&f.name, &f.brand))))
+                    .add_td(Td::new(S::create(&format!("{:.2}", f.cal100)))),
+            );
+        }
+        container = container.add_element(unused_tbl.as_box());
+    }
+
+    let doc = html::Builder::new("Статистика продуктов")
+        .add_element(container.as_box())
+        .add_element(Script::create_asset(Asset::JsBootstrap));
+
+    Ok(Some((
+        format!(
+            "food_stats_{}_{}.html",
+            format_timestamp(ts_from, "%d.%m.%Y", tz),
+            format_timestamp(ts_to, "%d.%m.%Y", tz)
+        ),
+        doc.build(),
+    )))
+}
+
+fn meals() -> impl Iterator<Item = Meal> {
+    (0..MEALS as u8).filter_map(|v| Meal::new(v).ok())
+}
+
+fn food_title(name: &str, brand: &str) -> String {
+    if brand.is_empty() {
+        name.into()
+    } else {
+        format!("{name} - {brand}")
+    }
+}
+
+fn usage_table(usage: &[FoodUsage]) -> Table {
+    let mut tbl = Table::new(vec![
+        "Наименование".into(),
+        "Раз".into(),
+        "Вес".into(),
+        "ККал".into(),
+        "Белки".into(),
+    ]);
+    for fu in usage {
+        tbl.add_row(
+            Tr::new()
+                .add_td(Td::new(S::create(&food_title(
+                    &fu.food_name,
+                    &fu.food_brand,
+                ))))
+                .add_td(Td::new(S::create(&fu.count.to_string())))
+                .add_td(Td::new(S::create(&format!("{:.0}", fu.food_weight))))
+                .add_td(Td::new(S::create(&format!("{:.2}", fu.cal))))
+                .add_td(Td::new(S::create(&format!("{:.2}", fu.prot)))),
+        );
+    }
+
+    tbl
+}
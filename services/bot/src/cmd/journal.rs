@@ -1,342 +1,318 @@
+use analytics::day_report::DayReport;
+use chart::{
+    get_chart_snippet, Axis, AxisPosition, ChartData, ChartDataset, ChartType, CHART_COLORS,
+    CHART_COLOR_BLUE, CHART_COLOR_GREEN, CHART_COLOR_GREY, CHART_COLOR_RED, CHART_COLOR_YELLOW,
+};
 use chrono::Duration;
 use chrono_tz::Tz;
 use html::{
     attrs::Attrs,
     b::B,
+    canvas::Canvas,
     div::Div,
     h::H,
+    progress::Progress,
+    raw::Raw,
     s::S,
+    script::Script,
     span::Span,
     table::{Table, Td, Tr},
-    Element,
+    Asset, Element,
 };
 use model::{Journal, Meal, UserSettings};
-use std::sync::Arc;
-use storage::{Storage, StorageError};
-use teloxide::{
-    prelude::*,
-    types::{InputFile, ParseMode},
-};
+use storage::{storage_async::StorageAsync, StorageError};
 use types::timestamp::Timestamp;
 
 use crate::{
-    messages::{
-        ERR_BUNDLE_NOT_FOUND, ERR_EMPTY, ERR_FOOD_NOT_FOUND, ERR_INTERNAL, ERR_WRONG_COMMAND, OK,
-    },
+    messages::{ERR_EMPTY, ERR_INTERNAL, OK},
+    output::Output,
     HandlerResult,
 };
 
-use super::{format_timestamp, parse_timestamp};
-
-pub async fn process_journal_command(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-    tz: Tz,
-) -> HandlerResult {
-    if args.is_empty() {
-        log::error!("empty args");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
-
-    match *args.first().unwrap() {
-        "set" => {
-            journal_set(bot, user_id, chat_id, args[1..].to_vec(), stg, tz).await?;
-        }
-        "sb" => {
-            journal_set_bundle(bot, user_id, chat_id, args[1..].to_vec(), stg, tz).await?;
-        }
-        "del" => {
-            journal_del(bot, user_id, chat_id, args[1..].to_vec(), stg, tz).await?;
-        }
-        "dm" => {
-            journal_del_meal(bot, user_id, chat_id, args[1..].to_vec(), stg, tz).await?;
-        }
-        "rd" => {
-            journal_report_day(bot, user_id, chat_id, args[1..].to_vec(), stg, tz).await?;
-        }
-        "tm" => {
-            journal_template_meal(bot, user_id, chat_id, args[1..].to_vec(), stg, tz).await?;
-        }
-        "fa" => {
-            journal_food_avg_weight(bot, user_id, chat_id, args[1..].to_vec(), stg, tz).await?;
-        }
-        _ => {
-            log::error!("unknown command");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        }
-    };
+use super::{
+    food_stats::journal_food_stats,
+    format_timestamp,
+    registry::{Arg, ArgKind, Args, Command, Group},
+    send_chart_photo, storage_error_message,
+    summary::{day_report_text, load_day_report, remaining_text},
+    Ctx,
+};
 
-    Ok(())
-}
+const MACROS_AXIS: &str = "y_macros";
+
+pub const GROUP: Group = Group {
+    name: "j",
+    title: "Журнал приема пищи",
+    commands: &[
+        Command {
+            name: "set",
+            aliases: &[],
+            args: &[
+                Arg::req("timestamp", ArgKind::Date),
+                Arg::req("meal", ArgKind::Meal),
+                Arg::req("food_key", ArgKind::Key),
+                Arg::req("food_weight", ArgKind::Float),
+            ],
+            description: "Добавить еду в журнал",
+            handler: |ctx, args| Box::pin(journal_set(ctx, args)),
+        },
+        Command {
+            name: "sb",
+            aliases: &[],
+            args: &[
+                Arg::req("timestamp", ArgKind::Date),
+                Arg::req("meal", ArgKind::Meal),
+                Arg::req("bndl_key", ArgKind::Key),
+            ],
+            description: "Добавить бандл в журнал",
+            handler: |ctx, args| Box::pin(journal_set_bundle(ctx, args)),
+        },
+        Command {
+            name: "del",
+            aliases: &[],
+            args: &[
+                Arg::req("timestamp", ArgKind::Date),
+                Arg::req("meal", ArgKind::Meal),
+                Arg::req("food_key", ArgKind::Key),
+            ],
+            description: "Удалить еду из журнала",
+            handler: |ctx, args| Box::pin(journal_del(ctx, args)),
+        },
+        Command {
+            name: "dm",
+            aliases: &[],
+            args: &[
+                Arg::req("timestamp", ArgKind::Date),
+                Arg::req("meal", ArgKind::Meal),
+            ],
+            description: "Удалить прием пищи из журнала",
+            handler: |ctx, args| Box::pin(journal_del_meal(ctx, args)),
+        },
+        Command {
+            name: "rd",
+            aliases: &[],
+            args: &[Arg::req("timestamp", ArgKind::Date)],
+            description: "Отчет за день",
+            handler: |ctx, args| Box::pin(journal_report_day(ctx, args)),
+        },
+        Command {
+            name: "rt",
+            aliases: &[],
+            args: &[Arg::req("timestamp", ArgKind::Date)],
+            description: "Текстовый отчет за день",
+            handler: |ctx, args| Box::pin(journal_report_text(ctx, args)),
+        },
+        Command {
+            name: "tm",
+            aliases: &[],
+            args: &[
+                Arg::req("timestamp", ArgKind::Date),
+                Arg::req("meal", ArgKind::Meal),
+            ],
+            description: "Шаблон команд приема пищи",
+            handler: |ctx, args| Box::pin(journal_template_meal(ctx, args)),
+        },
+        Command {
+            name: "fa",
+            aliases: &[],
+            args: &[Arg::req("food_key", ArgKind::Key)],
+            description: "Средний вес еды за год",
+            handler: |ctx, args| Box::pin(journal_food_avg_weight(ctx, args)),
+        },
+        Command {
+            name: "fs",
+            aliases: &[],
+            args: &[],
+            description: "Статистика продуктов за 30 дней",
+            handler: |ctx, args| Box::pin(journal_food_stats(ctx, args)),
+        },
+        Command {
+            name: "fs",
+            aliases: &[],
+            args: &[
+                Arg::req("ts_from", ArgKind::Date),
+                Arg::req("ts_to", ArgKind::Date),
+            ],
+            description: "Статистика продуктов за период",
+            handler: |ctx, args| Box::pin(journal_food_stats(ctx, args)),
+        },
+    ],
+};
 
-async fn journal_set(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-    tz: Tz,
-) -> HandlerResult {
-    if args.len() != 4 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn journal_set(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
 
     // Parse args
-    let timestamp = match parse_timestamp(args.first().unwrap(), tz) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse timestamp error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
-
-    let meal = match Meal::new_str(args.get(1).unwrap()) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse meal error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
-
-    let food_key = args.get(2).unwrap().to_string();
-
-    let food_weight = match args.get(3).unwrap().parse::<f64>() {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse food weight error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
+    let timestamp: Timestamp = args.get("timestamp");
+    let meal: Meal = args.get("meal");
+    let food_key: String = args.get("food_key");
+    let food_weight: f64 = args.get("food_weight");
 
     // Call storage
-    match stg.set_journal(
-        user_id,
-        &Journal {
-            timestamp,
-            meal,
-            food_key,
-            food_weight,
-        },
-    ) {
+    let journal = Journal {
+        timestamp: timestamp.clone(),
+        meal,
+        food_key,
+        food_weight,
+    };
+    match stg.call(move |s| s.set_journal(user_id, &journal)).await {
         Ok(_) => {
-            bot.send_message(chat_id, OK).await?;
+            out.text(OK).await?;
+            send_remaining_status(&out, user_id, &stg, &timestamp, tz).await?;
         }
         Err(err) => {
             log::error!("set journal error: {err}");
-            if stg.is_storage_error(StorageError::FoodNotFound, &err) {
-                bot.send_message(chat_id, ERR_FOOD_NOT_FOUND).await?;
-            } else {
-                bot.send_message(chat_id, ERR_INTERNAL).await?;
-            }
+            out.text(storage_error_message(&err)).await?;
         }
     }
 
     Ok(())
 }
 
-async fn journal_set_bundle(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-    tz: Tz,
-) -> HandlerResult {
-    if args.len() != 3 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn journal_set_bundle(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
 
     // Parse args
-    let timestamp = match parse_timestamp(args.first().unwrap(), tz) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse timestamp error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
-
-    let meal = match Meal::new_str(args.get(1).unwrap()) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse meal error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
-
-    let bnld_key = args.get(2).unwrap();
+    let timestamp: Timestamp = args.get("timestamp");
+    let meal: Meal = args.get("meal");
+    let bndl_key: String = args.get("bndl_key");
 
     // Call storage
-    match stg.set_journal_bundle(user_id, timestamp, meal, bnld_key) {
+    let ts = timestamp.clone();
+    match stg
+        .call(move |s| s.set_journal_bundle(user_id, ts, meal, &bndl_key))
+        .await
+    {
         Ok(_) => {
-            bot.send_message(chat_id, OK).await?;
+            out.text(OK).await?;
+            send_remaining_status(&out, user_id, &stg, &timestamp, tz).await?;
         }
         Err(err) => {
             log::error!("set journal error: {err}");
-            if stg.is_storage_error(StorageError::FoodNotFound, &err) {
-                bot.send_message(chat_id, ERR_FOOD_NOT_FOUND).await?;
-            } else if stg.is_storage_error(StorageError::BundleNotFound, &err) {
-                bot.send_message(chat_id, ERR_BUNDLE_NOT_FOUND).await?;
-            } else {
-                bot.send_message(chat_id, ERR_INTERNAL).await?;
-            }
+            out.text(storage_error_message(&err)).await?;
         }
     }
 
     Ok(())
 }
 
-async fn journal_del(
-    bot: Bot,
+// One line status for the day of journal entry, errors are only logged
+// because the entry is already saved.
+async fn send_remaining_status(
+    out: &Output,
     user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
+    stg: &StorageAsync,
+    timestamp: &Timestamp,
     tz: Tz,
 ) -> HandlerResult {
-    if args.len() != 3 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
+    let ts = timestamp.clone();
+    match stg.call(move |s| load_day_report(user_id, s, &ts)).await {
+        Ok(dr) => {
+            let ts_str = format_timestamp(timestamp, "%d.%m.%Y", tz);
+            let day = if ts_str == format_timestamp(&Timestamp::now(), "%d.%m.%Y", tz) {
+                String::from("на сегодня")
+            } else {
+                format!("на {ts_str}")
+            };
+            out.text(remaining_text(&dr, &day)).await?;
+        }
+        Err(err) => log::error!("load day report error: {err}"),
     }
 
-    // Parse args
-    let timestamp = match parse_timestamp(args.first().unwrap(), tz) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse timestamp error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
+    Ok(())
+}
 
-    let meal = match Meal::new_str(args.get(1).unwrap()) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse meal error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
+async fn journal_del(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
 
-    let food_key = args.get(2).unwrap();
+    // Parse args
+    let timestamp: Timestamp = args.get("timestamp");
+    let meal: Meal = args.get("meal");
+    let food_key: String = args.get("food_key");
 
     // Call storage
-    if let Err(err) = stg.delete_journal(user_id, timestamp, meal, food_key) {
+    if let Err(err) = stg
+        .call(move |s| s.delete_journal(user_id, timestamp, meal, &food_key))
+        .await
+    {
         log::error!("del journal error: {err}");
-        bot.send_message(chat_id, ERR_INTERNAL).await?;
+        out.text(storage_error_message(&err)).await?;
         return Ok(());
     }
 
-    bot.send_message(chat_id, OK).await?;
+    out.text(OK).await?;
 
     Ok(())
 }
 
-async fn journal_del_meal(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-    tz: Tz,
-) -> HandlerResult {
-    if args.len() != 2 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn journal_del_meal(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
 
     // Parse args
-    let timestamp = match parse_timestamp(args.first().unwrap(), tz) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse timestamp error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
-
-    let meal = match Meal::new_str(args.get(1).unwrap()) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse meal error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
+    let timestamp: Timestamp = args.get("timestamp");
+    let meal: Meal = args.get("meal");
 
     // Call storage
-    if let Err(err) = stg.delete_journal_meal(user_id, timestamp, meal) {
+    if let Err(err) = stg
+        .call(move |s| s.delete_journal_meal(user_id, timestamp, meal))
+        .await
+    {
         log::error!("del journal meal error: {err}");
-        bot.send_message(chat_id, ERR_INTERNAL).await?;
+        out.text(storage_error_message(&err)).await?;
         return Ok(());
     }
 
-    bot.send_message(chat_id, OK).await?;
+    out.text(OK).await?;
 
     Ok(())
 }
 
-async fn journal_report_day(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-    tz: Tz,
-) -> HandlerResult {
-    if args.len() != 1 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn journal_report_day(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
 
     // Parse args
-    let timestamp = match parse_timestamp(args.first().unwrap(), tz) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse timestamp error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
+    let timestamp: Timestamp = args.get("timestamp");
 
     // Call storage
-    let rep = match stg.get_journal_report(user_id, timestamp.clone(), timestamp.clone()) {
+    let ts = timestamp.clone();
+    let rep = match stg
+        .call(move |s| s.get_journal_report(user_id, ts.clone(), ts))
+        .await
+    {
         Ok(v) => v,
         Err(err) => {
             log::error!("get journal report error: {err}");
-            if stg.is_storage_error(StorageError::EmptyResult, &err) {
-                bot.send_message(chat_id, ERR_EMPTY).await?;
-            } else {
-                bot.send_message(chat_id, ERR_INTERNAL).await?;
-            }
+            out.text(storage_error_message(&err)).await?;
             return Ok(());
         }
     };
 
-    let us: Option<UserSettings> = match stg.get_user_settings(user_id) {
+    let us: Option<UserSettings> = match stg.call(move |s| s.get_user_settings(user_id)).await {
         Ok(v) => Some(v),
+        Err(StorageError::UserSettingsNotFound) => None,
         Err(err) => {
-            if stg.is_storage_error(StorageError::UserSettingsNotFound, &err) {
-                None
-            } else {
-                bot.send_message(chat_id, ERR_INTERNAL).await?;
-                return Ok(());
-            }
+            log::error!("get user settings error: {err}");
+            out.text(storage_error_message(&err)).await?;
+            return Ok(());
         }
     };
 
@@ -352,9 +328,8 @@ async fn journal_report_day(
     ]);
     let ts_str = format_timestamp(&timestamp, "%d.%m.%Y", tz);
 
-    let (mut total_cal, mut total_prot, mut total_fat, mut total_carb) = (0.0, 0.0, 0.0, 0.0);
-    let (mut sub_total_cal, mut sub_total_prot, mut sub_total_fat, mut sub_total_carb) =
-        (0.0, 0.0, 0.0, 0.0);
+    let dr = DayReport::new(&rep, us.as_ref().map(|v| v.cal_limit));
+    let mut meals = dr.meals.iter();
     let mut last_meal: Option<Meal> = None;
 
     for i in 0..rep.len() {
@@ -396,18 +371,9 @@ async fn journal_report_day(
                 .add_td(Td::new(S::create(&format!("{:.2}", jr.carb)))),
         );
 
-        total_cal += jr.cal;
-        total_prot += jr.prot;
-        total_fat += jr.fat;
-        total_carb += jr.carb;
-
-        sub_total_cal += jr.cal;
-        sub_total_prot += jr.prot;
-        sub_total_fat += jr.fat;
-        sub_total_carb += jr.carb;
-
         // Add subtotal row
         if i == rep.len() - 1 || rep[i + 1].meal != jr.meal {
+            let sub_total = meals.next().unwrap().total;
             tbl.add_row(
                 Tr::new()
                     .add_td(
@@ -415,32 +381,30 @@ async fn journal_report_day(
                             vec![("colspan", "2"), ("align", "right")].into_iter(),
                         )),
                     )
-                    .add_td(Td::new(S::create(&format!("{:.2}", sub_total_cal))))
-                    .add_td(Td::new(S::create(&format!("{:.2}", sub_total_prot))))
-                    .add_td(Td::new(S::create(&format!("{:.2}", sub_total_fat))))
-                    .add_td(Td::new(S::create(&format!("{:.2}", sub_total_carb)))),
+                    .add_td(Td::new(S::create(&format!("{:.2}", sub_total.cal))))
+                    .add_td(Td::new(S::create(&format!("{:.2}", sub_total.prot))))
+                    .add_td(Td::new(S::create(&format!("{:.2}", sub_total.fat))))
+                    .add_td(Td::new(S::create(&format!("{:.2}", sub_total.carb)))),
             );
-
-            (sub_total_cal, sub_total_prot, sub_total_fat, sub_total_carb) = (0.0, 0.0, 0.0, 0.0);
         }
     }
 
     // Footer
-    let total_pfc = total_prot + total_fat + total_carb;
+    let total = dr.total;
+    let total_pfc = total.prot + total.fat + total.carb;
     tbl.add_footer_element(
         Tr::new()
             .add_td(
                 Td::new(Span::create(vec![
                     B::new("Всего потреблено, ккал: ").as_box(),
-                    S::create(&format!("{:.2}", total_cal)),
+                    S::create(&format!("{:.2}", total.cal)),
                 ]))
                 .set_attrs(Attrs::from_items(vec![("colspan", "6")].into_iter())),
             )
             .as_box(),
     );
 
-    if us.is_some() {
-        let us = us.unwrap();
+    if let Some(us) = &us {
         tbl.add_footer_element(
             Tr::new()
                 .add_td(
@@ -457,7 +421,7 @@ async fn journal_report_day(
                 .add_td(
                     Td::new(Span::create(vec![
                         B::new("Разница, ккал: ").as_box(),
-                        call_diff_snippet(us.cal_limit - total_cal),
+                        call_diff_snippet(us.cal_limit - total.cal),
                     ]))
                     .set_attrs(Attrs::from_items(vec![("colspan", "6")].into_iter())),
                 )
@@ -470,7 +434,7 @@ async fn journal_report_day(
             .add_td(
                 Td::new(Span::create(vec![
                     B::new("Всего, Б: ").as_box(),
-                    pfc_snippet(total_prot, total_pfc),
+                    pfc_snippet(total.prot, total_pfc),
                 ]))
                 .set_attrs(Attrs::from_items(vec![("colspan", "6")].into_iter())),
             )
@@ -481,7 +445,7 @@ async fn journal_report_day(
             .add_td(
                 Td::new(Span::create(vec![
                     B::new("Всего, Ж: ").as_box(),
-                    pfc_snippet(total_fat, total_pfc),
+                    pfc_snippet(total.fat, total_pfc),
                 ]))
                 .set_attrs(Attrs::from_items(vec![("colspan", "6")].into_iter())),
             )
@@ -492,76 +456,223 @@ async fn journal_report_day(
             .add_td(
                 Td::new(Span::create(vec![
                     B::new("Всего, У: ").as_box(),
-                    pfc_snippet(total_carb, total_pfc),
+                    pfc_snippet(total.carb, total_pfc),
                 ]))
                 .set_attrs(Attrs::from_items(vec![("colspan", "6")].into_iter())),
             )
             .as_box(),
     );
 
-    doc = doc.add_element(
-        Div::new_container()
+    // Charts of macro split and calories by meal
+    let pfc_chart = ChartData {
+        elem_id: "chart_pfc".into(),
+        x_labels: vec!["Белки, г".into(), "Жиры, г".into(), "Углеводы, г".into()],
+        ctype: ChartType::Doughnut,
+        datasets: vec![ChartDataset {
+            data: vec![total.prot, total.fat, total.carb],
+            label: "БЖУ".into(),
+            color: CHART_COLOR_GREY.into(),
+            colors: vec![
+                CHART_COLOR_RED.into(),
+                CHART_COLOR_YELLOW.into(),
+                CHART_COLOR_GREEN.into(),
+            ],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let meal_chart = ChartData {
+        elem_id: "chart_meal".into(),
+        x_labels: dr.meals.iter().map(|mt| String::from(mt.meal)).collect(),
+        ctype: ChartType::Doughnut,
+        datasets: vec![ChartDataset {
+            data: dr.meals.iter().map(|mt| mt.total.cal).collect(),
+            label: "ККал".into(),
+            color: CHART_COLOR_GREY.into(),
+            colors: (0..dr.meals.len())
+                .map(|i| CHART_COLORS[i % CHART_COLORS.len()].into())
+                .collect(),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    let mut chart_snips = Vec::new();
+    for chart_data in [&pfc_chart, &meal_chart] {
+        match get_chart_snippet(chart_data) {
+            Ok(snip) => chart_snips.push(snip),
+            Err(err) => {
+                log::error!("chart snippet error: {err}");
+                out.text(ERR_INTERNAL).await?;
+                return Ok(());
+            }
+        }
+    }
+
+    let mut container = Div::new_container()
+        .add_element(
+            H::new(&format!("Журнал приема пищи за {}", ts_str), 5)
+                .set_attr(Attrs::from_items(vec![("align", "center")].into_iter()))
+                .as_box(),
+        )
+        .add_element(tbl.as_box());
+
+    // Calories against the limit
+    if let Some(us) = &us {
+        container = container.add_element(
+            Progress::new(total.cal, us.cal_limit)
+                .set_label(&format!(
+                    "{:.0} / {:.0} ккал ({:.0}%)",
+                    total.cal,
+                    us.cal_limit,
+                    dr.limit_percent().unwrap_or_default()
+                ))
+                .set_class(if total.cal > us.cal_limit {
+                    "bg-danger"
+                } else {
+                    "bg-success"
+                })
+                .as_box(),
+        );
+    }
+
+    container = container.add_element(
+        Div::new("row mt-3")
+            .add_element(
+                Div::new("col-md-6")
+                    .add_element(H::new("БЖУ", 6).as_box())
+                    .add_element(Canvas::create(&pfc_chart.elem_id))
+                    .as_box(),
+            )
             .add_element(
-                H::new(&format!("Журнал приема пищи за {}", ts_str), 5)
-                    .set_attr(Attrs::from_items(vec![("align", "center")].into_iter()))
+                Div::new("col-md-6")
+                    .add_element(H::new("ККал по приемам пищи", 6).as_box())
+                    .add_element(Canvas::create(&meal_chart.elem_id))
                     .as_box(),
             )
-            .add_element(tbl.as_box())
             .as_box(),
     );
 
-    bot.send_document(
-        chat_id,
-        InputFile::memory(doc.build()).file_name(format!("report_{}.html", ts_str)),
-    )
-    .await?;
+    doc = doc
+        .add_element(container.as_box())
+        .add_element(Script::create_asset(Asset::JsBootstrap))
+        .add_element(Script::create_asset(Asset::JsChart));
+    for snip in &chart_snips {
+        doc = doc.add_element(Raw::create(snip));
+    }
+
+    out.document(format!("report_{}.html", ts_str), doc.build())
+        .await?;
+
+    // Chart of calories and macros by meal
+    let mut chart_data = ChartData {
+        elem_id: "chart".into(),
+        x_labels: Vec::new(),
+        ctype: ChartType::Bar,
+        // Calories on the left axis, macros in grams on the right one
+        datasets: [
+            ("ККал", CHART_COLOR_BLUE, None),
+            ("Белки", CHART_COLOR_RED, Some(MACROS_AXIS)),
+            ("Жиры", CHART_COLOR_YELLOW, Some(MACROS_AXIS)),
+            ("Углеводы", CHART_COLOR_GREEN, Some(MACROS_AXIS)),
+        ]
+        .into_iter()
+        .map(|(label, color, axis)| ChartDataset {
+            data: Vec::new(),
+            label: label.into(),
+            color: color.into(),
+            axis: axis.map(String::from),
+            ..Default::default()
+        })
+        .collect(),
+        axes: vec![
+            Axis {
+                unit: "ккал".into(),
+                ..Default::default()
+            },
+            Axis {
+                id: MACROS_AXIS.into(),
+                position: AxisPosition::Right,
+                unit: "г".into(),
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+    for mt in &dr.meals {
+        chart_data.x_labels.push(String::from(mt.meal));
+        for (ds, v) in chart_data.datasets.iter_mut().zip([
+            mt.total.cal,
+            mt.total.prot,
+            mt.total.fat,
+            mt.total.carb,
+        ]) {
+            ds.data.push(v);
+        }
+    }
+
+    send_chart_photo(&out, chart_data, &format!("report_{}.png", ts_str)).await?;
 
     Ok(())
 }
 
-async fn journal_template_meal(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-    tz: Tz,
-) -> HandlerResult {
-    if args.len() != 2 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn journal_report_text(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
 
     // Parse args
-    let timestamp = match parse_timestamp(args.first().unwrap(), tz) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse timestamp error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
+    let timestamp: Timestamp = args.get("timestamp");
 
-    let meal = match Meal::new_str(args.get(1).unwrap()) {
+    // Call storage
+    let ts = timestamp.clone();
+    let dr = match stg.call(move |s| load_day_report(user_id, s, &ts)).await {
         Ok(v) => v,
         Err(err) => {
-            log::error!("parse meal error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
+            log::error!("load day report error: {err}");
+            out.text(ERR_INTERNAL).await?;
             return Ok(());
         }
     };
 
+    if dr.is_empty() {
+        out.text(ERR_EMPTY).await?;
+        return Ok(());
+    }
+
+    let title = format!(
+        "Журнал приема пищи за {}",
+        format_timestamp(&timestamp, "%d.%m.%Y", tz)
+    );
+    out.html(day_report_text(&dr, &title)).await?;
+
+    Ok(())
+}
+
+async fn journal_template_meal(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
+
+    // Parse args
+    let timestamp: Timestamp = args.get("timestamp");
+    let meal: Meal = args.get("meal");
+
     // Call storage
-    let rep = match stg.get_journal_report(user_id, timestamp.clone(), timestamp) {
+    let rep = match stg
+        .call(move |s| s.get_journal_report(user_id, timestamp.clone(), timestamp))
+        .await
+    {
         Ok(v) => v,
         Err(err) => {
             log::error!("get journal report error: {err}");
-            if stg.is_storage_error(StorageError::EmptyResult, &err) {
-                bot.send_message(chat_id, ERR_EMPTY).await?;
-            } else {
-                bot.send_message(chat_id, ERR_INTERNAL).await?;
-            }
+            out.text(storage_error_message(&err)).await?;
             return Ok(());
         }
     };
@@ -574,84 +685,68 @@ async fn journal_template_meal(
     }
 
     if meal_rep.is_empty() {
-        bot.send_message(chat_id, ERR_EMPTY).await?;
+        out.text(ERR_EMPTY).await?;
         return Ok(());
     }
 
     // Send response
-    bot.send_message(chat_id, "<b>Изменение еды</b>")
-        .parse_mode(ParseMode::Html)
-        .await?;
+    out.html("<b>Изменение еды</b>").await?;
 
     for jr in &meal_rep {
-        bot.send_message(
-            chat_id,
-            format!(
-                "j,set,{},{},{},{:.1}",
-                format_timestamp(&jr.timestamp, "%d.%m.%Y", tz),
-                String::from(jr.meal),
-                jr.food_key,
-                jr.food_weight
-            ),
-        )
+        out.text(format!(
+            "j,set,{},{},{},{:.1}",
+            format_timestamp(&jr.timestamp, "%d.%m.%Y", tz),
+            String::from(jr.meal),
+            jr.food_key,
+            jr.food_weight
+        ))
         .await?;
     }
 
-    bot.send_message(chat_id, "<b>Удаление еды</b>")
-        .parse_mode(ParseMode::Html)
-        .await?;
+    out.html("<b>Удаление еды</b>").await?;
 
     for jr in &meal_rep {
-        bot.send_message(
-            chat_id,
-            format!(
-                "j,del,{},{},{}",
-                format_timestamp(&jr.timestamp, "%d.%m.%Y", tz),
-                String::from(jr.meal),
-                jr.food_key,
-            ),
-        )
+        out.text(format!(
+            "j,del,{},{},{}",
+            format_timestamp(&jr.timestamp, "%d.%m.%Y", tz),
+            String::from(jr.meal),
+            jr.food_key,
+        ))
         .await?;
     }
 
     Ok(())
 }
 
-async fn journal_food_avg_weight(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-    tz: Tz,
-) -> HandlerResult {
-    if args.len() != 1 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn journal_food_avg_weight(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
 
     // Parse args
-    let food_key = args.first().unwrap();
+    let food_key: String = args.get("food_key");
 
     let ts_to = Timestamp::now().with_timezone(tz);
     let ts_from = ts_to.sub(Duration::days(365));
 
     // Call storage
-    let res = match stg.get_journal_food_avg_weight(user_id, food_key, ts_from, ts_to) {
+    let res = match stg
+        .call(move |s| s.get_journal_food_avg_weight(user_id, &food_key, ts_from, ts_to))
+        .await
+    {
         Ok(v) => v,
         Err(err) => {
             log::error!("get journal avg weight error: {err}");
-            bot.send_message(chat_id, ERR_INTERNAL).await?;
+            out.text(storage_error_message(&err)).await?;
             return Ok(());
         }
     };
 
-    bot.send_message(
-        chat_id,
-        format!("Средний вес приема пищи за год, гр.: {:.1}", res),
-    )
-    .await?;
+    out.text(format!("Средний вес приема пищи за год, гр.: {:.1}", res))
+        .await?;
 
     Ok(())
 }
@@ -1,73 +1,256 @@
-use std::{io::Read, sync::Arc};
+use std::io::Read;
 
 use anyhow::Context;
 use chrono_tz::Tz;
 use flate2::{bufread::GzEncoder, Compression};
-use serde_json::json;
-use storage::Storage;
-use teloxide::{
-    prelude::*,
-    types::{ChatId, InputFile},
-    Bot,
+use html::{
+    attrs::Attrs,
+    div::Div,
+    h::H,
+    s::S,
+    table::{Table, Td, Tr},
 };
+use model::Change;
+use serde_json::json;
+use storage::{CHANGE_LOG_SIZE, SYSTEM_USER_ID};
 use types::timestamp::Timestamp;
 
-use crate::{
-    messages::{ERR_INTERNAL, ERR_WRONG_COMMAND},
-    HandlerResult,
+use crate::{messages::MSG_UNDONE, HandlerResult};
+
+use super::{
+    format_timestamp,
+    registry::{Arg, ArgKind, Args, Command, Group},
+    storage_error_message, Ctx,
 };
 
-use super::format_timestamp;
-
-pub async fn process_maintenance(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-    tz: Tz,
-) -> HandlerResult {
-    if args.is_empty() {
-        log::error!("empty args");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+pub const GROUP: Group = Group {
+    name: "m",
+    title: "Обслуживание",
+    commands: &[
+        Command {
+            name: "backup",
+            aliases: &[],
+            args: &[],
+            description: "Резервная копия данных",
+            handler: |ctx, _| Box::pin(backup(ctx)),
+        },
+        Command {
+            name: "undo",
+            aliases: &["u"],
+            args: &[Arg::opt("count", ArgKind::Int)],
+            description: "Отменить последние изменения, по умолчанию одно",
+            handler: |ctx, args| Box::pin(undo(ctx, args)),
+        },
+        Command {
+            name: "changes",
+            aliases: &["ch"],
+            args: &[Arg::opt("count", ArgKind::Int)],
+            description: "Последние изменения, по умолчанию 10",
+            handler: |ctx, args| Box::pin(changes(ctx, args)),
+        },
+        Command {
+            name: "audit",
+            aliases: &["a"],
+            args: &[
+                Arg::req("ts_from", ArgKind::Date),
+                Arg::req("ts_to", ArgKind::Date),
+            ],
+            description: "Журнал изменений данных всеми пользователями",
+            handler: |ctx, args| Box::pin(audit(ctx, args)),
+        },
+    ],
+};
 
-    match *args.first().unwrap() {
-        "backup" => {
-            backup(bot, user_id, chat_id, stg, tz).await?;
-        }
-        _ => {
-            log::error!("unknown command");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        }
-    };
+const DEFAULT_CHANGES_COUNT: i64 = 10;
 
-    Ok(())
-}
+async fn backup(ctx: Ctx) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
 
-async fn backup(bot: Bot, user_id: i64, chat_id: ChatId, stg: Arc<Box<dyn Storage>>, tz: Tz) -> HandlerResult {
     // Get storage data for backup
-    let res = stg.backup(user_id);
+    let res = stg.call(move |s| s.backup(user_id)).await;
     if let Err(err) = res {
         log::error!("backup error: {err}");
-        bot.send_message(chat_id, ERR_INTERNAL).await?;
+        out.text(storage_error_message(&err)).await?;
         return Ok(());
     }
 
     let data = serde_json::to_vec(&json!(res.unwrap())).context("failed to serde JSON backup")?;
     let mut gz = GzEncoder::new(&data[..], Compression::best());
-    let mut out = Vec::new();
-    gz.read_to_end(&mut out).context("failed to gzip backup")?;
+    let mut gz_data = Vec::new();
+    gz.read_to_end(&mut gz_data)
+        .context("failed to gzip backup")?;
 
-    bot.send_document(
-        chat_id,
-        InputFile::memory(out).file_name(format!(
+    out.document(
+        format!(
             "backup_{}.json.gz",
             format_timestamp(&Timestamp::now(), "%d.%m.%Y", tz)
-        )),
+        ),
+        gz_data,
     )
     .await?;
 
     Ok(())
 }
+
+async fn undo(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
+
+    let Some(count) = changes_count(&args, 1) else {
+        out.html(args.wrong_arg("count", &count_reason())).await?;
+        return Ok(());
+    };
+
+    let changes = match stg.call(move |s| s.undo(user_id, count)).await {
+        Ok(v) => v,
+        Err(err) => {
+            log::error!("undo error: {err}");
+            out.text(storage_error_message(&err)).await?;
+            return Ok(());
+        }
+    };
+
+    out.text(format!("{MSG_UNDONE}:\n{}", format_changes(&changes, tz)))
+        .await?;
+
+    Ok(())
+}
+
+async fn changes(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
+
+    let Some(count) = changes_count(&args, DEFAULT_CHANGES_COUNT) else {
+        out.html(args.wrong_arg("count", &count_reason())).await?;
+        return Ok(());
+    };
+
+    let changes = match stg.call(move |s| s.get_change_list(user_id, count)).await {
+        Ok(v) => v,
+        Err(err) => {
+            log::error!("change list error: {err}");
+            out.text(storage_error_message(&err)).await?;
+            return Ok(());
+        }
+    };
+
+    out.text(format_changes(&changes, tz)).await?;
+
+    Ok(())
+}
+
+async fn audit(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx { out, stg, tz, .. } = ctx;
+
+    // Parse args
+    let ts_from: Timestamp = args.get("ts_from");
+    let ts_to: Timestamp = args.get("ts_to");
+
+    // Call storage
+    let (from, to) = (ts_from.start_of_day(), ts_to.end_of_day());
+    let a_lst = match stg.call(move |s| s.get_audit_list(from, to)).await {
+        Err(err) => {
+            log::error!("audit list error: {err}");
+            out.text(storage_error_message(&err)).await?;
+            return Ok(());
+        }
+        Ok(lst) => lst,
+    };
+
+    // Generate HTML
+    let mut doc = html::Builder::new("Журнал изменений");
+    let mut tbl = Table::new(vec![
+        "Дата".into(),
+        "Пользователь".into(),
+        "Данные".into(),
+        "Ключ".into(),
+        "Действие".into(),
+        "Было".into(),
+        "Стало".into(),
+    ]);
+
+    for a in &a_lst {
+        tbl.add_row(
+            Tr::new()
+                .add_td(Td::new(S::create(&format_timestamp(
+                    &a.timestamp,
+                    "%d.%m.%Y %H:%M:%S",
+                    tz,
+                ))))
+                .add_td(Td::new(S::create(&if a.user_id == SYSTEM_USER_ID {
+                    "система".to_string()
+                } else {
+                    a.user_id.to_string()
+                })))
+                .add_td(Td::new(S::create(&a.entity)))
+                .add_td(Td::new(S::create(&a.key)))
+                .add_td(Td::new(S::create(&String::from(a.action))))
+                .add_td(Td::new(S::create(a.old_value.as_deref().unwrap_or(""))))
+                .add_td(Td::new(S::create(a.new_value.as_deref().unwrap_or("")))),
+        );
+    }
+
+    doc = doc.add_element(
+        Div::new_container()
+            .add_element(
+                H::new(
+                    &format!(
+                        "Журнал изменений за {} - {}",
+                        format_timestamp(&ts_from, "%d.%m.%Y", tz),
+                        format_timestamp(&ts_to, "%d.%m.%Y", tz)
+                    ),
+                    5,
+                )
+                .set_attr(Attrs::from_items(vec![("align", "center")].into_iter()))
+                .as_box(),
+            )
+            .add_element(tbl.as_box())
+            .as_box(),
+    );
+
+    out.document("audit.html", doc.build()).await?;
+
+    Ok(())
+}
+
+// Only changes kept in change log can be requested
+fn changes_count(args: &Args, default: i64) -> Option<usize> {
+    let count = args.opt::<i64>("count").unwrap_or(default);
+    (1..=CHANGE_LOG_SIZE as i64)
+        .contains(&count)
+        .then_some(count as usize)
+}
+
+fn count_reason() -> String {
+    format!("должно быть от 1 до {CHANGE_LOG_SIZE}")
+}
+
+fn format_changes(changes: &[Change], tz: Tz) -> String {
+    let mut res = String::new();
+    for c in changes {
+        res.push_str(&format!(
+            "{} {}",
+            format_timestamp(&c.timestamp, "%d.%m.%Y %H:%M", tz),
+            String::from(c.action)
+        ));
+        if !c.key.is_empty() {
+            res.push_str(&format!(": {}", c.key));
+        }
+        res.push('\n');
+    }
+
+    res
+}
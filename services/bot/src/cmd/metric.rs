@@ -0,0 +1,413 @@
+use chart::{get_chart_snippet, ChartData, ChartDataset, ChartType, CHART_COLORS};
+use html::{
+    accordion::{Accordion, AccordionItem},
+    attrs::Attrs,
+    canvas::Canvas,
+    div::Div,
+    h::H,
+    raw::Raw,
+    s::S,
+    script::Script,
+    table::{Table, Td, Tr},
+    Asset,
+};
+use model::{Metric, MetricValue};
+use types::timestamp::Timestamp;
+
+use crate::{
+    messages::{ERR_INTERNAL, OK},
+    HandlerResult,
+};
+
+use super::{
+    format_timestamp,
+    registry::{Arg, ArgKind, Args, Command, Group},
+    storage_error_message, Ctx,
+};
+
+pub const GROUP: Group = Group {
+    name: "mt",
+    title: "Метрики",
+    commands: &[
+        Command {
+            name: "set",
+            aliases: &[],
+            args: &[
+                Arg::req("key", ArgKind::Key),
+                Arg::req("name", ArgKind::Text),
+                Arg::req("unit", ArgKind::Text),
+                Arg::many("fields", ArgKind::Key),
+            ],
+            description: "Установить метрику",
+            handler: |ctx, args| Box::pin(metric_set(ctx, args)),
+        },
+        Command {
+            name: "st",
+            aliases: &[],
+            args: &[Arg::req("key", ArgKind::Key)],
+            description: "Шаблон команды установки метрики",
+            handler: |ctx, args| Box::pin(metric_set_template(ctx, args)),
+        },
+        Command {
+            name: "list",
+            aliases: &[],
+            args: &[],
+            description: "Список метрик",
+            handler: |ctx, _| Box::pin(metric_list(ctx)),
+        },
+        Command {
+            name: "del",
+            aliases: &[],
+            args: &[Arg::req("key", ArgKind::Key)],
+            description: "Удалить метрику",
+            handler: |ctx, args| Box::pin(metric_del(ctx, args)),
+        },
+        Command {
+            name: "vs",
+            aliases: &[],
+            args: &[
+                Arg::req("timestamp", ArgKind::DateTime),
+                Arg::req("metric_key", ArgKind::Key),
+                Arg::many("values", ArgKind::Float),
+            ],
+            description: "Установить значения метрики",
+            handler: |ctx, args| Box::pin(metric_value_set(ctx, args)),
+        },
+        Command {
+            name: "vd",
+            aliases: &[],
+            args: &[
+                Arg::req("timestamp", ArgKind::DateTime),
+                Arg::req("metric_key", ArgKind::Key),
+            ],
+            description: "Удалить значения метрики",
+            handler: |ctx, args| Box::pin(metric_value_del(ctx, args)),
+        },
+        Command {
+            name: "vl",
+            aliases: &[],
+            args: &[
+                Arg::req("metric_key", ArgKind::Key),
+                Arg::req("ts_from", ArgKind::Date),
+                Arg::req("ts_to", ArgKind::Date),
+            ],
+            description: "Отчет по значениям метрики",
+            handler: |ctx, args| Box::pin(metric_value_list(ctx, args)),
+        },
+    ],
+};
+
+async fn metric_set(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
+
+    let key: String = args.get("key");
+    let name: String = args.get("name");
+    let unit: String = args.get("unit");
+    let fields: Vec<String> = args.many("fields");
+
+    // Call storage
+    if let Err(err) = stg
+        .call(move |s| {
+            s.set_metric(
+                user_id,
+                &Metric {
+                    key,
+                    name,
+                    unit,
+                    fields,
+                },
+            )
+        })
+        .await
+    {
+        log::error!("set metric error: {err}");
+        out.text(storage_error_message(&err)).await?;
+    } else {
+        out.text(OK).await?;
+    }
+
+    Ok(())
+}
+
+async fn metric_set_template(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
+
+    let key: String = args.get("key");
+
+    // Call storage
+    let metric = match stg.call(move |s| s.get_metric(user_id, &key)).await {
+        Err(err) => {
+            log::error!("get metric error: {err}");
+            out.text(storage_error_message(&err)).await?;
+            return Ok(());
+        }
+        Ok(m) => m,
+    };
+
+    out.text(format!(
+        "mt,set,{},{},{},{}",
+        metric.key,
+        metric.name,
+        metric.unit,
+        metric.fields.join(",")
+    ))
+    .await?;
+
+    Ok(())
+}
+
+async fn metric_list(ctx: Ctx) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
+
+    // Call storage
+    let m_lst = match stg.call(move |s| s.get_metric_list(user_id)).await {
+        Err(err) => {
+            log::error!("metric list error: {err}");
+            out.text(storage_error_message(&err)).await?;
+            return Ok(());
+        }
+        Ok(lst) => lst,
+    };
+
+    let mut doc = html::Builder::new("Список метрик");
+    let mut tbl = Table::new(vec![
+        "Ключ".into(),
+        "Наименование".into(),
+        "Единица измерения".into(),
+        "Поля".into(),
+    ]);
+
+    for m in &m_lst {
+        tbl.add_row(
+            Tr::new()
+                .add_td(Td::new(S::create(&m.key)))
+                .add_td(Td::new(S::create(&m.name)))
+                .add_td(Td::new(S::create(&m.unit)))
+                .add_td(Td::new(S::create(&m.fields.join(", ")))),
+        );
+    }
+
+    doc = doc.add_element(
+        Div::new_container()
+            .add_element(
+                H::new("Список метрик", 5)
+                    .set_attr(Attrs::from_items(vec![("align", "center")].into_iter()))
+                    .as_box(),
+            )
+            .add_element(tbl.as_box())
+            .as_box(),
+    );
+
+    out.document("metric.html", doc.build()).await?;
+
+    Ok(())
+}
+
+async fn metric_del(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
+
+    let key: String = args.get("key");
+
+    // Call storage
+    if let Err(err) = stg.call(move |s| s.delete_metric(user_id, &key)).await {
+        log::error!("del metric error: {err}");
+        out.text(storage_error_message(&err)).await?;
+        return Ok(());
+    };
+
+    out.text(OK).await?;
+
+    Ok(())
+}
+
+async fn metric_value_set(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
+
+    // Parse args
+    let timestamp: Timestamp = args.get("timestamp");
+    let metric_key: String = args.get("metric_key");
+    let values: Vec<f64> = args.many("values");
+
+    // Call storage
+    if let Err(err) = stg
+        .call(move |s| {
+            s.set_metric_value(
+                user_id,
+                &MetricValue {
+                    metric_key,
+                    timestamp,
+                    values,
+                },
+            )
+        })
+        .await
+    {
+        log::error!("set metric value error: {err}");
+        out.text(storage_error_message(&err)).await?;
+        return Ok(());
+    }
+
+    out.text(OK).await?;
+
+    Ok(())
+}
+
+async fn metric_value_del(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
+
+    // Parse args
+    let timestamp: Timestamp = args.get("timestamp");
+    let metric_key: String = args.get("metric_key");
+
+    // Call storage
+    if let Err(err) = stg
+        .call(move |s| s.delete_metric_value(user_id, timestamp, &metric_key))
+        .await
+    {
+        log::error!("del metric value error: {err}");
+        out.text(storage_error_message(&err)).await?;
+        return Ok(());
+    }
+
+    out.text(OK).await?;
+
+    Ok(())
+}
+
+async fn metric_value_list(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
+
+    let metric_key: String = args.get("metric_key");
+
+    // Parse args
+    let ts_from: Timestamp = args.get("ts_from");
+    let ts_to: Timestamp = args.get("ts_to");
+
+    // Call storage
+    let key = metric_key.clone();
+    let metric = match stg.call(move |s| s.get_metric(user_id, &key)).await {
+        Err(err) => {
+            log::error!("get metric error: {err}");
+            out.text(storage_error_message(&err)).await?;
+            return Ok(());
+        }
+        Ok(m) => m,
+    };
+
+    let (from, to) = (ts_from.clone(), ts_to.end_of_day());
+    let v_lst = match stg
+        .call(move |s| s.get_metric_value_list(user_id, &metric_key, from, to))
+        .await
+    {
+        Err(err) => {
+            log::error!("metric value list error: {err}");
+            out.text(storage_error_message(&err)).await?;
+            return Ok(());
+        }
+        Ok(lst) => lst,
+    };
+
+    // Generate HTML
+    let ts_from = format_timestamp(&ts_from, "%d.%m.%Y", tz);
+    let ts_to = format_timestamp(&ts_to, "%d.%m.%Y", tz);
+    let title = format!("{}, {}", metric.name, metric.unit);
+
+    let mut doc = html::Builder::new(&metric.name);
+    let mut accrd = Accordion::new("accordionMetric");
+
+    // Table
+    let mut header = Vec::with_capacity(metric.fields.len() + 1);
+    header.push("Дата".into());
+    header.extend(metric.fields.iter().cloned());
+
+    let mut tbl = Table::new(header);
+
+    let mut x_labels = Vec::with_capacity(v_lst.len());
+    let mut data: Vec<Vec<f64>> = vec![Vec::with_capacity(v_lst.len()); metric.fields.len()];
+
+    for v in &v_lst {
+        let ts = format_timestamp(&v.timestamp, "%d.%m.%Y %H:%M", tz);
+
+        let mut tr = Tr::new().add_td(Td::new(S::create(&ts)));
+        for (i, val) in v.values.iter().enumerate() {
+            tr = tr.add_td(Td::new(S::create(&format!("{:.1}", val))));
+            if let Some(d) = data.get_mut(i) {
+                d.push(*val);
+            }
+        }
+        tbl.add_row(tr);
+
+        x_labels.push(ts);
+    }
+
+    accrd.add_item(AccordionItem::new(
+        "tbl",
+        &format!("Таблица \"{}\" за {} - {}", &title, &ts_from, &ts_to),
+        tbl.as_box(),
+    ));
+
+    // Chart
+    accrd.add_item(AccordionItem::new(
+        "graph",
+        &format!("График \"{}\" за {} - {}", &title, &ts_from, &ts_to),
+        Canvas::create("chart"),
+    ));
+
+    let chart_snip = match get_chart_snippet(&ChartData {
+        elem_id: "chart".into(),
+        x_labels,
+        ctype: ChartType::Line,
+        datasets: metric
+            .fields
+            .iter()
+            .zip(data)
+            .enumerate()
+            .map(|(i, (field, data))| ChartDataset {
+                data,
+                label: field.clone(),
+                color: CHART_COLORS[i % CHART_COLORS.len()].into(),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    }) {
+        Err(err) => {
+            log::error!("chart snippet error: {err}");
+            out.text(ERR_INTERNAL).await?;
+            return Ok(());
+        }
+        Ok(snip) => snip,
+    };
+
+    // Doc
+    doc = doc
+        .add_element(Div::new_container().add_element(accrd.as_box()).as_box())
+        .add_element(Script::create_asset(Asset::JsBootstrap))
+        .add_element(Script::create_asset(Asset::JsChart))
+        .add_element(Raw::create(&chart_snip));
+
+    out.document(
+        format!("metric_{}_{}_{}.html", metric.key, &ts_from, &ts_to),
+        doc.build(),
+    )
+    .await?;
+
+    Ok(())
+}
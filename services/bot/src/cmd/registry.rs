@@ -0,0 +1,455 @@
+use chrono::NaiveTime;
+use chrono_tz::Tz;
+use html::escape;
+use model::{Meal, ScheduleKind, Sex};
+use std::{collections::HashMap, future::Future, pin::Pin};
+use types::timestamp::Timestamp;
+
+use crate::{
+    messages::{
+        ERR_UNKNOWN_COMMAND, ERR_WRONG_ARG, ERR_WRONG_ARGS_COUNT, MSG_HELP_HINT, MSG_USAGE,
+    },
+    HandlerResult,
+};
+
+use super::{parse_datetime, parse_timestamp, Ctx};
+
+pub type HandlerFuture = Pin<Box<dyn Future<Output = HandlerResult> + Send>>;
+pub type Handler = fn(Ctx, Args) -> HandlerFuture;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArgKind {
+    // Date, empty or omitted optional - today
+    Date,
+    // Date with optional time, empty - now
+    DateTime,
+    Meal,
+    Float,
+    Int,
+    // Non empty string
+    Key,
+    // Any string, may be empty
+    Text,
+    // One of listed values
+    Choice(&'static [&'static str]),
+    Sex,
+    ScheduleKind,
+    // HH:MM
+    Time,
+    Tz,
+    // food_key:weight or bundle_key
+    BundleItem,
+}
+
+impl ArgKind {
+    pub fn description(&self) -> String {
+        match self {
+            Self::Date => "дата ДД.ММ.ГГГГ, пусто - сегодня".into(),
+            Self::DateTime => "дата ДД.ММ.ГГГГ или ДД.ММ.ГГГГ ЧЧ:ММ, пусто - сейчас".into(),
+            Self::Meal => "прием пищи: завтрак, до обеда, обед, полдник, до ужина, ужин".into(),
+            Self::Float => "число".into(),
+            Self::Int => "целое число".into(),
+            Self::Key => "ключ".into(),
+            Self::Text => "текст".into(),
+            Self::Choice(values) => format!("одно из: {}", values.join(", ")),
+            Self::Sex => "пол: м, ж".into(),
+            Self::ScheduleKind => "вид расписания: w, j, sum, dw, dm".into(),
+            Self::Time => "время ЧЧ:ММ".into(),
+            Self::Tz => "часовой пояс, например Europe/Moscow".into(),
+            Self::BundleItem => "ключ_еды:вес или ключ_бандла".into(),
+        }
+    }
+
+    fn parse(&self, input: &str, tz: Tz) -> Option<ArgValue> {
+        match self {
+            Self::Date => parse_timestamp(input, tz).ok().map(ArgValue::Timestamp),
+            Self::DateTime => parse_datetime(input, tz).ok().map(ArgValue::Timestamp),
+            Self::Meal => Meal::new_str(input).ok().map(ArgValue::Meal),
+            Self::Float => input.parse::<f64>().ok().map(ArgValue::Float),
+            Self::Int => input.parse::<i64>().ok().map(ArgValue::Int),
+            Self::Key => (!input.is_empty()).then(|| ArgValue::Str(input.into())),
+            Self::Text => Some(ArgValue::Str(input.into())),
+            Self::Choice(values) => values.contains(&input).then(|| ArgValue::Str(input.into())),
+            Self::Sex => Sex::new_str(input).ok().map(ArgValue::Sex),
+            Self::ScheduleKind => ScheduleKind::new_str(input)
+                .ok()
+                .map(ArgValue::ScheduleKind),
+            Self::Time => NaiveTime::parse_from_str(input, "%H:%M")
+                .ok()
+                .map(ArgValue::Time),
+            Self::Tz => input.parse::<Tz>().ok().map(ArgValue::Tz),
+            Self::BundleItem => match input.split_once(':') {
+                None if !input.is_empty() => Some(ArgValue::BundleItem(input.into(), 0.0)),
+                None => None,
+                Some((key, weight)) => {
+                    let (key, weight) = (key.trim(), weight.trim().parse::<f64>().ok()?);
+                    (!key.is_empty()).then(|| ArgValue::BundleItem(key.into(), weight))
+                }
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arity {
+    Required,
+    // Only after required args
+    Optional,
+    // One or more values, only last arg
+    Many,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub arity: Arity,
+}
+
+impl Arg {
+    pub const fn req(name: &'static str, kind: ArgKind) -> Self {
+        Self {
+            name,
+            kind,
+            arity: Arity::Required,
+        }
+    }
+
+    pub const fn opt(name: &'static str, kind: ArgKind) -> Self {
+        Self {
+            name,
+            kind,
+            arity: Arity::Optional,
+        }
+    }
+
+    pub const fn many(name: &'static str, kind: ArgKind) -> Self {
+        Self {
+            name,
+            kind,
+            arity: Arity::Many,
+        }
+    }
+
+    fn usage(&self) -> String {
+        match self.arity {
+            Arity::Required => format!("<{}>", self.name),
+            Arity::Optional => format!("[{}]", self.name),
+            Arity::Many => format!("<{}>...", self.name),
+        }
+    }
+}
+
+pub struct Command {
+    // Empty for group with single command
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub args: &'static [Arg],
+    pub description: &'static str,
+    pub handler: Handler,
+}
+
+impl Command {
+    fn matches(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+
+    fn accepts(&self, count: usize) -> bool {
+        let required = self
+            .args
+            .iter()
+            .filter(|a| a.arity != Arity::Optional)
+            .count();
+        if self.args.iter().any(|a| a.arity == Arity::Many) {
+            count >= required
+        } else {
+            count >= required && count <= self.args.len()
+        }
+    }
+
+    fn parse(&self, input: &[&str], usage: String, tz: Tz) -> Result<Args, UsageError> {
+        let mut values: HashMap<&'static str, Vec<ArgValue>> = HashMap::new();
+        for (i, arg) in self.args.iter().enumerate() {
+            let inputs: &[&str] = match arg.arity {
+                Arity::Many => &input[i..],
+                _ => input.get(i..=i).unwrap_or_default(),
+            };
+
+            let mut parsed = Vec::with_capacity(inputs.len());
+            for v in inputs {
+                // Empty optional is the same as omitted
+                if arg.arity == Arity::Optional && v.is_empty() && arg.kind != ArgKind::Date {
+                    continue;
+                }
+                match arg.kind.parse(v, tz) {
+                    Some(v) => parsed.push(v),
+                    None => return Err(UsageError::WrongArg(arg.name, arg.kind)),
+                }
+            }
+            if parsed.is_empty() && arg.kind == ArgKind::Date {
+                parsed.push(ArgValue::Timestamp(parse_timestamp("", tz).unwrap()));
+            }
+
+            values.insert(arg.name, parsed);
+        }
+
+        Ok(Args { values, usage })
+    }
+}
+
+pub struct Group {
+    pub name: &'static str,
+    pub title: &'static str,
+    pub commands: &'static [Command],
+}
+
+impl Group {
+    fn usage(&self, cmd: &Command) -> String {
+        let mut res = String::from(self.name);
+        if !cmd.name.is_empty() {
+            res.push(',');
+            res.push_str(cmd.name);
+        }
+        for arg in cmd.args {
+            res.push(',');
+            res.push_str(&arg.usage());
+        }
+
+        res
+    }
+
+    fn is_single(&self) -> bool {
+        self.commands.iter().all(|c| c.name.is_empty())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgValue {
+    Timestamp(Timestamp),
+    Meal(Meal),
+    Float(f64),
+    Int(i64),
+    Str(String),
+    Sex(Sex),
+    ScheduleKind(ScheduleKind),
+    Time(NaiveTime),
+    Tz(Tz),
+    BundleItem(String, f64),
+}
+
+pub trait FromArg: Sized {
+    fn from_arg(v: &ArgValue) -> Option<Self>;
+}
+
+macro_rules! impl_from_arg {
+    ($t:ty, $p:pat => $v:expr) => {
+        impl FromArg for $t {
+            fn from_arg(v: &ArgValue) -> Option<Self> {
+                match v {
+                    $p => Some($v),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+impl_from_arg!(Timestamp, ArgValue::Timestamp(v) => v.clone());
+impl_from_arg!(Meal, ArgValue::Meal(v) => *v);
+impl_from_arg!(f64, ArgValue::Float(v) => *v);
+impl_from_arg!(i64, ArgValue::Int(v) => *v);
+impl_from_arg!(String, ArgValue::Str(v) => v.clone());
+impl_from_arg!(Sex, ArgValue::Sex(v) => *v);
+impl_from_arg!(ScheduleKind, ArgValue::ScheduleKind(v) => *v);
+impl_from_arg!(NaiveTime, ArgValue::Time(v) => *v);
+impl_from_arg!(Tz, ArgValue::Tz(v) => *v);
+impl_from_arg!((String, f64), ArgValue::BundleItem(k, v) => (k.clone(), *v));
+
+// Parsed and validated command arguments.
+// Getters panic if argument is not declared in command with the requested type,
+// it's an error in command declaration, not in user input.
+pub struct Args {
+    values: HashMap<&'static str, Vec<ArgValue>>,
+    usage: String,
+}
+
+impl Args {
+    pub fn get<T: FromArg>(&self, name: &str) -> T {
+        self.opt(name)
+            .unwrap_or_else(|| panic!("required arg \"{name}\" is not declared"))
+    }
+
+    pub fn opt<T: FromArg>(&self, name: &str) -> Option<T> {
+        self.many(name).into_iter().next()
+    }
+
+    // Empty if arg is omitted or not declared in this overload of command
+    pub fn many<T: FromArg>(&self, name: &str) -> Vec<T> {
+        self.values
+            .get(name)
+            .map(|values| {
+                values
+                    .iter()
+                    .map(|v| {
+                        T::from_arg(v).unwrap_or_else(|| panic!("arg \"{name}\" has another type"))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // Telegram HTML text for arg rejected by handler checks
+    pub fn wrong_arg(&self, name: &str, reason: &str) -> String {
+        format!(
+            "{ERR_WRONG_ARG} {}: {}\n{MSG_USAGE}: <code>{}</code>",
+            escape(name),
+            escape(reason),
+            self.usage
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UsageError {
+    WrongArgsCount,
+    WrongArg(&'static str, ArgKind),
+}
+
+pub struct Registry {
+    groups: &'static [Group],
+}
+
+impl Registry {
+    pub const fn new(groups: &'static [Group]) -> Self {
+        Self { groups }
+    }
+
+    // Finds command for input parts and parses its args.
+    // On error returns Telegram HTML text for user with correct usage.
+    pub fn resolve(&self, parts: &[&str], tz: Tz) -> Result<(Handler, Args), String> {
+        let Some(group) = parts
+            .first()
+            .and_then(|name| self.groups.iter().find(|g| g.name == *name))
+        else {
+            return Err(format!("{ERR_UNKNOWN_COMMAND}\n{MSG_HELP_HINT}"));
+        };
+
+        // Overloaded commands share name and differ by args count
+        let (candidates, input): (Vec<&Command>, &[&str]) = if group.is_single() {
+            (group.commands.iter().collect(), &parts[1..])
+        } else {
+            let name = parts.get(1).unwrap_or(&"");
+            (
+                group.commands.iter().filter(|c| c.matches(name)).collect(),
+                parts.get(2..).unwrap_or_default(),
+            )
+        };
+        if candidates.is_empty() {
+            return Err(format!("{ERR_UNKNOWN_COMMAND}\n\n{}", group_help(group)));
+        }
+
+        let Some(cmd) = candidates.iter().find(|c| c.accepts(input.len())) else {
+            return Err(usage_error(UsageError::WrongArgsCount, group, &candidates));
+        };
+
+        match cmd.parse(input, escape(&group.usage(cmd)), tz) {
+            Ok(args) => Ok((cmd.handler, args)),
+            Err(err) => Err(usage_error(err, group, &[cmd])),
+        }
+    }
+
+    // List of groups with their commands
+    pub fn help(&self) -> String {
+        let mut res = String::new();
+        for group in self.groups {
+            res.push_str(&format!("<b>{}</b> - {}", group.name, escape(group.title)));
+            if !group.is_single() {
+                let mut names: Vec<&str> = Vec::with_capacity(group.commands.len());
+                for cmd in group.commands {
+                    // Overloaded commands share one name
+                    if !names.contains(&cmd.name) {
+                        names.push(cmd.name);
+                    }
+                }
+                res.push_str(&format!(": {}", names.join(", ")));
+            }
+            res.push('\n');
+        }
+        res.push_str(&format!("\n{MSG_HELP_HINT}"));
+
+        res
+    }
+
+    // Help for group or command of group, None if not found
+    pub fn command_help(&self, group: &str, command: Option<&str>) -> Option<String> {
+        let group = self.groups.iter().find(|g| g.name == group)?;
+        let Some(command) = command else {
+            return Some(group_help(group));
+        };
+
+        let commands: Vec<&Command> = group
+            .commands
+            .iter()
+            .filter(|c| c.matches(command))
+            .collect();
+        if commands.is_empty() {
+            return None;
+        }
+
+        let mut res = String::new();
+        for cmd in commands {
+            res.push_str(&command_usage(group, cmd));
+            for arg in cmd.args {
+                res.push_str(&format!(
+                    "    {} - {}\n",
+                    escape(arg.name),
+                    escape(&arg.kind.description())
+                ));
+            }
+            if !cmd.aliases.is_empty() {
+                res.push_str(&format!(
+                    "    Синонимы: {}\n",
+                    escape(&cmd.aliases.join(", "))
+                ));
+            }
+        }
+
+        Some(res)
+    }
+}
+
+fn usage_error(err: UsageError, group: &Group, commands: &[&Command]) -> String {
+    let mut res = match err {
+        UsageError::WrongArgsCount => ERR_WRONG_ARGS_COUNT.to_string(),
+        UsageError::WrongArg(name, kind) => format!(
+            "{ERR_WRONG_ARG} {}: {}",
+            escape(name),
+            escape(&kind.description())
+        ),
+    };
+    for cmd in commands {
+        res.push_str(&format!(
+            "\n{MSG_USAGE}: <code>{}</code>",
+            escape(&group.usage(cmd))
+        ));
+    }
+
+    res
+}
+
+fn group_help(group: &Group) -> String {
+    let mut res = format!("<b>{}</b>\n", escape(group.title));
+    for cmd in group.commands {
+        res.push_str(&command_usage(group, cmd));
+    }
+
+    res
+}
+
+fn command_usage(group: &Group, cmd: &Command) -> String {
+    format!(
+        "<code>{}</code> - {}\n",
+        escape(&group.usage(cmd)),
+        escape(cmd.description)
+    )
+}
@@ -0,0 +1,145 @@
+use chrono::{NaiveTime, Timelike};
+use chrono_tz::Tz;
+use model::{Schedule, ScheduleKind};
+
+use crate::{messages::OK, HandlerResult};
+
+use super::{
+    registry::{Arg, ArgKind, Args, Command, Group},
+    storage_error_message, Ctx,
+};
+
+pub const GROUP: Group = Group {
+    name: "sc",
+    title: "Расписание",
+    commands: &[
+        Command {
+            name: "set",
+            aliases: &[],
+            args: &[
+                Arg::req("kind", ArgKind::ScheduleKind),
+                Arg::req("time", ArgKind::Time),
+                Arg::opt("tz", ArgKind::Tz),
+            ],
+            description: "Установить расписание",
+            handler: |ctx, args| Box::pin(schedule_set(ctx, args)),
+        },
+        Command {
+            name: "del",
+            aliases: &[],
+            args: &[
+                Arg::req("kind", ArgKind::ScheduleKind),
+                Arg::req("time", ArgKind::Time),
+            ],
+            description: "Удалить расписание",
+            handler: |ctx, args| Box::pin(schedule_del(ctx, args)),
+        },
+        Command {
+            name: "list",
+            aliases: &[],
+            args: &[],
+            description: "Список расписаний",
+            handler: |ctx, _| Box::pin(schedule_list(ctx)),
+        },
+    ],
+};
+
+async fn schedule_set(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
+
+    let (kind, hour, minute) = kind_time(&args);
+
+    let sc_tz = args.opt::<Tz>("tz").unwrap_or(tz);
+
+    // Call storage
+    if let Err(err) = stg
+        .call(move |s| {
+            s.set_schedule(
+                user_id,
+                &Schedule {
+                    kind,
+                    hour,
+                    minute,
+                    tz: sc_tz.name().into(),
+                    last_run: None,
+                },
+            )
+        })
+        .await
+    {
+        log::error!("set schedule error: {err}");
+        out.text(storage_error_message(&err)).await?;
+    } else {
+        out.text(OK).await?;
+    }
+
+    Ok(())
+}
+
+async fn schedule_del(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
+
+    let (kind, hour, minute) = kind_time(&args);
+
+    // Call storage
+    if let Err(err) = stg
+        .call(move |s| s.delete_schedule(user_id, kind, hour, minute))
+        .await
+    {
+        log::error!("delete schedule error: {err}");
+        out.text(storage_error_message(&err)).await?;
+    } else {
+        out.text(OK).await?;
+    }
+
+    Ok(())
+}
+
+async fn schedule_list(ctx: Ctx) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
+
+    // Call storage
+    let sc_lst = match stg.call(move |s| s.get_schedule_list(user_id)).await {
+        Ok(v) => v,
+        Err(err) => {
+            log::error!("schedule list error: {err}");
+            out.text(storage_error_message(&err)).await?;
+            return Ok(());
+        }
+    };
+
+    let mut res = String::new();
+    res.push_str("<b>Расписание</b>\n\n");
+    for sc in &sc_lst {
+        res.push_str(&format!(
+            "<b>{:02}:{:02}</b> ({}) {}\nУдалить: sc,del,{},{:02}:{:02}\n\n",
+            sc.hour,
+            sc.minute,
+            sc.tz,
+            String::from(sc.kind),
+            sc.kind.key(),
+            sc.hour,
+            sc.minute
+        ));
+    }
+
+    out.html(res).await?;
+
+    Ok(())
+}
+
+fn kind_time(args: &Args) -> (ScheduleKind, u8, u8) {
+    let kind: ScheduleKind = args.get("kind");
+    let time: NaiveTime = args.get("time");
+
+    (kind, time.hour() as u8, time.minute() as u8)
+}
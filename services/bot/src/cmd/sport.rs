@@ -1,4 +1,4 @@
-use chrono_tz::Tz;
+use chart::{Axis, ChartData, ChartDataset, ChartType, CHART_COLORS};
 use html::{
     attrs::Attrs,
     div::Div,
@@ -7,142 +7,142 @@ use html::{
     table::{Table, Td, Tr},
 };
 use model::{Sport, SportActivity};
-use std::{collections::BTreeMap, sync::Arc};
-use storage::{Storage, StorageError};
-use teloxide::{prelude::*, types::InputFile};
-
-use crate::{
-    messages::{
-        ERR_EMPTY, ERR_INTERNAL, ERR_SPORT_IS_USED, ERR_SPORT_NOT_FOUND, ERR_WRONG_COMMAND, OK,
-    },
-    HandlerResult,
-};
+use std::collections::{BTreeMap, BTreeSet};
+use types::timestamp::Timestamp;
 
-use super::{format_timestamp, parse_timestamp};
-
-pub async fn process_sport_command(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-    tz: Tz,
-) -> HandlerResult {
-    if args.is_empty() {
-        log::error!("empty args");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+use crate::{messages::OK, HandlerResult};
 
-    match *args.first().unwrap() {
-        // Sport
-        "set" => {
-            sport_set(bot, chat_id, args[1..].to_vec(), stg).await?;
-        }
-        "st" => {
-            sport_set_template(bot, chat_id, args[1..].to_vec(), stg).await?;
-        }
-        "list" => {
-            sport_list(bot, chat_id, stg).await?;
-        }
-        "del" => {
-            sport_del(bot, chat_id, args[1..].to_vec(), stg).await?;
-        }
-        // Sport activity
-        "as" => {
-            sport_activity_set(bot, user_id, chat_id, args[1..].to_vec(), stg, tz).await?;
-        }
-        "ad" => {
-            sport_activity_del(bot, user_id, chat_id, args[1..].to_vec(), stg, tz).await?;
-        }
-        "ar" => {
-            sport_activity_report(bot, user_id, chat_id, args[1..].to_vec(), stg, tz).await?;
-        }
-        _ => {
-            log::error!("unknown command");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        }
-    };
+use super::{
+    format_timestamp,
+    registry::{Arg, ArgKind, Args, Command, Group},
+    send_chart_photo, storage_error_message, Ctx,
+};
 
-    Ok(())
-}
+pub const GROUP: Group = Group {
+    name: "s",
+    title: "Спорт",
+    commands: &[
+        Command {
+            name: "set",
+            aliases: &[],
+            args: &[
+                Arg::req("key", ArgKind::Key),
+                Arg::req("name", ArgKind::Text),
+                Arg::req("comment", ArgKind::Text),
+            ],
+            description: "Установить спорт",
+            handler: |ctx, args| Box::pin(sport_set(ctx, args)),
+        },
+        Command {
+            name: "st",
+            aliases: &[],
+            args: &[Arg::req("key", ArgKind::Key)],
+            description: "Шаблон команды установки спорта",
+            handler: |ctx, args| Box::pin(sport_set_template(ctx, args)),
+        },
+        Command {
+            name: "list",
+            aliases: &[],
+            args: &[],
+            description: "Список спорта",
+            handler: |ctx, _| Box::pin(sport_list(ctx)),
+        },
+        Command {
+            name: "del",
+            aliases: &[],
+            args: &[Arg::req("key", ArgKind::Key)],
+            description: "Удалить спорт",
+            handler: |ctx, args| Box::pin(sport_del(ctx, args)),
+        },
+        Command {
+            name: "as",
+            aliases: &[],
+            args: &[
+                Arg::req("timestamp", ArgKind::Date),
+                Arg::req("sport_key", ArgKind::Key),
+                Arg::many("sets", ArgKind::Int),
+            ],
+            description: "Установить активность",
+            handler: |ctx, args| Box::pin(sport_activity_set(ctx, args)),
+        },
+        Command {
+            name: "ad",
+            aliases: &[],
+            args: &[
+                Arg::req("timestamp", ArgKind::Date),
+                Arg::req("sport_key", ArgKind::Key),
+            ],
+            description: "Удалить активность",
+            handler: |ctx, args| Box::pin(sport_activity_del(ctx, args)),
+        },
+        Command {
+            name: "ar",
+            aliases: &[],
+            args: &[
+                Arg::req("ts_from", ArgKind::Date),
+                Arg::req("ts_to", ArgKind::Date),
+            ],
+            description: "Отчет по активностям",
+            handler: |ctx, args| Box::pin(sport_activity_report(ctx, args)),
+        },
+    ],
+};
 
-async fn sport_set(
-    bot: Bot,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-) -> HandlerResult {
-    if args.len() != 3 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn sport_set(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
 
-    let key = args.first().unwrap().to_string();
-    let name = args.get(1).unwrap().to_string();
-    let comment = args.get(2).unwrap().to_string();
+    let key: String = args.get("key");
+    let name: String = args.get("name");
+    let comment: String = args.get("comment");
 
     // Call storage
-    if let Err(err) = stg.set_sport(&Sport { key, name, comment }) {
+    if let Err(err) = stg
+        .call(move |s| s.set_sport(user_id, &Sport { key, name, comment }))
+        .await
+    {
         log::error!("set sport error: {err}");
-        if stg.is_storage_error(StorageError::SportInvalid, &err) {
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        } else {
-            bot.send_message(chat_id, ERR_INTERNAL).await?;
-        }
+        out.text(storage_error_message(&err)).await?;
     } else {
-        bot.send_message(chat_id, OK).await?;
+        out.text(OK).await?;
     }
 
     Ok(())
 }
 
-async fn sport_set_template(
-    bot: Bot,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-) -> HandlerResult {
-    if args.len() != 1 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn sport_set_template(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx { out, stg, .. } = ctx;
+
+    let key: String = args.get("key");
 
     // Call storage
-    let sport = match stg.get_sport(args.first().unwrap()) {
+    let sport = match stg.call(move |s| s.get_sport(&key)).await {
         Err(err) => {
             log::error!("get sport error: {err}");
-            if stg.is_storage_error(StorageError::SportNotFound, &err) {
-                bot.send_message(chat_id, ERR_SPORT_NOT_FOUND).await?;
-            } else {
-                bot.send_message(chat_id, ERR_INTERNAL).await?;
-            }
+            out.text(storage_error_message(&err)).await?;
             return Ok(());
         }
         Ok(f) => f,
     };
 
-    bot.send_message(
-        chat_id,
-        format!("s,set,{},{},{}", sport.key, sport.name, sport.comment),
-    )
+    out.text(format!(
+        "s,set,{},{},{}",
+        sport.key, sport.name, sport.comment
+    ))
     .await?;
 
     Ok(())
 }
 
-async fn sport_list(bot: Bot, chat_id: ChatId, stg: Arc<Box<dyn Storage>>) -> HandlerResult {
+async fn sport_list(ctx: Ctx) -> HandlerResult {
+    let Ctx { out, stg, .. } = ctx;
+
     // Call storage
-    let f_lst = match stg.get_sport_list() {
+    let f_lst = match stg.call(|s| s.get_sport_list()).await {
         Err(err) => {
             log::error!("sport list error: {err}");
-            if stg.is_storage_error(StorageError::EmptyResult, &err) {
-                bot.send_message(chat_id, ERR_EMPTY).await?;
-            } else {
-                bot.send_message(chat_id, ERR_INTERNAL).await?;
-            }
+            out.text(storage_error_message(&err)).await?;
             return Ok(());
         }
         Ok(lst) => lst,
@@ -175,189 +175,149 @@ async fn sport_list(bot: Bot, chat_id: ChatId, stg: Arc<Box<dyn Storage>>) -> Ha
             .as_box(),
     );
 
-    bot.send_document(
-        chat_id,
-        InputFile::memory(doc.build()).file_name("sport.html"),
-    )
-    .await?;
+    out.document("sport.html", doc.build()).await?;
 
     Ok(())
 }
 
-async fn sport_del(
-    bot: Bot,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-) -> HandlerResult {
-    if args.len() != 1 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn sport_del(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
+
+    let key: String = args.get("key");
 
     // Call storage
-    if let Err(err) = stg.delete_sport(args.first().unwrap()) {
+    if let Err(err) = stg.call(move |s| s.delete_sport(user_id, &key)).await {
         log::error!("del sport error: {err}");
-        if stg.is_storage_error(StorageError::SportIsUsedViolation, &err) {
-            bot.send_message(chat_id, ERR_SPORT_IS_USED).await?;
-        } else {
-            bot.send_message(chat_id, ERR_INTERNAL).await?;
-        }
+        out.text(storage_error_message(&err)).await?;
         return Ok(());
     };
 
-    bot.send_message(chat_id, OK).await?;
+    out.text(OK).await?;
 
     Ok(())
 }
 
-async fn sport_activity_set(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-    tz: Tz,
-) -> HandlerResult {
-    if args.len() < 3 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn sport_activity_set(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
 
     // Parse args
-    let timestamp = match parse_timestamp(args.first().unwrap(), tz) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse timestamp error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
-
-    let sport_key = args.get(1).unwrap().to_string();
-
-    let mut sets = Vec::new();
-    for i in 2..args.len() {
-        let set = match args.get(i).unwrap().parse::<i64>() {
-            Ok(v) => v,
-            Err(err) => {
-                log::error!("parse sport activity arg {i}: {err}");
-                bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-                return Ok(());
-            }
-        };
-
-        sets.push(set);
-    }
+    let timestamp: Timestamp = args.get("timestamp");
+    let sport_key: String = args.get("sport_key");
+    let sets: Vec<i64> = args.many("sets");
 
     // Call storage
-    if let Err(err) = stg.set_sport_activity(
-        user_id,
-        &SportActivity {
-            sport_key,
-            sets,
-            timestamp,
-        },
-    ) {
+    if let Err(err) = stg
+        .call(move |s| {
+            s.set_sport_activity(
+                user_id,
+                &SportActivity {
+                    sport_key,
+                    sets,
+                    timestamp,
+                },
+            )
+        })
+        .await
+    {
         log::error!("set sport activity error: {err}");
-        if stg.is_storage_error(StorageError::SportInvalid, &err) {
-            bot.send_message(chat_id, ERR_SPORT_NOT_FOUND).await?;
-        } else {
-            bot.send_message(chat_id, ERR_INTERNAL).await?;
-        }
+        out.text(storage_error_message(&err)).await?;
         return Ok(());
     }
 
-    bot.send_message(chat_id, OK).await?;
+    out.text(OK).await?;
 
     Ok(())
 }
 
-async fn sport_activity_del(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-    tz: Tz,
-) -> HandlerResult {
-    if args.len() != 2 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn sport_activity_del(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
 
     // Parse args
-    let timestamp = match parse_timestamp(args.first().unwrap(), tz) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse timestamp error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
-
-    let sport_key = args.get(1).unwrap().to_string();
+    let timestamp: Timestamp = args.get("timestamp");
+    let sport_key: String = args.get("sport_key");
 
     // Call storage
-    if let Err(err) = stg.delete_sport_activity(user_id, timestamp, &sport_key) {
+    if let Err(err) = stg
+        .call(move |s| s.delete_sport_activity(user_id, timestamp, &sport_key))
+        .await
+    {
         log::error!("del sport activity error: {err}");
-        bot.send_message(chat_id, ERR_INTERNAL).await?;
+        out.text(storage_error_message(&err)).await?;
         return Ok(());
     }
 
-    bot.send_message(chat_id, OK).await?;
+    out.text(OK).await?;
 
     Ok(())
 }
 
-async fn sport_activity_report(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-    tz: Tz,
-) -> HandlerResult {
-    if args.len() != 2 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn sport_activity_report(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
 
     // Parse args
-    let ts_from = match parse_timestamp(args.first().unwrap(), tz) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse timestamp from error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
+    let ts_from: Timestamp = args.get("ts_from");
+    let ts_to: Timestamp = args.get("ts_to");
 
-    let ts_to = match parse_timestamp(args.get(1).unwrap(), tz) {
-        Ok(v) => v,
+    // Call storage
+    let (from, to) = (ts_from.clone(), ts_to.clone());
+    let db_res = match stg
+        .call(move |s| s.get_sport_activity_report(user_id, from, to))
+        .await
+    {
+        Ok(res) => res,
         Err(err) => {
-            log::error!("parse timestamp to error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
+            log::error!("set sport activity error: {err}");
+            out.text(storage_error_message(&err)).await?;
             return Ok(());
         }
     };
 
-    // Call storage
-    let db_res = match stg.get_sport_activity_report(user_id, ts_from.clone(), ts_to.clone()) {
-        Ok(res) => res,
-        Err(err) => {
-            log::error!("set sport activity error: {err}");
-            if stg.is_storage_error(StorageError::EmptyResult, &err) {
-                bot.send_message(chat_id, ERR_EMPTY).await?;
-            } else {
-                bot.send_message(chat_id, ERR_INTERNAL).await?;
+    // Chart of total repetitions by day for each sport
+    let mut x_labels: Vec<String> = Vec::new();
+    let sports: BTreeSet<&str> = db_res.iter().map(|sa| sa.sport_name.as_str()).collect();
+    let mut datasets: Vec<ChartDataset> = sports
+        .iter()
+        .enumerate()
+        .map(|(i, name)| ChartDataset {
+            data: Vec::new(),
+            label: name.to_string(),
+            color: CHART_COLORS[i % CHART_COLORS.len()].into(),
+            ..Default::default()
+        })
+        .collect();
+    for sa in &db_res {
+        let ts = format_timestamp(&sa.timestamp, "%d.%m.%Y", tz);
+        if x_labels.last() != Some(&ts) {
+            x_labels.push(ts);
+            for ds in datasets.iter_mut() {
+                ds.data.push(0.0);
             }
-            return Ok(());
         }
+        let ds = &mut datasets[sports.iter().position(|s| *s == sa.sport_name).unwrap()];
+        *ds.data.last_mut().unwrap() += sa.sets.iter().sum::<i64>() as f64;
+    }
+    let chart_data = ChartData {
+        elem_id: "chart".into(),
+        x_labels,
+        ctype: ChartType::Bar,
+        datasets,
+        axes: vec![Axis {
+            unit: "повт.".into(),
+            ..Default::default()
+        }],
+        stacked: true,
+        ..Default::default()
     };
 
     // Generate HTML
@@ -427,9 +387,16 @@ async fn sport_activity_report(
             .as_box(),
     );
 
-    bot.send_document(
-        chat_id,
-        InputFile::memory(doc.build()).file_name(format!("sport_act_{}_{}.html", &ts_from, &ts_to)),
+    out.document(
+        format!("sport_act_{}_{}.html", &ts_from, &ts_to),
+        doc.build(),
+    )
+    .await?;
+
+    send_chart_photo(
+        &out,
+        chart_data,
+        &format!("sport_act_{}_{}.png", &ts_from, &ts_to),
     )
     .await?;
 
@@ -0,0 +1,85 @@
+use analytics::day_report::{DayReport, Nutrients};
+use anyhow::{Context, Result};
+use storage::{Storage, StorageError};
+use types::timestamp::Timestamp;
+
+use super::or_empty;
+
+// Loads journal and calories limit for the day of timestamp
+pub fn load_day_report(
+    user_id: i64,
+    stg: &dyn Storage,
+    timestamp: &Timestamp,
+) -> Result<DayReport> {
+    let rep =
+        or_empty(stg.get_journal_report(user_id, timestamp.start_of_day(), timestamp.end_of_day()))
+            .context("get journal report")?;
+
+    let cal_limit = match stg.get_user_settings(user_id) {
+        Ok(us) => Some(us.cal_limit),
+        Err(StorageError::UserSettingsNotFound) => None,
+        Err(err) => return Err(err).context("get user settings"),
+    };
+
+    Ok(DayReport::new(&rep, cal_limit))
+}
+
+// Day report as Telegram HTML message
+pub fn day_report_text(dr: &DayReport, title: &str) -> String {
+    let mut res = format!("<b>{title}</b>\n\n");
+
+    for mt in &dr.meals {
+        res.push_str(&format!(
+            "<b>{}:</b> {}\n",
+            String::from(mt.meal),
+            nutrients_text(&mt.total)
+        ));
+    }
+    if !dr.meals.is_empty() {
+        res.push('\n');
+    }
+
+    res.push_str(&format!("<b>Всего:</b> {}\n", nutrients_text(&dr.total)));
+
+    let (prot, fat, carb) = dr.total.pfc_percent();
+    res.push_str(&format!(
+        "<b>БЖУ, %:</b> {prot:.0} / {fat:.0} / {carb:.0}\n"
+    ));
+
+    if let (Some(cal_limit), Some(remaining)) = (dr.cal_limit, dr.remaining_cal()) {
+        res.push_str(&format!("<b>Лимит:</b> {cal_limit:.0} ккал\n"));
+        if remaining >= 0.0 {
+            res.push_str(&format!("<b>Остаток:</b> {remaining:.0} ккал\n"));
+        } else {
+            res.push_str(&format!("<b>Превышение:</b> {:.0} ккал\n", -remaining));
+        }
+    }
+
+    res
+}
+
+// One line status, e.g. after journal update
+pub fn remaining_text(dr: &DayReport, day: &str) -> String {
+    let macros = format!(
+        "Б {:.1} / Ж {:.1} / У {:.1}",
+        dr.total.prot, dr.total.fat, dr.total.carb
+    );
+
+    match (dr.cal_limit, dr.remaining_cal()) {
+        (Some(cal_limit), Some(remaining)) if remaining >= 0.0 => {
+            format!("Осталось {day}: {remaining:.0} из {cal_limit:.0} ккал | {macros}")
+        }
+        (Some(cal_limit), Some(remaining)) => format!(
+            "Превышение {day}: {:.0} ккал сверх {cal_limit:.0} | {macros}",
+            -remaining
+        ),
+        _ => format!("Потреблено {day}: {:.0} ккал | {macros}", dr.total.cal),
+    }
+}
+
+fn nutrients_text(n: &Nutrients) -> String {
+    format!(
+        "{:.0} ккал, Б {:.1}, Ж {:.1}, У {:.1}",
+        n.cal, n.prot, n.fat, n.carb
+    )
+}
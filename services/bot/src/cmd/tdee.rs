@@ -0,0 +1,156 @@
+use analytics::tdee::{daily_calories, estimate, Confidence, WINDOW_DAYS};
+use chrono::Duration;
+use model::UserSettings;
+use storage::StorageError;
+use types::timestamp::Timestamp;
+
+use crate::{
+    messages::{ERR_TDEE_NOT_ENOUGH_DATA, ERR_WRONG_COMMAND},
+    HandlerResult,
+};
+
+use super::{
+    format_timestamp,
+    registry::{Arg, ArgKind, Args, Command, Group},
+    storage_error_message, Ctx,
+};
+
+pub const GROUP: Group = Group {
+    name: "td",
+    title: "Фактический расход калорий",
+    commands: &[Command {
+        name: "",
+        aliases: &[],
+        args: &[
+            Arg::opt("weekly_change", ArgKind::Float),
+            Arg::opt("apply", ArgKind::Choice(&["set"])),
+        ],
+        description: "Оценка расхода по журналу и весу, set - установить лимит",
+        handler: |ctx, args| Box::pin(tdee(ctx, args)),
+    }],
+};
+
+async fn tdee(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
+
+    let weekly_change: Option<f64> = args.opt("weekly_change");
+    let apply = args.opt::<String>("apply").is_some();
+
+    // Today is not finished yet, so window ends yesterday
+    let ts_to = Timestamp::now()
+        .with_timezone(tz)
+        .start_of_day()
+        .sub(Duration::days(1));
+    let ts_from = ts_to.sub(Duration::days(WINDOW_DAYS - 1));
+
+    // Call storage
+    let (from, to) = (ts_from.clone(), ts_to.clone());
+    let rep = match stg
+        .call(move |s| s.get_journal_report(user_id, from, to))
+        .await
+    {
+        Ok(v) => v,
+        Err(StorageError::EmptyResult) => Vec::new(),
+        Err(err) => {
+            log::error!("get journal report error: {err}");
+            out.text(storage_error_message(&err)).await?;
+            return Ok(());
+        }
+    };
+
+    let (from, to) = (ts_from.clone(), ts_to.clone());
+    let weights = match stg
+        .call(move |s| s.get_weight_list(user_id, from, to))
+        .await
+    {
+        Ok(v) => v,
+        Err(StorageError::EmptyResult) => Vec::new(),
+        Err(err) => {
+            log::error!("get weight list error: {err}");
+            out.text(storage_error_message(&err)).await?;
+            return Ok(());
+        }
+    };
+
+    let Some(est) = estimate(&daily_calories(&rep), &weights, WINDOW_DAYS) else {
+        out.text(ERR_TDEE_NOT_ENOUGH_DATA).await?;
+        return Ok(());
+    };
+
+    let mut res = String::new();
+    res.push_str("<b>Оценка расхода энергии (TDEE)</b>\n");
+    res.push_str(&format!(
+        "<b>Период:</b> {} - {}\n\n",
+        format_timestamp(&ts_from, "%d.%m.%Y", tz),
+        format_timestamp(&ts_to, "%d.%m.%Y", tz)
+    ));
+    res.push_str(&format!(
+        "<b>Среднее потребление:</b> {} ккал\n",
+        est.avg_intake as i64
+    ));
+    res.push_str(&format!(
+        "<b>Изменение веса:</b> {:+.2} кг/нед\n",
+        est.weekly_rate
+    ));
+    res.push_str(&format!("<b>Расход:</b> {} ккал\n", est.tdee as i64));
+    res.push_str(&format!(
+        "<b>Достоверность:</b> {} (дней в журнале: {} из {}, взвешиваний: {})\n",
+        confidence_str(est.confidence),
+        est.logged_days,
+        WINDOW_DAYS,
+        est.weigh_ins
+    ));
+
+    if let Some(weekly_change) = weekly_change {
+        let cal_limit = est.suggest_cal_limit(weekly_change).round();
+        if cal_limit <= 0.0 {
+            log::error!("suggested cal limit <= 0: {cal_limit}");
+            out.text(ERR_WRONG_COMMAND).await?;
+            return Ok(());
+        }
+
+        res.push_str(&format!(
+            "\n<b>Лимит для изменения {:+.2} кг/нед:</b> {} ккал\n",
+            weekly_change, cal_limit as i64
+        ));
+
+        if apply {
+            let mut us = match stg.call(move |s| s.get_user_settings(user_id)).await {
+                Ok(v) => v,
+                Err(StorageError::UserSettingsNotFound) => UserSettings::default(),
+                Err(err) => {
+                    log::error!("get user settings error: {err}");
+                    out.text(storage_error_message(&err)).await?;
+                    return Ok(());
+                }
+            };
+            us.cal_limit = cal_limit;
+
+            if let Err(err) = stg.call(move |s| s.set_user_settings(user_id, &us)).await {
+                log::error!("set user settings error: {err}");
+                out.text(storage_error_message(&err)).await?;
+                return Ok(());
+            }
+            res.push_str("Лимит калорий установлен\n");
+        } else {
+            res.push_str(&format!("Установить: td,{},set\n", weekly_change));
+        }
+    }
+
+    out.html(res).await?;
+
+    Ok(())
+}
+
+fn confidence_str(c: Confidence) -> &'static str {
+    match c {
+        Confidence::Low => "низкая",
+        Confidence::Medium => "средняя",
+        Confidence::High => "высокая",
+    }
+}
@@ -0,0 +1,484 @@
+use std::sync::Mutex;
+
+use super::*;
+use crate::{
+    messages::{
+        ERR_BUNDLE_IS_USED, ERR_EMPTY, ERR_FOOD_NOT_FOUND, ERR_UNKNOWN_COMMAND, ERR_WRONG_ARG,
+        ERR_WRONG_ARGS_COUNT, MSG_UNDONE, OK,
+    },
+    output::{Reply, SendFuture, Sink},
+};
+use storage::{storage_async::StorageAsync, storage_memory::StorageMemory};
+
+const USER_ID: i64 = 1;
+
+//
+// Harness
+//
+
+// Keeps replies instead of sending them to Telegram
+#[derive(Default)]
+struct RecordingSink {
+    replies: Mutex<Vec<Reply>>,
+}
+
+impl Sink for RecordingSink {
+    fn send(&self, chat_id: ChatId, reply: Reply) -> SendFuture<'_> {
+        assert_eq!(USER_ID, chat_id.0);
+        self.replies.lock().unwrap().push(reply);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+struct Harness {
+    sink: Arc<RecordingSink>,
+    stg: StorageAsync,
+}
+
+impl Harness {
+    fn new() -> Self {
+        Self {
+            sink: Arc::new(RecordingSink::default()),
+            stg: StorageAsync::new(Box::new(StorageMemory::new())),
+        }
+    }
+
+    // Runs command and returns replies sent by it
+    async fn run(&self, input: &str) -> Vec<Reply> {
+        let ctx = Ctx {
+            out: Output::new(self.sink.clone(), ChatId(USER_ID)),
+            user_id: USER_ID,
+            stg: self.stg.clone(),
+            tz: chrono_tz::Europe::Moscow,
+        };
+        execute(ctx, input).await.unwrap();
+
+        std::mem::take(&mut *self.sink.replies.lock().unwrap())
+    }
+
+    // Runs command expecting single text reply
+    async fn text(&self, input: &str) -> String {
+        match self.run(input).await.as_slice() {
+            [Reply::Text(v)] | [Reply::Html(v)] => v.clone(),
+            res => panic!("{input}: unexpected replies {res:?}"),
+        }
+    }
+
+    async fn ok(&self, input: &str) {
+        assert_eq!(OK, self.text(input).await, "{input}");
+    }
+
+    // Runs command expecting HTML document first, returns its name and content
+    async fn document(&self, input: &str) -> (String, String, Vec<Reply>) {
+        let mut res = self.run(input).await;
+        assert!(!res.is_empty(), "{input}: no replies");
+        match res.remove(0) {
+            Reply::Document { file_name, data } => {
+                (file_name, String::from_utf8(data).unwrap(), res)
+            }
+            v => panic!("{input}: unexpected reply {v:?}"),
+        }
+    }
+
+    async fn setup_food(&self) {
+        self.ok("f,set,apple,Яблоко,,52,0.3,0.2,14,").await;
+        self.ok("f,set,bread,Хлеб,Пекарня,250,8,3,49,ржаной").await;
+    }
+}
+
+fn texts(replies: &[Reply]) -> Vec<&str> {
+    replies
+        .iter()
+        .map(|r| match r {
+            Reply::Text(v) | Reply::Html(v) => v.as_str(),
+            v => panic!("unexpected reply {v:?}"),
+        })
+        .collect()
+}
+
+//
+// Registry
+//
+
+#[tokio::test]
+async fn test_help() {
+    let h = Harness::new();
+
+    let res = h.text("h").await;
+    assert!(res.contains("<b>j</b>"));
+    assert!(res.contains("Журнал приема пищи"));
+
+    let res = h.text("h,j,set").await;
+    assert!(res.contains("j,set,&lt;timestamp&gt;,&lt;meal&gt;"));
+
+    assert_eq!(ERR_UNKNOWN_COMMAND, h.text("h,zz").await);
+}
+
+#[tokio::test]
+async fn test_usage_errors() {
+    let h = Harness::new();
+
+    assert!(h.text("zz").await.starts_with(ERR_UNKNOWN_COMMAND));
+    assert!(h.text("f,zz").await.starts_with(ERR_UNKNOWN_COMMAND));
+    assert!(h
+        .text("f,set,apple")
+        .await
+        .starts_with(ERR_WRONG_ARGS_COUNT));
+
+    let res = h.text("w,set,01.01.2024,heavy").await;
+    assert!(res.starts_with(ERR_WRONG_ARG));
+    assert!(res.contains("value"));
+
+    let res = h.text("w,list,01.01.2024,31.01.2024,-1").await;
+    assert!(res.starts_with(ERR_WRONG_ARG));
+    assert!(res.contains("target"));
+}
+
+//
+// Food and bundles
+//
+
+#[tokio::test]
+async fn test_food() {
+    let h = Harness::new();
+    assert_eq!(ERR_EMPTY, h.text("f,list").await);
+
+    h.setup_food().await;
+
+    assert_eq!(
+        "f,set,bread,Хлеб,Пекарня,250.00,8.00,3.00,49.00,ржаной",
+        h.text("f,st,bread").await
+    );
+    assert_eq!(
+        format!("{ERR_FOOD_NOT_FOUND}: pear"),
+        h.text("f,st,pear").await
+    );
+
+    let (file_name, doc, _) = h.document("f,list").await;
+    assert_eq!("food.html", file_name);
+    assert!(doc.contains("Яблоко"));
+    assert!(doc.contains("Пекарня"));
+
+    let res = h.text("f,find,bre").await;
+    assert!(res.contains("bread"));
+    assert!(!res.contains("apple"));
+
+    h.ok("f,del,apple").await;
+    assert!(!h.text("f,find,app").await.contains("apple"));
+}
+
+#[tokio::test]
+async fn test_bundle() {
+    let h = Harness::new();
+    h.setup_food().await;
+
+    h.ok("b,set,snack,apple:150,bread:50").await;
+    h.ok("b,set,day,snack").await;
+    assert!(h.text("b,st,snack").await.starts_with("b,set,snack,"));
+
+    let (file_name, doc, _) = h.document("b,list").await;
+    assert_eq!("bundles.html", file_name);
+    assert!(doc.contains("snack"));
+
+    assert_eq!(
+        format!("{ERR_BUNDLE_IS_USED}: snack -> day"),
+        h.text("b,del,snack").await
+    );
+    h.ok("b,del,day").await;
+    h.ok("b,del,snack").await;
+    assert_eq!(ERR_EMPTY, h.text("b,list").await);
+}
+
+//
+// Journal
+//
+
+#[tokio::test]
+async fn test_journal() {
+    let h = Harness::new();
+    h.setup_food().await;
+    h.ok("u,set,2000").await;
+
+    // Entry is confirmed with status of the day
+    let res = h.run("j,set,01.02.2024,завтрак,bread,100").await;
+    let res = texts(&res);
+    assert_eq!(2, res.len());
+    assert_eq!(OK, res[0]);
+    assert!(res[1].contains("на 01.02.2024"));
+
+    h.run("j,set,01.02.2024,обед,apple,200").await;
+
+    let (file_name, doc, rest) = h.document("j,rd,01.02.2024").await;
+    assert_eq!("report_01.02.2024.html", file_name);
+    assert!(doc.contains("Хлеб"));
+    assert!(doc.contains("Яблоко"));
+    assert!(matches!(rest.as_slice(), [Reply::Photo { .. }]));
+
+    let res = h.text("j,rt,01.02.2024").await;
+    assert!(res.contains("354"));
+
+    let res = h.run("j,tm,01.02.2024,завтрак").await;
+    let res = texts(&res);
+    assert!(res.contains(&"j,set,01.02.2024,Завтрак,bread,100.0"));
+    assert!(res.contains(&"j,del,01.02.2024,Завтрак,bread"));
+
+    h.ok("j,del,01.02.2024,обед,apple").await;
+    h.ok("j,dm,01.02.2024,завтрак").await;
+    assert_eq!(ERR_EMPTY, h.text("j,rt,01.02.2024").await);
+}
+
+#[tokio::test]
+async fn test_journal_bundle_and_stats() {
+    let h = Harness::new();
+    h.setup_food().await;
+    h.ok("b,set,snack,apple:150,bread:50").await;
+
+    let res = h.run("j,sb,,полдник,snack").await;
+    assert_eq!(OK, texts(&res)[0]);
+
+    let res = h.text("j,fa,apple").await;
+    assert!(res.ends_with("150.0"));
+
+    let (_, doc, _) = h.document("j,fs").await;
+    assert!(doc.contains("Яблоко"));
+
+    let res = h.text("j,fs,02.02.2024,01.02.2024").await;
+    assert!(res.starts_with(ERR_WRONG_ARG));
+}
+
+//
+// User settings and calories
+//
+
+#[tokio::test]
+async fn test_user_settings() {
+    let h = Harness::new();
+
+    h.ok("u,set,1800").await;
+    h.ok("u,sp,м,180,01.01.1990").await;
+
+    let res = h.text("u,get").await;
+    assert!(res.contains("1800"));
+    assert!(res.contains("Мужской"));
+
+    assert!(h
+        .text("u,sp,x,180,01.01.1990")
+        .await
+        .starts_with(ERR_WRONG_ARG));
+}
+
+#[tokio::test]
+async fn test_user_settings_profile_first() {
+    let h = Harness::new();
+
+    // Profile without settings gets default calories limit
+    h.ok("u,sp,ж,165,01.01.1990").await;
+    let res = h.text("u,get").await;
+    assert!(res.contains("2000"));
+    assert!(res.contains("Женский"));
+
+    // Own limit keeps profile
+    h.ok("u,set,1600").await;
+    let res = h.text("u,get").await;
+    assert!(res.contains("1600"));
+    assert!(res.contains("Женский"));
+}
+
+#[tokio::test]
+async fn test_cal_calc() {
+    let h = Harness::new();
+    h.ok("u,set,1800").await;
+    h.ok("u,sp,м,180,01.01.1990").await;
+    h.ok("w,set,,80").await;
+
+    let res = h.text("cc").await;
+    assert!(res.contains("Миффлина"));
+
+    assert!(h.text("cc,km").await.contains("процент жира"));
+    assert!(h.text("cc,km,0,120").await.starts_with(ERR_WRONG_ARG));
+    assert!(h.text("cc,xx").await.starts_with(ERR_WRONG_ARG));
+}
+
+#[tokio::test]
+async fn test_tdee() {
+    let h = Harness::new();
+    assert!(h.text("td").await.starts_with("Недостаточно данных"));
+    assert!(h.text("td,0,get").await.starts_with(ERR_WRONG_ARG));
+}
+
+//
+// Weight, sport and metrics
+//
+
+#[tokio::test]
+async fn test_weight() {
+    let h = Harness::new();
+
+    h.ok("w,set,01.02.2024,80.5").await;
+    h.ok("w,set,03.02.2024,80.1").await;
+
+    let (file_name, doc, rest) = h.document("w,list,01.02.2024,03.02.2024,75").await;
+    assert_eq!("weight_01.02.2024_03.02.2024.html", file_name);
+    assert!(doc.contains("80.5"));
+    assert!(matches!(rest.as_slice(), [Reply::Photo { .. }]));
+
+    h.ok("w,del,01.02.2024").await;
+    h.ok("w,del,03.02.2024").await;
+    assert_eq!(ERR_EMPTY, h.text("w,list,01.02.2024,03.02.2024").await);
+}
+
+#[tokio::test]
+async fn test_sport() {
+    let h = Harness::new();
+
+    h.ok("s,set,pushups,Отжимания,").await;
+    assert_eq!("s,set,pushups,Отжимания,", h.text("s,st,pushups").await);
+
+    h.ok("s,as,01.02.2024,pushups,10,15,20").await;
+
+    let (_, doc, rest) = h.document("s,ar,01.02.2024,01.02.2024").await;
+    assert!(doc.contains("Отжимания"));
+    assert!(doc.contains("45"));
+    assert!(matches!(rest.as_slice(), [Reply::Photo { .. }]));
+
+    assert!(h
+        .text("s,as,01.02.2024,pushups,ten")
+        .await
+        .starts_with(ERR_WRONG_ARG));
+
+    let (_, doc, _) = h.document("s,list").await;
+    assert!(doc.contains("pushups"));
+
+    h.ok("s,ad,01.02.2024,pushups").await;
+    h.ok("s,del,pushups").await;
+}
+
+#[tokio::test]
+async fn test_metric() {
+    let h = Harness::new();
+
+    h.ok("mt,set,bp,Давление,мм рт. ст.,sys,dia").await;
+    assert!(h.text("mt,st,bp").await.starts_with("mt,set,bp,"));
+
+    h.ok("mt,vs,01.02.2024 08:00,bp,120,80").await;
+
+    let (_, doc, _) = h.document("mt,vl,bp,01.02.2024,01.02.2024").await;
+    assert!(doc.contains("120"));
+
+    let (_, doc, _) = h.document("mt,list").await;
+    assert!(doc.contains("Давление"));
+
+    h.ok("mt,vd,01.02.2024 08:00,bp").await;
+    h.ok("mt,del,bp").await;
+}
+
+//
+// Reports
+//
+
+#[tokio::test]
+async fn test_reports() {
+    let h = Harness::new();
+    h.setup_food().await;
+    h.ok("u,set,2000").await;
+    h.run("j,set,,ужин,bread,200").await;
+    h.ok("w,set,,80").await;
+
+    let (file_name, doc, _) = h.document("dg,w").await;
+    assert!(file_name.starts_with("digest_"));
+    assert!(doc.contains("Хлеб"));
+
+    let (file_name, doc, _) = h.document("cal").await;
+    assert!(file_name.starts_with("calendar_"));
+    assert!(doc.contains("⚖"));
+
+    let (file_name, doc, _) = h.document("cmp,7").await;
+    assert!(file_name.starts_with("compare_"));
+    assert!(doc.contains("Хлеб"));
+
+    assert!(h.text("cmp,0").await.starts_with(ERR_WRONG_ARG));
+    assert!(h.text("cmp,100000000").await.starts_with(ERR_WRONG_ARG));
+    assert!(h
+        .text("cmp,10.01.2024,01.01.2024,01.02.2024,10.02.2024")
+        .await
+        .starts_with(ERR_WRONG_ARG));
+    assert_eq!(
+        ERR_EMPTY,
+        h.text("cmp,01.01.2020,10.01.2020,01.02.2020,10.02.2020")
+            .await
+    );
+    assert!(h.text("dg,y").await.starts_with(ERR_WRONG_ARG));
+}
+
+//
+// Schedule and maintenance
+//
+
+#[tokio::test]
+async fn test_schedule() {
+    let h = Harness::new();
+
+    h.ok("sc,set,w,09:00").await;
+    h.ok("sc,set,dw,10:30,Europe/London").await;
+
+    let res = h.text("sc,list").await;
+    assert!(res.contains("sc,del,w,09:00"));
+    assert!(res.contains("Europe/London"));
+
+    assert!(h.text("sc,set,w,25:00").await.starts_with(ERR_WRONG_ARG));
+    h.ok("sc,del,w,09:00").await;
+    assert!(!h.text("sc,list").await.contains("sc,del,w,09:00"));
+}
+
+#[tokio::test]
+async fn test_backup() {
+    let h = Harness::new();
+    h.setup_food().await;
+
+    let res = h.run("m,backup").await;
+    let [Reply::Document { file_name, data }] = res.as_slice() else {
+        panic!("unexpected replies {res:?}");
+    };
+    assert!(file_name.starts_with("backup_"));
+    assert!(file_name.ends_with(".json.gz"));
+    assert!(!data.is_empty());
+}
+
+#[tokio::test]
+async fn test_undo() {
+    let h = Harness::new();
+    assert_eq!(ERR_EMPTY, h.text("m,changes").await);
+    assert_eq!(ERR_EMPTY, h.text("m,undo").await);
+
+    h.setup_food().await;
+    h.ok("f,set,apple,Груша,,57,0.4,0.1,15,").await;
+
+    let res = h.text("m,ch,2").await;
+    assert!(res.contains("Изменение еды: apple"));
+    assert!(res.contains("Изменение еды: bread"));
+    assert!(!res.contains("Груша"));
+
+    let res = h.text("m,u").await;
+    assert!(res.starts_with(MSG_UNDONE));
+    assert!(res.contains("apple"));
+    assert!(h.text("f,find,Ябл").await.contains("apple"));
+
+    assert!(h.text("m,u,0").await.starts_with(ERR_WRONG_ARG));
+    assert!(h.text("m,u,2").await.contains("bread"));
+    assert_eq!(ERR_EMPTY, h.text("f,list").await);
+}
+
+#[tokio::test]
+async fn test_audit() {
+    let h = Harness::new();
+    assert_eq!(ERR_EMPTY, h.text("m,audit,01.01.2024,01.01.2024").await);
+
+    h.setup_food().await;
+    h.ok("f,del,apple").await;
+
+    let (file_name, doc, _) = h.document("m,a,,").await;
+    assert_eq!("audit.html", file_name);
+    assert!(doc.contains("Удаление"));
+    assert!(doc.contains("bread"));
+    assert!(doc.contains("Яблоко"));
+}
@@ -1,95 +1,149 @@
-use model::UserSettings;
-use std::sync::Arc;
-use storage::{Storage, StorageError};
-use teloxide::{prelude::*, types::ParseMode};
-
-use crate::{
-    messages::{ERR_INTERNAL, ERR_USER_SETTINGS_NOT_FOUND, ERR_WRONG_COMMAND, OK},
-    HandlerResult,
+use model::{Sex, UserSettings};
+use storage::StorageError;
+use types::timestamp::Timestamp;
+
+use crate::{messages::OK, HandlerResult};
+
+use super::{
+    format_timestamp,
+    registry::{Arg, ArgKind, Args, Command, Group},
+    storage_error_message, Ctx,
 };
 
-pub async fn process_user_settings_command(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-) -> HandlerResult {
-    if args.is_empty() {
-        log::error!("empty args");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+// Calories limit of settings created with body profile, until user sets own
+const DEFAULT_CAL_LIMIT: f64 = 2000.0;
 
-    match *args.first().unwrap() {
-        "set" => {
-            user_settings_set(bot, user_id, chat_id, args[1..].to_vec(), stg).await?;
-        }
-        "get" => {
-            user_settings_get(bot, user_id, chat_id, stg).await?;
-        }
-        _ => {
-            log::error!("unknown command");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
+pub const GROUP: Group = Group {
+    name: "u",
+    title: "Настройки пользователя",
+    commands: &[
+        Command {
+            name: "set",
+            aliases: &[],
+            args: &[Arg::req("cal_limit", ArgKind::Float)],
+            description: "Установить лимит калорий",
+            handler: |ctx, args| Box::pin(user_settings_set(ctx, args)),
+        },
+        Command {
+            name: "sp",
+            aliases: &[],
+            args: &[
+                Arg::req("sex", ArgKind::Sex),
+                Arg::req("height", ArgKind::Float),
+                Arg::req("birth_date", ArgKind::Date),
+            ],
+            description: "Установить профиль",
+            handler: |ctx, args| Box::pin(user_settings_set_profile(ctx, args)),
+        },
+        Command {
+            name: "get",
+            aliases: &[],
+            args: &[],
+            description: "Показать настройки",
+            handler: |ctx, _| Box::pin(user_settings_get(ctx)),
+        },
+    ],
+};
+
+async fn user_settings_set(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
+
+    let cal_limit: f64 = args.get("cal_limit");
+
+    // Keep body profile if settings already exist
+    let mut us = match stg.call(move |s| s.get_user_settings(user_id)).await {
+        Ok(v) => v,
+        Err(StorageError::UserSettingsNotFound) => default_settings(),
+        Err(err) => {
+            log::error!("get user settings error: {err}");
+            out.text(storage_error_message(&err)).await?;
+            return Ok(());
         }
     };
+    us.cal_limit = cal_limit;
+
+    if let Err(err) = stg.call(move |s| s.set_user_settings(user_id, &us)).await {
+        log::error!("set user settings error: {err}");
+        out.text(storage_error_message(&err)).await?;
+    } else {
+        out.text(OK).await?;
+    }
 
     Ok(())
 }
 
-async fn user_settings_set(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-) -> HandlerResult {
-    if args.len() != 1 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn user_settings_set_profile(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
+
+    let sex: Sex = args.get("sex");
+    let height: f64 = args.get("height");
+    let birth_date: Timestamp = args.get("birth_date");
 
-    let cal_limit = match args.first().unwrap().parse::<f64>() {
+    // Keep calories limit if settings already exist
+    let mut us = match stg.call(move |s| s.get_user_settings(user_id)).await {
         Ok(v) => v,
+        Err(StorageError::UserSettingsNotFound) => default_settings(),
         Err(err) => {
-            log::error!("parse cal_limit error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
+            log::error!("get user settings error: {err}");
+            out.text(storage_error_message(&err)).await?;
             return Ok(());
         }
     };
+    us.sex = Some(sex);
+    us.height = Some(height);
+    us.birth_date = Some(birth_date);
 
-    if let Err(err) = stg.set_user_settings(user_id, &UserSettings { cal_limit }) {
-        log::error!("set sport error: {err}");
-        bot.send_message(chat_id, ERR_INTERNAL).await?;
+    if let Err(err) = stg.call(move |s| s.set_user_settings(user_id, &us)).await {
+        log::error!("set user settings error: {err}");
+        out.text(storage_error_message(&err)).await?;
     } else {
-        bot.send_message(chat_id, OK).await?;
+        out.text(OK).await?;
     }
 
     Ok(())
 }
 
-async fn user_settings_get(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    stg: Arc<Box<dyn Storage>>,
-) -> HandlerResult {
+fn default_settings() -> UserSettings {
+    UserSettings {
+        cal_limit: DEFAULT_CAL_LIMIT,
+        ..Default::default()
+    }
+}
+
+async fn user_settings_get(ctx: Ctx) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
+
     // Call storage
-    match stg.get_user_settings(user_id) {
+    match stg.call(move |s| s.get_user_settings(user_id)).await {
         Err(err) => {
             log::error!("get user settings error: {err}");
-            if stg.is_storage_error(StorageError::UserSettingsNotFound, &err) {
-                bot.send_message(chat_id, ERR_USER_SETTINGS_NOT_FOUND)
-                    .await?;
-            } else {
-                bot.send_message(chat_id, ERR_INTERNAL).await?;
-            }
+            out.text(storage_error_message(&err)).await?;
         }
         Ok(us) => {
-            bot.send_message(chat_id, format!("<b>Лимит калорий:</b> {}", us.cal_limit))
-                .parse_mode(ParseMode::Html)
-                .await?;
+            let mut res = format!("<b>Лимит калорий:</b> {}\n", us.cal_limit);
+            if let Some(sex) = us.sex {
+                res.push_str(&format!("<b>Пол:</b> {}\n", String::from(sex)));
+            }
+            if let Some(height) = us.height {
+                res.push_str(&format!("<b>Рост:</b> {height} см\n"));
+            }
+            if let Some(birth_date) = us.birth_date {
+                res.push_str(&format!(
+                    "<b>Дата рождения:</b> {}\n",
+                    format_timestamp(&birth_date, "%d.%m.%Y", tz)
+                ));
+            }
+
+            out.html(res).await?;
         }
     };
 
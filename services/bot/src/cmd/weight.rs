@@ -1,191 +1,143 @@
-use chart::{get_chart_snippet, ChartData, ChartDataset, CHART_COLOR_BLUE};
+use analytics::weight::WeightTrend;
+use chart::{
+    get_chart_snippet, Axis, ChartData, ChartDataset, ChartType, PointStyle, CHART_COLOR_BLUE,
+    CHART_COLOR_GREEN, CHART_COLOR_ORANGE, CHART_COLOR_RED,
+};
 use chrono_tz::Tz;
 use html::accordion::{Accordion, AccordionItem};
+use html::b::B;
 use html::canvas::Canvas;
 use html::div::Div;
+use html::raw::Raw;
 use html::s::S;
 use html::script::Script;
 use html::table::Table;
 use html::table::{Td, Tr};
-use html::{JS_BOOTSTRAP_URL, JS_CHART_URL};
+use html::Asset;
 use model::Weight;
-use std::sync::Arc;
-use storage::{Storage, StorageError};
-use teloxide::{prelude::*, types::InputFile};
+use types::timestamp::Timestamp;
 
 use crate::{
-    messages::{ERR_EMPTY, ERR_INTERNAL, ERR_WRONG_COMMAND, OK},
+    messages::{ERR_INTERNAL, ERR_WRONG_COMMAND, OK},
     HandlerResult,
 };
 
-use super::{format_timestamp, parse_timestamp};
-
-pub async fn process_weight_command(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-    tz: Tz,
-) -> HandlerResult {
-    if args.is_empty() {
-        log::error!("empty args");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
-
-    match *args.first().unwrap() {
-        "set" => {
-            weight_set(bot, user_id, chat_id, args[1..].to_vec(), stg, tz).await?;
-        }
-        "del" => {
-            weight_del(bot, user_id, chat_id, args[1..].to_vec(), stg, tz).await?;
-        }
-        "list" => {
-            weight_list(bot, user_id, chat_id, args[1..].to_vec(), stg, tz).await?;
-        }
-        _ => {
-            log::error!("unknown command");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        }
-    };
+use super::{
+    format_timestamp,
+    registry::{Arg, ArgKind, Args, Command, Group},
+    send_chart_photo, storage_error_message, Ctx,
+};
 
-    Ok(())
-}
+pub const GROUP: Group = Group {
+    name: "w",
+    title: "Вес",
+    commands: &[
+        Command {
+            name: "set",
+            aliases: &[],
+            args: &[
+                Arg::req("timestamp", ArgKind::Date),
+                Arg::req("value", ArgKind::Float),
+            ],
+            description: "Установить вес",
+            handler: |ctx, args| Box::pin(weight_set(ctx, args)),
+        },
+        Command {
+            name: "del",
+            aliases: &[],
+            args: &[Arg::req("timestamp", ArgKind::Date)],
+            description: "Удалить вес",
+            handler: |ctx, args| Box::pin(weight_del(ctx, args)),
+        },
+        Command {
+            name: "list",
+            aliases: &[],
+            args: &[
+                Arg::req("ts_from", ArgKind::Date),
+                Arg::req("ts_to", ArgKind::Date),
+                Arg::opt("target", ArgKind::Float),
+            ],
+            description: "Отчет по весу с трендом и целью",
+            handler: |ctx, args| Box::pin(weight_list(ctx, args)),
+        },
+    ],
+};
 
-async fn weight_set(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-    tz: Tz,
-) -> HandlerResult {
-    if args.len() != 2 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn weight_set(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
 
     // Parse args
-    let timestamp = match parse_timestamp(args.first().unwrap(), tz) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse timestamp error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
-
-    let value = match args.get(1).unwrap().parse::<f64>() {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse timestamp error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
+    let timestamp: Timestamp = args.get("timestamp");
+    let value: f64 = args.get("value");
 
     // Validate weight
     let w = Weight { timestamp, value };
     if !w.validate() {
         log::error!("invalid weight value: {:#?}", w);
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
+        out.text(ERR_WRONG_COMMAND).await?;
         return Ok(());
     }
 
     // Call storage
-    if let Err(err) = stg.set_weight(user_id, &w) {
+    if let Err(err) = stg.call(move |s| s.set_weight(user_id, &w)).await {
         log::error!("set weight error: {err}");
-        if stg.is_storage_error(StorageError::WeightInvalid, &err) {
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        } else {
-            bot.send_message(chat_id, ERR_INTERNAL).await?;
-        }
+        out.text(storage_error_message(&err)).await?;
     } else {
-        bot.send_message(chat_id, OK).await?;
+        out.text(OK).await?;
     }
 
     Ok(())
 }
 
-async fn weight_del(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-    tz: Tz,
-) -> HandlerResult {
-    if args.len() != 1 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn weight_del(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out, user_id, stg, ..
+    } = ctx;
 
     // Parse args
-    let timestamp = match parse_timestamp(args.first().unwrap(), tz) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse timestamp error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
+    let timestamp: Timestamp = args.get("timestamp");
 
     // Call storage
-    if let Err(err) = stg.delete_weight(user_id, timestamp) {
+    if let Err(err) = stg.call(move |s| s.delete_weight(user_id, timestamp)).await {
         log::error!("delete weight error: {err}");
-        bot.send_message(chat_id, ERR_INTERNAL).await?;
+        out.text(storage_error_message(&err)).await?;
     } else {
-        bot.send_message(chat_id, OK).await?;
+        out.text(OK).await?;
     }
 
     Ok(())
 }
 
-async fn weight_list(
-    bot: Bot,
-    user_id: i64,
-    chat_id: ChatId,
-    args: Vec<&str>,
-    stg: Arc<Box<dyn Storage>>,
-    tz: Tz,
-) -> HandlerResult {
-    if args.len() != 2 {
-        log::error!("wrong args count");
-        bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-        return Ok(());
-    }
+async fn weight_list(ctx: Ctx, args: Args) -> HandlerResult {
+    let Ctx {
+        out,
+        user_id,
+        stg,
+        tz,
+    } = ctx;
 
     // Parse args
-    let ts_from = match parse_timestamp(args.first().unwrap(), tz) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse timestamp from error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
-
-    let ts_to = match parse_timestamp(args.get(1).unwrap(), tz) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("parse timestamp to error: {err}");
-            bot.send_message(chat_id, ERR_WRONG_COMMAND).await?;
-            return Ok(());
-        }
-    };
+    let ts_from: Timestamp = args.get("ts_from");
+    let ts_to: Timestamp = args.get("ts_to");
+    let target: Option<f64> = args.opt("target");
+    if target.is_some_and(|v| v <= 0.0) {
+        log::error!("wrong target weight");
+        out.html(args.wrong_arg("target", "должно быть больше 0"))
+            .await?;
+        return Ok(());
+    }
 
     // Call storage
-    let w_lst = match stg.get_weight_list(user_id, ts_from.clone(), ts_to.clone()) {
+    let (from, to) = (ts_from.clone(), ts_to.clone());
+    let w_lst = match stg
+        .call(move |s| s.get_weight_list(user_id, from, to))
+        .await
+    {
         Err(err) => {
             log::error!("weight list error: {err}");
-            if stg.is_storage_error(StorageError::EmptyResult, &err) {
-                bot.send_message(chat_id, ERR_EMPTY).await?;
-            } else {
-                bot.send_message(chat_id, ERR_INTERNAL).await?;
-            }
+            out.text(storage_error_message(&err)).await?;
             return Ok(());
         }
         Ok(lst) => lst,
@@ -198,13 +150,28 @@ async fn weight_list(
     let mut doc = html::Builder::new("Таблица веса");
     let mut accrd = Accordion::new("accordionWeight");
 
+    let trend = WeightTrend::new(&w_lst);
+
+    // Summary
+    accrd.add_item(AccordionItem::new(
+        "summary",
+        &format!("Сводка за {} - {}", &ts_from, &ts_to),
+        weight_summary(&w_lst, &trend, target, tz).as_box(),
+    ));
+
     // Table
-    let mut tbl = Table::new(vec!["Дата".into(), "Вес".into()]);
+    let mut tbl = Table::new(vec![
+        "Дата".into(),
+        "Вес".into(),
+        "Тренд".into(),
+        "Среднее 7 дн.".into(),
+        "Среднее 30 дн.".into(),
+    ]);
 
     let mut x_labels = Vec::with_capacity(w_lst.len());
     let mut data = Vec::with_capacity(w_lst.len());
 
-    for w in &w_lst {
+    for (i, w) in w_lst.iter().enumerate() {
         tbl.add_row(
             Tr::new()
                 .add_td(Td::new(S::create(&format_timestamp(
@@ -212,7 +179,10 @@ async fn weight_list(
                     "%d.%m.%Y",
                     tz,
                 ))))
-                .add_td(Td::new(S::create(&format!("{:.1}", w.value)))),
+                .add_td(Td::new(S::create(&format!("{:.1}", w.value))))
+                .add_td(Td::new(S::create(&format!("{:.1}", trend.trend[i]))))
+                .add_td(Td::new(S::create(&format!("{:.1}", trend.ma7[i]))))
+                .add_td(Td::new(S::create(&format!("{:.1}", trend.ma30[i])))),
         );
         x_labels.push(format_timestamp(&w.timestamp, "%d.%m.%Y", tz));
         data.push(w.value);
@@ -231,19 +201,50 @@ async fn weight_list(
         Canvas::create("chart"),
     ));
 
-    let chart_snip = match get_chart_snippet(ChartData {
+    let chart_data = ChartData {
         elem_id: "chart".into(),
         x_labels,
-        ctype: "line".into(),
-        datasets: vec![ChartDataset {
-            data,
-            label: "Вес".into(),
-            color: CHART_COLOR_BLUE.into(),
+        ctype: ChartType::Line,
+        datasets: vec![
+            ChartDataset {
+                data,
+                label: "Вес".into(),
+                color: CHART_COLOR_BLUE.into(),
+                ..Default::default()
+            },
+            ChartDataset {
+                data: trend.trend.clone(),
+                label: "Тренд".into(),
+                color: CHART_COLOR_RED.into(),
+                point_style: PointStyle::Hidden,
+                ..Default::default()
+            },
+            ChartDataset {
+                data: trend.ma7.clone(),
+                label: "Среднее 7 дн.".into(),
+                color: CHART_COLOR_GREEN.into(),
+                point_style: PointStyle::Hidden,
+                ..Default::default()
+            },
+            ChartDataset {
+                data: trend.ma30.clone(),
+                label: "Среднее 30 дн.".into(),
+                color: CHART_COLOR_ORANGE.into(),
+                point_style: PointStyle::Hidden,
+                ..Default::default()
+            },
+        ],
+        axes: vec![Axis {
+            unit: "кг".into(),
+            ..Default::default()
         }],
-    }) {
+        ..Default::default()
+    };
+
+    let chart_snip = match get_chart_snippet(&chart_data) {
         Err(err) => {
             log::error!("chart snippet error: {err}");
-            bot.send_message(chat_id, ERR_INTERNAL).await?;
+            out.text(ERR_INTERNAL).await?;
             return Ok(());
         }
         Ok(snip) => snip,
@@ -252,15 +253,64 @@ async fn weight_list(
     // Doc
     doc = doc
         .add_element(Div::new_container().add_element(accrd.as_box()).as_box())
-        .add_element(Script::create(JS_BOOTSTRAP_URL))
-        .add_element(Script::create(JS_CHART_URL))
-        .add_element(S::create(&chart_snip));
+        .add_element(Script::create_asset(Asset::JsBootstrap))
+        .add_element(Script::create_asset(Asset::JsChart))
+        .add_element(Raw::create(&chart_snip));
+
+    out.document(format!("weight_{}_{}.html", &ts_from, &ts_to), doc.build())
+        .await?;
 
-    bot.send_document(
-        chat_id,
-        InputFile::memory(doc.build()).file_name(format!("weight_{}_{}.html", &ts_from, &ts_to)),
+    send_chart_photo(
+        &out,
+        chart_data,
+        &format!("weight_{}_{}.png", &ts_from, &ts_to),
     )
     .await?;
 
     Ok(())
 }
+
+fn weight_summary(w_lst: &[Weight], trend: &WeightTrend, target: Option<f64>, tz: Tz) -> Table {
+    let mut tbl = Table::new(vec!["Показатель".into(), "Значение".into()]);
+
+    let mut add_row = |name: &str, val: String| {
+        tbl.add_row(
+            Tr::new()
+                .add_td(Td::new(B::new(name).as_box()))
+                .add_td(Td::new(S::create(&val))),
+        );
+    };
+
+    if let Some(w) = w_lst.last() {
+        add_row("Последний вес, кг", format!("{:.1}", w.value));
+    }
+    if let Some(t) = trend.last_trend() {
+        add_row("Тренд, кг", format!("{:.1}", t));
+    }
+    if let Some(v) = trend.ma7.last() {
+        add_row("Среднее за 7 дн., кг", format!("{:.1}", v));
+    }
+    if let Some(v) = trend.ma30.last() {
+        add_row("Среднее за 30 дн., кг", format!("{:.1}", v));
+    }
+    add_row(
+        "Изменение в неделю, кг",
+        match trend.weekly_rate {
+            Some(r) => format!("{:+.2}", r),
+            None => "-".into(),
+        },
+    );
+
+    if let Some(target) = target {
+        add_row("Целевой вес, кг", format!("{:.1}", target));
+        add_row(
+            "Прогноз достижения цели",
+            match trend.goal_date(target) {
+                Some(ts) => format_timestamp(&ts, "%d.%m.%Y", tz),
+                None => "недостижима при текущем тренде".into(),
+            },
+        );
+    }
+
+    tbl
+}
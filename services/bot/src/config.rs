@@ -1,3 +1,4 @@
+use html::AssetMode;
 use std::sync::Arc;
 
 use crate::args::ArgsCli;
@@ -8,6 +9,7 @@ pub struct Config {
     pub db_file_path: String,
     pub tz: String,
     pub debug: bool,
+    pub asset_mode: AssetMode,
 }
 
 impl Config {
@@ -18,6 +20,11 @@ impl Config {
             db_file_path: args.db_file_path,
             tz: args.tz,
             debug: args.debug,
+            asset_mode: if args.cdn_assets {
+                AssetMode::Cdn
+            } else {
+                AssetMode::Inline
+            },
         }
     }
 }
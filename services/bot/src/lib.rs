@@ -3,5 +3,7 @@ pub mod args;
 mod cmd;
 mod config;
 mod messages;
+mod output;
+mod scheduler;
 
 type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
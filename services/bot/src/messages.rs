@@ -1,4 +1,7 @@
 pub const ERR_WRONG_COMMAND: &str = "Неправильная команда";
+pub const ERR_UNKNOWN_COMMAND: &str = "Неизвестная команда";
+pub const ERR_WRONG_ARGS_COUNT: &str = "Неправильное количество аргументов";
+pub const ERR_WRONG_ARG: &str = "Неправильный аргумент";
 pub const ERR_INTERNAL: &str = "Внутренняя ошибка";
 pub const ERR_EMPTY: &str = "Пустой результат";
 pub const ERR_FOOD_NOT_FOUND: &str = "Еда не найдена";
@@ -11,5 +14,21 @@ pub const ERR_BUNDLE_NOT_FOUND: &str = "Бандл не найден";
 pub const ERR_DEP_BUNDLE_NOT_FOUND: &str = "Зависимый бандл не найден в базе данных";
 pub const ERR_DEP_FOOD_NOT_FOUND: &str = "Зависимая еда не найдена в базе данных";
 pub const ERR_DEP_BUNDLE_RECURSIVE: &str = "Зависимый бандл не может быть рекурсивным";
+pub const ERR_METRIC_NOT_FOUND: &str = "Метрика не найдена";
+pub const ERR_METRIC_IS_USED: &str = "Метрика уже используется в значениях";
+pub const ERR_UNDO_CONFLICT: &str =
+    "Изменение нельзя отменить: связанные данные изменены позже, сначала отмените их изменения";
+pub const ERR_TDEE_NOT_ENOUGH_DATA: &str =
+    "Недостаточно данных: нужен журнал приема пищи и минимум два взвешивания за период";
+pub const ERR_PROFILE_NOT_SET: &str =
+    "Профиль не заполнен: укажите пол, рост и дату рождения командой u,sp";
+pub const ERR_WEIGHT_NOT_FOUND: &str = "Нет данных о весе";
+pub const ERR_BODY_FAT_REQUIRED: &str = "Для формулы Кэтча-МакАрдла нужен процент жира";
+pub const MSG_REMIND_WEIGHT: &str = "Напоминание: сегодня вы еще не взвешивались";
+pub const MSG_REMIND_JOURNAL: &str = "Напоминание: сегодня в журнале приема пищи еще нет записей";
+pub const MSG_SUMMARY_EMPTY: &str = "Итоги дня: сегодня в журнале приема пищи нет записей";
+pub const MSG_UNDONE: &str = "Отменено";
+pub const MSG_USAGE: &str = "Использование";
+pub const MSG_HELP_HINT: &str = "Список команд: h, справка по команде: h,команда";
 pub const DEBUG_MODE: &str = "!!! ОТЛАДОЧНЫЙ РЕЖИМ !!!";
 pub const OK: &str = "OK";
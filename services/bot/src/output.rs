@@ -0,0 +1,117 @@
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use teloxide::{
+    prelude::*,
+    types::{InputFile, ParseMode},
+};
+
+use crate::HandlerResult;
+
+// Message to user, independent of transport
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Text(String),
+    // Telegram HTML subset
+    Html(String),
+    Document { file_name: String, data: Vec<u8> },
+    Photo { file_name: String, data: Vec<u8> },
+}
+
+pub type SendFuture<'a> = Pin<Box<dyn Future<Output = HandlerResult> + Send + 'a>>;
+
+pub trait Sink: Send + Sync {
+    fn send(&self, chat_id: ChatId, reply: Reply) -> SendFuture<'_>;
+}
+
+pub struct TelegramSink {
+    bot: Bot,
+}
+
+impl TelegramSink {
+    pub fn new(bot: Bot) -> Self {
+        Self { bot }
+    }
+}
+
+impl Sink for TelegramSink {
+    fn send(&self, chat_id: ChatId, reply: Reply) -> SendFuture<'_> {
+        Box::pin(async move {
+            match reply {
+                Reply::Text(text) => {
+                    self.bot.send_message(chat_id, text).await?;
+                }
+                Reply::Html(text) => {
+                    self.bot
+                        .send_message(chat_id, text)
+                        .parse_mode(ParseMode::Html)
+                        .await?;
+                }
+                Reply::Document { file_name, data } => {
+                    self.bot
+                        .send_document(chat_id, InputFile::memory(data).file_name(file_name))
+                        .await?;
+                }
+                Reply::Photo { file_name, data } => {
+                    self.bot
+                        .send_photo(chat_id, InputFile::memory(data).file_name(file_name))
+                        .await?;
+                }
+            };
+
+            Ok(())
+        })
+    }
+}
+
+// Replies to one chat
+#[derive(Clone)]
+pub struct Output {
+    sink: Arc<dyn Sink>,
+    chat_id: ChatId,
+}
+
+impl Output {
+    pub fn new(sink: Arc<dyn Sink>, chat_id: ChatId) -> Self {
+        Self { sink, chat_id }
+    }
+
+    pub async fn text(&self, text: impl Into<String>) -> HandlerResult {
+        self.sink.send(self.chat_id, Reply::Text(text.into())).await
+    }
+
+    pub async fn html(&self, text: impl Into<String>) -> HandlerResult {
+        self.sink.send(self.chat_id, Reply::Html(text.into())).await
+    }
+
+    pub async fn document(
+        &self,
+        file_name: impl Into<String>,
+        data: impl Into<Vec<u8>>,
+    ) -> HandlerResult {
+        self.sink
+            .send(
+                self.chat_id,
+                Reply::Document {
+                    file_name: file_name.into(),
+                    data: data.into(),
+                },
+            )
+            .await
+    }
+
+    pub async fn photo(
+        &self,
+        file_name: impl Into<String>,
+        data: impl Into<Vec<u8>>,
+    ) -> HandlerResult {
+        self.sink
+            .send(
+                self.chat_id,
+                Reply::Photo {
+                    file_name: file_name.into(),
+                    data: data.into(),
+                },
+            )
+            .await
+    }
+}
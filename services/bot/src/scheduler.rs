@@ -0,0 +1,231 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc, Weekday};
+use chrono_tz::Tz;
+use model::{Schedule, ScheduleKind};
+use std::sync::Arc;
+use storage::{storage_async::StorageAsync, StorageError};
+use teloxide::prelude::*;
+use types::timestamp::Timestamp;
+
+use crate::{
+    cmd::{
+        digest::{build_digest, Period},
+        summary::{day_report_text, load_day_report},
+    },
+    messages::{MSG_REMIND_JOURNAL, MSG_REMIND_WEIGHT, MSG_SUMMARY_EMPTY},
+    output::{Output, Sink, TelegramSink},
+    HandlerResult,
+};
+
+#[cfg(test)]
+mod test;
+
+// How often schedules are checked
+const TICK_SECS: u64 = 30;
+// Job is skipped if bot was down longer than this after due time
+const GRACE_MINUTES: i64 = 15;
+
+pub async fn run(bot: Bot, stg: StorageAsync, user_ids: Arc<Vec<u64>>) {
+    log::info!("starting scheduler...");
+
+    let sink: Arc<dyn Sink> = Arc::new(TelegramSink::new(bot));
+
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(TICK_SECS));
+    loop {
+        interval.tick().await;
+
+        for user_id in user_ids.iter() {
+            let user_id = *user_id as i64;
+            let out = Output::new(sink.clone(), ChatId(user_id));
+            if let Err(err) = process_user(&out, user_id, &stg, Utc::now()).await {
+                log::error!("process schedules for user {user_id} error: {err}");
+            }
+        }
+    }
+}
+
+async fn process_user(
+    out: &Output,
+    user_id: i64,
+    stg: &StorageAsync,
+    now: DateTime<Utc>,
+) -> HandlerResult {
+    let sc_lst = match stg.call(move |s| s.get_schedule_list(user_id)).await {
+        Ok(v) => v,
+        Err(StorageError::EmptyResult) => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+
+    for sc in &sc_lst {
+        let Some(due) = due_time(sc, now) else {
+            continue;
+        };
+        let tz = due.timezone();
+        let due = Timestamp::from(due.fixed_offset());
+
+        log::info!("run schedule {:?} for user {user_id}", sc.kind);
+
+        // Failed job doesn't stop other jobs of user and is retried on next ticks
+        // within grace period, it's marked as done only after it was sent
+        if let Err(err) = run_schedule(out, user_id, stg, sc, due, tz).await {
+            log::error!("run schedule {:?} for user {user_id} error: {err}", sc.kind);
+            continue;
+        }
+
+        let (sc_run, last_run) = (sc.clone(), Timestamp::from(now.fixed_offset()));
+        if let Err(err) = stg
+            .call(move |s| s.set_schedule_last_run(user_id, &sc_run, last_run))
+            .await
+        {
+            log::error!(
+                "set schedule {:?} last run for user {user_id} error: {err}",
+                sc.kind
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_schedule(
+    out: &Output,
+    user_id: i64,
+    stg: &StorageAsync,
+    sc: &Schedule,
+    due: Timestamp,
+    tz: Tz,
+) -> HandlerResult {
+    match sc.kind {
+        ScheduleKind::WeightReminder => remind_weight(out, user_id, stg, due).await,
+        ScheduleKind::JournalReminder => remind_journal(out, user_id, stg, due).await,
+        ScheduleKind::DaySummary => day_summary(out, user_id, stg, due).await,
+        // Digest covers previous period
+        ScheduleKind::WeeklyDigest => send_digest(out, user_id, stg, Period::Week, due, tz).await,
+        ScheduleKind::MonthlyDigest => send_digest(out, user_id, stg, Period::Month, due, tz).await,
+    }
+}
+
+// Returns due time in schedule timezone if job should run now.
+// Due time of previous day is checked too, so grace period spans midnight.
+fn due_time(sc: &Schedule, now: DateTime<Utc>) -> Option<DateTime<Tz>> {
+    let tz: Tz = match sc.tz.parse() {
+        Ok(v) => v,
+        Err(err) => {
+            log::error!("parse schedule tz error: {err}");
+            return None;
+        }
+    };
+
+    let local = now.with_timezone(&tz);
+    let today = local.date_naive();
+    let due = [today, today.pred_opt()?]
+        .into_iter()
+        .filter_map(|date| due_at(sc, date, tz))
+        .find(|due| *due <= local && local - *due <= Duration::minutes(GRACE_MINUTES))?;
+
+    let day_matches = match sc.kind {
+        ScheduleKind::WeeklyDigest => due.weekday() == Weekday::Mon,
+        ScheduleKind::MonthlyDigest => due.day() == 1,
+        _ => true,
+    };
+    if !day_matches {
+        return None;
+    }
+
+    if let Some(last_run) = &sc.last_run {
+        if last_run.unix_millis() >= due.timestamp_millis() {
+            return None;
+        }
+    }
+
+    Some(due)
+}
+
+// Local time skipped by DST shift is moved to the hour after,
+// repeated local time is taken at its first occurrence
+fn due_at(sc: &Schedule, date: NaiveDate, tz: Tz) -> Option<DateTime<Tz>> {
+    let naive = date.and_hms_opt(sc.hour as u32, sc.minute as u32, 0)?;
+    naive.and_local_timezone(tz).earliest().or_else(|| {
+        (naive + Duration::hours(1))
+            .and_local_timezone(tz)
+            .earliest()
+    })
+}
+
+async fn remind_weight(
+    out: &Output,
+    user_id: i64,
+    stg: &StorageAsync,
+    due: Timestamp,
+) -> HandlerResult {
+    match stg
+        .call(move |s| s.get_weight_list(user_id, due.start_of_day(), due.end_of_day()))
+        .await
+    {
+        Ok(_) => {}
+        Err(StorageError::EmptyResult) => {
+            out.text(MSG_REMIND_WEIGHT).await?;
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok(())
+}
+
+async fn remind_journal(
+    out: &Output,
+    user_id: i64,
+    stg: &StorageAsync,
+    due: Timestamp,
+) -> HandlerResult {
+    match stg
+        .call(move |s| s.get_journal_report(user_id, due.start_of_day(), due.end_of_day()))
+        .await
+    {
+        Ok(_) => {}
+        Err(StorageError::EmptyResult) => {
+            out.text(MSG_REMIND_JOURNAL).await?;
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok(())
+}
+
+async fn day_summary(
+    out: &Output,
+    user_id: i64,
+    stg: &StorageAsync,
+    due: Timestamp,
+) -> HandlerResult {
+    let ts = due.clone();
+    let dr = stg.call(move |s| load_day_report(user_id, s, &ts)).await?;
+    if dr.is_empty() {
+        out.text(MSG_SUMMARY_EMPTY).await?;
+        return Ok(());
+    }
+
+    let res = day_report_text(&dr, &format!("Итоги дня {}", due.format("%d.%m.%Y")));
+
+    out.html(res).await?;
+
+    Ok(())
+}
+
+async fn send_digest(
+    out: &Output,
+    user_id: i64,
+    stg: &StorageAsync,
+    period: Period,
+    due: Timestamp,
+    tz: Tz,
+) -> HandlerResult {
+    let ts = due.sub(Duration::days(1));
+    if let Some((file_name, doc)) = stg
+        .call(move |s| build_digest(user_id, s, period, &ts, tz))
+        .await?
+    {
+        out.document(file_name, doc).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,225 @@
+use std::sync::Mutex;
+
+use chrono::{TimeZone, Timelike};
+use model::{Journal, Meal, Weight};
+use storage::{storage_memory::StorageMemory, Result as StorageResult};
+
+use super::*;
+use crate::output::{Reply, SendFuture};
+
+const USER_ID: i64 = 1;
+
+// Keeps replies instead of sending them to Telegram
+#[derive(Default)]
+struct RecordingSink {
+    replies: Mutex<Vec<Reply>>,
+}
+
+impl Sink for RecordingSink {
+    fn send(&self, chat_id: ChatId, reply: Reply) -> SendFuture<'_> {
+        assert_eq!(USER_ID, chat_id.0);
+        self.replies.lock().unwrap().push(reply);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl RecordingSink {
+    fn take(&self) -> Vec<Reply> {
+        std::mem::take(&mut *self.replies.lock().unwrap())
+    }
+}
+
+fn sc(kind: ScheduleKind, hour: u8, minute: u8, tz: &str) -> Schedule {
+    Schedule {
+        kind,
+        hour,
+        minute,
+        tz: tz.into(),
+        last_run: None,
+    }
+}
+
+fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+        .unwrap()
+}
+
+fn ts(dt: DateTime<Utc>) -> Timestamp {
+    Timestamp::from(dt.fixed_offset())
+}
+
+//
+// Due time
+//
+
+#[test]
+fn test_due_time() {
+    // 09:00 in Moscow is 06:00 UTC
+    let s = sc(ScheduleKind::JournalReminder, 9, 0, "Europe/Moscow");
+
+    assert_eq!(None, due_time(&s, utc(2024, 6, 3, 5, 59)));
+    let due = due_time(&s, utc(2024, 6, 3, 6, 0)).unwrap();
+    assert_eq!(utc(2024, 6, 3, 6, 0), due.with_timezone(&Utc));
+    assert_eq!(9, due.hour());
+
+    // Grace period after due time
+    assert!(due_time(&s, utc(2024, 6, 3, 6, 15)).is_some());
+    assert_eq!(None, due_time(&s, utc(2024, 6, 3, 6, 16)));
+
+    // Same moment in other timezone
+    let s = sc(ScheduleKind::JournalReminder, 9, 0, "America/New_York");
+    assert_eq!(None, due_time(&s, utc(2024, 6, 3, 6, 0)));
+    assert!(due_time(&s, utc(2024, 6, 3, 13, 0)).is_some());
+
+    assert_eq!(
+        None,
+        due_time(
+            &sc(ScheduleKind::JournalReminder, 9, 0, "Mars/Olympus"),
+            utc(2024, 6, 3, 6, 0)
+        )
+    );
+}
+
+#[test]
+fn test_due_time_last_run() {
+    let mut s = sc(ScheduleKind::JournalReminder, 9, 0, "Europe/Moscow");
+
+    // Run on previous day
+    s.last_run = Some(ts(utc(2024, 6, 2, 6, 1)));
+    assert!(due_time(&s, utc(2024, 6, 3, 6, 1)).is_some());
+
+    // Already run today
+    s.last_run = Some(ts(utc(2024, 6, 3, 6, 1)));
+    assert_eq!(None, due_time(&s, utc(2024, 6, 3, 6, 2)));
+}
+
+#[test]
+fn test_due_time_midnight() {
+    // 23:55 in Moscow, checked at 00:05 of next day
+    let s = sc(ScheduleKind::DaySummary, 23, 55, "Europe/Moscow");
+
+    let due = due_time(&s, utc(2024, 6, 3, 21, 5)).unwrap();
+    assert_eq!(utc(2024, 6, 3, 20, 55), due.with_timezone(&Utc));
+    assert_eq!(3, due.day());
+
+    assert_eq!(None, due_time(&s, utc(2024, 6, 3, 21, 11)));
+}
+
+#[test]
+fn test_due_time_dst() {
+    // Berlin skips 02:00-03:00 on 31.03.2024, job runs at 03:30 CEST
+    let s = sc(ScheduleKind::JournalReminder, 2, 30, "Europe/Berlin");
+    assert_eq!(None, due_time(&s, utc(2024, 3, 31, 0, 35)));
+    let due = due_time(&s, utc(2024, 3, 31, 1, 35)).unwrap();
+    assert_eq!(utc(2024, 3, 31, 1, 30), due.with_timezone(&Utc));
+
+    // Berlin repeats 02:00-03:00 on 27.10.2024, job runs at first 02:30 only
+    let due = due_time(&s, utc(2024, 10, 27, 0, 35)).unwrap();
+    assert_eq!(utc(2024, 10, 27, 0, 30), due.with_timezone(&Utc));
+    assert_eq!(None, due_time(&s, utc(2024, 10, 27, 1, 35)));
+
+    // Job after shift keeps local time
+    let s = sc(ScheduleKind::JournalReminder, 9, 0, "Europe/Berlin");
+    assert!(due_time(&s, utc(2024, 3, 30, 8, 0)).is_some());
+    assert!(due_time(&s, utc(2024, 3, 31, 7, 0)).is_some());
+}
+
+#[test]
+fn test_due_time_digest_days() {
+    // 03.06.2024 is Monday
+    let s = sc(ScheduleKind::WeeklyDigest, 9, 0, "Europe/Moscow");
+    assert!(due_time(&s, utc(2024, 6, 3, 6, 0)).is_some());
+    assert_eq!(None, due_time(&s, utc(2024, 6, 4, 6, 0)));
+
+    let s = sc(ScheduleKind::MonthlyDigest, 9, 0, "Europe/Moscow");
+    assert!(due_time(&s, utc(2024, 6, 1, 6, 0)).is_some());
+    assert_eq!(None, due_time(&s, utc(2024, 6, 2, 6, 0)));
+}
+
+//
+// Tick
+//
+
+#[tokio::test]
+async fn test_process_user() -> StorageResult<()> {
+    let sink = Arc::new(RecordingSink::default());
+    let out = Output::new(sink.clone(), ChatId(USER_ID));
+    let stg = StorageAsync::new(Box::new(StorageMemory::new()));
+
+    // No schedules
+    process_user(&out, USER_ID, &stg, utc(2024, 6, 3, 6, 1))
+        .await
+        .unwrap();
+    assert!(sink.take().is_empty());
+
+    // Weight is logged already, journal is not
+    stg.call(|s| {
+        s.set_schedule(
+            USER_ID,
+            &sc(ScheduleKind::WeightReminder, 9, 0, "Europe/Moscow"),
+        )?;
+        s.set_schedule(
+            USER_ID,
+            &sc(ScheduleKind::JournalReminder, 9, 0, "Europe/Moscow"),
+        )?;
+        s.set_weight(
+            USER_ID,
+            &Weight {
+                timestamp: ts(utc(2024, 6, 3, 5, 0)),
+                value: 80.0,
+            },
+        )
+    })
+    .await?;
+
+    process_user(&out, USER_ID, &stg, utc(2024, 6, 3, 6, 1))
+        .await
+        .unwrap();
+    assert_eq!(vec![Reply::Text(MSG_REMIND_JOURNAL.into())], sink.take());
+
+    // Both jobs are done, skipped reminder included
+    let sc_lst = stg.call(|s| s.get_schedule_list(USER_ID)).await?;
+    assert!(sc_lst
+        .iter()
+        .all(|s| s.last_run == Some(ts(utc(2024, 6, 3, 6, 1)))));
+
+    // Not repeated on next tick
+    process_user(&out, USER_ID, &stg, utc(2024, 6, 3, 6, 2))
+        .await
+        .unwrap();
+    assert!(sink.take().is_empty());
+
+    // Next day journal is logged, weight is not
+    stg.call(|s| {
+        s.set_food(
+            USER_ID,
+            &model::Food {
+                key: "apple".into(),
+                name: "Яблоко".into(),
+                brand: "".into(),
+                cal100: 52.0,
+                prot100: 0.3,
+                fat100: 0.2,
+                carb100: 14.0,
+                comment: "".into(),
+            },
+        )?;
+        s.set_journal(
+            USER_ID,
+            &Journal {
+                timestamp: ts(utc(2024, 6, 4, 5, 0)),
+                meal: Meal::Breakfast,
+                food_key: "apple".into(),
+                food_weight: 100.0,
+            },
+        )
+    })
+    .await?;
+
+    process_user(&out, USER_ID, &stg, utc(2024, 6, 4, 6, 1))
+        .await
+        .unwrap();
+    assert_eq!(vec![Reply::Text(MSG_REMIND_WEIGHT.into())], sink.take());
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use storage::storage_sqlite::{MigrationState, MigrationStatus, Migrator};
+
+use super::args::{ArgsCli, MigrateCmd};
+
+pub struct App {
+    args: ArgsCli,
+}
+
+impl App {
+    pub fn new(args: ArgsCli) -> Self {
+        Self { args }
+    }
+
+    fn print_status(migrator: &Migrator, status: &[MigrationStatus]) -> Result<()> {
+        println!(
+            "current: {}, latest: {}",
+            migrator.current_id().context("get current migration id")?,
+            migrator.latest_id()
+        );
+        println!("{:>4}  {:<40}  {:<19}  state", "id", "name", "applied at");
+
+        for s in status {
+            let applied_at = match &s.applied_at {
+                Some(ts) => ts.format("%Y-%m-%d %H:%M:%S"),
+                None if s.state == MigrationState::Pending => String::new(),
+                None => "unknown".into(),
+            };
+            let state = match s.state {
+                MigrationState::Applied => "applied",
+                MigrationState::Pending => "pending",
+                MigrationState::Changed => "changed",
+                MigrationState::Unknown => "unknown",
+            };
+
+            println!("{:>4}  {:<40}  {:<19}  {state}", s.id, s.name, applied_at);
+        }
+
+        Ok(())
+    }
+}
+
+impl service::Service for App {
+    fn run(&mut self) -> Result<()> {
+        let mut migrator =
+            Migrator::open(Path::new(&self.args.db_file_path)).context("open migrator")?;
+        let from_id = migrator.current_id().context("get current migration id")?;
+
+        match self.args.cmd {
+            MigrateCmd::Status => {
+                let status = migrator.status().context("get migration status")?;
+                return Self::print_status(&migrator, &status);
+            }
+            MigrateCmd::Up { target_id, force } => {
+                let target_id = target_id.unwrap_or(migrator.latest_id());
+                migrator.up(target_id, force).context("migrate up")?;
+            }
+            MigrateCmd::Down { target_id, force } => {
+                migrator.down(target_id, force).context("migrate down")?;
+            }
+        }
+
+        println!(
+            "migrated from {from_id} to {}",
+            migrator.current_id().context("get current migration id")?
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+use clap::{Parser, Subcommand};
+
+#[derive(Parser, Debug)]
+#[command()]
+pub struct ArgsCli {
+    #[arg(short = 'd', required = true, help = "DB file path")]
+    pub db_file_path: String,
+
+    #[command(subcommand)]
+    pub cmd: MigrateCmd,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum MigrateCmd {
+    #[command(about = "Show applied and pending migrations")]
+    Status,
+
+    #[command(about = "Apply migrations up to target id")]
+    Up {
+        #[arg(help = "Target migration id, latest if not set")]
+        target_id: Option<i64>,
+
+        #[arg(long, help = "Apply even if applied migrations changed or are unknown")]
+        force: bool,
+    },
+
+    #[command(about = "Revert migrations down to target id")]
+    Down {
+        #[arg(help = "Target migration id, stays applied; 0 reverts all")]
+        target_id: i64,
+
+        #[arg(
+            long,
+            help = "Revert even if applied migrations changed or are unknown"
+        )]
+        force: bool,
+    },
+}
@@ -4,11 +4,13 @@ use clap::Parser;
 #[command()]
 enum Cli {
     Bot(bot::args::ArgsCli),
+    Migrate(migrate::args::ArgsCli),
 }
 
 pub fn parse() -> Box<dyn service::Service> {
     let cli = Cli::parse();
     match cli {
         Cli::Bot(args) => Box::new(bot::app::App::new(args)),
+        Cli::Migrate(args) => Box::new(migrate::app::App::new(args)),
     }
 }